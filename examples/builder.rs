@@ -0,0 +1,46 @@
+use compile::CompileIntoFunction;
+use glair::compile;
+use glair::ssa::{self, builder::FunctionBuilder};
+use glair::typing::Type;
+
+fn main() {
+    // sum = 0; i = 0; while i < 10 { sum = sum + i; i = i + 1; } return sum;
+    let mut func = ssa::Function::new("sum", ssa::Signature::new(vec![], Some(Type::U32)));
+    let mut b = FunctionBuilder::new(&mut func);
+    let sum = b.declare_var(Type::U32);
+    let i = b.declare_var(Type::U32);
+
+    let entry = b.create_block();
+    let header = b.create_block();
+    let body = b.create_block();
+    let exit = b.create_block();
+
+    b.seal_block(entry);
+    b.def_var(entry, sum, ssa::Literal::U32(0));
+    b.def_var(entry, i, ssa::Literal::U32(0));
+    b.emitter(entry).emit_jmp(header);
+
+    let i_val = b.use_var(header, i);
+    let cond = b
+        .emitter(header)
+        .emit_cmp(i_val, ssa::Literal::U32(10), ssa::CmpTy::Ult);
+    b.emitter(header).emit_br(cond, body, exit);
+
+    b.seal_block(body);
+    let (sum_val, i_val) = (b.use_var(body, sum), b.use_var(body, i));
+    let mut emitter = b.emitter(body);
+    let new_sum = emitter.emit_binop(sum_val, i_val, ssa::BinOpTy::Add);
+    let new_i = emitter.emit_binop(i_val, ssa::Literal::U32(1), ssa::BinOpTy::Add);
+    emitter.emit_jmp(header);
+    b.def_var(body, sum, new_sum);
+    b.def_var(body, i, new_i);
+    b.seal_block(header);
+
+    b.seal_block(exit);
+    let sum_val = b.use_var(exit, sum);
+    b.emitter(exit).emit_ret(sum_val);
+    b.finish();
+    print!("{}", func);
+
+    print!("{}", func.compile_into_function());
+}
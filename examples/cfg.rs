@@ -0,0 +1,39 @@
+use compile::CompileIntoFunction;
+use glair::codegen::{self, Codegen};
+use glair::compile;
+use glair::ssa;
+use glair::typing::Type;
+
+fn main() {
+    let mut func = ssa::Function::new("cfg", ssa::Signature::new(vec![], Some(Type::U32)));
+    let entry = func.create_block();
+    let then = func.create_block();
+    let els = func.create_block();
+
+    let mut emitter = func.emitter(entry);
+    let x = emitter.emit_cpy(ssa::Literal::U32(10));
+    let y = emitter.emit_binop(x, ssa::Literal::U32(3), ssa::BinOpTy::Sub);
+    let c = emitter.emit_cmp(y, ssa::Literal::U32(5), ssa::CmpTy::Ugt);
+    emitter.emit_br(c, then, els);
+
+    let mut emitter = func.emitter(then);
+    let z = emitter.emit_binop(y, ssa::Literal::U32(1), ssa::BinOpTy::Add);
+    emitter.emit_ret(z);
+
+    func.emitter(els).emit_ret(x);
+
+    for block in func.block_ids() {
+        println!(
+            "{}: preds {:?}, succs {:?}",
+            block,
+            func.predecessors(block),
+            func.successors(block)
+        );
+    }
+
+    let compiled = func.compile_into_function();
+
+    let mut codegen_ctx = codegen::CodegenContext::default();
+    print!("{}", compiled);
+    print!("{}", compiled.codegen_string(&mut codegen_ctx));
+}
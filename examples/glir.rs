@@ -0,0 +1,43 @@
+//! Compiles the module in a `.glir` file and prints the allocated RTL. A pipeline of
+//! SSA passes, like `verify`, may follow the file name; the module is printed again
+//! after it ran.
+//!
+//! Usage: `cargo run --example glir -- examples/glir/sum.glir [pipeline]`
+
+use compile::CompileIntoModule;
+use glair::compile;
+use glair::opt;
+use glair::pass::PassManager;
+use glair::ssa::{parse, verify};
+use std::{env, fs, process};
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "examples/glir/sum.glir".to_string());
+    let src = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let mut module = parse::parse_module(&src).unwrap_or_else(|err| {
+        eprintln!("{}:{}", path, err);
+        process::exit(1);
+    });
+
+    print!("{}", module);
+    if let Err(errors) = verify::verify_module(&module) {
+        for (name, err) in errors {
+            eprintln!("{}: @{}: {}", path, name, err);
+        }
+        process::exit(1);
+    }
+    if let Some(pipeline) = env::args().nth(2) {
+        let mut passes = PassManager::parse(&opt::registry(), &pipeline).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        passes.run(&mut module);
+        print!("{}", module);
+    }
+    print!("{}", module.compile_into_module());
+}
@@ -1,34 +1,30 @@
-use compile::CompileIntoBlock;
+use compile::CompileIntoFunction;
 use glair::compile;
-use glair::rtl;
+use glair::opt;
+use glair::pass::PassManager;
 use glair::ssa;
+use glair::typing;
 
 fn main() {
-    let mut bb = ssa::BasicBlock::new();
-    let mut sv = ssa::GLIRSupervisor::new();
-    let mut emitter = bb.emitter(&mut sv);
+    let mut module = ssa::Module::new();
+    let mut func = ssa::Function::new("sub", ssa::Signature::new(vec![], Some(typing::Type::U32)));
+    let entry = func.create_block();
+    let mut emitter = func.emitter(entry);
     let x_0 = emitter.emit_cpy(ssa::RValue::Lit(ssa::Literal::U32(10)));
     let y_0 = emitter.emit_cpy(ssa::RValue::Var(x_0));
-    let _y_1 = emitter.emit_binop(
+    let y_1 = emitter.emit_binop(
         ssa::RValue::Var(y_0),
         ssa::RValue::Var(x_0),
         ssa::BinOpTy::Sub,
     );
+    emitter.emit_ret(y_1);
+    module.add_function(func);
 
-    let mut compiled_rtl = bb.compile_into_block();
-    let (regs, occupied) = compile::ralloc::analyze_rtl(&compiled_rtl.ops);
-    let mut allocator =
-        compile::ralloc::Allocator::new(regs.entries().map(|(k, v)| (k, *v)).collect(), occupied);
-    allocator.create_allocations();
-    let map = allocator.map();
-    dbg!(map);
+    let mut passes = PassManager::parse(&opt::registry(), "sccp,verify").expect("known passes");
+    passes.run(&mut module);
 
-    // let mut codegen_ctx = codegen::CodegenContext::default();
-    rtl::promote_registers_in_ops(&mut compiled_rtl.ops, |vir| {
-        map.get(vir)
-            .map(|alloc| alloc.kind)
-            .expect("unmapped register")
-    });
-
-    println!("{}", compiled_rtl);
+    // Lowers to RTL and allocates registers.
+    for func in module.functions() {
+        println!("{}", func.compile_into_function());
+    }
 }
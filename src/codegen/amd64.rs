@@ -1,5 +1,733 @@
 use super::{Codegen, CodegenContext};
 use crate::rtl;
+use crate::rtl::amd64::Amd64Register;
+use crate::rtl::OpJcc;
+
+/// Never handed out by the register allocator, so instructions that can't encode their
+/// operands directly may stage one of them in it.
+const SCRATCH: Amd64Register = Amd64Register::R11;
+
+/// Holds the base of a memory operand whose base register was spilled.
+const BASE_SCRATCH: Amd64Register = Amd64Register::R10;
+
+/// XMM registers the allocator leaves alone, for the same purpose as [`SCRATCH`].
+const XMM_SCRATCH: Amd64Register = Amd64Register::Xmm15D;
+const XMM_SCRATCH2: Amd64Register = Amd64Register::Xmm14D;
+
+fn size_keyword(bytes: usize) -> &'static str {
+    match bytes {
+        1 => "byte",
+        2 => "word",
+        4 => "dword",
+        8 => "qword",
+        _ => panic!("no {} byte operands", bytes),
+    }
+}
+
+/// An instruction of the form `op dst, src`. Two memory operands, or a 64-bit immediate
+/// anywhere but a `mov` into a register, go through the scratch register.
+fn two_operand(
+    mnemonic: &str,
+    dst: &rtl::Register,
+    src: &rtl::RValue,
+    context: &mut CodegenContext,
+) -> String {
+    super::check_lvalue_rvalue(dst, src);
+    let needs_scratch = match src {
+        rtl::RValue::Register(rtl::Register::Stack(..)) => {
+            matches!(dst, rtl::Register::Stack(..))
+        }
+        rtl::RValue::Lit(lit) => {
+            i32::try_from(lit.signed()).is_err()
+                && !(mnemonic == "mov" && matches!(dst, rtl::Register::Real(..)))
+        }
+        rtl::RValue::Register(..) => false,
+    };
+    let bytes = dst.sz();
+    let dst = dst.codegen_string(context);
+    let src = src.codegen_string(context);
+    match needs_scratch {
+        true => {
+            let scratch = SCRATCH.with_size(bytes);
+            format!(
+                "mov {}, {}\n{} {}, {}",
+                scratch.name(),
+                src,
+                mnemonic,
+                dst,
+                scratch.name()
+            )
+        }
+        false => format!("{} {}, {}", mnemonic, dst, src),
+    }
+}
+
+/// A shift of `val` by an immediate, or by `cl` when the count is in a register. The
+/// processor only looks at the low 6 bits of the count, so an immediate is cut down
+/// the same way.
+fn shift(
+    mnemonic: &str,
+    val: &rtl::Register,
+    by: &rtl::RValue,
+    context: &mut CodegenContext,
+) -> String {
+    let count = match by {
+        rtl::RValue::Lit(lit) => (lit.bits() & 63).to_string(),
+        rtl::RValue::Register(rtl::Register::Real(rtl::RealRegister::Amd64(reg)))
+            if reg.with_size(8) == Amd64Register::Rcx =>
+        {
+            "cl".to_string()
+        }
+        rtl::RValue::Register(..) => panic!("variable shift counts are in cl"),
+    };
+    format!("{} {}, {}", mnemonic, val.codegen_string(context), count)
+}
+
+/// `imul` of `val` by `with`, which only writes a register. A value in memory is
+/// multiplied in the scratch register, and bytes are multiplied as `dword`s since
+/// there is no byte form with two operands.
+fn int_mul(mul: &rtl::OpMul, context: &mut CodegenContext) -> String {
+    super::check_lvalue_rvalue(&mul.val, &mul.with);
+    let bytes = mul.val.sz();
+    let val = mul.val.codegen_string(context);
+    let mut lines = Vec::new();
+    let dst = match (bytes, &mul.val) {
+        (1, _) => {
+            lines.push(format!("movzx {}, {}", SCRATCH.with_size(4).name(), val));
+            SCRATCH.with_size(4).name().to_string()
+        }
+        (_, rtl::Register::Stack(..)) => {
+            lines.push(format!("mov {}, {}", SCRATCH.with_size(bytes).name(), val));
+            SCRATCH.with_size(bytes).name().to_string()
+        }
+        _ => val.clone(),
+    };
+    match &mul.with {
+        rtl::RValue::Lit(lit) => match i32::try_from(lit.signed()) {
+            Ok(imm) => lines.push(format!("imul {}, {}, {}", dst, dst, imm)),
+            Err(..) => {
+                lines.push(format!("mov {}, {}", BASE_SCRATCH.name(), lit.signed()));
+                lines.push(format!("imul {}, {}", dst, BASE_SCRATCH.name()));
+            }
+        },
+        rtl::RValue::Register(reg) if bytes == 1 => {
+            let with = BASE_SCRATCH.with_size(4).name();
+            lines.push(format!("movzx {}, {}", with, reg.codegen_string(context)));
+            lines.push(format!("imul {}, {}", dst, with));
+        }
+        rtl::RValue::Register(reg) => {
+            lines.push(format!("imul {}, {}", dst, reg.codegen_string(context)))
+        }
+    }
+    if dst != val {
+        lines.push(format!("mov {}, {}", val, SCRATCH.with_size(bytes).name()));
+    }
+    lines.join("\n")
+}
+
+/// Extends `rax` into `rdx` for a division: `cqo` and its smaller forms sign extend. A
+/// byte is divided as a `dword`, see [`int_div`].
+fn extend_high(ext: &rtl::OpExtendHigh) -> String {
+    let rtl::RealRegister::Amd64(lo) = ext.lo.unwrap_real();
+    let rtl::RealRegister::Amd64(hi) = ext.hi.unwrap_real();
+    assert!(
+        lo.with_size(8) == Amd64Register::Rax && hi.with_size(8) == Amd64Register::Rdx,
+        "division extends rax into rdx"
+    );
+    match (ext.signed, ext.lo.sz()) {
+        (true, 1) => "movsx eax, al\ncdq".to_string(),
+        (false, 1) => "movzx eax, al\nxor edx, edx".to_string(),
+        (true, 2) => "cwd".to_string(),
+        (true, 4) => "cdq".to_string(),
+        (true, _) => "cqo".to_string(),
+        (false, _) => "xor edx, edx".to_string(),
+    }
+}
+
+/// `idiv` or `div` of `rdx:rax`. There is no form taking an immediate, so one is moved
+/// to the scratch register first. A byte divisor is extended to a `dword`, since the
+/// byte forms would put the remainder into `ah`.
+fn int_div(div: &rtl::OpIntDiv, context: &mut CodegenContext) -> String {
+    let mnemonic = match div.signed {
+        true => "idiv",
+        false => "div",
+    };
+    let bytes = div.lo.sz();
+    let scratch = SCRATCH.with_size(if bytes == 1 { 4 } else { bytes }).name();
+    match &div.by {
+        rtl::RValue::Lit(lit) => {
+            let lit = match div.signed {
+                true => lit.signed(),
+                false => lit.bits() as i64,
+            };
+            format!("mov {}, {}\n{} {}", scratch, lit, mnemonic, scratch)
+        }
+        rtl::RValue::Register(reg) if bytes == 1 => {
+            let ext = match div.signed {
+                true => "movsx",
+                false => "movzx",
+            };
+            format!(
+                "{} {}, {}\n{} {}",
+                ext,
+                scratch,
+                reg.codegen_string(context),
+                mnemonic,
+                scratch
+            )
+        }
+        rtl::RValue::Register(reg) => format!("{} {}", mnemonic, reg.codegen_string(context)),
+    }
+}
+
+/// `mul` or `imul` of `rax`, leaving the high half of the product in `rdx`. The one
+/// operand forms take no immediate, so one is moved to the scratch register first.
+/// Bytes are multiplied as `dword`s into `edx` and shifted down, since the byte forms
+/// put the high half into `ah`.
+fn mul_high(mul: &rtl::OpMulHigh, context: &mut CodegenContext) -> String {
+    let rtl::RealRegister::Amd64(lo) = mul.lo.unwrap_real();
+    let rtl::RealRegister::Amd64(hi) = mul.hi.unwrap_real();
+    assert!(
+        lo.with_size(8) == Amd64Register::Rax && hi.with_size(8) == Amd64Register::Rdx,
+        "the product is in rdx:rax"
+    );
+    let bytes = mul.lo.sz();
+    let (mnemonic, ext, shift) = match mul.signed {
+        true => ("imul", "movsx", "sar"),
+        false => ("mul", "movzx", "shr"),
+    };
+    match (&mul.with, bytes) {
+        (rtl::RValue::Lit(lit), 1) => {
+            let lit = match mul.signed {
+                true => lit.signed(),
+                false => lit.bits() as i64,
+            };
+            format!("{} eax, al\nimul edx, eax, {}\n{} edx, 8", ext, lit, shift)
+        }
+        (rtl::RValue::Lit(lit), _) => {
+            let scratch = SCRATCH.with_size(bytes).name();
+            format!(
+                "mov {}, {}\n{} {}",
+                scratch,
+                lit.signed(),
+                mnemonic,
+                scratch
+            )
+        }
+        (rtl::RValue::Register(reg), 1) => {
+            let scratch = SCRATCH.with_size(4).name();
+            format!(
+                "{} eax, al\n{} {}, {}\nimul eax, {}\nmov edx, eax\n{} edx, 8",
+                ext,
+                ext,
+                scratch,
+                reg.codegen_string(context),
+                scratch,
+                shift
+            )
+        }
+        (rtl::RValue::Register(reg), _) => {
+            format!("{} {}", mnemonic, reg.codegen_string(context))
+        }
+    }
+}
+
+/// `cmov` of `from` into `to`. There is no byte form, so bytes are moved as `dword`s,
+/// and only a register can be written, so a value in memory is moved in the scratch
+/// register. A byte in memory is zero extended first rather than read as a `dword`.
+fn cmov(cmov: &rtl::OpCMov, context: &mut CodegenContext) -> String {
+    let bytes = cmov.to.sz();
+    let size = if bytes == 1 { 4 } else { bytes };
+    let mut lines = Vec::new();
+    let mut operand = |reg: &rtl::Register, scratch: Amd64Register| match reg {
+        rtl::Register::Stack(..) => {
+            let load = if bytes == 1 { "movzx" } else { "mov" };
+            let scratch = scratch.with_size(size).name();
+            lines.push(format!(
+                "{} {}, {}",
+                load,
+                scratch,
+                reg.codegen_string(context)
+            ));
+            scratch.to_string()
+        }
+        _ => {
+            let rtl::RealRegister::Amd64(reg) = reg.unwrap_real();
+            reg.with_size(size).name().to_string()
+        }
+    };
+    let from = operand(&cmov.from, BASE_SCRATCH);
+    let to = operand(&cmov.to, SCRATCH);
+    lines.push(format!(
+        "cmov{} {}, {}",
+        cmov.cond.codegen_string(context),
+        to,
+        from
+    ));
+    if let rtl::Register::Stack(..) = cmov.to {
+        lines.push(format!(
+            "mov {}, {}",
+            cmov.to.codegen_string(context),
+            SCRATCH.with_size(bytes).name()
+        ));
+    }
+    lines.join("\n")
+}
+
+/// `ss` or `sd`, the suffix of scalar SSE instructions on floats of this size.
+fn sse_suffix(bytes: usize) -> &'static str {
+    match bytes {
+        4 => "ss",
+        8 => "sd",
+        _ => panic!("no {} byte floats", bytes),
+    }
+}
+
+fn is_xmm(reg: &rtl::Register) -> bool {
+    matches!(reg, rtl::Register::Real(real) if real.class() == rtl::RegClass::Xmm)
+}
+
+/// Loads the bits of a float immediate into an XMM register, through [`SCRATCH`].
+fn load_float_lit(xmm: Amd64Register, lit: &rtl::Lit) -> String {
+    let (mov, gpr) = match lit.sz() {
+        4 => ("movd", SCRATCH.with_size(4)),
+        _ => ("movq", SCRATCH.with_size(8)),
+    };
+    format!(
+        "mov {}, {}\n{} {}, {}",
+        gpr.name(),
+        lit.signed(),
+        mov,
+        xmm.name(),
+        gpr.name()
+    )
+}
+
+/// Copies a float between XMM registers and memory. Copies from memory to memory and of
+/// immediates to memory only move bits, which [`two_operand`] does.
+fn float_copy(dst: &rtl::Register, src: &rtl::RValue, context: &mut CodegenContext) -> String {
+    super::check_lvalue_rvalue(dst, src);
+    let mov = format!("mov{}", sse_suffix(dst.sz()));
+    match (dst, src) {
+        (rtl::Register::Real(..), rtl::RValue::Register(rtl::Register::Real(..))) => format!(
+            "movaps {}, {}",
+            dst.codegen_string(context),
+            src.codegen_string(context)
+        ),
+        (rtl::Register::Real(rtl::RealRegister::Amd64(xmm)), rtl::RValue::Lit(lit)) => {
+            load_float_lit(xmm.with_size(8), lit)
+        }
+        _ => format!(
+            "{} {}, {}",
+            mov,
+            dst.codegen_string(context),
+            src.codegen_string(context)
+        ),
+    }
+}
+
+/// A scalar SSE instruction of the form `op dst, src`, like `addsd`. The destination
+/// has to be a register, so memory destinations are loaded into a scratch register, and
+/// written back if `writes_dst`. Immediates are loaded into another scratch register.
+fn sse_two_operand(
+    mnemonic: &str,
+    dst: &rtl::Register,
+    src: &rtl::RValue,
+    writes_dst: bool,
+    context: &mut CodegenContext,
+) -> String {
+    super::check_lvalue_rvalue(dst, src);
+    let suffix = sse_suffix(dst.sz());
+    let mut lines = Vec::new();
+    let src = match src {
+        rtl::RValue::Lit(lit) => {
+            lines.push(load_float_lit(XMM_SCRATCH, lit));
+            XMM_SCRATCH.name().to_string()
+        }
+        rtl::RValue::Register(reg) => reg.codegen_string(context),
+    };
+    let mem_dst = match dst {
+        rtl::Register::Stack(..) => Some(dst.codegen_string(context)),
+        _ => None,
+    };
+    let dst = match &mem_dst {
+        Some(mem) => {
+            lines.push(format!("mov{} {}, {}", suffix, XMM_SCRATCH2.name(), mem));
+            XMM_SCRATCH2.name().to_string()
+        }
+        None => dst.codegen_string(context),
+    };
+    lines.push(format!("{}{} {}, {}", mnemonic, suffix, dst, src));
+    if let (Some(mem), true) = (mem_dst, writes_dst) {
+        lines.push(format!("mov{} {}, {}", suffix, mem, dst));
+    }
+    lines.join("\n")
+}
+
+/// The memory operand `[base+index*scale+disp]`, without a size. A spilled base or
+/// index is loaded into [`BASE_SCRATCH`] first, which is pushed to `lines`. When both
+/// are spilled, the scaled index is added to the base there.
+fn address(addr: &rtl::Address, lines: &mut Vec<String>, context: &mut CodegenContext) -> String {
+    // A `dword` address is for an `lea` on `dword`s, whose upper halves don't matter.
+    let scratch = BASE_SCRATCH.with_size(addr.base.sz()).name().to_string();
+    let spilled = |reg: &rtl::Register| matches!(reg, rtl::Register::Stack(..));
+    let (base, index) = match addr.index {
+        Some((index, scale)) if spilled(&addr.base) && spilled(&index) => {
+            lines.push(format!(
+                "mov {}, {}",
+                scratch,
+                index.codegen_string(context)
+            ));
+            if scale > 1 {
+                lines.push(format!("shl {}, {}", scratch, scale.trailing_zeros()));
+            }
+            lines.push(format!(
+                "add {}, {}",
+                scratch,
+                addr.base.codegen_string(context)
+            ));
+            (scratch, None)
+        }
+        index => {
+            let mut operand = |reg: &rtl::Register| match spilled(reg) {
+                true => {
+                    lines.push(format!("mov {}, {}", scratch, reg.codegen_string(context)));
+                    scratch.clone()
+                }
+                false => reg.codegen_string(context),
+            };
+            let base = operand(&addr.base);
+            let index = index.map(|(index, scale)| (operand(&index), scale));
+            (base, index)
+        }
+    };
+    let base = match index {
+        Some((index, 1)) => format!("{}+{}", base, index),
+        Some((index, scale)) => format!("{}+{}*{}", base, index, scale),
+        None => base,
+    };
+    match addr.disp {
+        0 => format!("[{}]", base),
+        disp if disp < 0 => format!("[{}-{}]", base, disp.unsigned_abs()),
+        disp => format!("[{}+{}]", base, disp),
+    }
+}
+
+/// A `mov` between a register and memory, `load` tells the direction. Values in
+/// memory go through a scratch register, since `mov` can't have two memory operands.
+fn memory_mov(
+    reg: &rtl::Register,
+    addr: &rtl::Address,
+    load: bool,
+    context: &mut CodegenContext,
+) -> String {
+    let mut lines = Vec::new();
+    let bytes = reg.sz();
+    let mem = format!(
+        "{} {}",
+        size_keyword(bytes),
+        address(addr, &mut lines, context)
+    );
+    let mov = match is_xmm(reg) {
+        true => format!("mov{}", sse_suffix(bytes)),
+        false => "mov".to_string(),
+    };
+    let (staged, reg_str) = match reg {
+        rtl::Register::Stack(..) => (true, SCRATCH.with_size(bytes).name().to_string()),
+        _ => (false, reg.codegen_string(context)),
+    };
+    if staged && !load {
+        lines.push(format!("mov {}, {}", reg_str, reg.codegen_string(context)));
+    }
+    match load {
+        true => lines.push(format!("{} {}, {}", mov, reg_str, mem)),
+        false => lines.push(format!("{} {}, {}", mov, mem, reg_str)),
+    }
+    if staged && load {
+        lines.push(format!("mov {}, {}", reg.codegen_string(context), reg_str));
+    }
+    lines.join("\n")
+}
+
+fn store(store: &rtl::OpStore, context: &mut CodegenContext) -> String {
+    match &store.from {
+        rtl::RValue::Register(reg) => memory_mov(reg, &store.addr, false, context),
+        rtl::RValue::Lit(lit) => {
+            let mut lines = Vec::new();
+            let mem = format!(
+                "{} {}",
+                size_keyword(lit.sz()),
+                address(&store.addr, &mut lines, context)
+            );
+            match i32::try_from(lit.signed()) {
+                Ok(imm) => lines.push(format!("mov {}, {}", mem, imm)),
+                Err(..) => {
+                    lines.push(format!("mov {}, {}", SCRATCH.name(), lit.signed()));
+                    lines.push(format!("mov {}, {}", mem, SCRATCH.name()));
+                }
+            }
+            lines.join("\n")
+        }
+    }
+}
+
+fn lea(lea: &rtl::OpLea, context: &mut CodegenContext) -> String {
+    let mut lines = Vec::new();
+    let addr = address(&lea.addr, &mut lines, context);
+    match lea.to {
+        rtl::Register::Stack(..) => {
+            let scratch = SCRATCH.with_size(lea.to.sz()).name();
+            lines.push(format!("lea {}, {}", scratch, addr));
+            lines.push(format!(
+                "mov {}, {}",
+                lea.to.codegen_string(context),
+                scratch
+            ));
+        }
+        _ => lines.push(format!("lea {}, {}", lea.to.codegen_string(context), addr)),
+    }
+    lines.join("\n")
+}
+
+/// `lea` of a global, relative to `rip` so the code stays position independent.
+fn addr_of(addr_of: &rtl::OpAddrOf, context: &mut CodegenContext) -> String {
+    let addr = format!("[rel {}]", addr_of.symbol);
+    match addr_of.to {
+        rtl::Register::Stack(..) => format!(
+            "lea {}, {}\nmov {}, {}",
+            SCRATCH.name(),
+            addr,
+            addr_of.to.codegen_string(context),
+            SCRATCH.name()
+        ),
+        _ => format!("lea {}, {}", addr_of.to.codegen_string(context), addr),
+    }
+}
+
+/// The section directive, alignment, label and contents of a global.
+fn global_data(global: &rtl::Global) -> String {
+    let mut lines = Vec::new();
+    match global.section {
+        rtl::Section::Data => lines.push("section .data".to_string()),
+        rtl::Section::ReadOnly => lines.push("section .rodata".to_string()),
+        rtl::Section::Bss => lines.push("section .bss".to_string()),
+    }
+    match global.section {
+        rtl::Section::Bss => lines.push(format!("alignb {}", global.align)),
+        _ => lines.push(format!("align {}", global.align)),
+    }
+    lines.push(format!("{}:", global.name));
+    match global.section {
+        rtl::Section::Bss => lines.push(format!("resb {}", global.size)),
+        _ => {
+            for row in global.bytes.chunks(16) {
+                let row: Vec<String> = row.iter().map(|byte| byte.to_string()).collect();
+                lines.push(format!("db {}", row.join(", ")));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+fn convert(convert: &rtl::OpConvert, context: &mut CodegenContext) -> String {
+    let (to, from) = (&convert.to, &convert.from);
+    let from_str = from.codegen_string(context);
+    let mut lines = Vec::new();
+    // Conversions to floats need a register destination.
+    let float_dst = |context: &mut CodegenContext| match to {
+        rtl::Register::Stack(..) => XMM_SCRATCH.with_size(to.sz()).name().to_string(),
+        _ => to.codegen_string(context),
+    };
+    match convert.conv {
+        rtl::Conv::SiToFp | rtl::Conv::UiToFp => {
+            let dst = float_dst(context);
+            let cvt = format!("cvtsi2{}", sse_suffix(to.sz()));
+            let signed = convert.conv == rtl::Conv::SiToFp;
+            match (from.sz(), signed) {
+                (1 | 2, true) => {
+                    lines.push(format!(
+                        "movsx {}, {}",
+                        SCRATCH.with_size(4).name(),
+                        from_str
+                    ));
+                    lines.push(format!("{} {}, {}", cvt, dst, SCRATCH.with_size(4).name()));
+                }
+                (1 | 2, false) => {
+                    lines.push(format!(
+                        "movzx {}, {}",
+                        SCRATCH.with_size(4).name(),
+                        from_str
+                    ));
+                    lines.push(format!("{} {}, {}", cvt, dst, SCRATCH.with_size(4).name()));
+                }
+                (4, false) => {
+                    // Writing the low half of a register clears the high half, which
+                    // leaves a non-negative 64-bit integer.
+                    lines.push(format!("mov {}, {}", SCRATCH.with_size(4).name(), from_str));
+                    lines.push(format!("{} {}, {}", cvt, dst, SCRATCH.name()));
+                }
+                (_, false) => {
+                    // Integers with the top bit set are halved first, keeping the lowest
+                    // bit so the result rounds correctly, and doubled afterwards.
+                    let (big, even, done) = (
+                        context.local_label(),
+                        context.local_label(),
+                        context.local_label(),
+                    );
+                    let add = format!("add{}", sse_suffix(to.sz()));
+                    lines.push(format!("mov {}, {}", SCRATCH.name(), from_str));
+                    lines.push(format!("test {}, {}", SCRATCH.name(), SCRATCH.name()));
+                    lines.push(format!("js {}", big));
+                    lines.push(format!("{} {}, {}", cvt, dst, SCRATCH.name()));
+                    lines.push(format!("jmp {}", done));
+                    lines.push(format!("{}:", big));
+                    lines.push(format!("shr {}, 1", SCRATCH.name()));
+                    lines.push(format!("jnc {}", even));
+                    lines.push(format!("or {}, 1", SCRATCH.name()));
+                    lines.push(format!("{}:", even));
+                    lines.push(format!("{} {}, {}", cvt, dst, SCRATCH.name()));
+                    lines.push(format!("{} {}, {}", add, dst, dst));
+                    lines.push(format!("{}:", done));
+                }
+                (_, true) => lines.push(format!("{} {}, {}", cvt, dst, from_str)),
+            }
+        }
+        rtl::Conv::FpToSi | rtl::Conv::FpToUi => {
+            let cvt = format!("cvtt{}2si", sse_suffix(from.sz()));
+            let signed = convert.conv == rtl::Conv::FpToSi;
+            // The widest result that still holds every value of the destination type.
+            let wide = match (to.sz(), signed) {
+                (8, _) | (4, false) => SCRATCH,
+                _ => SCRATCH.with_size(4),
+            };
+            if to.sz() == 8 && !signed {
+                // Floats from 2^63 up don't fit a signed integer. They are converted
+                // after subtracting 2^63, which is then added back as the top bit.
+                let (big, done) = (context.local_label(), context.local_label());
+                let suffix = sse_suffix(from.sz());
+                let two_pow_63 = match from.sz() {
+                    4 => rtl::Lit::LitU32((2.0f32).powi(63).to_bits()),
+                    _ => rtl::Lit::LitU64((2.0f64).powi(63).to_bits()),
+                };
+                let (value, limit) = (
+                    XMM_SCRATCH.with_size(from.sz()),
+                    XMM_SCRATCH2.with_size(from.sz()),
+                );
+                lines.push(format!("mov{} {}, {}", suffix, value.name(), from_str));
+                lines.push(load_float_lit(limit, &two_pow_63));
+                lines.push(format!(
+                    "ucomi{} {}, {}",
+                    suffix,
+                    value.name(),
+                    limit.name()
+                ));
+                lines.push(format!("jae {}", big));
+                lines.push(format!("{} {}, {}", cvt, wide.name(), value.name()));
+                lines.push(format!("jmp {}", done));
+                lines.push(format!("{}:", big));
+                lines.push(format!("sub{} {}, {}", suffix, value.name(), limit.name()));
+                lines.push(format!("{} {}, {}", cvt, wide.name(), value.name()));
+                lines.push(format!("btc {}, 63", wide.name()));
+                lines.push(format!("{}:", done));
+            } else {
+                lines.push(format!("{} {}, {}", cvt, wide.name(), from_str));
+            }
+            lines.push(format!(
+                "mov {}, {}",
+                to.codegen_string(context),
+                SCRATCH.with_size(to.sz()).name()
+            ));
+        }
+        rtl::Conv::ZExt | rtl::Conv::SExt if from.sz() == to.sz() => {
+            lines.push(two_operand(
+                "mov",
+                to,
+                &rtl::RValue::Register(*from),
+                context,
+            ));
+        }
+        rtl::Conv::ZExt | rtl::Conv::SExt => {
+            // `movzx` and `movsx` only write registers.
+            let wide = match to {
+                rtl::Register::Stack(..) => SCRATCH.with_size(to.sz()),
+                _ => {
+                    let rtl::RealRegister::Amd64(reg) = to.unwrap_real();
+                    *reg
+                }
+            };
+            lines.push(match (convert.conv, from.sz()) {
+                // Writing the low half of a register clears the high half.
+                (rtl::Conv::ZExt, 4) => format!("mov {}, {}", wide.with_size(4).name(), from_str),
+                (rtl::Conv::ZExt, _) => format!("movzx {}, {}", wide.name(), from_str),
+                (_, 4) => format!("movsxd {}, {}", wide.name(), from_str),
+                _ => format!("movsx {}, {}", wide.name(), from_str),
+            });
+            if let rtl::Register::Stack(..) = to {
+                lines.push(format!(
+                    "mov {}, {}",
+                    to.codegen_string(context),
+                    wide.name()
+                ));
+            }
+        }
+        rtl::Conv::Trunc => {
+            // The low bytes of a value, in memory too since amd64 is little endian.
+            let low = match from {
+                rtl::Register::Stack(ss) => rtl::Register::Stack(rtl::StackRegister {
+                    bytes: to.sz(),
+                    ..*ss
+                }),
+                _ => {
+                    let rtl::RealRegister::Amd64(reg) = from.unwrap_real();
+                    rtl::Register::Real(rtl::RealRegister::Amd64(reg.with_size(to.sz())))
+                }
+            };
+            lines.push(two_operand("mov", to, &rtl::RValue::Register(low), context));
+        }
+        rtl::Conv::BitCast if !is_xmm(to) && !is_xmm(from) => {
+            lines.push(two_operand(
+                "mov",
+                to,
+                &rtl::RValue::Register(*from),
+                context,
+            ));
+        }
+        // Between an XMM register and a general purpose register or memory.
+        rtl::Conv::BitCast => {
+            let mov = match to.sz() {
+                4 => "movd",
+                _ => "movq",
+            };
+            lines.push(format!(
+                "{} {}, {}",
+                mov,
+                to.codegen_string(context),
+                from_str
+            ));
+        }
+        rtl::Conv::FpExt | rtl::Conv::FpTrunc => {
+            let dst = float_dst(context);
+            lines.push(format!(
+                "cvt{}2{} {}, {}",
+                sse_suffix(from.sz()),
+                sse_suffix(to.sz()),
+                dst,
+                from_str
+            ));
+        }
+    }
+    if let (rtl::Register::Stack(..), rtl::Conv::SiToFp | rtl::Conv::UiToFp)
+    | (rtl::Register::Stack(..), rtl::Conv::FpExt | rtl::Conv::FpTrunc) = (to, convert.conv)
+    {
+        lines.push(format!(
+            "mov{} {}, {}",
+            sse_suffix(to.sz()),
+            to.codegen_string(context),
+            XMM_SCRATCH.name()
+        ));
+    }
+    lines.join("\n")
+}
 
 impl Codegen for rtl::amd64::Amd64Register {
     fn codegen_string(&self, _context: &mut CodegenContext) -> String {
@@ -9,10 +737,9 @@ impl Codegen for rtl::amd64::Amd64Register {
 
 impl Codegen for rtl::Lit {
     fn codegen_string(&self, _context: &mut CodegenContext) -> String {
-        match self {
-            rtl::Lit::LitU8(val) => val.to_string(),
-            rtl::Lit::LitU32(val) => val.to_string(),
-        }
+        // Assemblers accept a negative immediate at any size, but not a large positive
+        // one where the instruction sign extends a 32-bit immediate.
+        self.signed().to_string()
     }
 }
 
@@ -26,7 +753,11 @@ impl Codegen for rtl::RealRegister {
 
 impl Codegen for rtl::Register {
     fn codegen_string(&self, context: &mut CodegenContext) -> String {
-        self.unwrap_real().codegen_string(context)
+        match self {
+            // Stack slots live in the frame, below the frame pointer.
+            rtl::Register::Stack(ss) => format!("{} [rbp-{}]", size_keyword(ss.bytes), ss.slot),
+            _ => self.unwrap_real().codegen_string(context),
+        }
     }
 }
 
@@ -39,47 +770,120 @@ impl Codegen for rtl::RValue {
     }
 }
 
+impl Codegen for rtl::Cond {
+    fn codegen_string(&self, _context: &mut CodegenContext) -> String {
+        match self {
+            rtl::Cond::Eq => "e",
+            rtl::Cond::Ne => "ne",
+            rtl::Cond::Lt => "l",
+            rtl::Cond::Le => "le",
+            rtl::Cond::Gt => "g",
+            rtl::Cond::Ge => "ge",
+            rtl::Cond::Ult => "b",
+            rtl::Cond::Ule => "be",
+            rtl::Cond::Ugt => "a",
+            rtl::Cond::Uge => "ae",
+            rtl::Cond::FEq | rtl::Cond::FNe => {
+                panic!("{} is tested with more than one instruction", self)
+            }
+        }
+        .to_string()
+    }
+}
+
 impl Codegen for rtl::Op {
     fn codegen_string(&self, context: &mut CodegenContext) -> String {
         match self {
-            rtl::Op::Copy(cp) => {
-                // Check that both operands are of the same size.
-                super::check_lvalue_rvalue(&cp.to, &cp.from);
-                format!(
-                    "mov {}, {}",
-                    cp.to.codegen_string(context),
-                    cp.from.codegen_string(context)
-                )
+            rtl::Op::Copy(cp) => match &cp.from {
+                rtl::RValue::Register(from) if is_xmm(from) => {
+                    float_copy(&cp.to, &cp.from, context)
+                }
+                _ if is_xmm(&cp.to) => float_copy(&cp.to, &cp.from, context),
+                _ => two_operand("mov", &cp.to, &cp.from, context),
+            },
+            rtl::Op::Add(add) if add.to.class() == rtl::RegClass::Xmm => {
+                sse_two_operand("add", &add.to, &add.val, true, context)
+            }
+            rtl::Op::Sub(sub) if sub.from.class() == rtl::RegClass::Xmm => {
+                sse_two_operand("sub", &sub.from, &sub.val, true, context)
+            }
+            rtl::Op::Mul(mul) if mul.val.class() == rtl::RegClass::Xmm => {
+                sse_two_operand("mul", &mul.val, &mul.with, true, context)
+            }
+            rtl::Op::Div(div) if div.val.class() == rtl::RegClass::Xmm => {
+                sse_two_operand("div", &div.val, &div.with, true, context)
+            }
+            rtl::Op::Cmp(cmp) if cmp.a.class() == rtl::RegClass::Xmm => {
+                sse_two_operand("ucomi", &cmp.a, &cmp.b, false, context)
             }
-            rtl::Op::Add(add) => {
-                super::check_lvalue_rvalue(&add.to, &add.val);
-                format!(
-                    "add {}, {}",
-                    add.to.codegen_string(context),
-                    add.val.codegen_string(context)
-                )
+            rtl::Op::Add(add) => two_operand("add", &add.to, &add.val, context),
+            rtl::Op::Sub(sub) => two_operand("sub", &sub.from, &sub.val, context),
+            rtl::Op::Mul(mul) => int_mul(mul, context),
+            rtl::Op::Div(..) => panic!("integers are divided by OpIntDiv"),
+            rtl::Op::ExtendHigh(ext) => extend_high(ext),
+            rtl::Op::IntDiv(div) => int_div(div, context),
+            rtl::Op::MulHigh(mul) => mul_high(mul, context),
+            rtl::Op::And(and) => two_operand("and", &and.to, &and.val, context),
+            rtl::Op::Or(or) => two_operand("or", &or.to, &or.val, context),
+            rtl::Op::Xor(xor) => two_operand("xor", &xor.to, &xor.val, context),
+            rtl::Op::Shl(shl) => shift("shl", &shl.val, &shl.by, context),
+            rtl::Op::LShr(lshr) => shift("shr", &lshr.val, &lshr.by, context),
+            rtl::Op::AShr(ashr) => shift("sar", &ashr.val, &ashr.by, context),
+            rtl::Op::Not(not) => format!("not {}", not.val.codegen_string(context)),
+            rtl::Op::Neg(neg) => format!("neg {}", neg.val.codegen_string(context)),
+            rtl::Op::Cmp(cmp) => two_operand("cmp", &cmp.a, &cmp.b, context),
+            // Block labels are local to the label of their function.
+            rtl::Op::Jmp(jmp) => format!("jmp .{}", jmp.target),
+            rtl::Op::Jcc(OpJcc {
+                cond: rtl::Cond::FNe,
+                target,
+            }) => format!("jne .{}\njp .{}", target, target),
+            rtl::Op::Jcc(jcc) => format!("j{} .{}", jcc.cond.codegen_string(context), jcc.target),
+            rtl::Op::SetCc(setcc) => {
+                assert_eq!(setcc.to.sz(), 1, "setcc writes a byte register");
+                let to = setcc.to.codegen_string(context);
+                let parity = SCRATCH.with_size(1).name();
+                match setcc.cond {
+                    // Unordered operands set the parity flag.
+                    rtl::Cond::FEq => {
+                        format!("sete {}\nsetnp {}\nand {}, {}", to, parity, to, parity)
+                    }
+                    rtl::Cond::FNe => {
+                        format!("setne {}\nsetp {}\nor {}, {}", to, parity, to, parity)
+                    }
+                    cond => format!("set{} {}", cond.codegen_string(context), to),
+                }
             }
-            rtl::Op::Sub(sub) => {
-                // Check that both operands are of the same size.
-                super::check_lvalue_rvalue(&sub.from, &sub.val);
-                format!(
-                    "sub {}, {}",
-                    sub.from.codegen_string(context),
-                    sub.val.codegen_string(context)
-                )
+            rtl::Op::CMov(cmov) => self::cmov(cmov, context),
+            rtl::Op::Convert(cv) => convert(cv, context),
+            rtl::Op::Load(load) => memory_mov(&load.to, &load.addr, true, context),
+            rtl::Op::Store(st) => store(st, context),
+            rtl::Op::Lea(le) => lea(le, context),
+            rtl::Op::AddrOf(addr) => addr_of(addr, context),
+            // Arguments and the result are already in place, see `compile::call`.
+            rtl::Op::Call(call) => format!("call {} wrt ..plt", call.target),
+            // Restores the callee-saved registers, then the stack and frame pointers of
+            // the caller.
+            rtl::Op::Ret(..) => {
+                let saved = context.saved.clone();
+                let mut lines: Vec<String> = saved
+                    .iter()
+                    .map(|(reg, disp)| {
+                        format!("mov {}, [rbp{:+}]", reg.codegen_string(context), disp)
+                    })
+                    .collect();
+                lines.push("leave\nret".to_string());
+                lines.join("\n")
             }
-            rtl::Op::Mul(..) => todo!("codegen amd64 nasm for mul"),
-            rtl::Op::Div(..) => todo!("codegen amd64 nasm for div"),
         }
     }
 }
 
 impl Codegen for rtl::Block {
     fn codegen_string(&self, context: &mut CodegenContext) -> String {
-        let mut buf = ";; ".to_string();
-        match &self.name {
-            Some(name) => buf.push_str(name.as_str()),
-            None => buf.push_str("<unnamed block>"),
+        let mut buf = match &self.name {
+            Some(name) => format!(".{}:", name),
+            None => ";; <unnamed block>".to_string(),
         };
         buf.push('\n');
 
@@ -91,3 +895,57 @@ impl Codegen for rtl::Block {
         buf
     }
 }
+
+impl Codegen for rtl::Function {
+    /// The function under its own label. The prologue sets up a frame that keeps the
+    /// stack pointer aligned to 16 bytes and saves the callee-saved registers the
+    /// function uses, `ret` takes it down again.
+    fn codegen_string(&self, context: &mut CodegenContext) -> String {
+        let mut buf = format!("{}:\npush rbp\nmov rbp, rsp\n", self.name);
+        let frame_size = self.frame_size.next_multiple_of(16);
+        if frame_size > 0 {
+            buf.push_str(&format!("sub rsp, {}\n", frame_size));
+        }
+        for (reg, disp) in &self.saved {
+            buf.push_str(&format!(
+                "mov [rbp{:+}], {}\n",
+                disp,
+                reg.codegen_string(context)
+            ));
+        }
+        context.saved = self.saved.clone();
+        for block in &self.blocks {
+            buf.push_str(&block.codegen_string(context));
+        }
+        buf
+    }
+}
+
+impl Codegen for rtl::Module {
+    /// A NASM source file for the module. Every function and global is exported,
+    /// externs are called through the PLT so the object can be linked into a position
+    /// independent binary.
+    fn codegen_string(&self, context: &mut CodegenContext) -> String {
+        let mut buf = "default rel\n".to_string();
+        for name in &self.externs {
+            buf.push_str(&format!("extern {}\n", name));
+        }
+        for func in &self.functions {
+            buf.push_str(&format!("global {}\n", func.name));
+        }
+        for global in &self.globals {
+            buf.push_str(&format!("global {}\n", global.name));
+        }
+        buf.push_str("\nsection .text\n");
+        for func in &self.functions {
+            buf.push('\n');
+            buf.push_str(&func.codegen_string(context));
+        }
+        for global in &self.globals {
+            buf.push('\n');
+            buf.push_str(&global_data(global));
+            buf.push('\n');
+        }
+        buf
+    }
+}
@@ -7,7 +7,21 @@ pub trait Codegen {
 }
 
 #[derive(Default)]
-pub struct CodegenContext;
+pub struct CodegenContext {
+    next_label: usize,
+    /// The callee-saved registers of the function being generated, and where they are
+    /// kept in its frame.
+    saved: Vec<(rtl::RealRegister, i32)>,
+}
+
+impl CodegenContext {
+    /// A fresh label for a jump within the code of a single op.
+    pub fn local_label(&mut self) -> String {
+        let n = self.next_label;
+        self.next_label += 1;
+        format!(".L{}", n)
+    }
+}
 
 pub fn unwrap_phys_register(
     opt: Option<&rtl::RealRegister>,
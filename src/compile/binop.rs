@@ -1,4 +1,6 @@
+use super::strength::{self, MulStep};
 use crate::rtl;
+use crate::rtl::amd64::Amd64Register;
 use crate::ssa;
 use crate::typing::Typed;
 
@@ -35,8 +37,14 @@ pub fn compile(
         "dest and operand types are equal"
     );
     let a_rv = super::rtl_rvalue_from_ssa(a);
-    let b_rv = super::rtl_rvalue_from_ssa(b);
+    let mut b_rv = super::rtl_rvalue_from_ssa(b);
     let dest_reg = rtl::Register::Vir(dest.as_vir_reg());
+    if let (ssa::BinOpTy::Mul, rtl::RValue::Lit(lit)) = (&binop_ty, &b_rv) {
+        if dest.data_ty().is_integer() {
+            multiply(dest_reg, a_rv, lit.bits(), ops);
+            return;
+        }
+    }
     match a_rv {
         rtl::RValue::Register(reg) if dest_reg == reg => (),
         _ => ops.push(rtl::Op::Copy(rtl::OpCopy {
@@ -44,6 +52,21 @@ pub fn compile(
             from: a_rv,
         })),
     };
+    let is_shift = matches!(
+        binop_ty,
+        ssa::BinOpTy::Shl | ssa::BinOpTy::LShr | ssa::BinOpTy::AShr
+    );
+    if let (true, rtl::RValue::Register(..)) = (is_shift, &b_rv) {
+        // amd64 only shifts by a variable count in `cl`.
+        let count = rtl::Register::Real(rtl::RealRegister::Amd64(
+            Amd64Register::Rcx.with_size(b.data_ty().mem_size()),
+        ));
+        ops.push(rtl::Op::Copy(rtl::OpCopy {
+            to: count,
+            from: b_rv,
+        }));
+        b_rv = rtl::RValue::Register(count);
+    }
     ops.push(match binop_ty {
         ssa::BinOpTy::Add => rtl::Op::Add(rtl::OpAdd {
             to: dest_reg,
@@ -61,9 +84,80 @@ pub fn compile(
             val: dest_reg,
             with: b_rv,
         }),
+        ssa::BinOpTy::SDiv | ssa::BinOpTy::UDiv | ssa::BinOpTy::SRem | ssa::BinOpTy::URem => {
+            panic!("integer division is lowered by `div::compile`")
+        }
+        ssa::BinOpTy::And => rtl::Op::And(rtl::OpAnd {
+            to: dest_reg,
+            val: b_rv,
+        }),
+        ssa::BinOpTy::Or => rtl::Op::Or(rtl::OpOr {
+            to: dest_reg,
+            val: b_rv,
+        }),
+        ssa::BinOpTy::Xor => rtl::Op::Xor(rtl::OpXor {
+            to: dest_reg,
+            val: b_rv,
+        }),
+        ssa::BinOpTy::Shl => rtl::Op::Shl(rtl::OpShl {
+            val: dest_reg,
+            by: b_rv,
+        }),
+        ssa::BinOpTy::LShr => rtl::Op::LShr(rtl::OpLShr {
+            val: dest_reg,
+            by: b_rv,
+        }),
+        ssa::BinOpTy::AShr => rtl::Op::AShr(rtl::OpAShr {
+            val: dest_reg,
+            by: b_rv,
+        }),
     })
 }
 
+/// Multiplies `x` by `factor` into `to`, with the steps [`strength::multiply_plan`]
+/// finds, or `imul` if there are none. Steps that add or subtract `x` need it to stay
+/// around, so they are only taken when `x` isn't `to`.
+pub(super) fn multiply(to: rtl::Register, x: rtl::RValue, factor: u64, ops: &mut rtl::Ops) {
+    let bytes = to.sz();
+    let in_place = matches!(x, rtl::RValue::Register(reg) if reg == to);
+    if !in_place {
+        ops.push(rtl::Op::Copy(rtl::OpCopy { to, from: x }));
+    }
+    let lit = |bits: u64| rtl::RValue::Lit(rtl::Lit::from_bits(bytes, bits));
+    let steps = strength::multiply_plan(factor, bytes as u32 * 8).filter(|steps| {
+        !in_place
+            || !steps
+                .iter()
+                .any(|step| matches!(step, MulStep::Add | MulStep::Sub))
+    });
+    let Some(steps) = steps else {
+        ops.push(rtl::Op::Mul(rtl::OpMul {
+            val: to,
+            with: lit(factor),
+        }));
+        return;
+    };
+    for step in steps {
+        ops.push(match step {
+            MulStep::Shl(shift) => rtl::Op::Shl(rtl::OpShl {
+                val: to,
+                by: lit(shift as u64),
+            }),
+            MulStep::Lea(scale) => rtl::Op::Lea(rtl::OpLea {
+                to,
+                addr: rtl::Address {
+                    base: to,
+                    index: Some((to, scale)),
+                    disp: 0,
+                },
+            }),
+            MulStep::Add => rtl::Op::Add(rtl::OpAdd { to, val: x }),
+            MulStep::Sub => rtl::Op::Sub(rtl::OpSub { from: to, val: x }),
+            MulStep::Neg => rtl::Op::Neg(rtl::OpNeg { val: to }),
+        });
+    }
+}
+
 /*fn compile_binop(
     dest: rtl::Register,
     op: &ssa::BinOp,
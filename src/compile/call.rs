@@ -0,0 +1,158 @@
+//! Calls and parameters, following the System V AMD64 calling convention.
+
+use crate::rtl;
+use crate::rtl::amd64::Amd64Register;
+use crate::ssa;
+use crate::typing::{self, Typed};
+
+/// Registers that pass the first integer, boolean and pointer arguments, in order.
+const INT_ARGS: [Amd64Register; 6] = [
+    Amd64Register::Rdi,
+    Amd64Register::Rsi,
+    Amd64Register::Rdx,
+    Amd64Register::Rcx,
+    Amd64Register::R8,
+    Amd64Register::R9,
+];
+
+/// Registers that pass the first float arguments, in order.
+const FLOAT_ARGS: [Amd64Register; 8] = [
+    Amd64Register::Xmm0D,
+    Amd64Register::Xmm1D,
+    Amd64Register::Xmm2D,
+    Amd64Register::Xmm3D,
+    Amd64Register::Xmm4D,
+    Amd64Register::Xmm5D,
+    Amd64Register::Xmm6D,
+    Amd64Register::Xmm7D,
+];
+
+/// Registers a callee is free to overwrite.
+const CALLER_SAVED: [Amd64Register; 25] = [
+    Amd64Register::Rax,
+    Amd64Register::Rcx,
+    Amd64Register::Rdx,
+    Amd64Register::Rsi,
+    Amd64Register::Rdi,
+    Amd64Register::R8,
+    Amd64Register::R9,
+    Amd64Register::R10,
+    Amd64Register::R11,
+    Amd64Register::Xmm0D,
+    Amd64Register::Xmm1D,
+    Amd64Register::Xmm2D,
+    Amd64Register::Xmm3D,
+    Amd64Register::Xmm4D,
+    Amd64Register::Xmm5D,
+    Amd64Register::Xmm6D,
+    Amd64Register::Xmm7D,
+    Amd64Register::Xmm8D,
+    Amd64Register::Xmm9D,
+    Amd64Register::Xmm10D,
+    Amd64Register::Xmm11D,
+    Amd64Register::Xmm12D,
+    Amd64Register::Xmm13D,
+    Amd64Register::Xmm14D,
+    Amd64Register::Xmm15D,
+];
+
+/// Where an argument is passed.
+enum ArgLocation {
+    Reg(rtl::Register),
+    /// Offset into the arguments passed on the stack, which start at the stack pointer
+    /// of the caller.
+    Stack(i32),
+}
+
+/// Locations of arguments of these types, and the bytes taken by those passed on the
+/// stack. Each of those takes 8 bytes.
+fn arg_locations(types: impl Iterator<Item = typing::Type>) -> (Vec<ArgLocation>, usize) {
+    let (mut ints, mut floats, mut stack) = (0, 0, 0);
+    let locations = types
+        .map(|ty| {
+            let (regs, used): (&[Amd64Register], &mut usize) = match rtl::RegClass::of(ty) {
+                rtl::RegClass::Gpr => (&INT_ARGS, &mut ints),
+                rtl::RegClass::Xmm => (&FLOAT_ARGS, &mut floats),
+            };
+            match regs.get(*used) {
+                Some(reg) => {
+                    *used += 1;
+                    let reg = reg.with_size(ty.mem_size());
+                    ArgLocation::Reg(rtl::Register::Real(rtl::RealRegister::Amd64(reg)))
+                }
+                None => {
+                    stack += 8;
+                    ArgLocation::Stack(stack as i32 - 8)
+                }
+            }
+        })
+        .collect();
+    (locations, stack)
+}
+
+/// Copies the parameters from where the caller passed them into their virtual
+/// registers. Above the frame pointer are the saved frame pointer of the caller and the
+/// return address, then the arguments on the stack.
+pub fn compile_params(func: &ssa::Function, ops: &mut rtl::Ops) {
+    let params = func.params();
+    let (locations, _) = arg_locations(params.iter().map(|param| param.data_ty()));
+    for (param, location) in params.iter().zip(locations) {
+        let to = rtl::Register::Vir(param.as_vir_reg());
+        ops.push(match location {
+            ArgLocation::Reg(reg) => rtl::Op::Copy(rtl::OpCopy {
+                to,
+                from: rtl::RValue::Register(reg),
+            }),
+            ArgLocation::Stack(offset) => rtl::Op::Load(rtl::OpLoad {
+                to,
+                addr: rtl::Address::new(super::mem::frame_pointer(), 16 + offset),
+            }),
+        });
+    }
+}
+
+/// Lowers a call. Arguments on the stack are stored to the bottom of the frame, which
+/// is kept free for them, before the others are copied to their registers.
+pub fn compile(
+    dest: &Option<ssa::Variable>,
+    callee: &str,
+    args: &[ssa::RValue],
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let (locations, stack_bytes) = arg_locations(args.iter().map(|arg| arg.data_ty()));
+    context.outgoing_args = context.outgoing_args.max(stack_bytes);
+    let stack_pointer = rtl::Register::Real(rtl::RealRegister::Amd64(Amd64Register::Rsp));
+    for (arg, location) in args.iter().zip(&locations) {
+        if let ArgLocation::Stack(offset) = location {
+            ops.push(rtl::Op::Store(rtl::OpStore {
+                addr: rtl::Address::new(stack_pointer, *offset),
+                from: super::rtl_rvalue_from_ssa(arg),
+            }));
+        }
+    }
+    let mut arg_regs = Vec::new();
+    for (arg, location) in args.iter().zip(&locations) {
+        if let ArgLocation::Reg(reg) = location {
+            ops.push(rtl::Op::Copy(rtl::OpCopy {
+                to: *reg,
+                from: super::rtl_rvalue_from_ssa(arg),
+            }));
+            arg_regs.push(*reg);
+        }
+    }
+    ops.push(rtl::Op::Call(rtl::OpCall {
+        target: callee.to_string(),
+        args: arg_regs,
+        clobbers: CALLER_SAVED
+            .iter()
+            .map(|reg| rtl::Register::Real(rtl::RealRegister::Amd64(*reg)))
+            .collect(),
+    }));
+    if let Some(dest) = dest {
+        ops.push(rtl::Op::Copy(rtl::OpCopy {
+            to: rtl::Register::Vir(dest.as_vir_reg()),
+            from: rtl::RValue::Register(super::term::return_register(dest.data_ty())),
+        }));
+    }
+}
@@ -0,0 +1,38 @@
+use crate::rtl;
+use crate::ssa;
+use crate::typing::{self, Typed};
+
+pub fn compile(
+    dest: &ssa::Variable,
+    val: &ssa::RValue,
+    cast_ty: ssa::CastTy,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    assert!(
+        cast_ty.accepts(val.data_ty(), dest.data_ty()),
+        "cast converts between the right types"
+    );
+    let conv = match cast_ty {
+        ssa::CastTy::SiToFp => rtl::Conv::SiToFp,
+        ssa::CastTy::UiToFp => rtl::Conv::UiToFp,
+        ssa::CastTy::FpToSi => rtl::Conv::FpToSi,
+        ssa::CastTy::FpToUi => rtl::Conv::FpToUi,
+        ssa::CastTy::FpExt => rtl::Conv::FpExt,
+        ssa::CastTy::FpTrunc => rtl::Conv::FpTrunc,
+        ssa::CastTy::ZExt => rtl::Conv::ZExt,
+        ssa::CastTy::SExt => rtl::Conv::SExt,
+        ssa::CastTy::Trunc => rtl::Conv::Trunc,
+        ssa::CastTy::BitCast => rtl::Conv::BitCast,
+    };
+    let from = context.register_of(val, ops);
+    let to = rtl::Register::Vir(dest.as_vir_reg());
+    ops.push(rtl::Op::Convert(rtl::OpConvert { conv, to, from }));
+    if cast_ty == ssa::CastTy::Trunc && dest.data_ty() == typing::Type::Bool {
+        // Only the lowest bit of a `Bool` may be set.
+        ops.push(rtl::Op::And(rtl::OpAnd {
+            to,
+            val: rtl::RValue::Lit(rtl::Lit::from_bits(1, 1)),
+        }));
+    }
+}
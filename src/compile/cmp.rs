@@ -0,0 +1,105 @@
+use crate::rtl;
+use crate::ssa;
+use crate::typing::Typed;
+
+pub fn cond(cmp_ty: ssa::CmpTy) -> rtl::Cond {
+    match cmp_ty {
+        ssa::CmpTy::Eq => rtl::Cond::Eq,
+        ssa::CmpTy::Ne => rtl::Cond::Ne,
+        ssa::CmpTy::Slt => rtl::Cond::Lt,
+        ssa::CmpTy::Sle => rtl::Cond::Le,
+        ssa::CmpTy::Sgt => rtl::Cond::Gt,
+        ssa::CmpTy::Sge => rtl::Cond::Ge,
+        ssa::CmpTy::Ult => rtl::Cond::Ult,
+        ssa::CmpTy::Ule => rtl::Cond::Ule,
+        ssa::CmpTy::Ugt => rtl::Cond::Ugt,
+        ssa::CmpTy::Uge => rtl::Cond::Uge,
+        // `ucomiss` and `ucomisd` set the flags like an unsigned comparison, except that
+        // unordered operands look both equal and less.
+        ssa::CmpTy::Feq => rtl::Cond::FEq,
+        ssa::CmpTy::Fne => rtl::Cond::FNe,
+        ssa::CmpTy::Fgt => rtl::Cond::Ugt,
+        ssa::CmpTy::Fge => rtl::Cond::Uge,
+        ssa::CmpTy::Fult => rtl::Cond::Ult,
+        ssa::CmpTy::Fule => rtl::Cond::Ule,
+        ssa::CmpTy::Flt | ssa::CmpTy::Fle | ssa::CmpTy::Fugt | ssa::CmpTy::Fuge => {
+            panic!("{} is lowered with swapped operands", cmp_ty.name())
+        }
+    }
+}
+
+/// Lowers a comparison into a `cmp` that sets the flags, and returns the condition
+/// that holds in the flags when the comparison is true.
+pub fn compile_flags(
+    a: &ssa::RValue,
+    b: &ssa::RValue,
+    cmp_ty: ssa::CmpTy,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Cond {
+    assert_eq!(a.data_ty(), b.data_ty(), "operand types are equal");
+    let swap = match cmp_ty {
+        // Only "greater" can be tested in the flags of a float comparison when the
+        // operands are ordered, and only "less" when they are unordered.
+        ssa::CmpTy::Flt | ssa::CmpTy::Fle | ssa::CmpTy::Fugt | ssa::CmpTy::Fuge => true,
+        _ if cmp_ty.is_float() => false,
+        // `cmp` wants a register on the left.
+        _ => matches!((a, b), (ssa::RValue::Lit(..), ssa::RValue::Var(..))),
+    };
+    let (a, b, cmp_ty) = match swap {
+        true => (b, a, cmp_ty.swapped()),
+        false => (a, b, cmp_ty),
+    };
+    let a = context.register_of(a, ops);
+    ops.push(rtl::Op::Cmp(rtl::OpCmp {
+        a,
+        b: super::rtl_rvalue_from_ssa(b),
+    }));
+    cond(cmp_ty)
+}
+
+pub fn compile(
+    dest: &ssa::Variable,
+    a: &ssa::RValue,
+    b: &ssa::RValue,
+    cmp_ty: ssa::CmpTy,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let cond = compile_flags(a, b, cmp_ty, ops, context);
+    ops.push(rtl::Op::SetCc(rtl::OpSetCc {
+        cond,
+        to: rtl::Register::Vir(dest.as_vir_reg()),
+    }));
+}
+
+/// The comparison at the end of `bb` whose only use is the branch of `bb`. The branch
+/// can test the flags of that comparison directly, without materializing a `bool`.
+pub fn fused_with_branch<'f>(
+    func: &'f ssa::Function,
+    bb: &'f ssa::BasicBlock,
+) -> Option<&'f ssa::Ins> {
+    let last = bb.ins_list().last()?;
+    let (dest, cond) = match (last, bb.terminator()?) {
+        (ssa::Ins::Cmp(dest, ..), ssa::Terminator::Br(ssa::RValue::Var(cond), ..)) => (dest, cond),
+        _ => return None,
+    };
+    if dest != cond {
+        return None;
+    }
+    let uses = func
+        .blocks()
+        .flat_map(|(_, bb)| {
+            let term_operands = bb.terminator().into_iter().flat_map(|term| term.operands());
+            bb.ins_list()
+                .iter()
+                .flat_map(|ins| ins.operands())
+                .chain(term_operands)
+        })
+        .filter(|val| **val == ssa::RValue::Var(*dest))
+        .count();
+    match uses {
+        1 => Some(last),
+        _ => None,
+    }
+}
@@ -0,0 +1,206 @@
+//! Integer division, which amd64 does on `rdx:rax`. Division by a constant is done
+//! without `div`, as [`strength::Division`] plans it.
+
+use super::strength::{self, Division};
+use crate::rtl;
+use crate::rtl::amd64::Amd64Register;
+use crate::ssa;
+use crate::typing::{self, Typed};
+
+/// Lowers `SDiv`, `UDiv`, `SRem` or `URem`. The dividend is copied to
+/// `rax` and extended into `rdx`, so the register allocator keeps values that live
+/// across the division out of both. The multiply-high that replaces a division by a
+/// constant uses the same two registers.
+pub fn compile(
+    dest: &ssa::Variable,
+    a: &ssa::RValue,
+    b: &ssa::RValue,
+    binop_ty: ssa::BinOpTy,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let (signed, rem) = match binop_ty {
+        ssa::BinOpTy::SDiv => (true, false),
+        ssa::BinOpTy::UDiv => (false, false),
+        ssa::BinOpTy::SRem => (true, true),
+        ssa::BinOpTy::URem => (false, true),
+        _ => panic!("not an integer division"),
+    };
+    assert_eq!(a.data_ty(), b.data_ty(), "operand types are equal");
+    assert_eq!(
+        dest.data_ty(),
+        a.data_ty(),
+        "dest and operand types are equal"
+    );
+    let bytes = dest.data_ty().mem_size();
+    let fixed =
+        |reg: Amd64Register| rtl::Register::Real(rtl::RealRegister::Amd64(reg.with_size(bytes)));
+    let (lo, hi) = (fixed(Amd64Register::Rax), fixed(Amd64Register::Rdx));
+    let dest_reg = rtl::Register::Vir(dest.as_vir_reg());
+    let x = super::rtl_rvalue_from_ssa(a);
+    let bits = bytes as u32 * 8;
+    if let rtl::RValue::Lit(by) = super::rtl_rvalue_from_ssa(b) {
+        if let Some(division) = Division::new(by.bits(), bits, signed) {
+            let divisor = by.bits();
+            match (rem, division) {
+                (false, _) => quotient(
+                    dest_reg,
+                    dest.data_ty(),
+                    &division,
+                    x,
+                    (lo, hi),
+                    ops,
+                    context,
+                ),
+                (true, Division::Dividend | Division::Negated) => copy(
+                    dest_reg,
+                    rtl::RValue::Lit(rtl::Lit::from_bits(bytes, 0)),
+                    ops,
+                ),
+                (true, Division::ShiftRight(shift)) => {
+                    copy(dest_reg, x, ops);
+                    ops.push(rtl::Op::And(rtl::OpAnd {
+                        to: dest_reg,
+                        val: rtl::RValue::Lit(rtl::Lit::from_bits(bytes, (1 << shift) - 1)),
+                    }));
+                }
+                (true, _) => {
+                    let q = rtl::Register::Vir(context.fresh_vir(dest.data_ty()));
+                    quotient(q, dest.data_ty(), &division, x, (lo, hi), ops, context);
+                    let product = rtl::Register::Vir(context.fresh_vir(dest.data_ty()));
+                    super::binop::multiply(product, rtl::RValue::Register(q), divisor, ops);
+                    copy(dest_reg, x, ops);
+                    ops.push(rtl::Op::Sub(rtl::OpSub {
+                        from: dest_reg,
+                        val: rtl::RValue::Register(product),
+                    }));
+                }
+            }
+            return;
+        }
+    }
+    copy(lo, x, ops);
+    ops.push(rtl::Op::ExtendHigh(rtl::OpExtendHigh { signed, lo, hi }));
+    ops.push(rtl::Op::IntDiv(rtl::OpIntDiv {
+        signed,
+        lo,
+        hi,
+        by: super::rtl_rvalue_from_ssa(b),
+    }));
+    copy(
+        dest_reg,
+        rtl::RValue::Register(if rem { hi } else { lo }),
+        ops,
+    );
+}
+
+/// Puts the quotient of `x` into `to`, of type `ty`, without dividing. `fixed` are `rax` and `rdx`,
+/// for the multiply-high.
+fn quotient(
+    to: rtl::Register,
+    ty: typing::Type,
+    division: &Division,
+    x: rtl::RValue,
+    fixed: (rtl::Register, rtl::Register),
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let bytes = to.sz();
+    let bits = bytes as u32 * 8;
+    let lit = |bits: u64| rtl::RValue::Lit(rtl::Lit::from_bits(bytes, bits));
+    let shift = |op: fn(rtl::Register, rtl::RValue) -> rtl::Op, by: u32, ops: &mut rtl::Ops| {
+        if by > 0 {
+            ops.push(op(to, lit(by as u64)));
+        }
+    };
+    let lshr = |val, by| rtl::Op::LShr(rtl::OpLShr { val, by });
+    let ashr = |val, by| rtl::Op::AShr(rtl::OpAShr { val, by });
+    let add = |to, val| rtl::Op::Add(rtl::OpAdd { to, val });
+    let (lo, hi) = fixed;
+    let negate = match *division {
+        Division::Dividend => {
+            copy(to, x, ops);
+            false
+        }
+        Division::Negated => {
+            copy(to, x, ops);
+            true
+        }
+        Division::ShiftRight(by) => {
+            copy(to, x, ops);
+            shift(lshr, by, ops);
+            false
+        }
+        Division::SignedShiftRight { shift: by, negate } => {
+            // Biases negative dividends by `2^by - 1`.
+            copy(to, x, ops);
+            shift(ashr, bits - 1, ops);
+            shift(lshr, bits - by, ops);
+            ops.push(add(to, x));
+            shift(ashr, by, ops);
+            negate
+        }
+        Division::Unsigned(strength::UnsignedMagic {
+            multiplier,
+            shift: by,
+            add: wide,
+        }) => {
+            copy(lo, x, ops);
+            ops.push(rtl::Op::MulHigh(rtl::OpMulHigh {
+                signed: false,
+                lo,
+                hi,
+                with: lit(multiplier),
+            }));
+            match wide {
+                true => {
+                    // `(x - t) / 2 + t` is `(x + t) / 2`, which doesn't fit.
+                    let t = rtl::Register::Vir(context.fresh_vir(ty));
+                    copy(t, rtl::RValue::Register(hi), ops);
+                    copy(to, x, ops);
+                    ops.push(rtl::Op::Sub(rtl::OpSub {
+                        from: to,
+                        val: rtl::RValue::Register(t),
+                    }));
+                    shift(lshr, 1, ops);
+                    ops.push(add(to, rtl::RValue::Register(t)));
+                }
+                false => copy(to, rtl::RValue::Register(hi), ops),
+            }
+            shift(lshr, by, ops);
+            false
+        }
+        Division::Signed(strength::SignedMagic {
+            multiplier,
+            shift: by,
+            add: wide,
+            negate,
+        }) => {
+            copy(lo, x, ops);
+            ops.push(rtl::Op::MulHigh(rtl::OpMulHigh {
+                signed: true,
+                lo,
+                hi,
+                with: lit(multiplier),
+            }));
+            copy(to, rtl::RValue::Register(hi), ops);
+            if wide {
+                ops.push(add(to, x));
+            }
+            shift(ashr, by, ops);
+            // Negative quotients are one too small, and their sign bit is one.
+            let sign = rtl::Register::Vir(context.fresh_vir(ty));
+            copy(sign, rtl::RValue::Register(to), ops);
+            ops.push(lshr(sign, lit(bits as u64 - 1)));
+            ops.push(add(to, rtl::RValue::Register(sign)));
+            negate
+        }
+    };
+    if negate {
+        ops.push(rtl::Op::Neg(rtl::OpNeg { val: to }));
+    }
+}
+
+fn copy(to: rtl::Register, from: rtl::RValue, ops: &mut rtl::Ops) {
+    ops.push(rtl::Op::Copy(rtl::OpCopy { to, from }));
+}
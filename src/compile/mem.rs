@@ -0,0 +1,362 @@
+use crate::rtl;
+use crate::rtl::amd64::Amd64Register;
+use crate::ssa;
+use crate::typing::{self, Typed};
+use std::collections::{HashMap, HashSet};
+
+/// The register allocas and stack slots are addressed from. It points right above the
+/// frame and is aligned to 16 bytes.
+pub fn frame_pointer() -> rtl::Register {
+    rtl::Register::Real(rtl::RealRegister::Amd64(Amd64Register::Rbp))
+}
+
+/// The allocas of a function, placed one below the other under the frame pointer, and
+/// the addresses computed by `gep`s.
+#[derive(Default)]
+pub struct Frame {
+    size: usize,
+    /// Displacements of the allocas from the frame pointer.
+    slots: HashMap<usize, i32>,
+    /// Allocas and `gep`s whose address is used as a value, rather than only to access
+    /// memory or to compute another address.
+    escaping: HashSet<usize>,
+    /// Addresses of the `gep`s lowered so far that don't escape. Accesses through them
+    /// use the address directly.
+    addresses: HashMap<usize, rtl::Address>,
+}
+
+impl Frame {
+    /// Bytes below the frame pointer taken by allocas.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn slot(&mut self, alloca: &ssa::Variable, size: u32, align: u32) -> i32 {
+        let frame_size = &mut self.size;
+        *self.slots.entry(alloca.id()).or_insert_with(|| {
+            *frame_size = (*frame_size + size as usize).next_multiple_of(align as usize);
+            -i32::try_from(*frame_size).expect("frame fits 2 GiB")
+        })
+    }
+
+    /// Places every alloca of `func`, and finds the allocas and `gep`s whose address
+    /// has to be in a register. The others are folded into the memory operands of the
+    /// accesses through them.
+    pub fn layout(&mut self, func: &ssa::Function) {
+        let ins_list = || func.blocks().flat_map(|(_, bb)| bb.ins_list());
+        for ins in ins_list() {
+            if let ssa::Ins::Alloca(dest, size, align) = ins {
+                self.slot(dest, *size, *align);
+            }
+        }
+        let term_operands = func
+            .blocks()
+            .filter_map(|(_, bb)| bb.terminator())
+            .flat_map(|term| term.operands());
+        let value_operands = ins_list()
+            .flat_map(|ins| match ins {
+                ssa::Ins::Load(..) => Vec::new(),
+                ssa::Ins::Store(_, val, _) => vec![val],
+                ssa::Ins::Gep(_, _, _, indices) => indices.iter().collect(),
+                _ => ins.operands(),
+            })
+            .chain(term_operands);
+        for val in value_operands {
+            if let ssa::RValue::Var(var) = val {
+                self.escaping.insert(var.id());
+            }
+        }
+        // Blocks are lowered in order, so a `gep` can only be folded into the accesses
+        // of its own block and of the blocks after it.
+        let mut gep_blocks = HashMap::new();
+        for (id, bb) in func.blocks() {
+            for ins in bb.ins_list() {
+                if let ssa::Ins::Gep(dest, ..) = ins {
+                    gep_blocks.insert(dest.id(), id.index());
+                }
+            }
+        }
+        for (id, bb) in func.blocks() {
+            for ins in bb.ins_list() {
+                let ptr = match ins {
+                    ssa::Ins::Load(_, ptr, _)
+                    | ssa::Ins::Store(ptr, ..)
+                    | ssa::Ins::Gep(_, _, ptr, _) => ptr,
+                    _ => continue,
+                };
+                if let ssa::RValue::Var(var) = ptr {
+                    if gep_blocks
+                        .get(&var.id())
+                        .is_some_and(|def| *def > id.index())
+                    {
+                        self.escaping.insert(var.id());
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn compile_alloca(
+    dest: &ssa::Variable,
+    size: u32,
+    align: u32,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let disp = context.frame.slot(dest, size, align);
+    if context.frame.escaping.contains(&dest.id()) {
+        ops.push(rtl::Op::Lea(rtl::OpLea {
+            to: rtl::Register::Vir(dest.as_vir_reg()),
+            addr: rtl::Address::new(frame_pointer(), disp),
+        }));
+    }
+}
+
+/// The memory operand for the address in `ptr`.
+fn address(
+    ptr: &ssa::RValue,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Address {
+    if let ssa::RValue::Var(var) = ptr {
+        if let Some(disp) = context.frame.slots.get(&var.id()) {
+            return rtl::Address::new(frame_pointer(), *disp);
+        }
+        if let Some(addr) = context.frame.addresses.get(&var.id()) {
+            return *addr;
+        }
+    }
+    rtl::Address::new(context.register_of(ptr, ops), 0)
+}
+
+pub fn compile_load(
+    dest: &ssa::Variable,
+    ptr: &ssa::RValue,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let addr = address(ptr, ops, context);
+    ops.push(rtl::Op::Load(rtl::OpLoad {
+        to: rtl::Register::Vir(dest.as_vir_reg()),
+        addr,
+    }));
+}
+
+pub fn compile_store(
+    ptr: &ssa::RValue,
+    val: &ssa::RValue,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let addr = address(ptr, ops, context);
+    ops.push(rtl::Op::Store(rtl::OpStore {
+        addr,
+        from: super::rtl_rvalue_from_ssa(val),
+    }));
+}
+
+/// Lowers a `gep` into a memory operand on its base. Literal indices only move the
+/// displacement, and one index whose stride is 1, 2, 4 or 8 becomes the index register
+/// of the operand. Other indices are scaled and added up in registers.
+pub fn compile_gep(
+    dest: &ssa::Variable,
+    ty: &typing::MemType,
+    ptr: &ssa::RValue,
+    indices: &[ssa::RValue],
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let mut addr = address(ptr, ops, context);
+    let mut ty = ty;
+    for (i, idx) in indices.iter().enumerate() {
+        let stride = match (i, ty) {
+            (0, _) => ty.size(),
+            (_, typing::MemType::Struct(..)) => {
+                let field = match idx {
+                    ssa::RValue::Lit(lit) => lit.to_bits() as usize,
+                    ssa::RValue::Var(..) => panic!("fields are picked by literals"),
+                };
+                let (offset, field) = ty.field(field).expect("field exists");
+                addr = add_disp(addr, offset as i64, ops, context);
+                ty = field;
+                continue;
+            }
+            (_, typing::MemType::Array(elem, _)) => {
+                ty = elem;
+                elem.size()
+            }
+            (_, typing::MemType::Scalar(..)) => panic!("scalars have no parts"),
+        };
+        addr = match idx {
+            ssa::RValue::Lit(lit) => {
+                let offset = index_value(lit).wrapping_mul(stride as i64);
+                add_disp(addr, offset, ops, context)
+            }
+            ssa::RValue::Var(..) => {
+                let index = widen_index(idx, ops, context);
+                add_index(addr, index, stride, ops, context)
+            }
+        };
+    }
+    match context.frame.escaping.contains(&dest.id()) {
+        true => ops.push(rtl::Op::Lea(rtl::OpLea {
+            to: rtl::Register::Vir(dest.as_vir_reg()),
+            addr,
+        })),
+        false => {
+            context.frame.addresses.insert(dest.id(), addr);
+        }
+    }
+}
+
+/// The value of an index literal, extended to 64 bits like [`ssa::Ins::Gep`] says.
+fn index_value(lit: &ssa::Literal) -> i64 {
+    let ty = lit.data_ty();
+    match ty.is_signed() {
+        true => ((lit.to_bits() << (64 - ty.bits())) as i64) >> (64 - ty.bits()),
+        false => lit.to_bits() as i64,
+    }
+}
+
+/// An index variable extended to 64 bits.
+fn widen_index(
+    idx: &ssa::RValue,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Register {
+    let reg = context.register_of(idx, ops);
+    let ty = idx.data_ty();
+    if ty.mem_size() == 8 {
+        return reg;
+    }
+    let wide = rtl::Register::Vir(context.fresh_vir(typing::Type::I64));
+    ops.push(rtl::Op::Convert(rtl::OpConvert {
+        conv: match ty.is_signed() {
+            true => rtl::Conv::SExt,
+            false => rtl::Conv::ZExt,
+        },
+        to: wide,
+        from: reg,
+    }));
+    wide
+}
+
+/// Moves `addr` by `offset` bytes, in a register when the displacement would not fit.
+fn add_disp(
+    mut addr: rtl::Address,
+    offset: i64,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Address {
+    match i32::try_from(addr.disp as i64 + offset) {
+        Ok(disp) => {
+            addr.disp = disp;
+            addr
+        }
+        Err(..) => {
+            let reg = rtl::Register::Vir(context.fresh_vir(typing::Type::I64));
+            ops.push(rtl::Op::Copy(rtl::OpCopy {
+                to: reg,
+                from: rtl::RValue::Lit(rtl::Lit::LitU64(offset as u64)),
+            }));
+            add_index(addr, reg, 1, ops, context)
+        }
+    }
+}
+
+/// Adds `index * stride` to `addr`. The address can only hold one index register, so
+/// a second one is added to the first with a `lea`.
+fn add_index(
+    mut addr: rtl::Address,
+    index: rtl::Register,
+    stride: usize,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Address {
+    let scalable = matches!(stride, 1 | 2 | 4 | 8);
+    if addr.index.is_none() && scalable {
+        addr.index = Some((index, stride as u8));
+        return addr;
+    }
+    let scaled = match scalable {
+        true => (index, stride as u8),
+        false => (scaled(index, stride, ops, context), 1),
+    };
+    let existing = match addr.index {
+        Some(existing) => existing,
+        None => {
+            addr.index = Some(scaled);
+            return addr;
+        }
+    };
+    let base = scaled_by_one(existing, ops, context);
+    let sum = rtl::Register::Vir(context.fresh_vir(typing::Type::I64));
+    ops.push(rtl::Op::Lea(rtl::OpLea {
+        to: sum,
+        addr: rtl::Address {
+            base,
+            index: Some(scaled),
+            disp: 0,
+        },
+    }));
+    addr.index = Some((sum, 1));
+    addr
+}
+
+/// `index * stride` in a fresh register.
+fn scaled(
+    index: rtl::Register,
+    stride: usize,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Register {
+    let scaled = rtl::Register::Vir(context.fresh_vir(typing::Type::I64));
+    ops.push(rtl::Op::Copy(rtl::OpCopy {
+        to: scaled,
+        from: rtl::RValue::Register(index),
+    }));
+    ops.push(rtl::Op::Mul(rtl::OpMul {
+        val: scaled,
+        with: rtl::RValue::Lit(rtl::Lit::LitU64(stride as u64)),
+    }));
+    scaled
+}
+
+fn scaled_by_one(
+    (index, scale): (rtl::Register, u8),
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) -> rtl::Register {
+    match scale {
+        1 => index,
+        _ => scaled(index, scale as usize, ops, context),
+    }
+}
+
+pub fn compile_addr(dest: &ssa::Variable, name: &str, ops: &mut rtl::Ops) {
+    ops.push(rtl::Op::AddrOf(rtl::OpAddrOf {
+        to: rtl::Register::Vir(dest.as_vir_reg()),
+        symbol: name.to_string(),
+    }));
+}
+
+/// Places mutable globals in `.data`, or in `.bss` when they start out zeroed, and
+/// constant ones in `.rodata`.
+pub fn compile_global(global: &ssa::Global) -> rtl::Global {
+    let section = match (global.constant, &global.init) {
+        (true, _) => rtl::Section::ReadOnly,
+        (false, ssa::GlobalInit::Zeroed(..)) => rtl::Section::Bss,
+        (false, _) => rtl::Section::Data,
+    };
+    rtl::Global {
+        name: global.name.clone(),
+        section,
+        align: global.align,
+        size: global.init.size(),
+        bytes: match section {
+            rtl::Section::Bss => Vec::new(),
+            _ => global.init.to_bytes(),
+        },
+    }
+}
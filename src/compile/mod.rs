@@ -1,17 +1,98 @@
 mod binop;
+mod call;
+mod cast;
+mod cmp;
 mod cpy;
+mod div;
+mod mem;
+mod phi;
 pub mod ralloc;
+mod select;
+pub mod strength;
+mod term;
+mod unop;
 
+use crate::pass::{PassManager, PassRegistry};
 use crate::rtl;
 use crate::ssa;
+use crate::typing::{self, Typed};
+
+/// The RTL passes that turn lowered functions into ones with real registers, in the
+/// order they run.
+pub const ALLOCATION: &str = "ralloc,promote";
+
+/// Every RTL pass of the crate under its pipeline name.
+pub fn registry() -> PassRegistry<rtl::Module> {
+    let mut registry = PassRegistry::new();
+    registry.register_function_pass("ralloc", || ralloc::RegAlloc);
+    registry.register_function_pass("promote", || ralloc::Promote);
+    registry
+}
+
+/// Runs the [`ALLOCATION`] pipeline over `module`.
+fn allocate(module: &mut rtl::Module) {
+    PassManager::parse(&registry(), ALLOCATION)
+        .expect("allocation passes are registered")
+        .run(module);
+}
 
 #[derive(Default)]
-pub struct CompileContext;
+pub struct CompileContext {
+    next_vir: usize,
+    frame: mem::Frame,
+    /// Bytes at the bottom of the frame needed by arguments of calls.
+    outgoing_args: usize,
+}
+
+impl CompileContext {
+    /// A virtual register for a value of type `ty` that no ssa variable maps to.
+    fn fresh_vir(&mut self, ty: typing::Type) -> rtl::VirRegister {
+        let n = self.next_vir;
+        self.next_vir += 1;
+        rtl::VirRegister {
+            bytes: ty.mem_size(),
+            n,
+            class: rtl::RegClass::of(ty),
+        }
+    }
+
+    /// `val` as a register, copying it to a fresh one if it is a literal.
+    fn register_of(&mut self, val: &ssa::RValue, ops: &mut rtl::Ops) -> rtl::Register {
+        match rtl_rvalue_from_ssa(val) {
+            rtl::RValue::Register(reg) => reg,
+            lit @ rtl::RValue::Lit(..) => {
+                let tmp = rtl::Register::Vir(self.fresh_vir(val.data_ty()));
+                ops.push(rtl::Op::Copy(rtl::OpCopy { to: tmp, from: lit }));
+                tmp
+            }
+        }
+    }
+}
 
 pub trait CompileIntoBlock {
     fn compile_into_block(&self) -> rtl::Block;
 }
 
+pub trait CompileIntoBlocks {
+    fn compile_into_blocks(&self) -> Vec<rtl::Block>;
+}
+
+pub trait CompileIntoFunction {
+    fn compile_into_function(&self) -> rtl::Function;
+}
+
+pub trait CompileIntoModule {
+    fn compile_into_module(&self) -> rtl::Module;
+}
+
+pub trait LowerIntoFunction {
+    fn lower_into_function(&self) -> rtl::Function;
+}
+
+pub trait LowerIntoModule {
+    fn lower_into_module(&self) -> rtl::Module;
+}
+
 pub trait CompileIntoOps {
     fn compile_into_ops(&self, ops: &mut rtl::Ops, context: &mut CompileContext);
 }
@@ -23,6 +104,9 @@ impl CompileIntoBlock for ssa::BasicBlock {
         for ins in &self.ins_list {
             ins.compile_into_ops(&mut ops, &mut context);
         }
+        if let Some(term) = &self.terminator {
+            term::compile(term, None, &term::block_label, None, &mut ops);
+        }
         rtl::Block {
             metadata: (),
             ops,
@@ -31,14 +115,197 @@ impl CompileIntoBlock for ssa::BasicBlock {
     }
 }
 
+impl CompileIntoBlocks for ssa::Function {
+    /// Lowers every block in order, each becoming an `rtl::Block` labelled after its id.
+    /// Phis become copies on the incoming edges, and a comparison that only feeds the
+    /// branch after it sets the flags for that branch directly. Edges that leave a conditional branch
+    /// get a block of their own for those copies, placed after all other blocks.
+    fn compile_into_blocks(&self) -> Vec<rtl::Block> {
+        lower_function(self).0
+    }
+}
+
+impl LowerIntoFunction for ssa::Function {
+    /// Lowers the function like [`CompileIntoBlocks`], still with virtual registers.
+    /// The frame holds the allocas so far, and RTL passes may run before the
+    /// [`ALLOCATION`] pipeline.
+    fn lower_into_function(&self) -> rtl::Function {
+        let (blocks, context) = lower_function(self);
+        rtl::Function {
+            name: self.name().to_string(),
+            blocks,
+            frame_size: context.frame.size(),
+            outgoing_args: context.outgoing_args,
+            saved: Vec::new(),
+            allocation: None,
+        }
+    }
+}
+
+impl CompileIntoFunction for ssa::Function {
+    /// Lowers the function and allocates its registers with the [`ALLOCATION`]
+    /// pipeline. Below the frame pointer come the allocas, then registers that don't
+    /// fit in real ones, then the callee-saved registers the function uses, and at the
+    /// bottom the arguments of calls passed on the stack.
+    fn compile_into_function(&self) -> rtl::Function {
+        let mut module = rtl::Module {
+            functions: vec![self.lower_into_function()],
+            externs: Vec::new(),
+            globals: Vec::new(),
+        };
+        allocate(&mut module);
+        module.functions.pop().expect("the function is still there")
+    }
+}
+
+impl LowerIntoModule for ssa::Module {
+    fn lower_into_module(&self) -> rtl::Module {
+        rtl::Module {
+            functions: self
+                .functions()
+                .iter()
+                .map(|func| func.lower_into_function())
+                .collect(),
+            externs: self
+                .externs()
+                .iter()
+                .map(|decl| decl.name.clone())
+                .collect(),
+            globals: self.globals().iter().map(mem::compile_global).collect(),
+        }
+    }
+}
+
+impl CompileIntoModule for ssa::Module {
+    fn compile_into_module(&self) -> rtl::Module {
+        let mut module = self.lower_into_module();
+        allocate(&mut module);
+        module
+    }
+}
+
+fn lower_function(func: &ssa::Function) -> (Vec<rtl::Block>, CompileContext) {
+    ssa::verify::debug_verify(func, "the last pass before lowering");
+    let mut context = CompileContext {
+        next_vir: func.supervisor().vars().len(),
+        frame: mem::Frame::default(),
+        outgoing_args: 0,
+    };
+    context.frame.layout(func);
+    let mut blocks = Vec::with_capacity(func.block_count() + 1);
+    if !func.params().is_empty() {
+        // Kept out of the entry block, which may be the target of a jump.
+        let mut ops = rtl::Ops::new();
+        call::compile_params(func, &mut ops);
+        blocks.push(rtl::Block {
+            metadata: (),
+            ops,
+            name: None,
+        });
+    }
+    let mut edge_blocks = Vec::new();
+    for (id, bb) in func.blocks() {
+        let mut ops = rtl::Ops::new();
+        let fused = cmp::fused_with_branch(func, bb);
+        for ins in bb.ins_list() {
+            if fused.is_some_and(|fused| std::ptr::eq(fused, ins)) {
+                break;
+            }
+            ins.compile_into_ops(&mut ops, &mut context);
+        }
+        let flags = match fused {
+            Some(ssa::Ins::Cmp(_, cmp_ty, a, b)) => {
+                Some(cmp::compile_flags(a, b, *cmp_ty, &mut ops, &mut context))
+            }
+            _ => None,
+        };
+        let layout_next = Some(id.index() + 1)
+            .filter(|next| *next < func.block_count())
+            .map(|next| term::block_label(ssa::BlockId(next)));
+        let term = bb
+            .terminator()
+            .unwrap_or_else(|| panic!("block {} is not terminated", id));
+
+        let mut split_edges = Vec::new();
+        match term.known_target() {
+            Some(target) => phi::compile_edge_copies(func, id, target, &mut ops, &mut context),
+            None => {
+                for succ in term.successors() {
+                    if func.block(succ).phis().next().is_none() {
+                        continue;
+                    }
+                    let mut edge_ops = rtl::Ops::new();
+                    phi::compile_edge_copies(func, id, succ, &mut edge_ops, &mut context);
+                    edge_ops.push(rtl::Op::Jmp(rtl::OpJmp {
+                        target: term::block_label(succ),
+                    }));
+                    edge_blocks.push(rtl::Block {
+                        metadata: (),
+                        ops: edge_ops,
+                        name: Some(term::edge_label(id, succ)),
+                    });
+                    split_edges.push(succ);
+                }
+            }
+        }
+        let target_label = |target| match split_edges.contains(&target) {
+            true => term::edge_label(id, target),
+            false => term::block_label(target),
+        };
+        term::compile(term, flags, &target_label, layout_next.as_deref(), &mut ops);
+        blocks.push(rtl::Block {
+            metadata: (),
+            ops,
+            name: Some(term::block_label(id)),
+        });
+    }
+    blocks.append(&mut edge_blocks);
+    (blocks, context)
+}
+
 impl CompileIntoOps for ssa::Ins {
-    fn compile_into_ops(&self, ops: &mut Vec<rtl::Op>, _context: &mut CompileContext) {
+    fn compile_into_ops(&self, ops: &mut Vec<rtl::Op>, context: &mut CompileContext) {
         match self {
             ssa::Ins::Add(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Add, ops),
             ssa::Ins::Sub(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Sub, ops),
             ssa::Ins::Mul(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Mul, ops),
             ssa::Ins::Div(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Div, ops),
+            ssa::Ins::SDiv(dest, a, b) => {
+                div::compile(dest, a, b, ssa::BinOpTy::SDiv, ops, context)
+            }
+            ssa::Ins::UDiv(dest, a, b) => {
+                div::compile(dest, a, b, ssa::BinOpTy::UDiv, ops, context)
+            }
+            ssa::Ins::SRem(dest, a, b) => {
+                div::compile(dest, a, b, ssa::BinOpTy::SRem, ops, context)
+            }
+            ssa::Ins::URem(dest, a, b) => {
+                div::compile(dest, a, b, ssa::BinOpTy::URem, ops, context)
+            }
+            ssa::Ins::And(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::And, ops),
+            ssa::Ins::Or(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Or, ops),
+            ssa::Ins::Xor(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Xor, ops),
+            ssa::Ins::Shl(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::Shl, ops),
+            ssa::Ins::LShr(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::LShr, ops),
+            ssa::Ins::AShr(dest, a, b) => binop::compile(dest, a, b, ssa::BinOpTy::AShr, ops),
+            ssa::Ins::Not(dest, a) => unop::compile(dest, a, ssa::UnOpTy::Not, ops),
+            ssa::Ins::Neg(dest, a) => unop::compile(dest, a, ssa::UnOpTy::Neg, ops),
             ssa::Ins::Cpy(dest, rhs) => cpy::compile(dest, rhs, ops),
+            ssa::Ins::Cmp(dest, cmp_ty, a, b) => cmp::compile(dest, a, b, *cmp_ty, ops, context),
+            ssa::Ins::Cast(dest, cast_ty, val) => cast::compile(dest, val, *cast_ty, ops, context),
+            ssa::Ins::Select(dest, cond, a, b) => select::compile(dest, cond, a, b, ops, context),
+            // Lowered as copies in the predecessors.
+            ssa::Ins::Phi(..) => (),
+            ssa::Ins::Alloca(dest, size, align) => {
+                mem::compile_alloca(dest, *size, *align, ops, context)
+            }
+            ssa::Ins::Load(dest, ptr, _) => mem::compile_load(dest, ptr, ops, context),
+            ssa::Ins::Store(ptr, val, _) => mem::compile_store(ptr, val, ops, context),
+            ssa::Ins::Gep(dest, ty, ptr, indices) => {
+                mem::compile_gep(dest, ty, ptr, indices, ops, context)
+            }
+            ssa::Ins::Addr(dest, name) => mem::compile_addr(dest, name, ops),
+            ssa::Ins::Call(dest, callee, args) => call::compile(dest, callee, args, ops, context),
         }
     }
 }
@@ -46,10 +313,9 @@ impl CompileIntoOps for ssa::Ins {
 #[inline]
 fn rtl_rvalue_from_ssa(ssa: &ssa::RValue) -> rtl::RValue {
     match ssa {
-        ssa::RValue::Lit(lit) => rtl::RValue::Lit(match lit {
-            ssa::Literal::U32(val) => rtl::Lit::LitU32(*val),
-            _ => todo!("Many literal types"),
-        }),
+        ssa::RValue::Lit(lit) => {
+            rtl::RValue::Lit(rtl::Lit::from_bits(lit.data_ty().mem_size(), lit.to_bits()))
+        }
         ssa::RValue::Var(var) => rtl::RValue::Register(rtl::Register::Vir(var.as_vir_reg())),
     }
 }
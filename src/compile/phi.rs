@@ -0,0 +1,59 @@
+use crate::rtl;
+use crate::ssa;
+use crate::typing::Typed;
+
+/// Copies that give the phis of `succ` their values when control comes from `pred`.
+/// All phis of a block take their values at once, so when one phi reads another the
+/// values go through fresh registers first.
+pub fn compile_edge_copies(
+    func: &ssa::Function,
+    pred: ssa::BlockId,
+    succ: ssa::BlockId,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    let copies: Vec<(ssa::Variable, ssa::RValue)> = func
+        .block(succ)
+        .phis()
+        .filter_map(|ins| match ins {
+            ssa::Ins::Phi(dest, incoming) => {
+                let (_, val) = incoming
+                    .iter()
+                    .find(|(block, _)| *block == pred)
+                    .unwrap_or_else(|| panic!("phi {} has no operand for {}", dest, pred));
+                Some((*dest, *val))
+            }
+            _ => None,
+        })
+        .filter(|(dest, val)| *val != ssa::RValue::Var(*dest))
+        .collect();
+
+    let reads_dest = copies.iter().any(|(_, val)| match val {
+        ssa::RValue::Var(var) => copies.iter().any(|(dest, _)| dest == var),
+        ssa::RValue::Lit(..) => false,
+    });
+    if !reads_dest {
+        for (dest, val) in &copies {
+            super::cpy::compile(dest, val, ops);
+        }
+        return;
+    }
+
+    let temps: Vec<rtl::Register> = copies
+        .iter()
+        .map(|(dest, val)| {
+            let tmp = rtl::Register::Vir(context.fresh_vir(dest.data_ty()));
+            ops.push(rtl::Op::Copy(rtl::OpCopy {
+                to: tmp,
+                from: super::rtl_rvalue_from_ssa(val),
+            }));
+            tmp
+        })
+        .collect();
+    for ((dest, _), tmp) in copies.iter().zip(temps) {
+        ops.push(rtl::Op::Copy(rtl::OpCopy {
+            to: rtl::Register::Vir(dest.as_vir_reg()),
+            from: rtl::RValue::Register(tmp),
+        }));
+    }
+}
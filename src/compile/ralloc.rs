@@ -1,52 +1,96 @@
+use crate::pass::{Analyses, FunctionPass, Preserved};
 use crate::rtl::{
-    amd64::Amd64Register, Op, Ops, RValue, RealRegister, Register, StackRegister, VirRegister,
+    self, amd64::Amd64Register, Address, Block, Op, OpAShr, OpAnd, OpLShr, OpNeg, OpNot, OpOr,
+    OpShl, OpXor, Ops, RValue, RealRegister, RegClass, Register, StackRegister, VirRegister,
 };
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-const AMD64_ALLOC_DWORD_REG: [Amd64Register; 4] = [
-    Amd64Register::Eax,
-    Amd64Register::Ebx,
-    Amd64Register::Ecx,
-    Amd64Register::Edx,
+/// Registers handed out by the allocator, at whatever size a virtual register needs.
+/// `r10` and `r11` are left out as scratch registers for the code generator, and `rbp`
+/// is the frame pointer. The registers a callee must preserve come last, since using
+/// them costs a save and a restore.
+const AMD64_ALLOC_REG: [Amd64Register; 12] = [
+    Amd64Register::Rax,
+    Amd64Register::Rcx,
+    Amd64Register::Rdx,
+    Amd64Register::Rsi,
+    Amd64Register::Rdi,
+    Amd64Register::R8,
+    Amd64Register::R9,
+    Amd64Register::Rbx,
+    Amd64Register::R12,
+    Amd64Register::R13,
+    Amd64Register::R14,
+    Amd64Register::R15,
+];
+
+/// Registers that a function has to give back to its caller unchanged.
+pub const AMD64_CALLEE_SAVED: [Amd64Register; 5] = [
+    Amd64Register::Rbx,
+    Amd64Register::R12,
+    Amd64Register::R13,
+    Amd64Register::R14,
+    Amd64Register::R15,
+];
+
+/// XMM registers handed out by the allocator. `xmm14` and `xmm15` are scratch registers
+/// for the code generator.
+const AMD64_ALLOC_XMM: [Amd64Register; 14] = [
+    Amd64Register::Xmm0D,
+    Amd64Register::Xmm1D,
+    Amd64Register::Xmm2D,
+    Amd64Register::Xmm3D,
+    Amd64Register::Xmm4D,
+    Amd64Register::Xmm5D,
+    Amd64Register::Xmm6D,
+    Amd64Register::Xmm7D,
+    Amd64Register::Xmm8D,
+    Amd64Register::Xmm9D,
+    Amd64Register::Xmm10D,
+    Amd64Register::Xmm11D,
+    Amd64Register::Xmm12D,
+    Amd64Register::Xmm13D,
 ];
 
 pub struct VirRegisterMap<T> {
-    arr: Vec<Option<(T, usize)>>,
+    arr: Vec<Option<(T, VirRegister)>>,
+}
+
+impl<T> Default for VirRegisterMap<T> {
+    fn default() -> Self {
+        VirRegisterMap { arr: Vec::new() }
+    }
 }
 
 impl<T> VirRegisterMap<T> {
     pub fn new() -> VirRegisterMap<T> {
-        VirRegisterMap { arr: Vec::new() }
+        Self::default()
     }
 
     pub fn insert(&mut self, vir: &VirRegister, val: T) {
         self.expand_to(vir.n + 1);
-        self.arr[vir.n] = Some((val, vir.bytes));
+        self.arr[vir.n] = Some((val, *vir));
     }
 
     pub fn get(&self, vir: &VirRegister) -> Option<&T> {
         if vir.n >= self.arr.len() {
             None
         } else {
-            match &self.arr[vir.n] {
-                Some((val, _bytes)) => Some(val),
-                None => None,
-            }
+            self.arr[vir.n].as_ref().map(|(val, _vir)| val)
         }
     }
 
     pub fn keys(&self) -> impl Iterator<Item = &T> + '_ {
-        self.arr.iter().filter_map(|x| match x {
-            Some((t, _bytes)) => Some(t),
-            None => None,
-        })
+        self.arr
+            .iter()
+            .filter_map(|x| x.as_ref().map(|(t, _vir)| t))
     }
 
     pub fn entries(&self) -> impl Iterator<Item = (VirRegister, &T)> + '_ {
-        self.arr.iter().enumerate().filter_map(|(n, e)| match e {
-            Some((t, bytes)) => Some((VirRegister { n, bytes: *bytes }, t)),
-            None => None,
-        })
+        self.arr
+            .iter()
+            .filter_map(|e| e.as_ref().map(|(t, vir)| (*vir, t)))
     }
 
     fn expand_to(&mut self, n: usize) {
@@ -62,16 +106,11 @@ impl<T> VirRegisterMap<T> {
 
 impl<T: fmt::Debug> fmt::Debug for VirRegisterMap<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "VirRegisterMap [\n")?;
+        writeln!(f, "VirRegisterMap [")?;
         // TODO: Indentation (PadAdapter)
-        for opt in &self.arr {
-            match opt {
-                Some((entry, _)) => {
-                    fmt::Debug::fmt(entry, f)?;
-                    write!(f, "\n")?;
-                }
-                None => (),
-            }
+        for (entry, _) in self.arr.iter().flatten() {
+            fmt::Debug::fmt(entry, f)?;
+            writeln!(f)?;
         }
         write!(f, "]")
     }
@@ -81,23 +120,35 @@ impl<T: fmt::Debug> fmt::Debug for VirRegisterMap<T> {
 pub struct Allocator {
     allocations: VirRegisterMap<Allocation>,
     virtuals: Vec<(VirRegister, VirRegisterInfo)>,
-    manually_excluded: Vec<RealRegister>,
+    /// Real registers the ops use directly, with the ops that need their value kept.
+    occupied: Vec<(RealRegister, VirRegisterInfo)>,
     stack_alloc_offset: usize,
 }
 
 impl Allocator {
     pub fn new(
         virtuals: Vec<(VirRegister, VirRegisterInfo)>,
-        occupied: Vec<RealRegister>,
+        occupied: Vec<(RealRegister, VirRegisterInfo)>,
     ) -> Allocator {
         Allocator {
             virtuals,
-            manually_excluded: occupied,
+            occupied,
             allocations: VirRegisterMap::new(),
             stack_alloc_offset: 0,
         }
     }
 
+    /// Keeps the first `bytes` below the frame pointer out of the stack slots handed
+    /// out for spilled registers.
+    pub fn reserve_stack(&mut self, bytes: usize) {
+        self.stack_alloc_offset = self.stack_alloc_offset.max(bytes);
+    }
+
+    /// Bytes below the frame pointer used by stack slots and reserved memory.
+    pub fn stack_size(&self) -> usize {
+        self.stack_alloc_offset
+    }
+
     #[inline]
     pub fn map(&self) -> &VirRegisterMap<Allocation> {
         &self.allocations
@@ -105,48 +156,57 @@ impl Allocator {
 
     pub fn create_allocations(&mut self) {
         fn lifetime_a_inside_b(a_info: &VirRegisterInfo, b_info: &VirRegisterInfo) -> bool {
-            (a_info.lifetime_begin >= b_info.lifetime_begin
-                && a_info.lifetime_begin <= b_info.lifetime_end)
-                || (a_info.lifetime_end <= b_info.lifetime_end
-                    && a_info.lifetime_end >= b_info.lifetime_begin)
+            a_info.lifetime_begin <= b_info.lifetime_end
+                && b_info.lifetime_begin <= a_info.lifetime_end
+        }
+
+        // A real register may hold a virtual one that dies where the real register is
+        // written, or that is written where the real register dies, like the source of
+        // an argument copy or the copy of a call's result.
+        fn lifetimes_cross(a_info: &VirRegisterInfo, b_info: &VirRegisterInfo) -> bool {
+            a_info.lifetime_begin < b_info.lifetime_end
+                && b_info.lifetime_begin < a_info.lifetime_end
         }
 
         for (vir, info) in &self.virtuals {
-            assert_eq!(vir.bytes, 4, "only support for 4 bytes");
             // FIXME: Support for other than AMD64
-            // FIXME: Support for other than 4 bytes
-            // FIXME: Make sure size is aligned
             let allocations: Vec<&Allocation> = self.allocations.keys().collect();
-            let mut choices: Vec<&Amd64Register> = AMD64_ALLOC_DWORD_REG
+            let pool: &[Amd64Register] = match vir.class {
+                RegClass::Gpr => &AMD64_ALLOC_REG,
+                RegClass::Xmm => &AMD64_ALLOC_XMM,
+            };
+            let mut choices: Vec<Amd64Register> = pool
                 .iter()
+                .map(|reg| reg.with_size(vir.bytes))
                 .filter(|reg| {
-                    !self.manually_excluded.contains(&RealRegister::Amd64(**reg))
-                        && allocations.iter().all(|alloc| match alloc.kind {
-                            AllocationKind::Reg(register)
-                                if *reg
-                                    == match &register {
-                                        RealRegister::Amd64(amd) => amd,
-                                    }
-                                    && lifetime_a_inside_b(info, &alloc.info) =>
-                            {
-                                false
-                            }
-                            AllocationKind::Reg(_) => true,
-                            AllocationKind::Stack { .. } => true,
-                        })
+                    let real = RealRegister::Amd64(*reg);
+                    !self.occupied.iter().any(|(fixed, fixed_info)| {
+                        fixed.overlaps(&real) && lifetimes_cross(info, fixed_info)
+                    }) && allocations.iter().all(|alloc| match alloc.kind {
+                        AllocationKind::Reg(register)
+                            if register.overlaps(&real)
+                                && lifetime_a_inside_b(info, &alloc.info) =>
+                        {
+                            false
+                        }
+                        AllocationKind::Reg(_) => true,
+                        AllocationKind::Stack { .. } => true,
+                    })
                 })
                 .collect();
 
             let kind = choices
                 .drain(..)
                 .next()
-                .map(|reg| AllocationKind::Reg(RealRegister::Amd64(*reg)))
+                .map(|reg| AllocationKind::Reg(RealRegister::Amd64(reg)))
                 .unwrap_or_else(|| {
-                    // Stack allocation when no registers left
-                    self.stack_alloc_offset += vir.bytes;
+                    // Stack allocation when no registers left, aligned to its size
+                    self.stack_alloc_offset =
+                        (self.stack_alloc_offset + vir.bytes).next_multiple_of(vir.bytes);
                     AllocationKind::Stack(StackRegister {
                         slot: self.stack_alloc_offset,
                         bytes: vir.bytes,
+                        class: vir.class,
                     })
                 });
 
@@ -162,74 +222,418 @@ impl Allocator {
     }
 }
 
-pub fn analyze_rtl(ops: &Ops) -> (VirRegisterMap<VirRegisterInfo>, Vec<RealRegister>) {
-    let mut map: VirRegisterMap<VirRegisterInfo> = VirRegisterMap::new();
-    let mut already_occupied: Vec<RealRegister> = Vec::new();
-
-    fn notice_reg(
-        map: &mut VirRegisterMap<VirRegisterInfo>,
-        already_occupied: &mut Vec<RealRegister>,
-        reg: &Register,
-        idx: InstrIdx,
-    ) {
-        let vir_reg = match reg {
-            Register::Real(real) => {
-                already_occupied.push(*real);
-                return;
-            }
-            Register::Stack(_ss) => {
-                todo!("pre-occupied stack slot");
+/// The lifetimes of the virtual registers of a function and the ranges where the real
+/// registers its ops name hold a value, as [`analyze_rtl_blocks`] finds them.
+pub struct Liveness {
+    pub virtuals: VirRegisterMap<VirRegisterInfo>,
+    pub fixed: Vec<(RealRegister, VirRegisterInfo)>,
+}
+
+impl Liveness {
+    pub fn compute(func: &rtl::Function) -> Liveness {
+        let (virtuals, fixed) = analyze_rtl_blocks(&func.blocks);
+        Liveness { virtuals, fixed }
+    }
+}
+
+/// Gives every virtual register a real register or a stack slot below what the frame
+/// already holds, and leaves the choice in [`rtl::Function::allocation`] for
+/// [`Promote`]. The ops are not changed.
+pub struct RegAlloc;
+
+impl FunctionPass<rtl::Function> for RegAlloc {
+    fn name(&self) -> &str {
+        "ralloc"
+    }
+
+    fn run(
+        &mut self,
+        func: &mut rtl::Function,
+        analyses: &mut Analyses<rtl::Function>,
+    ) -> Preserved {
+        let liveness = analyses.get::<Liveness>(func);
+        let virtuals = liveness.virtuals.entries().map(|(k, v)| (k, *v)).collect();
+        let mut allocator = Allocator::new(virtuals, liveness.fixed.clone());
+        allocator.reserve_stack(func.frame_size);
+        allocator.create_allocations();
+        func.frame_size = allocator.stack_size();
+        func.allocation = Some(allocator.allocations);
+        Preserved::All
+    }
+}
+
+/// Replaces the virtual registers with what [`RegAlloc`] chose for them, and makes room
+/// in the frame for the callee-saved registers the function now writes and for the
+/// arguments of its calls.
+pub struct Promote;
+
+impl FunctionPass<rtl::Function> for Promote {
+    fn name(&self) -> &str {
+        "promote"
+    }
+
+    fn run(&mut self, func: &mut rtl::Function, _: &mut Analyses<rtl::Function>) -> Preserved {
+        let map = func
+            .allocation
+            .take()
+            .expect("registers are allocated before they are promoted");
+        rtl::promote_registers_in_blocks(&mut func.blocks, |vir| {
+            map.get(vir)
+                .map(|alloc| alloc.kind)
+                .expect("unmapped register")
+        });
+        let mut saved: Vec<RealRegister> = Vec::new();
+        for (_, alloc) in map.entries() {
+            if let AllocationKind::Reg(reg) = alloc.kind {
+                let reg = match reg {
+                    RealRegister::Amd64(reg) => RealRegister::Amd64(reg.with_size(8)),
+                };
+                let callee_saved = AMD64_CALLEE_SAVED
+                    .iter()
+                    .any(|cs| RealRegister::Amd64(*cs) == reg);
+                if callee_saved && !saved.contains(&reg) {
+                    saved.push(reg);
+                }
             }
-            Register::Vir(vir) => vir,
-        };
-        match map.get(vir_reg) {
-            None => map.insert(
-                vir_reg,
-                VirRegisterInfo {
-                    lifetime_begin: idx,
-                    lifetime_end: idx,
-                },
-            ),
-            Some(info) => map.insert(
-                vir_reg,
-                VirRegisterInfo {
-                    lifetime_begin: info.lifetime_begin,
-                    lifetime_end: idx,
-                },
-            ),
         }
+        let mut frame_size = func.frame_size.next_multiple_of(8);
+        func.saved = saved
+            .into_iter()
+            .map(|reg| {
+                frame_size += 8;
+                (reg, -(frame_size as i32))
+            })
+            .collect();
+        func.frame_size = frame_size + func.outgoing_args;
+        Preserved::none()
     }
+}
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Access {
+    Use,
+    Def,
+    UseDef,
+}
+
+fn for_each_register(op: &Op, mut f: impl FnMut(&Register, Access)) {
     #[inline]
-    fn notice_rvalue(
-        map: &mut VirRegisterMap<VirRegisterInfo>,
-        already_occupied: &mut Vec<RealRegister>,
-        rv: &RValue,
-        idx: InstrIdx,
-    ) {
+    fn rvalue(rv: &RValue, f: &mut impl FnMut(&Register, Access)) {
         match rv {
             RValue::Lit(..) => (),
-            RValue::Register(reg) => notice_reg(map, already_occupied, reg, idx),
+            RValue::Register(reg) => f(reg, Access::Use),
         }
     }
 
-    macro_rules! notice_both {
-        ($map:expr,$occ:expr,$tgt:ident,$fa:ident,$fb:ident,$i:expr) => {{
-            notice_reg(&mut $map, &mut $occ, &$tgt.$fa, $i);
-            notice_rvalue(&mut $map, &mut $occ, &$tgt.$fb, $i);
-        }};
+    #[inline]
+    fn address(addr: &Address, f: &mut impl FnMut(&Register, Access)) {
+        f(&addr.base, Access::Use);
+        if let Some((index, _)) = &addr.index {
+            f(index, Access::Use);
+        }
     }
 
+    match op {
+        Op::Add(add) => {
+            f(&add.to, Access::UseDef);
+            rvalue(&add.val, &mut f);
+        }
+        Op::Sub(sub) => {
+            f(&sub.from, Access::UseDef);
+            rvalue(&sub.val, &mut f);
+        }
+        Op::Mul(mul) => {
+            f(&mul.val, Access::UseDef);
+            rvalue(&mul.with, &mut f);
+        }
+        Op::Div(div) => {
+            f(&div.val, Access::UseDef);
+            rvalue(&div.with, &mut f);
+        }
+        Op::ExtendHigh(ext) => {
+            f(&ext.lo, Access::Use);
+            f(&ext.hi, Access::Def);
+        }
+        Op::IntDiv(div) => {
+            f(&div.lo, Access::UseDef);
+            f(&div.hi, Access::UseDef);
+            rvalue(&div.by, &mut f);
+        }
+        Op::MulHigh(mul) => {
+            f(&mul.lo, Access::UseDef);
+            f(&mul.hi, Access::Def);
+            rvalue(&mul.with, &mut f);
+        }
+        Op::And(OpAnd { to, val }) | Op::Or(OpOr { to, val }) | Op::Xor(OpXor { to, val }) => {
+            f(to, Access::UseDef);
+            rvalue(val, &mut f);
+        }
+        Op::Shl(OpShl { val, by })
+        | Op::LShr(OpLShr { val, by })
+        | Op::AShr(OpAShr { val, by }) => {
+            f(val, Access::UseDef);
+            rvalue(by, &mut f);
+        }
+        Op::Not(OpNot { val }) | Op::Neg(OpNeg { val }) => f(val, Access::UseDef),
+        Op::Copy(copy) => {
+            f(&copy.to, Access::Def);
+            rvalue(&copy.from, &mut f);
+        }
+        Op::Cmp(cmp) => {
+            f(&cmp.a, Access::Use);
+            rvalue(&cmp.b, &mut f);
+        }
+        Op::SetCc(setcc) => f(&setcc.to, Access::Def),
+        Op::CMov(cmov) => {
+            f(&cmov.from, Access::Use);
+            f(&cmov.to, Access::UseDef);
+        }
+        Op::Convert(convert) => {
+            f(&convert.to, Access::Def);
+            f(&convert.from, Access::Use);
+        }
+        Op::Load(load) => {
+            f(&load.to, Access::Def);
+            address(&load.addr, &mut f);
+        }
+        Op::Store(store) => {
+            address(&store.addr, &mut f);
+            rvalue(&store.from, &mut f);
+        }
+        Op::Lea(lea) => {
+            f(&lea.to, Access::Def);
+            address(&lea.addr, &mut f);
+        }
+        Op::AddrOf(addr_of) => f(&addr_of.to, Access::Def),
+        Op::Call(call) => {
+            for reg in &call.args {
+                f(reg, Access::Use);
+            }
+            for reg in &call.clobbers {
+                f(reg, Access::Def);
+            }
+        }
+        Op::Jmp(..) | Op::Jcc(..) | Op::Ret(..) => (),
+    }
+}
+
+fn extend_lifetime(
+    map: &mut VirRegisterMap<VirRegisterInfo>,
+    vir_reg: &VirRegister,
+    idx: InstrIdx,
+) {
+    match map.get(vir_reg) {
+        None => map.insert(
+            vir_reg,
+            VirRegisterInfo {
+                lifetime_begin: idx,
+                lifetime_end: idx,
+            },
+        ),
+        Some(info) => map.insert(
+            vir_reg,
+            VirRegisterInfo {
+                lifetime_begin: info.lifetime_begin.min(idx),
+                lifetime_end: info.lifetime_end.max(idx),
+            },
+        ),
+    }
+}
+
+pub fn analyze_rtl(
+    ops: &Ops,
+) -> (
+    VirRegisterMap<VirRegisterInfo>,
+    Vec<(RealRegister, VirRegisterInfo)>,
+) {
+    let mut map: VirRegisterMap<VirRegisterInfo> = VirRegisterMap::new();
     for (i, op) in ops.iter().enumerate() {
-        match op {
-            Op::Add(add) => notice_both!(map, already_occupied, add, to, val, i),
-            Op::Sub(sub) => notice_both!(map, already_occupied, sub, from, val, i),
-            Op::Mul(mul) => notice_both!(map, already_occupied, mul, val, with, i),
-            Op::Div(div) => notice_both!(map, already_occupied, div, val, with, i),
-            Op::Copy(copy) => notice_both!(map, already_occupied, copy, to, from, i),
+        for_each_register(op, |reg, _| {
+            if let Register::Vir(vir) = reg {
+                extend_lifetime(&mut map, vir, i);
+            }
+        });
+    }
+    (map, fixed_ranges([ops.as_slice()]))
+}
+
+/// Like [`analyze_rtl`], but for blocks that jump between each other. The ops of all
+/// blocks are numbered in order, and a register that is live on entry to or exit from
+/// a block has its lifetime stretched to cover that edge of the block.
+pub fn analyze_rtl_blocks(
+    blocks: &[Block],
+) -> (
+    VirRegisterMap<VirRegisterInfo>,
+    Vec<(RealRegister, VirRegisterInfo)>,
+) {
+    let mut map: VirRegisterMap<VirRegisterInfo> = VirRegisterMap::new();
+
+    let mut starts = Vec::with_capacity(blocks.len());
+    let mut idx = 0;
+    for block in blocks {
+        starts.push(idx);
+        for op in &block.ops {
+            for_each_register(op, |reg, _| {
+                if let Register::Vir(vir) = reg {
+                    extend_lifetime(&mut map, vir, idx);
+                }
+            });
+            idx += 1;
+        }
+    }
+
+    let (live_in, live_out) = liveness(blocks);
+    for (b, block) in blocks.iter().enumerate() {
+        let begin = starts[b];
+        let end = (begin + block.ops.len()).saturating_sub(1).max(begin);
+        for vir in &live_in[b] {
+            extend_lifetime(&mut map, vir, begin);
+        }
+        for vir in &live_out[b] {
+            extend_lifetime(&mut map, vir, end);
+        }
+    }
+    let fixed = fixed_ranges(blocks.iter().map(|block| block.ops.as_slice()));
+    (map, fixed)
+}
+
+/// Where real registers named by the ops hold a value: from the op that writes one to
+/// the last op that reads it. A register read before any write in its block, like an
+/// incoming argument, holds its value from the start of the block. Ops are numbered
+/// across the blocks, in order.
+fn fixed_ranges<'o>(
+    blocks: impl IntoIterator<Item = &'o [Op]>,
+) -> Vec<(RealRegister, VirRegisterInfo)> {
+    let mut ranges: Vec<(RealRegister, VirRegisterInfo)> = Vec::new();
+    let mut idx = 0;
+    for ops in blocks {
+        let block_start = idx;
+        // Indices into `ranges` of the values real registers hold in this block.
+        let mut open: Vec<usize> = Vec::new();
+        for op in ops {
+            for_each_register(op, |reg, access| {
+                let real = match reg {
+                    Register::Real(real) => *real,
+                    _ => return,
+                };
+                let current = open
+                    .iter()
+                    .position(|range| ranges[*range].0.overlaps(&real));
+                match (access, current) {
+                    (Access::Use | Access::UseDef, Some(pos)) => {
+                        ranges[open[pos]].1.lifetime_end = idx;
+                    }
+                    (Access::Use | Access::UseDef, None) => {
+                        open.push(ranges.len());
+                        ranges.push((
+                            real,
+                            VirRegisterInfo {
+                                lifetime_begin: block_start,
+                                lifetime_end: idx,
+                            },
+                        ));
+                    }
+                    (Access::Def, current) => {
+                        if let Some(pos) = current {
+                            open.remove(pos);
+                        }
+                        open.push(ranges.len());
+                        ranges.push((
+                            real,
+                            VirRegisterInfo {
+                                lifetime_begin: idx,
+                                lifetime_end: idx,
+                            },
+                        ));
+                    }
+                }
+            });
+            idx += 1;
+        }
+    }
+    ranges
+}
+
+/// Successor block indices, from jump targets and falling through to the next block.
+fn block_successors(blocks: &[Block]) -> Vec<Vec<usize>> {
+    let labels: HashMap<&str, usize> = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, block)| block.name.as_deref().map(|name| (name, i)))
+        .collect();
+    blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| {
+            let mut succs: Vec<usize> = block
+                .ops
+                .iter()
+                .filter_map(|op| op.jump_target())
+                .map(|target| {
+                    *labels
+                        .get(target)
+                        .unwrap_or_else(|| panic!("jump to unknown label '{}'", target))
+                })
+                .collect();
+            let falls_through = !block
+                .ops
+                .last()
+                .is_some_and(|op| op.is_unconditional_exit());
+            if falls_through && i + 1 < blocks.len() {
+                succs.push(i + 1);
+            }
+            succs
+        })
+        .collect()
+}
+
+type LiveSet = HashSet<VirRegister>;
+
+/// Live-in and live-out virtual registers of every block.
+fn liveness(blocks: &[Block]) -> (Vec<LiveSet>, Vec<LiveSet>) {
+    let succs = block_successors(blocks);
+    let mut gen: Vec<LiveSet> = Vec::with_capacity(blocks.len());
+    let mut kill: Vec<LiveSet> = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let (mut g, mut k) = (LiveSet::new(), LiveSet::new());
+        for op in &block.ops {
+            // All uses of an op happen before its definitions.
+            for_each_register(op, |reg, access| match (reg, access) {
+                (Register::Vir(vir), Access::Use | Access::UseDef) if !k.contains(vir) => {
+                    g.insert(*vir);
+                }
+                _ => (),
+            });
+            for_each_register(op, |reg, access| {
+                if let (Register::Vir(vir), Access::Def | Access::UseDef) = (reg, access) {
+                    k.insert(*vir);
+                }
+            });
+        }
+        gen.push(g);
+        kill.push(k);
+    }
+
+    let mut live_in = vec![LiveSet::new(); blocks.len()];
+    let mut live_out = vec![LiveSet::new(); blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..blocks.len()).rev() {
+            let out: LiveSet = succs[b]
+                .iter()
+                .flat_map(|s| live_in[*s].iter().copied())
+                .collect();
+            let mut inn = gen[b].clone();
+            inn.extend(out.difference(&kill[b]).copied());
+            if inn != live_in[b] || out != live_out[b] {
+                changed = true;
+                live_in[b] = inn;
+                live_out[b] = out;
+            }
         }
     }
-    (map, already_occupied)
+    (live_in, live_out)
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -0,0 +1,52 @@
+//! `select`, as a conditional move.
+
+use crate::rtl;
+use crate::ssa;
+use crate::typing::Typed;
+
+/// Lowers a `Select`. The second value is copied to the destination and the first is
+/// moved over it with a `cmov` when the condition isn't zero. A literal condition
+/// picks a value right away.
+pub fn compile(
+    dest: &ssa::Variable,
+    cond: &ssa::RValue,
+    a: &ssa::RValue,
+    b: &ssa::RValue,
+    ops: &mut rtl::Ops,
+    context: &mut super::CompileContext,
+) {
+    assert_eq!(a.data_ty(), b.data_ty(), "operand types are equal");
+    assert_eq!(
+        dest.data_ty(),
+        a.data_ty(),
+        "dest and operand types are equal"
+    );
+    let dest_reg = rtl::Register::Vir(dest.as_vir_reg());
+    let cond = match cond {
+        ssa::RValue::Lit(lit) => {
+            let from = if lit.is_zero() { b } else { a };
+            ops.push(rtl::Op::Copy(rtl::OpCopy {
+                to: dest_reg,
+                from: super::rtl_rvalue_from_ssa(from),
+            }));
+            return;
+        }
+        ssa::RValue::Var(cond) => rtl::Register::Vir(cond.as_vir_reg()),
+    };
+    // `cmov` takes no immediate. Copies go before the `cmp`, so they can't touch the
+    // flags it sets.
+    let from = context.register_of(a, ops);
+    ops.push(rtl::Op::Copy(rtl::OpCopy {
+        to: dest_reg,
+        from: super::rtl_rvalue_from_ssa(b),
+    }));
+    ops.push(rtl::Op::Cmp(rtl::OpCmp {
+        a: cond,
+        b: rtl::RValue::Lit(rtl::Lit::from_bits(cond.sz(), 0)),
+    }));
+    ops.push(rtl::Op::CMov(rtl::OpCMov {
+        cond: rtl::Cond::Ne,
+        to: dest_reg,
+        from,
+    }));
+}
@@ -0,0 +1,271 @@
+//! Multiplication and division by constants without `imul` or `div`.
+//!
+//! A division by a constant becomes a multiplication by a "magic" number that takes
+//! the high half of the double width product, followed by shifts and adds, as found
+//! by Granlund and Montgomery. A multiplication by a small constant becomes a couple
+//! of `lea`s, shifts, adds or subtractions.
+//!
+//! The plans here say which instructions the lowering emits, and can be evaluated on
+//! a value to check that they compute what they replace. Values are the bits of a
+//! `bits` wide integer, zero extended to 64 bits.
+
+/// Division by a constant that is neither 0 nor a power of two, as an unsigned
+/// multiply-high by `multiplier` and a right shift by `shift`. When the multiplier needs
+/// one bit more than the operands, `add` is set and its low bits are the multiplier;
+/// the dividend is then added back in halves so nothing overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsignedMagic {
+    pub multiplier: u64,
+    pub shift: u32,
+    pub add: bool,
+}
+
+impl UnsignedMagic {
+    /// Finds the smallest shift for which a multiplier of `bits` bits is exact, and
+    /// falls back to one of `bits + 1` bits. Shifts are kept below `bits`, since the
+    /// processor takes the count modulo the width.
+    fn new(divisor: u64, bits: u32) -> UnsignedMagic {
+        let log = 64 - (divisor - 1).leading_zeros();
+        let d = divisor as u128;
+        for shift in 0..=log.min(bits - 1) {
+            let p = bits + shift;
+            let multiplier = (1u128 << p).div_ceil(d);
+            if multiplier < 1 << bits && multiplier * d - (1 << p) <= 1 << shift {
+                return UnsignedMagic {
+                    multiplier: multiplier as u64,
+                    shift,
+                    add: false,
+                };
+            }
+        }
+        // `ceil(2^(bits + log) / d) - 2^bits`, without the 129 bits of `2^128`.
+        let multiplier = ((1u128 << bits) * ((1 << log) - d)).div_ceil(d);
+        UnsignedMagic {
+            multiplier: multiplier as u64,
+            shift: log - 1,
+            add: true,
+        }
+    }
+
+    fn quotient(&self, x: u64, bits: u32) -> u64 {
+        let high = ((x as u128 * self.multiplier as u128) >> bits) as u64;
+        match self.add {
+            true => (((x - high) >> 1) + high) >> self.shift,
+            false => high >> self.shift,
+        }
+    }
+}
+
+/// Signed division by a constant whose magnitude is neither 0 nor a power of two, as
+/// a signed multiply-high by `multiplier`, with the dividend added when `add` is set,
+/// an arithmetic right shift by `shift`, and one added to negative results so they
+/// round towards zero. The quotient is negated for a negative divisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedMagic {
+    pub multiplier: u64,
+    pub shift: u32,
+    pub add: bool,
+    pub negate: bool,
+}
+
+impl SignedMagic {
+    fn new(magnitude: u64, negate: bool, bits: u32) -> SignedMagic {
+        let log = 64 - (magnitude - 1).leading_zeros();
+        let d = magnitude as u128;
+        let mut shift = 1;
+        loop {
+            let p = bits - 1 + shift;
+            let multiplier = (1u128 << p) / d + 1;
+            if multiplier < 1 << bits && multiplier * d - (1 << p) <= 1 << shift {
+                return SignedMagic {
+                    multiplier: multiplier as u64,
+                    shift: shift - 1,
+                    add: multiplier >= 1 << (bits - 1),
+                    negate,
+                };
+            }
+            assert!(shift < log, "a multiplier is found by the shift {}", log);
+            shift += 1;
+        }
+    }
+
+    fn quotient(&self, x: u64, bits: u32) -> u64 {
+        let product = sext(x, bits) as i128 * sext(self.multiplier, bits) as i128;
+        let mut q = (product >> bits) as u64;
+        if self.add {
+            q = q.wrapping_add(x);
+        }
+        q = truncate((sext(q, bits) >> self.shift) as u64, bits);
+        q = truncate(q.wrapping_add(q >> (bits - 1)), bits);
+        match self.negate {
+            true => truncate(q.wrapping_neg(), bits),
+            false => q,
+        }
+    }
+}
+
+/// How the lowering divides by a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    /// Division by 1.
+    Dividend,
+    /// Signed division by -1.
+    Negated,
+    /// Unsigned division by `2^k`, a logical right shift by `k`.
+    ShiftRight(u32),
+    /// Signed division by `2^shift` or its negation. Negative dividends are biased by
+    /// `2^shift - 1` before the arithmetic shift, so the result rounds towards zero.
+    SignedShiftRight {
+        shift: u32,
+        negate: bool,
+    },
+    Unsigned(UnsignedMagic),
+    Signed(SignedMagic),
+}
+
+impl Division {
+    /// The plan for dividing `bits` wide integers by `divisor`, or `None` for a
+    /// divisor of 0, where the division stays and traps.
+    pub fn new(divisor: u64, bits: u32, signed: bool) -> Option<Division> {
+        let divisor = truncate(divisor, bits);
+        if divisor == 0 {
+            return None;
+        }
+        if !signed {
+            return Some(match divisor {
+                1 => Division::Dividend,
+                _ if divisor.is_power_of_two() => Division::ShiftRight(divisor.trailing_zeros()),
+                _ => Division::Unsigned(UnsignedMagic::new(divisor, bits)),
+            });
+        }
+        let negate = sext(divisor, bits) < 0;
+        let magnitude = sext(divisor, bits).unsigned_abs();
+        Some(match (magnitude, negate) {
+            (1, false) => Division::Dividend,
+            (1, true) => Division::Negated,
+            _ if magnitude.is_power_of_two() => Division::SignedShiftRight {
+                shift: magnitude.trailing_zeros(),
+                negate,
+            },
+            _ => Division::Signed(SignedMagic::new(magnitude, negate, bits)),
+        })
+    }
+
+    /// The quotient of `x` computed the way the lowering does.
+    pub fn quotient(&self, x: u64, bits: u32) -> u64 {
+        let x = truncate(x, bits);
+        match self {
+            Division::Dividend => x,
+            Division::Negated => truncate(x.wrapping_neg(), bits),
+            Division::ShiftRight(shift) => x >> shift,
+            Division::SignedShiftRight { shift, negate } => {
+                let bias = truncate((sext(x, bits) >> (bits - 1)) as u64, bits) >> (bits - shift);
+                let q = truncate((sext(x.wrapping_add(bias), bits) >> shift) as u64, bits);
+                match negate {
+                    true => truncate(q.wrapping_neg(), bits),
+                    false => q,
+                }
+            }
+            Division::Unsigned(magic) => magic.quotient(x, bits),
+            Division::Signed(magic) => magic.quotient(x, bits),
+        }
+    }
+
+    /// The remainder of `x` computed the way the lowering does: `x - q * divisor`, or
+    /// the low bits of `x` for an unsigned power of two.
+    pub fn remainder(&self, x: u64, divisor: u64, bits: u32) -> u64 {
+        let x = truncate(x, bits);
+        match self {
+            Division::ShiftRight(shift) => x & ((1 << shift) - 1),
+            _ => {
+                let q = self.quotient(x, bits);
+                truncate(x.wrapping_sub(q.wrapping_mul(divisor)), bits)
+            }
+        }
+    }
+}
+
+/// A step of a multiplication by a constant. The product starts out as the value
+/// being multiplied, and each step changes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulStep {
+    /// Shifts the product left.
+    Shl(u32),
+    /// Adds the product times 2, 4 or 8 to itself with a `lea`.
+    Lea(u8),
+    /// Adds the value being multiplied.
+    Add,
+    /// Subtracts the value being multiplied.
+    Sub,
+    Neg,
+}
+
+impl MulStep {
+    /// The factor the product is multiplied by after the step, given the factor
+    /// before it.
+    fn apply(&self, factor: u64, bits: u32) -> u64 {
+        let factor = match self {
+            MulStep::Shl(shift) => factor << shift,
+            MulStep::Lea(scale) => factor.wrapping_mul(*scale as u64 + 1),
+            MulStep::Add => factor.wrapping_add(1),
+            MulStep::Sub => factor.wrapping_sub(1),
+            MulStep::Neg => factor.wrapping_neg(),
+        };
+        truncate(factor, bits)
+    }
+}
+
+/// At most two steps that multiply `bits` wide values by `factor`, or `None` if
+/// `imul` is as good. `lea` only works on 32 and 64 bit values.
+pub fn multiply_plan(factor: u64, bits: u32) -> Option<Vec<MulStep>> {
+    let factor = truncate(factor, bits);
+    if factor == 0 {
+        return None;
+    }
+    let mut steps = Vec::new();
+    if bits >= 32 {
+        steps.extend([MulStep::Lea(2), MulStep::Lea(4), MulStep::Lea(8)]);
+    }
+    steps.extend((1..bits).map(MulStep::Shl));
+    steps.extend([MulStep::Add, MulStep::Sub, MulStep::Neg]);
+    if factor == 1 {
+        return Some(Vec::new());
+    }
+    if let Some(step) = steps.iter().find(|step| step.apply(1, bits) == factor) {
+        return Some(vec![*step]);
+    }
+    for first in steps.iter() {
+        let after = first.apply(1, bits);
+        if let Some(second) = steps.iter().find(|step| step.apply(after, bits) == factor) {
+            return Some(vec![*first, *second]);
+        }
+    }
+    None
+}
+
+/// The product of `x` and the factor of `steps`, computed the way the lowering does.
+pub fn multiply(steps: &[MulStep], x: u64, bits: u32) -> u64 {
+    let x = truncate(x, bits);
+    let product = steps.iter().fold(x, |product, step| match step {
+        MulStep::Shl(shift) => product << shift,
+        MulStep::Lea(scale) => product.wrapping_add(product.wrapping_mul(*scale as u64)),
+        MulStep::Add => product.wrapping_add(x),
+        MulStep::Sub => product.wrapping_sub(x),
+        MulStep::Neg => product.wrapping_neg(),
+    });
+    truncate(product, bits)
+}
+
+/// The low `bits` bits of `val`.
+pub fn truncate(val: u64, bits: u32) -> u64 {
+    match bits {
+        64 => val,
+        _ => val & ((1 << bits) - 1),
+    }
+}
+
+/// `val`, a `bits` wide integer, sign extended to 64 bits.
+pub fn sext(val: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((val << shift) as i64) >> shift
+}
@@ -0,0 +1,77 @@
+use crate::rtl;
+use crate::ssa;
+use crate::typing::{self, Typed};
+
+pub fn block_label(block: ssa::BlockId) -> String {
+    format!("LBB_{}", block.index())
+}
+
+/// Label of the block holding the phi copies for the edge from `pred` to `succ`.
+pub fn edge_label(pred: ssa::BlockId, succ: ssa::BlockId) -> String {
+    format!("LBB_{}_{}", pred.index(), succ.index())
+}
+
+/// Lowers a terminator. Jumps go to `target_label(block)`, and `layout_next` is the
+/// label placed right after this block: jumps to it are left out and control falls
+/// through instead. `flags` is the condition under which a branch is taken when the
+/// comparison producing its condition was already lowered into a `cmp`.
+pub fn compile(
+    term: &ssa::Terminator,
+    flags: Option<rtl::Cond>,
+    target_label: &dyn Fn(ssa::BlockId) -> String,
+    layout_next: Option<&str>,
+    ops: &mut rtl::Ops,
+) {
+    match term {
+        ssa::Terminator::Jmp(..) | ssa::Terminator::Br(ssa::RValue::Lit(..), ..) => {
+            let target = term.known_target().expect("jump target is known");
+            compile_jmp(target_label(target), layout_next, ops);
+        }
+        ssa::Terminator::Br(ssa::RValue::Var(cond), then, els) => {
+            let taken = flags.unwrap_or_else(|| {
+                let cond = rtl::Register::Vir(cond.as_vir_reg());
+                ops.push(rtl::Op::Cmp(rtl::OpCmp {
+                    a: cond,
+                    b: rtl::RValue::Lit(rtl::Lit::from_bits(cond.sz(), 0)),
+                }));
+                rtl::Cond::Ne
+            });
+            let (then, els) = (target_label(*then), target_label(*els));
+            // Jump on the inverted condition if that lets control fall through to
+            // `then`. `FEq` can't be tested by a single jump, so jump on `FNe` instead.
+            let (cond, target, otherwise) = match taken {
+                _ if Some(then.as_str()) == layout_next && taken.inverse() != rtl::Cond::FEq => {
+                    (taken.inverse(), els, then)
+                }
+                rtl::Cond::FEq => (rtl::Cond::FNe, els, then),
+                _ => (taken, then, els),
+            };
+            ops.push(rtl::Op::Jcc(rtl::OpJcc { cond, target }));
+            compile_jmp(otherwise, layout_next, ops);
+        }
+        ssa::Terminator::Ret(val) => {
+            if let Some(val) = val {
+                ops.push(rtl::Op::Copy(rtl::OpCopy {
+                    to: return_register(val.data_ty()),
+                    from: super::rtl_rvalue_from_ssa(val),
+                }));
+            }
+            ops.push(rtl::Op::Ret(rtl::OpRet));
+        }
+    }
+}
+
+fn compile_jmp(target: String, layout_next: Option<&str>, ops: &mut rtl::Ops) {
+    if Some(target.as_str()) != layout_next {
+        ops.push(rtl::Op::Jmp(rtl::OpJmp { target }));
+    }
+}
+
+/// Where a value of type `ty` is returned.
+pub fn return_register(ty: typing::Type) -> rtl::Register {
+    let reg = match rtl::RegClass::of(ty) {
+        rtl::RegClass::Gpr => rtl::amd64::Amd64Register::Rax,
+        rtl::RegClass::Xmm => rtl::amd64::Amd64Register::Xmm0D,
+    };
+    rtl::Register::Real(rtl::RealRegister::Amd64(reg.with_size(ty.mem_size())))
+}
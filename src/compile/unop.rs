@@ -0,0 +1,25 @@
+use crate::rtl;
+use crate::ssa;
+use crate::typing::{self, Typed};
+
+pub fn compile(dest: &ssa::Variable, a: &ssa::RValue, unop_ty: ssa::UnOpTy, ops: &mut rtl::Ops) {
+    assert_eq!(
+        dest.data_ty(),
+        a.data_ty(),
+        "dest and operand types are equal"
+    );
+    let dest_reg = rtl::Register::Vir(dest.as_vir_reg());
+    ops.push(rtl::Op::Copy(rtl::OpCopy {
+        to: dest_reg,
+        from: super::rtl_rvalue_from_ssa(a),
+    }));
+    ops.push(match unop_ty {
+        // Only the lowest bit of a `Bool` may be set.
+        ssa::UnOpTy::Not if dest.data_ty() == typing::Type::Bool => rtl::Op::Xor(rtl::OpXor {
+            to: dest_reg,
+            val: rtl::RValue::Lit(rtl::Lit::from_bits(1, 1)),
+        }),
+        ssa::UnOpTy::Not => rtl::Op::Not(rtl::OpNot { val: dest_reg }),
+        ssa::UnOpTy::Neg => rtl::Op::Neg(rtl::OpNeg { val: dest_reg }),
+    });
+}
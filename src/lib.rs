@@ -1,5 +1,7 @@
 pub mod codegen;
 pub mod compile;
+pub mod opt;
+pub mod pass;
 pub mod rtl;
 pub mod ssa;
 pub mod typing;
@@ -0,0 +1,67 @@
+//! Copy propagation.
+//!
+//! Every use of the result of a `cpy` is rewritten to what was copied, whether a value
+//! or a literal, and the copy removed. A phi that takes the same value along every edge,
+//! or itself along some of them, is only a copy too, and goes the same way. That can
+//! make other phis trivial, so this repeats until none is left.
+
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::{Function, Ins, RValue, Variable};
+use std::collections::HashSet;
+
+pub struct CopyProp;
+
+impl FunctionPass<Function> for CopyProp {
+    fn name(&self) -> &str {
+        "copyprop"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        let mut removed = HashSet::new();
+        loop {
+            let copies: Vec<(Variable, RValue)> = func
+                .blocks()
+                .flat_map(|(_, bb)| bb.ins_list())
+                .filter_map(|ins| Some((ins.dest()?, copied(ins)?)))
+                .filter(|(dest, _)| !removed.contains(&dest.id()))
+                .collect();
+            if copies.is_empty() {
+                break;
+            }
+            for (dest, _) in copies {
+                // An earlier replacement may have changed what is copied.
+                let Some(src) = func.def_ins(dest).and_then(copied) else {
+                    continue;
+                };
+                func.replace_all_uses_with(dest, src);
+                removed.insert(dest.id());
+            }
+        }
+        if removed.is_empty() {
+            return Preserved::All;
+        }
+        for block in func.block_ids().collect::<Vec<_>>() {
+            func.block_mut(block)
+                .ins_list
+                .retain(|ins| !ins.dest().is_some_and(|dest| removed.contains(&dest.id())));
+        }
+        Preserved::control_flow()
+    }
+}
+
+/// The value `ins` only copies, if it is a `cpy` or a phi that can't take anything
+/// else.
+fn copied(ins: &Ins) -> Option<RValue> {
+    match ins {
+        Ins::Cpy(dest, src) if *src != RValue::Var(*dest) => Some(*src),
+        Ins::Phi(dest, incoming) => {
+            let mut values = incoming
+                .iter()
+                .map(|(_, val)| *val)
+                .filter(|val| *val != RValue::Var(*dest));
+            let first = values.next()?;
+            values.all(|val| val == first).then_some(first)
+        }
+        _ => None,
+    }
+}
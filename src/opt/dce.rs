@@ -0,0 +1,118 @@
+//! Dead code elimination.
+//!
+//! [`Dce`] removes instructions whose result has no uses, and then the instructions
+//! that only those used. It can't see that values which only use each other, like the
+//! phis of a loop counter nobody reads, are dead. [`Adce`] assumes everything is dead
+//! until it is needed by an instruction with side effects or by a terminator, and also
+//! removes the blocks that can't be reached from the entry.
+
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::cfg::Cfg;
+use crate::ssa::defuse::Def;
+use crate::ssa::{BlockId, Function, RValue};
+use std::collections::HashSet;
+
+pub struct Dce;
+
+impl FunctionPass<Function> for Dce {
+    fn name(&self) -> &str {
+        "dce"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        let vars = func.supervisor().vars().clone();
+        let mut uses: Vec<usize> = vars.iter().map(|var| func.uses_of(*var).len()).collect();
+        let mut dead = HashSet::new();
+        let mut worklist: Vec<_> = vars.iter().filter(|var| uses[var.id()] == 0).collect();
+        while let Some(var) = worklist.pop() {
+            let Some(Def::Ins(block, i)) = func.def_of(*var) else {
+                continue;
+            };
+            let ins = &func.block(block).ins_list()[i];
+            if ins.has_side_effects() || !dead.insert((block, i)) {
+                continue;
+            }
+            for val in ins.operands() {
+                if let RValue::Var(operand) = val {
+                    uses[operand.id()] -= 1;
+                    if uses[operand.id()] == 0 {
+                        worklist.push(&vars[operand.id()]);
+                    }
+                }
+            }
+        }
+        match dead.is_empty() {
+            true => Preserved::All,
+            false => {
+                remove_ins(func, |block, i| dead.contains(&(block, i)));
+                Preserved::control_flow()
+            }
+        }
+    }
+}
+
+pub struct Adce;
+
+impl FunctionPass<Function> for Adce {
+    fn name(&self) -> &str {
+        "adce"
+    }
+
+    fn run(&mut self, func: &mut Function, analyses: &mut Analyses<Function>) -> Preserved {
+        if func.block_count() == 0 {
+            return Preserved::All;
+        }
+        let cfg = analyses.get::<Cfg>(func);
+        let reachable: HashSet<BlockId> = cfg.postorder(func.entry()).into_iter().collect();
+        let mut live = HashSet::new();
+        let mut worklist = Vec::new();
+        for &block in reachable.iter() {
+            let bb = func.block(block);
+            for (i, ins) in bb.ins_list().iter().enumerate() {
+                if ins.has_side_effects() {
+                    live.insert((block, i));
+                    worklist.extend(ins.operands());
+                }
+            }
+            worklist.extend(bb.terminator().into_iter().flat_map(|term| term.operands()));
+        }
+        while let Some(val) = worklist.pop() {
+            let RValue::Var(var) = val else {
+                continue;
+            };
+            if let Some(Def::Ins(block, i)) = func.def_of(var) {
+                if live.insert((block, i)) {
+                    worklist.extend(func.block(block).ins_list()[i].operands());
+                }
+            }
+        }
+
+        let unreachable = reachable.len() < func.block_count();
+        let dead = reachable.iter().any(|block| {
+            (0..func.block(*block).ins_list().len()).any(|i| !live.contains(&(*block, i)))
+        });
+        if dead {
+            remove_ins(func, |block, i| !live.contains(&(block, i)));
+        }
+        if unreachable {
+            func.retain_blocks(|block| reachable.contains(&block));
+        }
+        match (unreachable, dead) {
+            (true, _) => Preserved::none(),
+            (false, true) => Preserved::control_flow(),
+            (false, false) => Preserved::All,
+        }
+    }
+}
+
+/// Removes the instructions at the indices `dead` picks, by their indices before any
+/// was removed.
+fn remove_ins(func: &mut Function, dead: impl Fn(BlockId, usize) -> bool) {
+    for block in func.block_ids().collect::<Vec<_>>() {
+        let mut i = 0;
+        func.block_mut(block).ins_list.retain(|_| {
+            i += 1;
+            !dead(block, i - 1)
+        });
+    }
+}
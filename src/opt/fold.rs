@@ -0,0 +1,248 @@
+//! Evaluation of instructions whose operands are literals.
+//!
+//! Integers wrap like the machine does. Instructions whose result is undefined, like
+//! dividing by zero, dividing the smallest signed value by -1 or shifting by the bit
+//! width or more, and float to integer conversions that don't fit, are not folded, so
+//! they keep whatever they do at run time.
+//!
+//! [`ConstFold`] is the pass that folds the instructions whose operands are literals
+//! where they are, without following branches like SCCP does.
+
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::{CastTy, CmpTy, Function, Ins, Literal, RValue, Variable};
+use crate::typing::{Type, Typed};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Replaces every instruction whose operands are literals with its value, and then
+/// the instructions whose operands became literals, until none is left. Branches on
+/// the literals stay for other passes.
+pub struct ConstFold;
+
+impl FunctionPass<Function> for ConstFold {
+    fn name(&self) -> &str {
+        "constfold"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        let mut folded = HashSet::new();
+        loop {
+            let consts: Vec<(Variable, Literal)> = func
+                .blocks()
+                .flat_map(|(_, bb)| bb.ins_list())
+                .filter_map(|ins| Some((ins.dest()?, fold(ins, literal)?)))
+                .filter(|(dest, _)| !folded.contains(&dest.id()))
+                .collect();
+            if consts.is_empty() {
+                break;
+            }
+            for (dest, lit) in consts {
+                func.replace_all_uses_with(dest, lit);
+                folded.insert(dest.id());
+            }
+        }
+        if folded.is_empty() {
+            return Preserved::All;
+        }
+        for block in func.block_ids().collect::<Vec<_>>() {
+            func.block_mut(block)
+                .ins_list
+                .retain(|ins| !ins.dest().is_some_and(|dest| folded.contains(&dest.id())));
+        }
+        Preserved::control_flow()
+    }
+}
+
+fn literal(val: &RValue) -> Option<Literal> {
+    match val {
+        RValue::Lit(lit) => Some(*lit),
+        RValue::Var(..) => None,
+    }
+}
+
+/// The value `ins` computes when `value` knows every operand, or `None` if it can't be
+/// folded. Phis and instructions that access memory or call are never folded.
+pub fn fold(ins: &Ins, value: impl Fn(&RValue) -> Option<Literal>) -> Option<Literal> {
+    let dest = ins.dest()?;
+    let ty = dest.data_ty();
+    match ins {
+        Ins::Add(_, a, b)
+        | Ins::Sub(_, a, b)
+        | Ins::Mul(_, a, b)
+        | Ins::Div(_, a, b)
+        | Ins::SDiv(_, a, b)
+        | Ins::UDiv(_, a, b)
+        | Ins::SRem(_, a, b)
+        | Ins::URem(_, a, b)
+        | Ins::And(_, a, b)
+        | Ins::Or(_, a, b)
+        | Ins::Xor(_, a, b)
+        | Ins::Shl(_, a, b)
+        | Ins::LShr(_, a, b)
+        | Ins::AShr(_, a, b) => binop(ins, ty, value(a)?, value(b)?),
+        Ins::Not(_, a) => Some(Literal::from_bits(ty, !value(a)?.to_bits())),
+        Ins::Neg(_, a) if ty.is_integer() => {
+            Some(Literal::from_bits(ty, value(a)?.to_bits().wrapping_neg()))
+        }
+        Ins::Cpy(_, a) => value(a),
+        Ins::Cmp(_, pred, a, b) => cmp(*pred, value(a)?, value(b)?).map(Literal::Bool),
+        Ins::Cast(_, cast, a) => self::cast(*cast, value(a)?, ty),
+        Ins::Select(_, cond, a, b) => match value(cond)?.is_zero() {
+            true => value(b),
+            false => value(a),
+        },
+        _ => None,
+    }
+}
+
+fn binop(ins: &Ins, ty: Type, a: Literal, b: Literal) -> Option<Literal> {
+    if ty.is_float() {
+        return float_binop(ins, a, b);
+    }
+    let (x, y) = (a.to_bits(), b.to_bits());
+    let (sx, sy) = (sext(x, ty), sext(y, ty));
+    let bits = match ins {
+        Ins::Add(..) => x.wrapping_add(y),
+        Ins::Sub(..) => x.wrapping_sub(y),
+        Ins::Mul(..) => x.wrapping_mul(y),
+        Ins::UDiv(..) | Ins::URem(..) if y == 0 => return None,
+        Ins::UDiv(..) => x / y,
+        Ins::URem(..) => x % y,
+        Ins::SDiv(..) | Ins::SRem(..) if sy == 0 || (sx == min(ty) && sy == -1) => return None,
+        Ins::SDiv(..) => (sx / sy) as u64,
+        Ins::SRem(..) => (sx % sy) as u64,
+        Ins::And(..) => x & y,
+        Ins::Or(..) => x | y,
+        Ins::Xor(..) => x ^ y,
+        Ins::Shl(..) | Ins::LShr(..) | Ins::AShr(..) if y >= ty.bits() as u64 => return None,
+        Ins::Shl(..) => x << y,
+        Ins::LShr(..) => x >> y,
+        Ins::AShr(..) => (sx >> y) as u64,
+        _ => return None,
+    };
+    Some(Literal::from_bits(ty, bits))
+}
+
+fn float_binop(ins: &Ins, a: Literal, b: Literal) -> Option<Literal> {
+    match (a, b) {
+        (Literal::F32(x), Literal::F32(y)) => Some(Literal::F32(match ins {
+            Ins::Add(..) => x + y,
+            Ins::Sub(..) => x - y,
+            Ins::Mul(..) => x * y,
+            Ins::Div(..) => x / y,
+            _ => return None,
+        })),
+        (Literal::F64(x), Literal::F64(y)) => Some(Literal::F64(match ins {
+            Ins::Add(..) => x + y,
+            Ins::Sub(..) => x - y,
+            Ins::Mul(..) => x * y,
+            Ins::Div(..) => x / y,
+            _ => return None,
+        })),
+        _ => None,
+    }
+}
+
+/// Whether the comparison holds, `None` for a predicate that doesn't apply to the
+/// operands.
+fn cmp(pred: CmpTy, a: Literal, b: Literal) -> Option<bool> {
+    let ty = a.data_ty();
+    if pred.is_float() {
+        let ord = float_value(a)?.partial_cmp(&float_value(b)?);
+        return Some(match pred {
+            CmpTy::Feq => ord == Some(Ordering::Equal),
+            CmpTy::Fne => ord != Some(Ordering::Equal),
+            CmpTy::Flt => ord == Some(Ordering::Less),
+            CmpTy::Fle => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+            CmpTy::Fgt => ord == Some(Ordering::Greater),
+            CmpTy::Fge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+            CmpTy::Fult => matches!(ord, None | Some(Ordering::Less)),
+            CmpTy::Fule => ord != Some(Ordering::Greater),
+            CmpTy::Fugt => matches!(ord, None | Some(Ordering::Greater)),
+            CmpTy::Fuge => ord != Some(Ordering::Less),
+            _ => unreachable!("float predicate"),
+        });
+    }
+    if ty.is_float() {
+        return None;
+    }
+    let (x, y) = (a.to_bits(), b.to_bits());
+    let (sx, sy) = (sext(x, ty), sext(y, ty));
+    Some(match pred {
+        CmpTy::Eq => x == y,
+        CmpTy::Ne => x != y,
+        CmpTy::Slt => sx < sy,
+        CmpTy::Sle => sx <= sy,
+        CmpTy::Sgt => sx > sy,
+        CmpTy::Sge => sx >= sy,
+        CmpTy::Ult => x < y,
+        CmpTy::Ule => x <= y,
+        CmpTy::Ugt => x > y,
+        CmpTy::Uge => x >= y,
+        _ => unreachable!("integer predicate"),
+    })
+}
+
+fn cast(cast: CastTy, a: Literal, to: Type) -> Option<Literal> {
+    let from = a.data_ty();
+    if !cast.accepts(from, to) {
+        return None;
+    }
+    let x = a.to_bits();
+    match cast {
+        CastTy::SiToFp => Some(float_literal(
+            sext(x, from) as f64,
+            sext(x, from) as f32,
+            to,
+        )),
+        CastTy::UiToFp => Some(float_literal(x as f64, x as f32, to)),
+        CastTy::FpToSi => {
+            let val = float_value(a)?.trunc();
+            let max = -(min(to) as f64);
+            match val >= min(to) as f64 && val < max {
+                true => Some(Literal::from_bits(to, val as i64 as u64)),
+                false => None,
+            }
+        }
+        CastTy::FpToUi => {
+            let val = float_value(a)?.trunc();
+            let max = 2f64.powi(to.bits() as i32);
+            match val >= 0.0 && val < max {
+                true => Some(Literal::from_bits(to, val as u64)),
+                false => None,
+            }
+        }
+        CastTy::FpExt => Some(Literal::F64(float_value(a)?)),
+        CastTy::FpTrunc => Some(Literal::F32(float_value(a)? as f32)),
+        CastTy::SExt => Some(Literal::from_bits(to, sext(x, from) as u64)),
+        CastTy::ZExt | CastTy::Trunc | CastTy::BitCast => Some(Literal::from_bits(to, x)),
+    }
+}
+
+/// The bits of a value of type `ty`, sign extended from its width.
+fn sext(bits: u64, ty: Type) -> i64 {
+    let shift = 64 - ty.bits();
+    ((bits << shift) as i64) >> shift
+}
+
+/// The smallest signed value as wide as `ty`.
+fn min(ty: Type) -> i64 {
+    i64::MIN >> (64 - ty.bits())
+}
+
+fn float_value(lit: Literal) -> Option<f64> {
+    match lit {
+        Literal::F32(val) => Some(val as f64),
+        Literal::F64(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// Integers are converted to `f32` directly rather than through `f64`, which could
+/// round twice.
+fn float_literal(wide: f64, narrow: f32, ty: Type) -> Literal {
+    match ty {
+        Type::F32 => Literal::F32(narrow),
+        _ => Literal::F64(wide),
+    }
+}
@@ -0,0 +1,148 @@
+//! Global value numbering.
+//!
+//! Blocks are visited in preorder of the dominator tree, so the definitions that
+//! dominate an instruction are seen before it. Each instruction that computes its
+//! result from its operands alone is turned into an expression, and when an equal
+//! expression was already computed in a dominating block, or earlier in the same one,
+//! the result is replaced by that earlier value. Since the later results are rewritten
+//! as they are found, instructions that only use redundant values become redundant as
+//! well.
+//!
+//! Expressions are put in a canonical form first. Operands of commutative operations
+//! are ordered with values before literals, comparisons are turned around to match,
+//! and subtracting an integer literal is adding its negation.
+
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::dom::DomTree;
+use crate::ssa::{BlockId, CastTy, CmpTy, Function, Ins, Literal, RValue, Variable};
+use crate::typing::{MemType, Type, Typed};
+use std::collections::{HashMap, HashSet};
+use std::mem::Discriminant;
+
+/// What an instruction computes, without where it puts the result.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Expr {
+    op: Discriminant<Ins>,
+    detail: Detail,
+    operands: Vec<RValue>,
+    ty: Type,
+}
+
+/// The parts of an instruction besides its opcode and operands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Detail {
+    None,
+    Cmp(CmpTy),
+    Cast(CastTy),
+    Gep(MemType),
+    Addr(String),
+    /// Phis are only equal within their block, when they take the same values from
+    /// the same predecessors, which are listed in order.
+    Phi(BlockId, Vec<BlockId>),
+}
+
+impl Expr {
+    /// The expression `ins` computes in `block`, or `None` if it does more than that
+    /// or two of them may give different results.
+    fn of(ins: &Ins, block: BlockId) -> Option<Expr> {
+        let dest = ins.dest()?;
+        let ty = dest.data_ty();
+        let add;
+        let ins = match ins {
+            Ins::Sub(_, a, RValue::Lit(lit)) if ty.is_integer() => {
+                let negated = Literal::from_bits(ty, lit.to_bits().wrapping_neg());
+                add = Ins::Add(dest, *a, RValue::Lit(negated));
+                &add
+            }
+            _ => ins,
+        };
+        let mut detail = Detail::None;
+        let mut operands: Vec<RValue> = ins.operands().into_iter().copied().collect();
+        match ins {
+            Ins::Add(..) | Ins::Mul(..) | Ins::And(..) | Ins::Or(..) | Ins::Xor(..) => {
+                operands.sort_by_key(rank);
+            }
+            Ins::Cmp(_, pred, a, b) => {
+                let pred = match rank(b) < rank(a) {
+                    true => {
+                        operands = vec![*b, *a];
+                        pred.swapped()
+                    }
+                    false => *pred,
+                };
+                detail = Detail::Cmp(pred);
+            }
+            Ins::Cast(_, cast, _) => detail = Detail::Cast(*cast),
+            Ins::Gep(_, mem, ..) => detail = Detail::Gep(mem.clone()),
+            Ins::Addr(_, name) => detail = Detail::Addr(name.clone()),
+            Ins::Phi(_, incoming) => {
+                let mut incoming = incoming.clone();
+                incoming.sort_by_key(|(from, _)| *from);
+                operands = incoming.iter().map(|(_, val)| *val).collect();
+                detail = Detail::Phi(block, incoming.iter().map(|(from, _)| *from).collect());
+            }
+            Ins::Alloca(..) | Ins::Load(..) | Ins::Store(..) | Ins::Call(..) => return None,
+            _ => (),
+        }
+        Some(Expr {
+            op: std::mem::discriminant(ins),
+            detail,
+            operands,
+            ty,
+        })
+    }
+}
+
+/// Values come before literals, and each kind is ordered among itself, so swapping
+/// the operands of a commutative operation gives the same order.
+fn rank(val: &RValue) -> (u8, usize, u64) {
+    match val {
+        RValue::Var(var) => (0, var.id(), 0),
+        RValue::Lit(lit) => (1, lit.data_ty() as usize, lit.to_bits()),
+    }
+}
+
+pub struct Gvn;
+
+impl FunctionPass<Function> for Gvn {
+    fn name(&self) -> &str {
+        "gvn"
+    }
+
+    fn run(&mut self, func: &mut Function, analyses: &mut Analyses<Function>) -> Preserved {
+        if func.block_count() == 0 {
+            return Preserved::All;
+        }
+        let dom = analyses.get::<DomTree>(func);
+        let mut leaders: HashMap<Expr, Vec<(BlockId, Variable)>> = HashMap::new();
+        let mut removed = HashSet::new();
+        for block in dom.preorder() {
+            for i in 0..func.block(block).ins_list().len() {
+                let ins = &func.block(block).ins_list()[i];
+                let (Some(dest), Some(expr)) = (ins.dest(), Expr::of(ins, block)) else {
+                    continue;
+                };
+                let candidates = leaders.entry(expr).or_default();
+                match candidates
+                    .iter()
+                    .find(|(def, _)| dom.dominates(*def, block))
+                {
+                    Some((_, leader)) => {
+                        func.replace_all_uses_with(dest, *leader);
+                        removed.insert(dest.id());
+                    }
+                    None => candidates.push((block, dest)),
+                }
+            }
+        }
+        if removed.is_empty() {
+            return Preserved::All;
+        }
+        for block in func.block_ids().collect::<Vec<_>>() {
+            func.block_mut(block)
+                .ins_list
+                .retain(|ins| !ins.dest().is_some_and(|dest| removed.contains(&dest.id())));
+        }
+        Preserved::control_flow()
+    }
+}
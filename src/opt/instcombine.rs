@@ -0,0 +1,450 @@
+//! Instruction combining.
+//!
+//! Each instruction is matched against a table of [`Rule`]s in order, and the first
+//! rule that applies rewrites it, either into a simpler instruction with the same
+//! result or into a value that its uses take instead. Rules can look at the
+//! definitions of the operands, so `(x + 1) + 2` becomes `x + 3`. The function is
+//! gone over again until no rule applies anywhere.
+//!
+//! Rules on integers hold for every width and for signed and unsigned types alike,
+//! since integers wrap; the ones that depend on the signedness of an operation, like
+//! turning a division into a shift, only match the unsigned one. Float arithmetic is
+//! only folded when all of it is literal, since `x + 0.0` is not `x` for `x = -0.0`
+//! and `x - x` is not `0.0` for infinities.
+//!
+//! To add a rule, write a function that returns the [`Rewrite`] for the instructions
+//! it matches and put it in [`RULES`], or pass it to [`InstCombine::add_rule`]. To see
+//! which rules fire, give the pass a sink with [`InstCombine::debug`].
+
+use super::fold;
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::{BlockId, CmpTy, Function, Ins, Literal, RValue};
+use crate::typing::{Type, Typed};
+use std::collections::HashSet;
+use std::fmt;
+
+/// What a rule turns an instruction into.
+#[derive(Debug, Clone)]
+pub enum Rewrite {
+    /// The result is this value, and the instruction goes away.
+    Value(RValue),
+    /// This instruction, which has the same destination, takes the place of the old
+    /// one.
+    Ins(Ins),
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rewrite::Value(val) => write!(f, "{}", val),
+            Rewrite::Ins(ins) => write!(f, "{}", ins),
+        }
+    }
+}
+
+/// A rewrite made by a rule, as the pass reports it to its debug sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fired {
+    pub function: String,
+    pub block: BlockId,
+    pub rule: &'static str,
+    /// The instruction before the rewrite.
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for Fired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "@{}: {}: {}: {} => {}",
+            self.function, self.block, self.rule, self.before, self.after
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Rule {
+    pub name: &'static str,
+    /// The rewrite of an instruction of `func`, or `None` if the rule doesn't match.
+    pub apply: fn(&Ins, &Function) -> Option<Rewrite>,
+}
+
+/// The rules of the pass, tried in order.
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "fold",
+        apply: fold_literals,
+    },
+    Rule {
+        name: "literal-right",
+        apply: literal_right,
+    },
+    Rule {
+        name: "identity",
+        apply: identity,
+    },
+    Rule {
+        name: "absorb",
+        apply: absorb,
+    },
+    Rule {
+        name: "self-cancel",
+        apply: self_cancel,
+    },
+    Rule {
+        name: "self-cmp",
+        apply: self_cmp,
+    },
+    Rule {
+        name: "double-negation",
+        apply: double_negation,
+    },
+    Rule {
+        name: "sub-literal",
+        apply: sub_literal,
+    },
+    Rule {
+        name: "sub-neg",
+        apply: sub_neg,
+    },
+    Rule {
+        name: "reassociate",
+        apply: reassociate,
+    },
+    Rule {
+        name: "mul-pow2",
+        apply: mul_pow2,
+    },
+    Rule {
+        name: "udiv-pow2",
+        apply: udiv_pow2,
+    },
+    Rule {
+        name: "urem-pow2",
+        apply: urem_pow2,
+    },
+];
+
+pub struct InstCombine {
+    rules: Vec<Rule>,
+    debug: Option<Box<dyn FnMut(Fired)>>,
+}
+
+impl Default for InstCombine {
+    fn default() -> Self {
+        InstCombine {
+            rules: RULES.to_vec(),
+            debug: None,
+        }
+    }
+}
+
+impl InstCombine {
+    pub fn new() -> InstCombine {
+        Self::default()
+    }
+
+    /// Hands every rewrite to `sink`, with the rule that made it.
+    pub fn debug(mut self, sink: impl FnMut(Fired) + 'static) -> InstCombine {
+        self.debug = Some(Box::new(sink));
+        self
+    }
+
+    /// Adds a rule, tried after the others.
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+}
+
+impl FunctionPass<Function> for InstCombine {
+    fn name(&self) -> &str {
+        match self.debug {
+            Some(_) => "instcombine-debug",
+            None => "instcombine",
+        }
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        let mut removed = HashSet::new();
+        let mut changed = false;
+        loop {
+            let mut fired = false;
+            for block in func.block_ids().collect::<Vec<_>>() {
+                for i in 0..func.block(block).ins_list().len() {
+                    let ins = &func.block(block).ins_list()[i];
+                    let Some(dest) = ins.dest() else {
+                        continue;
+                    };
+                    if ins.is_phi() || removed.contains(&dest.id()) {
+                        continue;
+                    }
+                    let Some((rule, rewrite)) = self
+                        .rules
+                        .iter()
+                        .find_map(|rule| Some((rule.name, (rule.apply)(ins, func)?)))
+                    else {
+                        continue;
+                    };
+                    if let Some(sink) = &mut self.debug {
+                        sink(Fired {
+                            function: func.name().to_string(),
+                            block,
+                            rule,
+                            before: ins.to_string(),
+                            after: rewrite.to_string(),
+                        });
+                    }
+                    match rewrite {
+                        Rewrite::Value(val) => {
+                            func.replace_all_uses_with(dest, val);
+                            removed.insert(dest.id());
+                        }
+                        Rewrite::Ins(new) => {
+                            assert_eq!(new.dest(), Some(dest), "{} keeps the destination", rule);
+                            func.replace_ins(block, i, new);
+                        }
+                    }
+                    fired = true;
+                }
+            }
+            if !fired {
+                break;
+            }
+            changed = true;
+        }
+        if !changed {
+            return Preserved::All;
+        }
+        for block in func.block_ids().collect::<Vec<_>>() {
+            func.block_mut(block)
+                .ins_list
+                .retain(|ins| !ins.dest().is_some_and(|dest| removed.contains(&dest.id())));
+        }
+        Preserved::control_flow()
+    }
+}
+
+fn lit(val: &RValue) -> Option<Literal> {
+    match val {
+        RValue::Lit(lit) => Some(*lit),
+        RValue::Var(..) => None,
+    }
+}
+
+/// The integer literal in `val` with the bits `bits`, at its width.
+fn is_bits(val: &RValue, bits: u64) -> bool {
+    lit(val).is_some_and(|lit| {
+        let ty = lit.data_ty();
+        !ty.is_float() && lit == Literal::from_bits(ty, bits)
+    })
+}
+
+/// The instruction that defines `val`, if it is a value defined by one.
+fn def<'f>(func: &'f Function, val: &RValue) -> Option<&'f Ins> {
+    match val {
+        RValue::Var(var) => func.def_ins(*var),
+        RValue::Lit(..) => None,
+    }
+}
+
+fn int_ty(ins: &Ins) -> Option<Type> {
+    Some(ins.dest()?.data_ty()).filter(|ty| ty.is_integer())
+}
+
+/// `k` when `val` is the integer literal `2^k`.
+fn log2(val: &RValue) -> Option<u64> {
+    let bits = lit(val).filter(|lit| lit.data_ty().is_integer())?.to_bits();
+    bits.is_power_of_two().then(|| bits.trailing_zeros() as u64)
+}
+
+/// An instruction whose operands are all literals is replaced by its result, unless
+/// it is one that [`fold`] leaves alone.
+fn fold_literals(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let lit = fold::fold(ins, lit)?;
+    Some(Rewrite::Value(RValue::Lit(lit)))
+}
+
+/// `c op x` becomes `x op c` for commutative `op`, so the other rules only look for
+/// literals on the right.
+fn literal_right(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let swapped = match ins {
+        Ins::Add(dest, a @ RValue::Lit(..), b @ RValue::Var(..)) => Ins::Add(*dest, *b, *a),
+        Ins::Mul(dest, a @ RValue::Lit(..), b @ RValue::Var(..)) => Ins::Mul(*dest, *b, *a),
+        Ins::And(dest, a @ RValue::Lit(..), b @ RValue::Var(..)) => Ins::And(*dest, *b, *a),
+        Ins::Or(dest, a @ RValue::Lit(..), b @ RValue::Var(..)) => Ins::Or(*dest, *b, *a),
+        Ins::Xor(dest, a @ RValue::Lit(..), b @ RValue::Var(..)) => Ins::Xor(*dest, *b, *a),
+        Ins::Cmp(dest, pred, a @ RValue::Lit(..), b @ RValue::Var(..)) => {
+            Ins::Cmp(*dest, pred.swapped(), *b, *a)
+        }
+        _ => return None,
+    };
+    Some(Rewrite::Ins(swapped))
+}
+
+/// `x + 0`, `x - 0`, `x * 1`, `x | 0`, `x ^ 0`, `x & -1` and shifts by 0 are `x`.
+fn identity(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let x = match ins {
+        Ins::Add(_, x, c)
+        | Ins::Sub(_, x, c)
+        | Ins::Or(_, x, c)
+        | Ins::Xor(_, x, c)
+        | Ins::Shl(_, x, c)
+        | Ins::LShr(_, x, c)
+        | Ins::AShr(_, x, c)
+            if is_bits(c, 0) =>
+        {
+            x
+        }
+        Ins::Mul(_, x, c) | Ins::SDiv(_, x, c) | Ins::UDiv(_, x, c) if is_bits(c, 1) => x,
+        Ins::And(_, x, c) if is_bits(c, u64::MAX) => x,
+        Ins::And(_, x, y) | Ins::Or(_, x, y) if x == y => x,
+        _ => return None,
+    };
+    Some(Rewrite::Value(*x))
+}
+
+/// `x * 0` and `x & 0` are 0, `x | -1` is -1.
+fn absorb(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let c = match ins {
+        Ins::Mul(_, _, c) | Ins::And(_, _, c) if is_bits(c, 0) => c,
+        Ins::Or(_, _, c) if is_bits(c, u64::MAX) => c,
+        _ => return None,
+    };
+    Some(Rewrite::Value(*c))
+}
+
+/// `x - x` and `x ^ x` are 0.
+fn self_cancel(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let ty = int_ty(ins)?;
+    match ins {
+        Ins::Sub(_, a, b) | Ins::Xor(_, a, b) if a == b => {
+            Some(Rewrite::Value(RValue::Lit(Literal::zero(ty))))
+        }
+        _ => None,
+    }
+}
+
+/// An integer compared with itself.
+fn self_cmp(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let Ins::Cmp(_, pred, a, b) = ins else {
+        return None;
+    };
+    if a != b || pred.is_float() {
+        return None;
+    }
+    let holds = matches!(
+        pred,
+        CmpTy::Eq | CmpTy::Sle | CmpTy::Sge | CmpTy::Ule | CmpTy::Uge
+    );
+    Some(Rewrite::Value(RValue::Lit(Literal::Bool(holds))))
+}
+
+/// `-(-x)` and `~(~x)` are `x`.
+fn double_negation(ins: &Ins, func: &Function) -> Option<Rewrite> {
+    match ins {
+        Ins::Neg(_, y) => match def(func, y)? {
+            Ins::Neg(_, x) => Some(Rewrite::Value(*x)),
+            _ => None,
+        },
+        Ins::Not(_, y) => match def(func, y)? {
+            Ins::Not(_, x) => Some(Rewrite::Value(*x)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `x - c` becomes `x + -c`, which [`reassociate`] combines with other additions.
+fn sub_literal(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let ty = int_ty(ins)?;
+    let Ins::Sub(dest, x @ RValue::Var(..), RValue::Lit(c)) = ins else {
+        return None;
+    };
+    let negated = Literal::from_bits(ty, c.to_bits().wrapping_neg());
+    Some(Rewrite::Ins(Ins::Add(*dest, *x, RValue::Lit(negated))))
+}
+
+/// `x - (-y)` is `x + y`.
+fn sub_neg(ins: &Ins, func: &Function) -> Option<Rewrite> {
+    let Ins::Sub(dest, x, y) = ins else {
+        return None;
+    };
+    match def(func, y) {
+        Some(Ins::Neg(_, y)) => Some(Rewrite::Ins(Ins::Add(*dest, *x, *y))),
+        _ => None,
+    }
+}
+
+/// `(x op c1) op c2` is `x op (c1 op c2)` for integer `+`, `*`, `&`, `|` and `^`.
+fn reassociate(ins: &Ins, func: &Function) -> Option<Rewrite> {
+    int_ty(ins)?;
+    let (inner, c2) = match ins {
+        Ins::Add(_, a, c @ RValue::Lit(..))
+        | Ins::Mul(_, a, c @ RValue::Lit(..))
+        | Ins::And(_, a, c @ RValue::Lit(..))
+        | Ins::Or(_, a, c @ RValue::Lit(..))
+        | Ins::Xor(_, a, c @ RValue::Lit(..)) => (def(func, a)?, c),
+        _ => return None,
+    };
+    let (x, c1) = match inner {
+        Ins::Add(_, x, c @ RValue::Lit(..))
+        | Ins::Mul(_, x, c @ RValue::Lit(..))
+        | Ins::And(_, x, c @ RValue::Lit(..))
+        | Ins::Or(_, x, c @ RValue::Lit(..))
+        | Ins::Xor(_, x, c @ RValue::Lit(..))
+            if std::mem::discriminant(inner) == std::mem::discriminant(ins) =>
+        {
+            (x, c)
+        }
+        _ => return None,
+    };
+    let mut combined = ins.clone();
+    for (operand, val) in combined.operands_mut().into_iter().zip([*c1, *c2]) {
+        *operand = val;
+    }
+    let c = fold::fold(&combined, self::lit)?;
+    for (operand, val) in combined
+        .operands_mut()
+        .into_iter()
+        .zip([*x, RValue::Lit(c)])
+    {
+        *operand = val;
+    }
+    Some(Rewrite::Ins(combined))
+}
+
+/// `x * 2^k` is `x << k`.
+fn mul_pow2(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let ty = int_ty(ins)?;
+    let Ins::Mul(dest, x, c) = ins else {
+        return None;
+    };
+    let k = log2(c).filter(|k| *k > 0)?;
+    let k = RValue::Lit(Literal::from_bits(ty, k));
+    Some(Rewrite::Ins(Ins::Shl(*dest, *x, k)))
+}
+
+/// Unsigned `x / 2^k` is `x >> k`. A signed division rounds towards zero, which a
+/// shift doesn't for negative `x`.
+fn udiv_pow2(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let ty = int_ty(ins)?;
+    let Ins::UDiv(dest, x, c) = ins else {
+        return None;
+    };
+    let k = log2(c).filter(|k| *k > 0)?;
+    let k = RValue::Lit(Literal::from_bits(ty, k));
+    Some(Rewrite::Ins(Ins::LShr(*dest, *x, k)))
+}
+
+/// Unsigned `x % 2^k` is `x & (2^k - 1)`.
+fn urem_pow2(ins: &Ins, _: &Function) -> Option<Rewrite> {
+    let ty = int_ty(ins)?;
+    let Ins::URem(dest, x, c) = ins else {
+        return None;
+    };
+    log2(c)?;
+    let mask = lit(c)?.to_bits() - 1;
+    let mask = RValue::Lit(Literal::from_bits(ty, mask));
+    Some(Rewrite::Ins(Ins::And(*dest, *x, mask)))
+}
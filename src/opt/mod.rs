@@ -0,0 +1,52 @@
+//! Optimizations of SSA functions, and the registry that names them for pipelines.
+
+pub mod copyprop;
+pub mod dce;
+pub mod fold;
+pub mod gvn;
+pub mod instcombine;
+pub mod sccp;
+pub mod simplifycfg;
+
+use crate::pass::{Analyses, FunctionPass, PassRegistry, Preserved};
+use crate::ssa;
+
+/// Every SSA pass of the crate under its pipeline name.
+pub fn registry() -> PassRegistry<ssa::Module> {
+    let mut registry = PassRegistry::new();
+    registry.register_function_pass("verify", || Verify);
+    registry.register_function_pass("constfold", || fold::ConstFold);
+    registry.register_function_pass("sccp", || sccp::Sccp);
+    registry.register_function_pass("dce", || dce::Dce);
+    registry.register_function_pass("adce", || dce::Adce);
+    registry.register_function_pass("copyprop", || copyprop::CopyProp);
+    registry.register_function_pass("gvn", || gvn::Gvn);
+    registry.register_function_pass("simplifycfg", || simplifycfg::SimplifyCfg);
+    registry.register_function_pass("instcombine", instcombine::InstCombine::new);
+    registry.register_function_pass("instcombine-debug", || {
+        instcombine::InstCombine::new().debug(|fired| eprintln!("{}", fired))
+    });
+    registry
+}
+
+/// Checks the function with [`ssa::verify::verify`] and panics if it is malformed.
+pub struct Verify;
+
+impl FunctionPass<ssa::Function> for Verify {
+    fn name(&self) -> &str {
+        "verify"
+    }
+
+    fn run(&mut self, func: &mut ssa::Function, _: &mut Analyses<ssa::Function>) -> Preserved {
+        if let Err(errors) = ssa::verify::verify(func) {
+            let list: Vec<String> = errors.iter().map(|err| format!("  {}", err)).collect();
+            panic!(
+                "@{} is malformed:\n{}\n{}",
+                func.name(),
+                list.join("\n"),
+                func
+            );
+        }
+        Preserved::All
+    }
+}
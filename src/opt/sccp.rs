@@ -0,0 +1,221 @@
+//! Sparse conditional constant propagation.
+//!
+//! Values start out unknown and only ever move down to a constant and then to
+//! varying. Only blocks that can be reached are looked at, following a branch only to
+//! the targets its condition allows, and a phi only takes the values that come in
+//! along edges that can be taken. Once nothing changes, every value that is a constant
+//! is replaced by it and its definition removed, and branches on constants become
+//! jumps. Blocks that can't be reached are left for other passes to remove.
+
+use super::fold;
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::defuse::Use;
+use crate::ssa::{BlockId, Function, Ins, Literal, RValue, Terminator, Variable};
+use std::collections::HashSet;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Value {
+    /// Nothing that can be reached defines the value yet.
+    Unknown,
+    Const(Literal),
+    Varying,
+}
+
+impl Value {
+    fn meet(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Unknown, val) | (val, Value::Unknown) => val,
+            (Value::Const(a), Value::Const(b)) if a == b => self,
+            _ => Value::Varying,
+        }
+    }
+}
+
+struct Solver<'f> {
+    func: &'f Function,
+    values: Vec<Value>,
+    reachable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+    /// Edges found to be taken that are not looked at yet.
+    flow: Vec<(BlockId, BlockId)>,
+    /// Values that changed whose uses are not looked at yet.
+    changed: Vec<Variable>,
+}
+
+impl<'f> Solver<'f> {
+    fn solve(func: &'f Function) -> Solver<'f> {
+        let mut solver = Solver {
+            func,
+            values: vec![Value::Unknown; func.supervisor().vars().len()],
+            reachable: vec![false; func.block_count()],
+            edges: HashSet::new(),
+            flow: Vec::new(),
+            changed: Vec::new(),
+        };
+        for param in func.params() {
+            solver.values[param.id()] = Value::Varying;
+        }
+        solver.reach(func.entry());
+        loop {
+            if let Some((from, to)) = solver.flow.pop() {
+                if !solver.edges.insert((from, to)) {
+                    continue;
+                }
+                match solver.reachable[to.0] {
+                    true => solver.visit_phis(to),
+                    false => solver.reach(to),
+                }
+            } else if let Some(var) = solver.changed.pop() {
+                for site in func.uses_of(var) {
+                    if !solver.reachable[site.block().0] {
+                        continue;
+                    }
+                    match *site {
+                        Use::Ins(block, i) => solver.visit_ins(block, i),
+                        Use::Term(block) => solver.visit_term(block),
+                    }
+                }
+            } else {
+                return solver;
+            }
+        }
+    }
+
+    fn reach(&mut self, block: BlockId) {
+        self.reachable[block.0] = true;
+        for i in 0..self.func.block(block).ins_list().len() {
+            self.visit_ins(block, i);
+        }
+        self.visit_term(block);
+    }
+
+    fn visit_phis(&mut self, block: BlockId) {
+        for i in 0..self.func.block(block).phis().count() {
+            self.visit_ins(block, i);
+        }
+    }
+
+    fn value_of(&self, val: &RValue) -> Value {
+        match val {
+            RValue::Lit(lit) => Value::Const(*lit),
+            RValue::Var(var) => self.values[var.id()],
+        }
+    }
+
+    fn visit_ins(&mut self, block: BlockId, i: usize) {
+        let ins = &self.func.block(block).ins_list()[i];
+        let Some(dest) = ins.dest() else {
+            return;
+        };
+        let val = match ins {
+            Ins::Phi(_, incoming) => incoming
+                .iter()
+                .filter(|(from, _)| self.edges.contains(&(*from, block)))
+                .fold(Value::Unknown, |acc, (_, val)| acc.meet(self.value_of(val))),
+            _ => {
+                let operands: Vec<Value> = ins
+                    .operands()
+                    .into_iter()
+                    .map(|val| self.value_of(val))
+                    .collect();
+                if operands.contains(&Value::Varying) {
+                    Value::Varying
+                } else if operands.contains(&Value::Unknown) {
+                    Value::Unknown
+                } else {
+                    let lit = fold::fold(ins, |val| match self.value_of(val) {
+                        Value::Const(lit) => Some(lit),
+                        _ => None,
+                    });
+                    lit.map_or(Value::Varying, Value::Const)
+                }
+            }
+        };
+        let old = self.values[dest.id()];
+        let new = old.meet(val);
+        if new != old {
+            self.values[dest.id()] = new;
+            self.changed.push(dest);
+        }
+    }
+
+    fn visit_term(&mut self, block: BlockId) {
+        match self.func.block(block).terminator() {
+            Some(Terminator::Jmp(target)) => self.flow.push((block, *target)),
+            Some(Terminator::Br(cond, then, els)) => match self.value_of(cond) {
+                Value::Unknown => (),
+                Value::Const(lit) => match lit.is_zero() {
+                    true => self.flow.push((block, *els)),
+                    false => self.flow.push((block, *then)),
+                },
+                Value::Varying => {
+                    self.flow.push((block, *then));
+                    self.flow.push((block, *els));
+                }
+            },
+            Some(Terminator::Ret(..)) | None => (),
+        }
+    }
+}
+
+pub struct Sccp;
+
+impl FunctionPass<Function> for Sccp {
+    fn name(&self) -> &str {
+        "sccp"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        if func.block_count() == 0 {
+            return Preserved::All;
+        }
+        let solver = Solver::solve(func);
+        let reachable = solver.reachable;
+        let consts: Vec<(Variable, Literal)> = solver
+            .values
+            .iter()
+            .enumerate()
+            .filter_map(|(id, val)| match val {
+                Value::Const(lit) => Some((func.supervisor().vars()[id], *lit)),
+                _ => None,
+            })
+            .collect();
+
+        for (var, lit) in consts.iter() {
+            func.replace_all_uses_with(*var, *lit);
+        }
+        let folded: HashSet<usize> = consts.iter().map(|(var, _)| var.id()).collect();
+        for block in func.block_ids().collect::<Vec<_>>() {
+            func.block_mut(block)
+                .ins_list
+                .retain(|ins| !ins.dest().is_some_and(|dest| folded.contains(&dest.id())));
+        }
+
+        let mut branches_folded = false;
+        for block in func
+            .block_ids()
+            .filter(|id| reachable[id.0])
+            .collect::<Vec<_>>()
+        {
+            let Some(Terminator::Br(RValue::Lit(cond), then, els)) = func.block(block).terminator()
+            else {
+                continue;
+            };
+            let (taken, dropped) = match cond.is_zero() {
+                true => (*els, *then),
+                false => (*then, *els),
+            };
+            if taken != dropped {
+                func.remove_incoming(dropped, block);
+            }
+            func.block_mut(block).terminator = Some(Terminator::Jmp(taken));
+            branches_folded = true;
+        }
+
+        match (branches_folded, consts.is_empty()) {
+            (true, _) => Preserved::none(),
+            (false, false) => Preserved::control_flow(),
+            (false, true) => Preserved::All,
+        }
+    }
+}
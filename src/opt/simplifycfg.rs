@@ -0,0 +1,353 @@
+//! Control flow simplification.
+//!
+//! Blocks that can't be reached are removed, and branches on constants, or to the
+//! same block either way, become jumps. A block whose only predecessor jumps to it is
+//! merged into that predecessor, and an empty block that only jumps on is skipped by
+//! the blocks that branch to it. A diamond, or a triangle, whose arms only compute a
+//! few values without side effects becomes those values and selects on the condition.
+//! A predecessor of a block that does nothing but branch on a phi, along an edge where
+//! the phi is a constant, jumps straight to where the branch would go.
+//!
+//! Each change can enable the others, so they are made one at a time until none
+//! applies.
+
+use crate::pass::{Analyses, FunctionPass, Preserved};
+use crate::ssa::cfg::Cfg;
+use crate::ssa::defuse::Use;
+use crate::ssa::{BlockId, Function, Ins, RValue, Terminator, Variable};
+use crate::typing::Typed;
+
+/// How many instructions an arm of a diamond may have to be run unconditionally.
+const MAX_SPECULATED: usize = 2;
+
+pub struct SimplifyCfg;
+
+impl FunctionPass<Function> for SimplifyCfg {
+    fn name(&self) -> &str {
+        "simplifycfg"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        if func.block_count() == 0 {
+            return Preserved::All;
+        }
+        let mut changed = false;
+        while remove_unreachable(func)
+            || fold_branches(func)
+            || merge_into_predecessor(func)
+            || skip_forwarding_block(func)
+            || thread_jump(func)
+            || diamond_to_select(func)
+        {
+            changed = true;
+        }
+        match changed {
+            true => Preserved::none(),
+            false => Preserved::All,
+        }
+    }
+}
+
+fn remove_unreachable(func: &mut Function) -> bool {
+    let mut reachable = vec![false; func.block_count()];
+    for block in func.cfg().postorder(func.entry()) {
+        reachable[block.0] = true;
+    }
+    if reachable.iter().all(|reachable| *reachable) {
+        return false;
+    }
+    func.retain_blocks(|block| reachable[block.0]);
+    true
+}
+
+fn fold_branches(func: &mut Function) -> bool {
+    let mut folded = false;
+    for block in func.block_ids().collect::<Vec<_>>() {
+        let Some(term @ Terminator::Br(_, then, els)) = func.block(block).terminator() else {
+            continue;
+        };
+        let Some(taken) = term.known_target() else {
+            continue;
+        };
+        for dropped in [*then, *els] {
+            if dropped != taken {
+                func.remove_incoming(dropped, block);
+            }
+        }
+        func.block_mut(block).terminator = Some(Terminator::Jmp(taken));
+        folded = true;
+    }
+    folded
+}
+
+/// Moves a block into its only predecessor when that jumps to it. The block is left
+/// empty and unreachable.
+fn merge_into_predecessor(func: &mut Function) -> bool {
+    let cfg = func.cfg();
+    for block in func.block_ids().skip(1) {
+        let &[pred] = cfg.predecessors(block) else {
+            continue;
+        };
+        if pred == block || !matches!(func.block(pred).terminator(), Some(Terminator::Jmp(_))) {
+            continue;
+        }
+        // With one predecessor, a phi is the value it takes from there.
+        let phis: Vec<_> = func
+            .block(block)
+            .phis()
+            .filter_map(|phi| match phi {
+                Ins::Phi(dest, incoming) => Some((*dest, incoming[0].1)),
+                _ => None,
+            })
+            .collect();
+        for (dest, val) in phis {
+            func.replace_all_uses_with(dest, val);
+        }
+        let bb = func.block_mut(block);
+        let ins_list = std::mem::take(&mut bb.ins_list);
+        let term = bb.terminator.take().expect("block is terminated");
+        for succ in term.successors() {
+            rename_incoming(func, succ, block, pred);
+        }
+        let pred_bb = func.block_mut(pred);
+        pred_bb
+            .ins_list
+            .extend(ins_list.into_iter().filter(|ins| !ins.is_phi()));
+        pred_bb.terminator = Some(term);
+        return true;
+    }
+    false
+}
+
+/// Sends the predecessors of an empty block that only jumps on to its target
+/// directly, unless one of them already branches there with other values for the
+/// target's phis.
+fn skip_forwarding_block(func: &mut Function) -> bool {
+    let cfg = func.cfg();
+    for block in func.block_ids().skip(1) {
+        let bb = func.block(block);
+        let Some(&Terminator::Jmp(target)) = bb.terminator() else {
+            continue;
+        };
+        let preds = cfg.predecessors(block);
+        if target == block || !bb.ins_list().is_empty() || preds.is_empty() {
+            continue;
+        }
+        let values = phi_values(func, target, block);
+        if !can_add_edges(func, &cfg, preds, target, &values) {
+            continue;
+        }
+        func.remove_incoming(target, block);
+        for &pred in preds {
+            retarget(func, &cfg, pred, block, target, &values);
+        }
+        return true;
+    }
+    false
+}
+
+/// Replaces a branch whose arms only compute a few values and meet again with those
+/// values and selects for the phis where they meet.
+fn diamond_to_select(func: &mut Function) -> bool {
+    let cfg = func.cfg();
+    for block in func.block_ids() {
+        let Some(&Terminator::Br(cond, then, els)) = func.block(block).terminator() else {
+            continue;
+        };
+        if then == els {
+            continue;
+        }
+        let (then_edge, els_edge, join) = match (
+            speculated_arm(func, &cfg, block, then),
+            speculated_arm(func, &cfg, block, els),
+        ) {
+            (Some(a), Some(b)) if a == b => (then, els, a),
+            (Some(join), _) if join == els => (then, block, els),
+            (_, Some(join)) if join == then => (block, els, then),
+            _ => continue,
+        };
+        let selectable = func
+            .block(join)
+            .phis()
+            .all(|phi| phi.dest().is_some_and(|dest| !dest.data_ty().is_float()));
+        if join == block || !selectable {
+            continue;
+        }
+        let then_values = phi_values(func, join, then_edge);
+        let els_values = phi_values(func, join, els_edge);
+        let mut speculated = Vec::new();
+        for arm in [then_edge, els_edge] {
+            if arm != block {
+                speculated.append(&mut func.block_mut(arm).ins_list);
+            }
+        }
+        let bb = func.block_mut(block);
+        bb.terminator = None;
+        bb.ins_list.append(&mut speculated);
+        let mut emitter = func.emitter(block);
+        let values: Vec<RValue> = then_values
+            .into_iter()
+            .zip(els_values)
+            .map(|(a, b)| match a == b {
+                true => a,
+                false => emitter.emit_select(cond, a, b).into(),
+            })
+            .collect();
+        emitter.emit_jmp(join);
+        func.remove_incoming(join, then_edge);
+        func.remove_incoming(join, els_edge);
+        add_incoming(func, join, block, values);
+        return true;
+    }
+    false
+}
+
+/// The block `arm` jumps to, if its only predecessor is `block` and all it does is
+/// compute a few values that are safe to compute on the other path too.
+fn speculated_arm(func: &Function, cfg: &Cfg, block: BlockId, arm: BlockId) -> Option<BlockId> {
+    let bb = func.block(arm);
+    let Some(&Terminator::Jmp(join)) = bb.terminator() else {
+        return None;
+    };
+    let speculatable = bb.ins_list().len() <= MAX_SPECULATED
+        && bb.ins_list().iter().all(|ins| {
+            !ins.has_side_effects()
+                && !matches!(
+                    ins,
+                    Ins::Phi(..) | Ins::Load(..) | Ins::Alloca(..) | Ins::Call(..)
+                )
+        });
+    (cfg.predecessors(arm) == [block] && arm != block && join != arm && speculatable)
+        .then_some(join)
+}
+
+/// Finds a block that is only phis and a branch on one of them, and sends a
+/// predecessor along whose edge the condition is a constant to where the branch goes
+/// for it. The phis may only be used by the branch and by the phis of the successors,
+/// which then take what the phis took from the predecessor.
+fn thread_jump(func: &mut Function) -> bool {
+    let cfg = func.cfg();
+    for block in func.block_ids() {
+        let bb = func.block(block);
+        let Some(&Terminator::Br(RValue::Var(cond), then, els)) = bb.terminator() else {
+            continue;
+        };
+        let phis: Vec<Variable> = bb.phis().filter_map(Ins::dest).collect();
+        if phis.len() != bb.ins_list().len() || !phis.contains(&cond) || !phis_flow_on(func, block)
+        {
+            continue;
+        }
+        for &pred in cfg.predecessors(block) {
+            let incoming = phi_values(func, block, pred);
+            let through = |val: RValue| match phis.iter().position(|phi| val == RValue::Var(*phi)) {
+                Some(i) => incoming[i],
+                None => val,
+            };
+            let RValue::Lit(lit) = through(RValue::Var(cond)) else {
+                continue;
+            };
+            let target = match lit.is_zero() {
+                true => els,
+                false => then,
+            };
+            if pred == block || target == block {
+                continue;
+            }
+            let values: Vec<RValue> = phi_values(func, target, block)
+                .into_iter()
+                .map(through)
+                .collect();
+            if !can_add_edges(func, &cfg, &[pred], target, &values) {
+                continue;
+            }
+            func.remove_incoming(block, pred);
+            retarget(func, &cfg, pred, block, target, &values);
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether the phis of `block` are only used by its terminator and by what phis take
+/// from it.
+fn phis_flow_on(func: &Function, block: BlockId) -> bool {
+    let mut phis = func.block(block).phis().filter_map(Ins::dest);
+    phis.all(|phi| {
+        func.uses_of(phi).iter().all(|site| match site {
+            Use::Term(user) => *user == block,
+            Use::Ins(user, i) => match &func.block(*user).ins_list()[*i] {
+                Ins::Phi(_, incoming) => incoming
+                    .iter()
+                    .all(|(from, val)| *from == block || *val != RValue::Var(phi)),
+                _ => false,
+            },
+        })
+    })
+}
+
+/// What each phi of `block` takes from `pred`, in order.
+fn phi_values(func: &Function, block: BlockId, pred: BlockId) -> Vec<RValue> {
+    func.block(block)
+        .phis()
+        .filter_map(|phi| match phi {
+            Ins::Phi(_, incoming) => incoming
+                .iter()
+                .find(|(from, _)| *from == pred)
+                .map(|(_, val)| *val),
+            _ => None,
+        })
+        .collect()
+}
+
+fn add_incoming(func: &mut Function, block: BlockId, pred: BlockId, values: Vec<RValue>) {
+    let phis = func.block_mut(block).ins_list.iter_mut();
+    for (ins, val) in phis.zip(values) {
+        if let Ins::Phi(_, incoming) = ins {
+            incoming.push((pred, val));
+        }
+    }
+}
+
+fn rename_incoming(func: &mut Function, block: BlockId, old: BlockId, new: BlockId) {
+    for ins in func.block_mut(block).ins_list.iter_mut() {
+        if let Ins::Phi(_, incoming) = ins {
+            for (from, _) in incoming.iter_mut() {
+                if *from == old {
+                    *from = new;
+                }
+            }
+        }
+    }
+}
+
+/// Whether each of `preds` can branch to `target` with `values` for its phis, which
+/// those already branching there must agree with.
+fn can_add_edges(
+    func: &Function,
+    cfg: &Cfg,
+    preds: &[BlockId],
+    target: BlockId,
+    values: &[RValue],
+) -> bool {
+    preds.iter().all(|pred| {
+        !cfg.predecessors(target).contains(pred) || phi_values(func, target, *pred) == values
+    })
+}
+
+/// Makes `pred` branch to `target` instead of `old`, with `values` for the phis of
+/// `target` unless it already branched there.
+fn retarget(
+    func: &mut Function,
+    cfg: &Cfg,
+    pred: BlockId,
+    old: BlockId,
+    target: BlockId,
+    values: &[RValue],
+) {
+    if let Some(term) = &mut func.block_mut(pred).terminator {
+        term.replace_target(old, target);
+    }
+    if !cfg.predecessors(target).contains(&pred) {
+        add_incoming(func, target, pred, values.to_vec());
+    }
+}
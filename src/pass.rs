@@ -0,0 +1,375 @@
+//! Running passes over the functions of a module.
+//!
+//! A [`PassManager`] holds a pipeline of function and module passes. It works for any
+//! IR whose module implements [`PassModule`], which both [`ssa::Module`] and
+//! [`rtl::Module`] do. Passes are looked up by name in a [`PassRegistry`], so a
+//! pipeline can also be written as a string like `"constfold,dce,copyprop"`. The SSA
+//! passes are registered in [`crate::opt::registry`], and the RTL passes that allocate
+//! registers in [`crate::compile::registry`].
+//!
+//! Passes ask for analyses of a function through [`Analyses`], which computes each
+//! [`Analysis`] once and keeps it until a pass changes the function in a way that
+//! the analysis doesn't survive. Every pass says which analyses it [`Preserved`].
+
+use crate::compile::ralloc;
+use crate::{rtl, ssa};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A module of some IR, as far as passes are concerned.
+pub trait PassModule {
+    type Function;
+
+    fn functions_mut(&mut self) -> &mut [Self::Function];
+
+    /// Panics in debug builds if `func` is malformed, naming `after` as what last
+    /// changed it. IRs without a verifier check nothing.
+    fn debug_verify(_func: &Self::Function, _after: &str) {}
+}
+
+impl PassModule for ssa::Module {
+    type Function = ssa::Function;
+
+    fn functions_mut(&mut self) -> &mut [ssa::Function] {
+        ssa::Module::functions_mut(self)
+    }
+
+    fn debug_verify(func: &ssa::Function, after: &str) {
+        ssa::verify::debug_verify(func, after);
+    }
+}
+
+impl PassModule for rtl::Module {
+    type Function = rtl::Function;
+
+    fn functions_mut(&mut self) -> &mut [rtl::Function] {
+        &mut self.functions
+    }
+}
+
+/// A fact about a function that passes can look up instead of working it out again.
+/// The implementing type names the analysis, `Result` is what it finds.
+pub trait Analysis<F>: 'static {
+    type Result: 'static;
+
+    fn compute(func: &F, analyses: &mut Analyses<F>) -> Self::Result;
+}
+
+impl Analysis<ssa::Function> for ssa::cfg::Cfg {
+    type Result = ssa::cfg::Cfg;
+
+    fn compute(func: &ssa::Function, _: &mut Analyses<ssa::Function>) -> ssa::cfg::Cfg {
+        ssa::cfg::Cfg::compute(func)
+    }
+}
+
+impl Analysis<ssa::Function> for ssa::dom::DomTree {
+    type Result = ssa::dom::DomTree;
+
+    fn compute(func: &ssa::Function, analyses: &mut Analyses<ssa::Function>) -> ssa::dom::DomTree {
+        let cfg = analyses.get::<ssa::cfg::Cfg>(func);
+        ssa::dom::DomTree::from_cfg(&cfg, func.entry())
+    }
+}
+
+impl Analysis<ssa::Function> for ssa::dom::PostDomTree {
+    type Result = ssa::dom::PostDomTree;
+
+    fn compute(
+        func: &ssa::Function,
+        analyses: &mut Analyses<ssa::Function>,
+    ) -> ssa::dom::PostDomTree {
+        let cfg = analyses.get::<ssa::cfg::Cfg>(func);
+        ssa::dom::PostDomTree::from_cfg(&cfg, func)
+    }
+}
+
+impl Analysis<rtl::Function> for ralloc::Liveness {
+    type Result = ralloc::Liveness;
+
+    fn compute(func: &rtl::Function, _: &mut Analyses<rtl::Function>) -> ralloc::Liveness {
+        ralloc::Liveness::compute(func)
+    }
+}
+
+/// The analyses computed so far for one function.
+pub struct Analyses<F> {
+    results: HashMap<TypeId, Rc<dyn Any>>,
+    _func: std::marker::PhantomData<fn(&F)>,
+}
+
+impl<F> Default for Analyses<F> {
+    fn default() -> Self {
+        Analyses {
+            results: HashMap::new(),
+            _func: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<F> Analyses<F> {
+    /// The result of `A` for `func`, computed now unless it is still known.
+    pub fn get<A: Analysis<F>>(&mut self, func: &F) -> Rc<A::Result> {
+        if let Some(result) = self.results.get(&TypeId::of::<A>()) {
+            return Rc::clone(result)
+                .downcast()
+                .expect("analyses are stored under their own type");
+        }
+        let result = Rc::new(A::compute(func, self));
+        self.results.insert(TypeId::of::<A>(), result.clone());
+        result
+    }
+
+    /// Whether `A` is known without computing it.
+    pub fn is_cached<A: Analysis<F>>(&self) -> bool {
+        self.results.contains_key(&TypeId::of::<A>())
+    }
+
+    /// Forgets every analysis that `preserved` doesn't keep.
+    pub fn invalidate(&mut self, preserved: &Preserved) {
+        match preserved {
+            Preserved::All => (),
+            Preserved::Only(kept) => self.results.retain(|id, _| kept.contains(id)),
+        }
+    }
+}
+
+/// The analyses that are still valid after a pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preserved {
+    /// The pass changed nothing, or nothing any analysis looks at.
+    All,
+    Only(Vec<TypeId>),
+}
+
+impl Preserved {
+    pub fn none() -> Preserved {
+        Preserved::Only(Vec::new())
+    }
+
+    /// `self`, and `A` as well.
+    pub fn and<F, A: Analysis<F>>(self) -> Preserved {
+        match self {
+            Preserved::All => Preserved::All,
+            Preserved::Only(mut kept) => {
+                kept.push(TypeId::of::<A>());
+                Preserved::Only(kept)
+            }
+        }
+    }
+
+    /// For a pass that changes instructions but not blocks or the edges between them.
+    pub fn control_flow() -> Preserved {
+        Preserved::none()
+            .and::<ssa::Function, ssa::cfg::Cfg>()
+            .and::<ssa::Function, ssa::dom::DomTree>()
+            .and::<ssa::Function, ssa::dom::PostDomTree>()
+    }
+
+    /// What is preserved after both `self` and `other` ran.
+    pub fn intersect(self, other: &Preserved) -> Preserved {
+        match (self, other) {
+            (Preserved::All, other) => other.clone(),
+            (kept, Preserved::All) => kept,
+            (Preserved::Only(kept), Preserved::Only(other)) => {
+                Preserved::Only(kept.into_iter().filter(|id| other.contains(id)).collect())
+            }
+        }
+    }
+}
+
+pub trait FunctionPass<F> {
+    fn name(&self) -> &str;
+
+    fn run(&mut self, func: &mut F, analyses: &mut Analyses<F>) -> Preserved;
+}
+
+/// A pass over the whole module, for changes that involve more than one function.
+/// `analyses` has an entry for every function, in order.
+pub trait ModulePass<M: PassModule> {
+    fn name(&self) -> &str;
+
+    fn run(&mut self, module: &mut M, analyses: &mut [Analyses<M::Function>]) -> Preserved;
+}
+
+enum Pass<M: PassModule> {
+    Function(Box<dyn FunctionPass<M::Function>>),
+    Module(Box<dyn ModulePass<M>>),
+}
+
+impl<M: PassModule> Pass<M> {
+    fn name(&self) -> &str {
+        match self {
+            Pass::Function(pass) => pass.name(),
+            Pass::Module(pass) => pass.name(),
+        }
+    }
+}
+
+type Constructor<M> = Box<dyn Fn() -> Pass<M>>;
+
+/// Passes by name, for pipelines written as strings.
+pub struct PassRegistry<M: PassModule> {
+    passes: Vec<(String, Constructor<M>)>,
+}
+
+impl<M: PassModule> Default for PassRegistry<M> {
+    fn default() -> Self {
+        PassRegistry { passes: Vec::new() }
+    }
+}
+
+impl<M: PassModule + 'static> PassRegistry<M> {
+    pub fn new() -> PassRegistry<M> {
+        Self::default()
+    }
+
+    /// Registers a function pass, made fresh by `new` for every pipeline it is in.
+    /// A later registration under the same name replaces the earlier one.
+    pub fn register_function_pass<P, C>(&mut self, name: &str, new: C)
+    where
+        P: FunctionPass<M::Function> + 'static,
+        C: Fn() -> P + 'static,
+    {
+        self.register(name, Box::new(move || Pass::Function(Box::new(new()))));
+    }
+
+    pub fn register_module_pass<P, C>(&mut self, name: &str, new: C)
+    where
+        P: ModulePass<M> + 'static,
+        C: Fn() -> P + 'static,
+    {
+        self.register(name, Box::new(move || Pass::Module(Box::new(new()))));
+    }
+
+    fn register(&mut self, name: &str, new: Constructor<M>) {
+        self.passes.retain(|(registered, _)| registered != name);
+        self.passes.push((name.to_string(), new));
+    }
+
+    /// Names of the registered passes, in the order they were registered.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.passes.iter().map(|(name, _)| name.as_str())
+    }
+
+    fn create(&self, name: &str) -> Option<Pass<M>> {
+        self.passes
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, new)| new())
+    }
+}
+
+/// A pipeline string that names a pass the registry doesn't know, or has an empty
+/// entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineError {
+    pub pass: String,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pass.is_empty() {
+            true => write!(f, "empty pass name in pipeline"),
+            false => write!(f, "unknown pass '{}'", self.pass),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Passes to run in order over a module.
+pub struct PassManager<M: PassModule> {
+    passes: Vec<Pass<M>>,
+}
+
+impl<M: PassModule> Default for PassManager<M> {
+    fn default() -> Self {
+        PassManager { passes: Vec::new() }
+    }
+}
+
+impl<M: PassModule + 'static> PassManager<M> {
+    pub fn new() -> PassManager<M> {
+        Self::default()
+    }
+
+    /// The passes named in `pipeline`, separated by commas. Whitespace around names is
+    /// ignored, and an empty string is an empty pipeline.
+    pub fn parse(
+        registry: &PassRegistry<M>,
+        pipeline: &str,
+    ) -> Result<PassManager<M>, PipelineError> {
+        let mut manager = PassManager::new();
+        if pipeline.trim().is_empty() {
+            return Ok(manager);
+        }
+        for name in pipeline.split(',').map(str::trim) {
+            match registry.create(name) {
+                Some(pass) => manager.passes.push(pass),
+                None => {
+                    return Err(PipelineError {
+                        pass: name.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(manager)
+    }
+
+    pub fn add_function_pass<P: FunctionPass<M::Function> + 'static>(&mut self, pass: P) {
+        self.passes.push(Pass::Function(Box::new(pass)));
+    }
+
+    pub fn add_module_pass<P: ModulePass<M> + 'static>(&mut self, pass: P) {
+        self.passes.push(Pass::Module(Box::new(pass)));
+    }
+
+    /// Names of the passes, in the order they run.
+    pub fn pipeline(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs every pass in order. Function passes run over each function before the
+    /// next pass starts. Returns what is preserved over the whole pipeline.
+    ///
+    /// In debug builds every function is verified before the first pass and after
+    /// each pass that ran over it, so a pass that breaks a function is named.
+    pub fn run(&mut self, module: &mut M) -> Preserved {
+        for func in module.functions_mut().iter() {
+            M::debug_verify(func, "the input of the pipeline");
+        }
+        let mut analyses: Vec<Analyses<M::Function>> = Vec::new();
+        let mut preserved = Preserved::All;
+        for pass in self.passes.iter_mut() {
+            let count = module.functions_mut().len();
+            if analyses.len() != count {
+                // A module pass added or removed functions.
+                analyses = (0..count).map(|_| Analyses::default()).collect();
+            }
+            match pass {
+                Pass::Function(pass) => {
+                    for (func, analyses) in module.functions_mut().iter_mut().zip(&mut analyses) {
+                        let kept = pass.run(func, analyses);
+                        M::debug_verify(func, pass.name());
+                        analyses.invalidate(&kept);
+                        preserved = preserved.intersect(&kept);
+                    }
+                }
+                Pass::Module(pass) => {
+                    let kept = pass.run(module, &mut analyses);
+                    for func in module.functions_mut().iter() {
+                        M::debug_verify(func, pass.name());
+                    }
+                    for analyses in analyses.iter_mut() {
+                        analyses.invalidate(&kept);
+                    }
+                    preserved = preserved.intersect(&kept);
+                }
+            }
+        }
+        preserved
+    }
+}
@@ -1,38 +1,371 @@
+use super::RegClass;
+
+/// Registers of amd64, under every name they have. Names of the same register at
+/// different sizes alias each other; [`Amd64Register::overlaps`] tells whether two
+/// names refer to the same register.
+///
+/// XMM registers have two names as well, `Xmm0S` is `xmm0` holding a single precision
+/// float and `Xmm0D` is `xmm0` holding a double precision float.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
 pub enum Amd64Register {
+    Al,
+    Cl,
+    Dl,
+    Bl,
+    Spl,
+    Bpl,
+    Sil,
+    Dil,
+    R8b,
+    R9b,
+    R10b,
+    R11b,
+    R12b,
+    R13b,
+    R14b,
+    R15b,
+
+    Ax,
+    Cx,
+    Dx,
+    Bx,
+    Sp,
+    Bp,
+    Si,
+    Di,
+    R8w,
+    R9w,
+    R10w,
+    R11w,
+    R12w,
+    R13w,
+    R14w,
+    R15w,
+
     Eax,
-    Ebx,
     Ecx,
     Edx,
+    Ebx,
     Esp,
+    Ebp,
+    Esi,
+    Edi,
+    R8d,
+    R9d,
+    R10d,
+    R11d,
+    R12d,
+    R13d,
+    R14d,
+    R15d,
 
     Rax,
     Rcx,
+    Rdx,
+    Rbx,
     Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+
+    Xmm0S,
+    Xmm1S,
+    Xmm2S,
+    Xmm3S,
+    Xmm4S,
+    Xmm5S,
+    Xmm6S,
+    Xmm7S,
+    Xmm8S,
+    Xmm9S,
+    Xmm10S,
+    Xmm11S,
+    Xmm12S,
+    Xmm13S,
+    Xmm14S,
+    Xmm15S,
+
+    Xmm0D,
+    Xmm1D,
+    Xmm2D,
+    Xmm3D,
+    Xmm4D,
+    Xmm5D,
+    Xmm6D,
+    Xmm7D,
+    Xmm8D,
+    Xmm9D,
+    Xmm10D,
+    Xmm11D,
+    Xmm12D,
+    Xmm13D,
+    Xmm14D,
+    Xmm15D,
 }
 
+/// The names of each register, by encoding and then by size: 1, 2, 4 and 8 bytes.
+const GPR_NAMES: [[Amd64Register; 4]; 16] = [
+    [
+        Amd64Register::Al,
+        Amd64Register::Ax,
+        Amd64Register::Eax,
+        Amd64Register::Rax,
+    ],
+    [
+        Amd64Register::Cl,
+        Amd64Register::Cx,
+        Amd64Register::Ecx,
+        Amd64Register::Rcx,
+    ],
+    [
+        Amd64Register::Dl,
+        Amd64Register::Dx,
+        Amd64Register::Edx,
+        Amd64Register::Rdx,
+    ],
+    [
+        Amd64Register::Bl,
+        Amd64Register::Bx,
+        Amd64Register::Ebx,
+        Amd64Register::Rbx,
+    ],
+    [
+        Amd64Register::Spl,
+        Amd64Register::Sp,
+        Amd64Register::Esp,
+        Amd64Register::Rsp,
+    ],
+    [
+        Amd64Register::Bpl,
+        Amd64Register::Bp,
+        Amd64Register::Ebp,
+        Amd64Register::Rbp,
+    ],
+    [
+        Amd64Register::Sil,
+        Amd64Register::Si,
+        Amd64Register::Esi,
+        Amd64Register::Rsi,
+    ],
+    [
+        Amd64Register::Dil,
+        Amd64Register::Di,
+        Amd64Register::Edi,
+        Amd64Register::Rdi,
+    ],
+    [
+        Amd64Register::R8b,
+        Amd64Register::R8w,
+        Amd64Register::R8d,
+        Amd64Register::R8,
+    ],
+    [
+        Amd64Register::R9b,
+        Amd64Register::R9w,
+        Amd64Register::R9d,
+        Amd64Register::R9,
+    ],
+    [
+        Amd64Register::R10b,
+        Amd64Register::R10w,
+        Amd64Register::R10d,
+        Amd64Register::R10,
+    ],
+    [
+        Amd64Register::R11b,
+        Amd64Register::R11w,
+        Amd64Register::R11d,
+        Amd64Register::R11,
+    ],
+    [
+        Amd64Register::R12b,
+        Amd64Register::R12w,
+        Amd64Register::R12d,
+        Amd64Register::R12,
+    ],
+    [
+        Amd64Register::R13b,
+        Amd64Register::R13w,
+        Amd64Register::R13d,
+        Amd64Register::R13,
+    ],
+    [
+        Amd64Register::R14b,
+        Amd64Register::R14w,
+        Amd64Register::R14d,
+        Amd64Register::R14,
+    ],
+    [
+        Amd64Register::R15b,
+        Amd64Register::R15w,
+        Amd64Register::R15d,
+        Amd64Register::R15,
+    ],
+];
+
+/// The names of each XMM register, by encoding and then by size: 4 and 8 bytes.
+const XMM_NAMES: [[Amd64Register; 2]; 16] = [
+    [Amd64Register::Xmm0S, Amd64Register::Xmm0D],
+    [Amd64Register::Xmm1S, Amd64Register::Xmm1D],
+    [Amd64Register::Xmm2S, Amd64Register::Xmm2D],
+    [Amd64Register::Xmm3S, Amd64Register::Xmm3D],
+    [Amd64Register::Xmm4S, Amd64Register::Xmm4D],
+    [Amd64Register::Xmm5S, Amd64Register::Xmm5D],
+    [Amd64Register::Xmm6S, Amd64Register::Xmm6D],
+    [Amd64Register::Xmm7S, Amd64Register::Xmm7D],
+    [Amd64Register::Xmm8S, Amd64Register::Xmm8D],
+    [Amd64Register::Xmm9S, Amd64Register::Xmm9D],
+    [Amd64Register::Xmm10S, Amd64Register::Xmm10D],
+    [Amd64Register::Xmm11S, Amd64Register::Xmm11D],
+    [Amd64Register::Xmm12S, Amd64Register::Xmm12D],
+    [Amd64Register::Xmm13S, Amd64Register::Xmm13D],
+    [Amd64Register::Xmm14S, Amd64Register::Xmm14D],
+    [Amd64Register::Xmm15S, Amd64Register::Xmm15D],
+];
+
 impl Amd64Register {
     pub fn name(&self) -> &'static str {
         match self {
+            Amd64Register::Al => "al",
+            Amd64Register::Cl => "cl",
+            Amd64Register::Dl => "dl",
+            Amd64Register::Bl => "bl",
+            Amd64Register::Spl => "spl",
+            Amd64Register::Bpl => "bpl",
+            Amd64Register::Sil => "sil",
+            Amd64Register::Dil => "dil",
+            Amd64Register::R8b => "r8b",
+            Amd64Register::R9b => "r9b",
+            Amd64Register::R10b => "r10b",
+            Amd64Register::R11b => "r11b",
+            Amd64Register::R12b => "r12b",
+            Amd64Register::R13b => "r13b",
+            Amd64Register::R14b => "r14b",
+            Amd64Register::R15b => "r15b",
+            Amd64Register::Ax => "ax",
+            Amd64Register::Cx => "cx",
+            Amd64Register::Dx => "dx",
+            Amd64Register::Bx => "bx",
+            Amd64Register::Sp => "sp",
+            Amd64Register::Bp => "bp",
+            Amd64Register::Si => "si",
+            Amd64Register::Di => "di",
+            Amd64Register::R8w => "r8w",
+            Amd64Register::R9w => "r9w",
+            Amd64Register::R10w => "r10w",
+            Amd64Register::R11w => "r11w",
+            Amd64Register::R12w => "r12w",
+            Amd64Register::R13w => "r13w",
+            Amd64Register::R14w => "r14w",
+            Amd64Register::R15w => "r15w",
             Amd64Register::Eax => "eax",
-            Amd64Register::Ebx => "ebx",
             Amd64Register::Ecx => "ecx",
             Amd64Register::Edx => "edx",
+            Amd64Register::Ebx => "ebx",
             Amd64Register::Esp => "esp",
+            Amd64Register::Ebp => "ebp",
+            Amd64Register::Esi => "esi",
+            Amd64Register::Edi => "edi",
+            Amd64Register::R8d => "r8d",
+            Amd64Register::R9d => "r9d",
+            Amd64Register::R10d => "r10d",
+            Amd64Register::R11d => "r11d",
+            Amd64Register::R12d => "r12d",
+            Amd64Register::R13d => "r13d",
+            Amd64Register::R14d => "r14d",
+            Amd64Register::R15d => "r15d",
             Amd64Register::Rax => "rax",
             Amd64Register::Rcx => "rcx",
+            Amd64Register::Rdx => "rdx",
+            Amd64Register::Rbx => "rbx",
             Amd64Register::Rsp => "rsp",
+            Amd64Register::Rbp => "rbp",
+            Amd64Register::Rsi => "rsi",
+            Amd64Register::Rdi => "rdi",
+            Amd64Register::R8 => "r8",
+            Amd64Register::R9 => "r9",
+            Amd64Register::R10 => "r10",
+            Amd64Register::R11 => "r11",
+            Amd64Register::R12 => "r12",
+            Amd64Register::R13 => "r13",
+            Amd64Register::R14 => "r14",
+            Amd64Register::R15 => "r15",
+            Amd64Register::Xmm0S | Amd64Register::Xmm0D => "xmm0",
+            Amd64Register::Xmm1S | Amd64Register::Xmm1D => "xmm1",
+            Amd64Register::Xmm2S | Amd64Register::Xmm2D => "xmm2",
+            Amd64Register::Xmm3S | Amd64Register::Xmm3D => "xmm3",
+            Amd64Register::Xmm4S | Amd64Register::Xmm4D => "xmm4",
+            Amd64Register::Xmm5S | Amd64Register::Xmm5D => "xmm5",
+            Amd64Register::Xmm6S | Amd64Register::Xmm6D => "xmm6",
+            Amd64Register::Xmm7S | Amd64Register::Xmm7D => "xmm7",
+            Amd64Register::Xmm8S | Amd64Register::Xmm8D => "xmm8",
+            Amd64Register::Xmm9S | Amd64Register::Xmm9D => "xmm9",
+            Amd64Register::Xmm10S | Amd64Register::Xmm10D => "xmm10",
+            Amd64Register::Xmm11S | Amd64Register::Xmm11D => "xmm11",
+            Amd64Register::Xmm12S | Amd64Register::Xmm12D => "xmm12",
+            Amd64Register::Xmm13S | Amd64Register::Xmm13D => "xmm13",
+            Amd64Register::Xmm14S | Amd64Register::Xmm14D => "xmm14",
+            Amd64Register::Xmm15S | Amd64Register::Xmm15D => "xmm15",
         }
     }
 
     pub fn reg_size(&self) -> usize {
-        match self {
-            Amd64Register::Eax
-            | Amd64Register::Ebx
-            | Amd64Register::Ecx
-            | Amd64Register::Edx
-            | Amd64Register::Esp => 4,
-            Amd64Register::Rax | Amd64Register::Rcx | Amd64Register::Rsp => 8,
+        match self.position() {
+            (RegClass::Gpr, _, size_index) => 1 << size_index,
+            (RegClass::Xmm, _, size_index) => 4 << size_index,
+        }
+    }
+
+    pub fn class(&self) -> RegClass {
+        self.position().0
+    }
+
+    /// Whether writing one register changes the other, like `al` and `eax`.
+    pub fn overlaps(&self, other: &Amd64Register) -> bool {
+        let (class, encoding, _) = self.position();
+        let (other_class, other_encoding, _) = other.position();
+        class == other_class && encoding == other_encoding
+    }
+
+    /// The name of the same register at another size, like `eax` for `al` and 4 bytes.
+    pub fn with_size(&self, bytes: usize) -> Amd64Register {
+        match (self.position(), bytes) {
+            ((RegClass::Gpr, encoding, _), 1) => GPR_NAMES[encoding][0],
+            ((RegClass::Gpr, encoding, _), 2) => GPR_NAMES[encoding][1],
+            ((RegClass::Gpr, encoding, _), 4) => GPR_NAMES[encoding][2],
+            ((RegClass::Gpr, encoding, _), 8) => GPR_NAMES[encoding][3],
+            ((RegClass::Xmm, encoding, _), 4) => XMM_NAMES[encoding][0],
+            ((RegClass::Xmm, encoding, _), 8) => XMM_NAMES[encoding][1],
+            ((class, ..), _) => panic!("no {} byte {:?} registers", bytes, class),
+        }
+    }
+
+    /// Number of the register in instruction encodings, 0 for `rax` up to 15 for `r15`,
+    /// and 0 to 15 for the XMM registers.
+    pub fn encoding(&self) -> usize {
+        self.position().1
+    }
+
+    /// Class, encoding and index of the size in the table of names.
+    fn position(&self) -> (RegClass, usize, usize) {
+        for (encoding, names) in GPR_NAMES.iter().enumerate() {
+            if let Some(size_index) = names.iter().position(|name| name == self) {
+                return (RegClass::Gpr, encoding, size_index);
+            }
+        }
+        for (encoding, names) in XMM_NAMES.iter().enumerate() {
+            if let Some(size_index) = names.iter().position(|name| name == self) {
+                return (RegClass::Xmm, encoding, size_index);
+            }
         }
+        unreachable!("every register is in a table of names")
     }
 }
@@ -4,7 +4,7 @@ use std::fmt::{Display, Formatter};
 impl Display for RealRegister {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            RealRegister::Amd64(reg) => write!(f, "(reg_amd64 {}", reg.name()),
+            RealRegister::Amd64(reg) => write!(f, "(reg_amd64 {})", reg.name()),
         }
     }
 }
@@ -12,10 +12,17 @@ impl Display for RealRegister {
 impl Display for Register {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Register::Vir(VirRegister { n, bytes }) => {
-                write!(f, "(reg:{bytes} {n})")
-            }
-            Register::Stack(StackRegister { slot, bytes }) => {
+            Register::Vir(VirRegister {
+                n,
+                bytes,
+                class: RegClass::Gpr,
+            }) => write!(f, "(reg:{bytes} {n})"),
+            Register::Vir(VirRegister {
+                n,
+                bytes,
+                class: RegClass::Xmm,
+            }) => write!(f, "(xmm:{bytes} {n})"),
+            Register::Stack(StackRegister { slot, bytes, .. }) => {
                 write!(f, "(stack:{bytes} {slot})")
             }
             Register::Real(reg) => Display::fmt(reg, f),
@@ -27,7 +34,9 @@ impl Display for Lit {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Lit::LitU8(val) => write!(f, "(lit_u8 {})", val),
+            Lit::LitU16(val) => write!(f, "(lit_u16 {})", val),
             Lit::LitU32(val) => write!(f, "(lit_u32 {})", val),
+            Lit::LitU64(val) => write!(f, "(lit_u64 {})", val),
         }
     }
 }
@@ -65,12 +74,207 @@ impl Display for OpDiv {
     }
 }
 
+impl Display for OpExtendHigh {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self.signed {
+            true => "sext_high",
+            false => "zext_high",
+        };
+        write!(f, "({} {} {})", name, self.lo, self.hi)
+    }
+}
+
+impl Display for OpIntDiv {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self.signed {
+            true => "sdiv",
+            false => "udiv",
+        };
+        write!(f, "({} {} {} {})", name, self.lo, self.hi, self.by)
+    }
+}
+
+impl Display for OpMulHigh {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self.signed {
+            true => "smul_high",
+            false => "umul_high",
+        };
+        write!(f, "({} {} {} {})", name, self.lo, self.hi, self.with)
+    }
+}
+
+impl Display for OpAnd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(and {} {})", self.to, self.val)
+    }
+}
+
+impl Display for OpOr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(or {} {})", self.to, self.val)
+    }
+}
+
+impl Display for OpXor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(xor {} {})", self.to, self.val)
+    }
+}
+
+impl Display for OpShl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(shl {} {})", self.val, self.by)
+    }
+}
+
+impl Display for OpLShr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(lshr {} {})", self.val, self.by)
+    }
+}
+
+impl Display for OpAShr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(ashr {} {})", self.val, self.by)
+    }
+}
+
+impl Display for OpNot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(not {})", self.val)
+    }
+}
+
+impl Display for OpNeg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(neg {})", self.val)
+    }
+}
+
 impl Display for OpCopy {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "(copy {} {})", self.to, self.from)
     }
 }
 
+impl Display for OpCmp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(cmp {} {})", self.a, self.b)
+    }
+}
+
+impl Display for Cond {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cond::Eq => write!(f, "eq"),
+            Cond::Ne => write!(f, "ne"),
+            Cond::Lt => write!(f, "lt"),
+            Cond::Le => write!(f, "le"),
+            Cond::Gt => write!(f, "gt"),
+            Cond::Ge => write!(f, "ge"),
+            Cond::Ult => write!(f, "ult"),
+            Cond::Ule => write!(f, "ule"),
+            Cond::Ugt => write!(f, "ugt"),
+            Cond::Uge => write!(f, "uge"),
+            Cond::FEq => write!(f, "feq"),
+            Cond::FNe => write!(f, "fne"),
+        }
+    }
+}
+
+impl Display for OpJmp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(jmp {})", self.target)
+    }
+}
+
+impl Display for OpJcc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(jcc {} {})", self.cond, self.target)
+    }
+}
+
+impl Display for OpSetCc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(setcc {} {})", self.cond, self.to)
+    }
+}
+
+impl Display for OpCMov {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(cmov {} {} {})", self.cond, self.to, self.from)
+    }
+}
+
+impl Display for Conv {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conv::SiToFp => write!(f, "sitofp"),
+            Conv::UiToFp => write!(f, "uitofp"),
+            Conv::FpToSi => write!(f, "fptosi"),
+            Conv::FpToUi => write!(f, "fptoui"),
+            Conv::FpExt => write!(f, "fpext"),
+            Conv::FpTrunc => write!(f, "fptrunc"),
+            Conv::ZExt => write!(f, "zext"),
+            Conv::SExt => write!(f, "sext"),
+            Conv::Trunc => write!(f, "trunc"),
+            Conv::BitCast => write!(f, "bitcast"),
+        }
+    }
+}
+
+impl Display for OpConvert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(convert {} {} {})", self.conv, self.to, self.from)
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.index {
+            Some((index, scale)) => {
+                write!(f, "(mem {} {} {} {})", self.base, index, scale, self.disp)
+            }
+            None => write!(f, "(mem {} {})", self.base, self.disp),
+        }
+    }
+}
+
+impl Display for OpLoad {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(load {} {})", self.to, self.addr)
+    }
+}
+
+impl Display for OpStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(store {} {})", self.addr, self.from)
+    }
+}
+
+impl Display for OpLea {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(lea {} {})", self.to, self.addr)
+    }
+}
+
+impl Display for OpAddrOf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(addr_of {} {})", self.to, self.symbol)
+    }
+}
+
+impl Display for OpCall {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(call {}", self.target)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        write!(f, ")")
+    }
+}
+
 impl Display for Op {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -79,6 +283,29 @@ impl Display for Op {
             Op::Sub(sub) => Display::fmt(sub, f),
             Op::Mul(mul) => Display::fmt(mul, f),
             Op::Div(div) => Display::fmt(div, f),
+            Op::ExtendHigh(ext) => Display::fmt(ext, f),
+            Op::IntDiv(div) => Display::fmt(div, f),
+            Op::MulHigh(mul) => Display::fmt(mul, f),
+            Op::And(and) => Display::fmt(and, f),
+            Op::Or(or) => Display::fmt(or, f),
+            Op::Xor(xor) => Display::fmt(xor, f),
+            Op::Shl(shl) => Display::fmt(shl, f),
+            Op::LShr(lshr) => Display::fmt(lshr, f),
+            Op::AShr(ashr) => Display::fmt(ashr, f),
+            Op::Not(not) => Display::fmt(not, f),
+            Op::Neg(neg) => Display::fmt(neg, f),
+            Op::Cmp(cmp) => Display::fmt(cmp, f),
+            Op::Jmp(jmp) => Display::fmt(jmp, f),
+            Op::Jcc(jcc) => Display::fmt(jcc, f),
+            Op::SetCc(setcc) => Display::fmt(setcc, f),
+            Op::CMov(cmov) => Display::fmt(cmov, f),
+            Op::Convert(convert) => Display::fmt(convert, f),
+            Op::Load(load) => Display::fmt(load, f),
+            Op::Store(store) => Display::fmt(store, f),
+            Op::Lea(lea) => Display::fmt(lea, f),
+            Op::AddrOf(addr_of) => Display::fmt(addr_of, f),
+            Op::Call(call) => Display::fmt(call, f),
+            Op::Ret(..) => write!(f, "(ret)"),
         }
     }
 }
@@ -86,14 +313,46 @@ impl Display for Op {
 impl Display for Block {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self.name {
-            Some(name) => write!(f, "# Block: '{}'\n", name)?,
-            None => write!(f, "# Block\n")?,
+            Some(name) => writeln!(f, "# Block: '{}'", name)?,
+            None => writeln!(f, "# Block")?,
         };
-        write!(f, "(\n")?;
+        writeln!(f, "(")?;
         for op in &self.ops {
-            write!(f, "    {}\n", op)?;
+            writeln!(f, "    {}", op)?;
+        }
+        writeln!(f, ")")?;
+        Ok(())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# Function: '{}' (frame {})", self.name, self.frame_size)?;
+        for (reg, disp) in &self.saved {
+            writeln!(f, "# Saves {} at {}", reg, disp)?;
+        }
+        for block in &self.blocks {
+            writeln!(f, "{}", block)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for name in &self.externs {
+            writeln!(f, "# Extern: '{}'", name)?;
+        }
+        for global in &self.globals {
+            writeln!(
+                f,
+                "# Global: '{}' ({:?}, {} bytes, align {})",
+                global.name, global.section, global.size, global.align
+            )?;
+        }
+        for func in &self.functions {
+            Display::fmt(func, f)?;
         }
-        write!(f, ")\n")?;
         Ok(())
     }
 }
@@ -1,4 +1,5 @@
-use crate::compile::ralloc::AllocationKind;
+use crate::compile::ralloc::{Allocation, AllocationKind, VirRegisterMap};
+use crate::typing;
 
 pub mod amd64;
 pub mod debug;
@@ -14,18 +15,50 @@ impl RealRegister {
             RealRegister::Amd64(reg) => reg.reg_size(),
         }
     }
+
+    pub fn class(&self) -> RegClass {
+        match self {
+            RealRegister::Amd64(reg) => reg.class(),
+        }
+    }
+
+    pub fn overlaps(&self, other: &RealRegister) -> bool {
+        match (self, other) {
+            (RealRegister::Amd64(a), RealRegister::Amd64(b)) => a.overlaps(b),
+        }
+    }
+}
+
+/// The kind of register a value lives in: integers, booleans and pointers go in
+/// general purpose registers, floats in XMM registers.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum RegClass {
+    Gpr,
+    Xmm,
+}
+
+impl RegClass {
+    pub fn of(ty: typing::Type) -> RegClass {
+        match ty.is_float() {
+            true => RegClass::Xmm,
+            false => RegClass::Gpr,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub struct VirRegister {
     pub bytes: usize,
     pub n: usize,
+    pub class: RegClass,
 }
 
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub struct StackRegister {
     pub bytes: usize,
     pub slot: usize,
+    /// Class of the value spilled to the slot.
+    pub class: RegClass,
 }
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
@@ -47,6 +80,14 @@ impl Register {
         }
     }
 
+    pub fn class(&self) -> RegClass {
+        match self {
+            Register::Real(real) => real.class(),
+            Register::Vir(pseudo) => pseudo.class,
+            Register::Stack(ss) => ss.class,
+        }
+    }
+
     pub fn sz(&self) -> usize {
         match self {
             Register::Real(real) => real.sz(),
@@ -56,20 +97,57 @@ impl Register {
     }
 }
 
+/// An immediate. Only its bits matter, signed values are stored in two's complement.
+#[derive(Copy, Clone)]
 pub enum Lit {
     LitU8(u8),
+    LitU16(u16),
     LitU32(u32),
+    LitU64(u64),
 }
 
 impl Lit {
+    /// The `bytes` wide immediate holding the low bits of `bits`.
+    pub fn from_bits(bytes: usize, bits: u64) -> Lit {
+        match bytes {
+            1 => Lit::LitU8(bits as u8),
+            2 => Lit::LitU16(bits as u16),
+            4 => Lit::LitU32(bits as u32),
+            8 => Lit::LitU64(bits),
+            _ => panic!("no {} byte immediates", bytes),
+        }
+    }
+
+    pub fn bits(&self) -> u64 {
+        match self {
+            Lit::LitU8(val) => *val as u64,
+            Lit::LitU16(val) => *val as u64,
+            Lit::LitU32(val) => *val as u64,
+            Lit::LitU64(val) => *val,
+        }
+    }
+
+    /// The value of the immediate when its bits are read as a signed integer.
+    pub fn signed(&self) -> i64 {
+        match self {
+            Lit::LitU8(val) => *val as i8 as i64,
+            Lit::LitU16(val) => *val as i16 as i64,
+            Lit::LitU32(val) => *val as i32 as i64,
+            Lit::LitU64(val) => *val as i64,
+        }
+    }
+
     pub fn sz(&self) -> usize {
         match self {
             Lit::LitU8(..) => 1,
+            Lit::LitU16(..) => 2,
             Lit::LitU32(..) => 4,
+            Lit::LitU64(..) => 8,
         }
     }
 }
 
+#[derive(Copy, Clone)]
 pub enum RValue {
     Register(Register),
     Lit(Lit),
@@ -109,12 +187,261 @@ pub struct OpDiv {
     pub with: RValue,
 }
 
+/// Sets `hi` to the sign extension of `lo`, or to zero, ahead of an `OpIntDiv`. On
+/// amd64, `lo` is `rax` and `hi` is `rdx`, at the size of the division.
+pub struct OpExtendHigh {
+    pub signed: bool,
+    pub lo: Register,
+    pub hi: Register,
+}
+
+/// Divides the double width value `hi:lo` by `by`, leaving the quotient in `lo` and the
+/// remainder in `hi`.
+pub struct OpIntDiv {
+    pub signed: bool,
+    pub lo: Register,
+    pub hi: Register,
+    pub by: RValue,
+}
+
+/// Multiplies `lo` by `with` and leaves the high half of the double width product in
+/// `hi`, clobbering `lo`. On amd64, `lo` is `rax` and `hi` is `rdx`.
+pub struct OpMulHigh {
+    pub signed: bool,
+    pub lo: Register,
+    pub hi: Register,
+    pub with: RValue,
+}
+
+pub struct OpAnd {
+    pub to: Register,
+    pub val: RValue,
+}
+
+pub struct OpOr {
+    pub to: Register,
+    pub val: RValue,
+}
+
+pub struct OpXor {
+    pub to: Register,
+    pub val: RValue,
+}
+
+/// Shifts `val` left. On amd64, a count in a register has to be in `cl`, which is also
+/// true for the shifts to the right.
+pub struct OpShl {
+    pub val: Register,
+    pub by: RValue,
+}
+
+pub struct OpLShr {
+    pub val: Register,
+    pub by: RValue,
+}
+
+pub struct OpAShr {
+    pub val: Register,
+    pub by: RValue,
+}
+
+pub struct OpNot {
+    pub val: Register,
+}
+
+pub struct OpNeg {
+    pub val: Register,
+}
+
+pub struct OpCmp {
+    pub a: Register,
+    pub b: RValue,
+}
+
+/// Condition tested by a conditional jump, based on the flags set by the last `OpCmp`.
+/// `Lt` to `Ge` compare signed, `Ult` to `Uge` unsigned values.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    /// Equal and ordered, after comparing floats. Only [`OpSetCc`] can test it, a
+    /// conditional jump would need two branches.
+    FEq,
+    /// Not equal or unordered, after comparing floats.
+    FNe,
+}
+
+impl Cond {
+    /// The condition that holds exactly when this one doesn't.
+    pub fn inverse(&self) -> Cond {
+        match self {
+            Cond::Eq => Cond::Ne,
+            Cond::Ne => Cond::Eq,
+            Cond::Lt => Cond::Ge,
+            Cond::Le => Cond::Gt,
+            Cond::Gt => Cond::Le,
+            Cond::Ge => Cond::Lt,
+            Cond::Ult => Cond::Uge,
+            Cond::Ule => Cond::Ugt,
+            Cond::Ugt => Cond::Ule,
+            Cond::Uge => Cond::Ult,
+            Cond::FEq => Cond::FNe,
+            Cond::FNe => Cond::FEq,
+        }
+    }
+}
+
+pub struct OpJmp {
+    pub target: String,
+}
+
+pub struct OpJcc {
+    pub cond: Cond,
+    pub target: String,
+}
+
+/// Conversion between integers and floats, between float sizes and between integer
+/// sizes. `BitCast` copies the bits between registers of the same size, which may be of
+/// different classes.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum Conv {
+    SiToFp,
+    UiToFp,
+    FpToSi,
+    FpToUi,
+    FpExt,
+    FpTrunc,
+    ZExt,
+    SExt,
+    Trunc,
+    BitCast,
+}
+
+pub struct OpConvert {
+    pub conv: Conv,
+    pub to: Register,
+    pub from: Register,
+}
+
+/// Sets a byte register to 1 if the condition holds, otherwise to 0.
+pub struct OpSetCc {
+    pub cond: Cond,
+    pub to: Register,
+}
+
+/// Copies `from` to `to` if `cond` holds in the flags, and leaves `to` alone otherwise.
+pub struct OpCMov {
+    pub cond: Cond,
+    pub to: Register,
+    pub from: Register,
+}
+
+/// A memory operand, the address `base + index * scale + disp`. The scale is 1, 2, 4
+/// or 8.
+#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+pub struct Address {
+    pub base: Register,
+    pub index: Option<(Register, u8)>,
+    pub disp: i32,
+}
+
+impl Address {
+    /// The address `base + disp`.
+    pub fn new(base: Register, disp: i32) -> Address {
+        Address {
+            base,
+            index: None,
+            disp,
+        }
+    }
+}
+
+/// Reads as many bytes as `to` holds from memory.
+pub struct OpLoad {
+    pub to: Register,
+    pub addr: Address,
+}
+
+/// Writes as many bytes as `from` holds to memory.
+pub struct OpStore {
+    pub addr: Address,
+    pub from: RValue,
+}
+
+/// Computes an address without accessing memory.
+pub struct OpLea {
+    pub to: Register,
+    pub addr: Address,
+}
+
+/// Computes the address of a global, relative to the instruction pointer.
+pub struct OpAddrOf {
+    pub to: Register,
+    pub symbol: String,
+}
+
+/// Calls the function at a label. Arguments are already in `args`, and the callee may
+/// overwrite every register in `clobbers`, which includes where the result comes back.
+pub struct OpCall {
+    pub target: String,
+    pub args: Vec<Register>,
+    pub clobbers: Vec<Register>,
+}
+
+pub struct OpRet;
+
 pub enum Op {
     Copy(OpCopy),
     Add(OpAdd),
     Sub(OpSub),
     Mul(OpMul),
     Div(OpDiv),
+    ExtendHigh(OpExtendHigh),
+    IntDiv(OpIntDiv),
+    MulHigh(OpMulHigh),
+    And(OpAnd),
+    Or(OpOr),
+    Xor(OpXor),
+    Shl(OpShl),
+    LShr(OpLShr),
+    AShr(OpAShr),
+    Not(OpNot),
+    Neg(OpNeg),
+    Cmp(OpCmp),
+    Jmp(OpJmp),
+    Jcc(OpJcc),
+    SetCc(OpSetCc),
+    CMov(OpCMov),
+    Convert(OpConvert),
+    Load(OpLoad),
+    Store(OpStore),
+    Lea(OpLea),
+    AddrOf(OpAddrOf),
+    Call(OpCall),
+    Ret(OpRet),
+}
+
+impl Op {
+    /// Label of the block this op may transfer control to.
+    pub fn jump_target(&self) -> Option<&str> {
+        match self {
+            Op::Jmp(OpJmp { target }) | Op::Jcc(OpJcc { target, .. }) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Whether control never continues to the next op.
+    pub fn is_unconditional_exit(&self) -> bool {
+        matches!(self, Op::Jmp(..) | Op::Ret(..))
+    }
 }
 
 pub type Ops = Vec<Op>;
@@ -125,6 +452,52 @@ pub struct Block {
     pub metadata: (),
 }
 
+/// The blocks of a function in layout order. Functions are lowered with virtual
+/// registers, which the `ralloc` and `promote` passes replace with real registers and
+/// stack slots.
+pub struct Function {
+    pub name: String,
+    pub blocks: Vec<Block>,
+    /// Bytes below the frame pointer that hold allocas, spilled registers, saved
+    /// registers and the arguments of calls that don't fit in registers. Until
+    /// registers are promoted, only what is placed so far.
+    pub frame_size: usize,
+    /// Bytes at the bottom of the frame for the arguments of calls passed on the stack.
+    pub outgoing_args: usize,
+    /// Registers the function must preserve but writes, with the offset from the frame
+    /// pointer where they are saved.
+    pub saved: Vec<(RealRegister, i32)>,
+    /// Where each virtual register goes, from register allocation until the registers
+    /// are promoted.
+    pub allocation: Option<VirRegisterMap<Allocation>>,
+}
+
+/// Where a global is placed.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Section {
+    Data,
+    ReadOnly,
+    /// Zero filled when the program starts, without taking room in the object file.
+    Bss,
+}
+
+/// A global of the module. Globals in [`Section::Bss`] have no `bytes`, only a size.
+pub struct Global {
+    pub name: String,
+    pub section: Section,
+    pub align: u32,
+    pub size: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Compiled functions, the names of the functions they call that are defined
+/// elsewhere, and the globals they use.
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub externs: Vec<String>,
+    pub globals: Vec<Global>,
+}
+
 fn promote_register(reg: &mut Register, mut promote: impl FnMut(&VirRegister) -> AllocationKind) {
     match reg {
         Register::Vir(vir) => match promote(vir) {
@@ -136,6 +509,13 @@ fn promote_register(reg: &mut Register, mut promote: impl FnMut(&VirRegister) ->
     }
 }
 
+fn promote_address(addr: &mut Address, mut promote: impl FnMut(&VirRegister) -> AllocationKind) {
+    promote_register(&mut addr.base, &mut promote);
+    if let Some((index, _)) = &mut addr.index {
+        promote_register(index, &mut promote);
+    }
+}
+
 fn promote_rvalue(rvalue: &mut RValue, promote: impl FnMut(&VirRegister) -> AllocationKind) {
     match rvalue {
         RValue::Lit(..) => (),
@@ -168,6 +548,59 @@ pub fn promote_registers_in_op(
             promote_register(val, &mut promote);
             promote_rvalue(with, &mut promote);
         }
+        Op::ExtendHigh(OpExtendHigh { lo, hi, .. }) => {
+            promote_register(lo, &mut promote);
+            promote_register(hi, &mut promote);
+        }
+        Op::IntDiv(OpIntDiv { lo, hi, by, .. }) => {
+            promote_register(lo, &mut promote);
+            promote_register(hi, &mut promote);
+            promote_rvalue(by, &mut promote);
+        }
+        Op::MulHigh(OpMulHigh { lo, hi, with, .. }) => {
+            promote_register(lo, &mut promote);
+            promote_register(hi, &mut promote);
+            promote_rvalue(with, &mut promote);
+        }
+        Op::And(OpAnd { to, val }) | Op::Or(OpOr { to, val }) | Op::Xor(OpXor { to, val }) => {
+            promote_register(to, &mut promote);
+            promote_rvalue(val, &mut promote);
+        }
+        Op::Shl(OpShl { val, by })
+        | Op::LShr(OpLShr { val, by })
+        | Op::AShr(OpAShr { val, by }) => {
+            promote_register(val, &mut promote);
+            promote_rvalue(by, &mut promote);
+        }
+        Op::Not(OpNot { val }) | Op::Neg(OpNeg { val }) => promote_register(val, &mut promote),
+        Op::Cmp(OpCmp { a, b }) => {
+            promote_register(a, &mut promote);
+            promote_rvalue(b, &mut promote);
+        }
+        Op::SetCc(OpSetCc { to, .. }) => promote_register(to, &mut promote),
+        Op::CMov(OpCMov { to, from, .. }) => {
+            promote_register(to, &mut promote);
+            promote_register(from, &mut promote);
+        }
+        Op::Convert(OpConvert { to, from, .. }) => {
+            promote_register(to, &mut promote);
+            promote_register(from, &mut promote);
+        }
+        Op::Load(OpLoad { to, addr }) | Op::Lea(OpLea { to, addr }) => {
+            promote_register(to, &mut promote);
+            promote_address(addr, &mut promote);
+        }
+        Op::Store(OpStore { addr, from }) => {
+            promote_address(addr, &mut promote);
+            promote_rvalue(from, &mut promote);
+        }
+        Op::AddrOf(OpAddrOf { to, .. }) => promote_register(to, &mut promote),
+        Op::Call(OpCall { args, clobbers, .. }) => {
+            for reg in args.iter_mut().chain(clobbers) {
+                promote_register(reg, &mut promote);
+            }
+        }
+        Op::Jmp(..) | Op::Jcc(..) | Op::Ret(..) => (),
     }
 }
 
@@ -179,3 +612,12 @@ pub fn promote_registers_in_ops(
         promote_registers_in_op(op, &mut promote);
     }
 }
+
+pub fn promote_registers_in_blocks(
+    blocks: &mut [Block],
+    mut promote: impl FnMut(&VirRegister) -> AllocationKind,
+) {
+    for block in blocks {
+        promote_registers_in_ops(&mut block.ops, &mut promote);
+    }
+}
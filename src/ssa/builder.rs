@@ -0,0 +1,250 @@
+//! On-the-fly SSA construction following Braun et al., "Simple and Efficient
+//! Construction of Static Single Assignment Form" (CC 2013).
+//!
+//! A frontend declares the mutable variables of its source language with
+//! [`FunctionBuilder::declare_var`], assigns them with [`FunctionBuilder::def_var`] and
+//! reads them with [`FunctionBuilder::use_var`]. Phis are inserted where control flow
+//! merges and removed again when they turn out to be trivial. A block must be sealed
+//! with [`FunctionBuilder::seal_block`] once all of its predecessors are known.
+//!
+//! The builder keeps the phis that use each value itself, as the paper does, rather
+//! than asking the function, whose def-use chains every emitted instruction drops.
+//! Instructions that use a removed phi are rewritten once, in
+//! [`FunctionBuilder::finish`].
+
+use super::{BasicBlockEmitter, BlockId, Function, Ins, Literal, RValue, Terminator, Variable};
+use crate::typing::{self, Typed};
+use std::collections::{HashMap, HashSet};
+
+/// A variable of the source language, which may be assigned any number of times.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SourceVar(usize);
+
+pub struct FunctionBuilder<'f> {
+    func: &'f mut Function,
+    var_types: Vec<typing::Type>,
+    current_defs: Vec<HashMap<BlockId, RValue>>,
+    sealed: HashSet<BlockId>,
+    incomplete_phis: HashMap<BlockId, Vec<(SourceVar, Variable)>>,
+    /// Phis whose operands are being added. They must not be judged trivial early.
+    filling: HashSet<Variable>,
+    /// The phis that take each value as an operand, with their blocks.
+    phi_users: HashMap<Variable, Vec<(BlockId, Variable)>>,
+    /// Removed trivial phis and the value each one stood for, which may be another
+    /// removed phi.
+    replaced: HashMap<Variable, RValue>,
+}
+
+impl<'f> FunctionBuilder<'f> {
+    pub fn new(func: &'f mut Function) -> FunctionBuilder<'f> {
+        FunctionBuilder {
+            func,
+            var_types: Vec::new(),
+            current_defs: Vec::new(),
+            sealed: HashSet::new(),
+            incomplete_phis: HashMap::new(),
+            filling: HashSet::new(),
+            phi_users: HashMap::new(),
+            replaced: HashMap::new(),
+        }
+    }
+
+    pub fn create_block(&mut self) -> BlockId {
+        self.func.create_block()
+    }
+
+    pub fn emitter(&mut self, block: BlockId) -> BasicBlockEmitter<'_> {
+        self.func.emitter(block)
+    }
+
+    pub fn declare_var(&mut self, ty: typing::Type) -> SourceVar {
+        self.var_types.push(ty);
+        self.current_defs.push(HashMap::new());
+        SourceVar(self.var_types.len() - 1)
+    }
+
+    pub fn def_var<R: Into<RValue>>(&mut self, block: BlockId, var: SourceVar, val: R) {
+        let val = val.into();
+        assert_eq!(
+            val.data_ty(),
+            self.var_types[var.0],
+            "assigned value has the variable's type"
+        );
+        self.current_defs[var.0].insert(block, val);
+    }
+
+    /// Reads a variable at the end of what has been emitted into `block` so far.
+    /// Reading a variable that is not assigned on some path yields zero.
+    pub fn use_var(&mut self, block: BlockId, var: SourceVar) -> RValue {
+        match self.current_defs[var.0].get(&block) {
+            Some(val) => self.resolve(*val),
+            None => self.use_var_recursive(block, var),
+        }
+    }
+
+    pub fn is_sealed(&self, block: BlockId) -> bool {
+        self.sealed.contains(&block)
+    }
+
+    /// Declares that no more predecessors will be added to `block`, which completes
+    /// the phis that were created while its predecessors were still unknown.
+    pub fn seal_block(&mut self, block: BlockId) {
+        assert!(!self.is_sealed(block), "block {} is already sealed", block);
+        for (var, phi) in self.incomplete_phis.remove(&block).unwrap_or_default() {
+            self.add_phi_operands(block, var, phi);
+        }
+        self.sealed.insert(block);
+    }
+
+    /// Finishes construction, and rewrites the operands that are still removed phis.
+    /// Every block has to be sealed by now, and the function is only complete after
+    /// this.
+    pub fn finish(mut self) {
+        for block in self.func.block_ids() {
+            assert!(self.is_sealed(block), "block {} was never sealed", block);
+        }
+        let removed: Vec<Variable> = self.replaced.keys().copied().collect();
+        let replaced: HashMap<Variable, RValue> = removed
+            .into_iter()
+            .map(|phi| (phi, self.resolve(RValue::Var(phi))))
+            .collect();
+        if !replaced.is_empty() {
+            for block in self.func.block_ids().collect::<Vec<_>>() {
+                let bb = self.func.block_mut(block);
+                let operands = bb
+                    .ins_list
+                    .iter_mut()
+                    .flat_map(Ins::operands_mut)
+                    .chain(bb.terminator.iter_mut().flat_map(Terminator::operands_mut));
+                for val in operands {
+                    if let RValue::Var(var) = val {
+                        if let Some(with) = replaced.get(var) {
+                            *val = *with;
+                        }
+                    }
+                }
+            }
+        }
+        super::verify::debug_verify(self.func, "SSA construction");
+    }
+
+    fn use_var_recursive(&mut self, block: BlockId, var: SourceVar) -> RValue {
+        let ty = self.var_types[var.0];
+        let val = if !self.is_sealed(block) {
+            let phi = self.func.emitter(block).emit_phi(ty, Vec::new());
+            self.incomplete_phis
+                .entry(block)
+                .or_default()
+                .push((var, phi));
+            RValue::Var(phi)
+        } else {
+            let preds = self.func.predecessors(block);
+            match preds.as_slice() {
+                [] => RValue::Lit(Literal::zero(ty)),
+                [pred] => self.use_var(*pred, var),
+                _ => {
+                    // Break cycles by defining the variable as the phi before reading
+                    // its operands.
+                    let phi = self.func.emitter(block).emit_phi(ty, Vec::new());
+                    self.def_var(block, var, phi);
+                    self.add_phi_operands(block, var, phi)
+                }
+            }
+        };
+        self.def_var(block, var, val);
+        val
+    }
+
+    fn add_phi_operands(&mut self, block: BlockId, var: SourceVar, phi: Variable) -> RValue {
+        self.filling.insert(phi);
+        for pred in self.func.predecessors(block) {
+            let val = self.use_var(pred, var);
+            self.phi_mut(block, phi)
+                .expect("phi being filled is still in its block")
+                .push((pred, val));
+            if let RValue::Var(used) = val {
+                let users = self.phi_users.entry(used).or_default();
+                if used != phi && !users.contains(&(block, phi)) {
+                    users.push((block, phi));
+                }
+            }
+        }
+        self.filling.remove(&phi);
+        self.try_remove_trivial_phi(block, phi)
+    }
+
+    fn try_remove_trivial_phi(&mut self, block: BlockId, phi: Variable) -> RValue {
+        if self.filling.contains(&phi) {
+            return RValue::Var(phi);
+        }
+        let incoming: Vec<RValue> = match self.phi_mut(block, phi) {
+            Some(incoming) => incoming.iter().map(|(_, val)| *val).collect(),
+            None => return RValue::Var(phi),
+        };
+        let mut same: Option<RValue> = None;
+        for val in incoming {
+            let val = self.resolve(val);
+            if Some(val) == same || val == RValue::Var(phi) {
+                continue;
+            }
+            if same.is_some() {
+                // The phi merges at least two values.
+                return RValue::Var(phi);
+            }
+            same = Some(val);
+        }
+        // A phi that only references itself sits in unreachable code.
+        let same = same.unwrap_or_else(|| RValue::Lit(Literal::zero(phi.data_ty())));
+
+        self.func.block_mut(block).ins_list.retain(|ins| match ins {
+            Ins::Phi(dest, _) => *dest != phi,
+            _ => true,
+        });
+        self.replaced.insert(phi, same);
+        let users = self.phi_users.remove(&phi).unwrap_or_default();
+        if let RValue::Var(same) = same {
+            let same_users = self.phi_users.entry(same).or_default();
+            for user in users.iter() {
+                if user.1 != same && !same_users.contains(user) {
+                    same_users.push(*user);
+                }
+            }
+        }
+
+        for (user_block, user) in users {
+            self.try_remove_trivial_phi(user_block, user);
+        }
+        same
+    }
+
+    /// The value `val` stands for once the removed phis are replaced. Chains of
+    /// replacements are shortened on the way.
+    fn resolve(&mut self, val: RValue) -> RValue {
+        let mut resolved = val;
+        let mut chain = Vec::new();
+        while let RValue::Var(var) = resolved {
+            match self.replaced.get(&var) {
+                Some(next) => {
+                    chain.push(var);
+                    resolved = *next;
+                }
+                None => break,
+            }
+        }
+        for var in chain {
+            self.replaced.insert(var, resolved);
+        }
+        resolved
+    }
+
+    fn phi_mut(&mut self, block: BlockId, phi: Variable) -> Option<&mut Vec<(BlockId, RValue)>> {
+        self.func
+            .block_mut(block)
+            .ins_list
+            .iter_mut()
+            .find_map(|ins| match ins {
+                Ins::Phi(dest, incoming) if *dest == phi => Some(incoming),
+                _ => None,
+            })
+    }
+}
@@ -0,0 +1,78 @@
+use super::{BlockId, Function};
+
+/// Predecessor and successor lists for every block of a function.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    preds: Vec<Vec<BlockId>>,
+    succs: Vec<Vec<BlockId>>,
+}
+
+impl Cfg {
+    pub fn compute(func: &Function) -> Cfg {
+        Cfg::from_successors(func.blocks().map(|(_, bb)| bb.successors()).collect())
+    }
+
+    /// The graph where block `i` goes to `succs[i]`, which need not be the blocks of a
+    /// function, like the reversed CFG post-dominators are found on.
+    pub(super) fn from_successors(succs: Vec<Vec<BlockId>>) -> Cfg {
+        let mut preds = vec![Vec::new(); succs.len()];
+        for (id, block_succs) in succs.iter().enumerate() {
+            for succ in block_succs {
+                if !preds[succ.0].contains(&BlockId(id)) {
+                    preds[succ.0].push(BlockId(id));
+                }
+            }
+        }
+        Cfg { preds, succs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.succs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succs.is_empty()
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> &[BlockId] {
+        &self.preds[block.0]
+    }
+
+    pub fn successors(&self, block: BlockId) -> &[BlockId] {
+        &self.succs[block.0]
+    }
+
+    /// Blocks reachable from `entry` in postorder.
+    pub fn postorder(&self, entry: BlockId) -> Vec<BlockId> {
+        let mut visited = vec![false; self.len()];
+        let mut order = Vec::with_capacity(self.len());
+        // Explicit stack of (block, next successor to visit) so deep CFGs don't overflow.
+        let mut stack = vec![(entry, 0)];
+        visited[entry.0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            let block = *block;
+            match self.succs[block.0].get(*next) {
+                Some(&succ) => {
+                    *next += 1;
+                    if !visited[succ.0] {
+                        visited[succ.0] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => {
+                    order.push(block);
+                    stack.pop();
+                }
+            }
+        }
+        order
+    }
+
+    /// Blocks reachable from `entry` in reverse postorder, so every block comes
+    /// before its successors except along back edges.
+    pub fn reverse_postorder(&self, entry: BlockId) -> Vec<BlockId> {
+        let mut order = self.postorder(entry);
+        order.reverse();
+        order
+    }
+}
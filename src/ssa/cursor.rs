@@ -0,0 +1,207 @@
+//! Editing of the instructions already in a function.
+//!
+//! A [`Cursor`] points between two instructions of a block, or before the first or
+//! after the last. Inserting puts the instruction at the cursor and moves the cursor
+//! past it, so a sequence of inserts comes out in order. Removing or replacing acts
+//! on the instruction right after the cursor. Values keep their ids through every
+//! edit, so [`Variable`]s held by a pass stay valid.
+
+use super::{BasicBlock, BlockId, Function, Ins, Terminator, Variable};
+use crate::typing;
+
+pub struct Cursor<'f> {
+    func: &'f mut Function,
+    block: BlockId,
+    pos: usize,
+}
+
+impl Function {
+    /// A cursor before the instruction at `index` of `block`. An `index` equal to the
+    /// number of instructions is the end of the block, before its terminator.
+    pub fn cursor(&mut self, block: BlockId, index: usize) -> Cursor<'_> {
+        assert!(
+            index <= self.block(block).ins_list().len(),
+            "cursor is within the block"
+        );
+        Cursor {
+            func: self,
+            block,
+            pos: index,
+        }
+    }
+
+    /// A cursor after the phis of `block`, where other instructions may be inserted
+    /// first.
+    pub fn cursor_after_phis(&mut self, block: BlockId) -> Cursor<'_> {
+        let index = self.block(block).phis().count();
+        self.cursor(block, index)
+    }
+
+    /// A cursor at the end of `block`, before its terminator.
+    pub fn cursor_at_end(&mut self, block: BlockId) -> Cursor<'_> {
+        let index = self.block(block).ins_list().len();
+        self.cursor(block, index)
+    }
+}
+
+impl Cursor<'_> {
+    pub fn func(&self) -> &Function {
+        self.func
+    }
+
+    pub fn block(&self) -> BlockId {
+        self.block
+    }
+
+    /// Index of the instruction after the cursor.
+    pub fn index(&self) -> usize {
+        self.pos
+    }
+
+    /// The current block, to be changed. The def-use chains of the function are
+    /// dropped since they may be asked for between edits.
+    fn bb(&mut self) -> &mut BasicBlock {
+        self.func.def_use.take();
+        &mut self.func.blocks[self.block.0]
+    }
+
+    /// The instruction after the cursor, `None` at the end of the block.
+    pub fn current(&self) -> Option<&Ins> {
+        self.func.block(self.block).ins_list().get(self.pos)
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.pos == self.func.block(self.block).ins_list().len()
+    }
+
+    /// Moves past the instruction after the cursor. Returns `false` at the end of the
+    /// block.
+    pub fn step_forward(&mut self) -> bool {
+        match self.is_at_end() {
+            true => false,
+            false => {
+                self.pos += 1;
+                true
+            }
+        }
+    }
+
+    /// Moves back before the previous instruction. Returns `false` at the start of the
+    /// block.
+    pub fn step_back(&mut self) -> bool {
+        match self.pos {
+            0 => false,
+            _ => {
+                self.pos -= 1;
+                true
+            }
+        }
+    }
+
+    /// Moves to before the instruction at `index` of `block`.
+    pub fn goto(&mut self, block: BlockId, index: usize) {
+        assert!(
+            index <= self.func.block(block).ins_list().len(),
+            "cursor is within the block"
+        );
+        self.block = block;
+        self.pos = index;
+    }
+
+    /// A fresh value of type `ty` for an instruction that is about to be inserted.
+    pub fn create_var(&mut self, ty: typing::Type) -> Variable {
+        self.func.sv.create_var(ty)
+    }
+
+    /// Inserts `ins` at the cursor, which ends up after it.
+    pub fn insert(&mut self, ins: Ins) {
+        let pos = self.pos;
+        self.bb().ins_list.insert(pos, ins);
+        self.pos += 1;
+    }
+
+    /// Inserts `ins` after the instruction after the cursor, without moving the
+    /// cursor.
+    pub fn insert_after(&mut self, ins: Ins) {
+        assert!(!self.is_at_end(), "there is an instruction to insert after");
+        let pos = self.pos + 1;
+        self.bb().ins_list.insert(pos, ins);
+    }
+
+    /// Removes and returns the instruction after the cursor. Uses of the value it
+    /// defined are left alone.
+    pub fn remove(&mut self) -> Ins {
+        assert!(!self.is_at_end(), "there is an instruction to remove");
+        let pos = self.pos;
+        self.bb().ins_list.remove(pos)
+    }
+
+    /// Puts `ins` in place of the instruction after the cursor, which is returned. The
+    /// def-use chains are kept.
+    pub fn replace(&mut self, ins: Ins) -> Ins {
+        assert!(!self.is_at_end(), "there is an instruction to replace");
+        self.func.replace_ins(self.block, self.pos, ins)
+    }
+
+    /// Replaces the terminator of the block, returning the old one.
+    pub fn set_terminator(&mut self, term: Terminator) -> Option<Terminator> {
+        self.bb().terminator.replace(term)
+    }
+
+    /// Moves the instruction after the cursor to before the instruction at `index` of
+    /// `block`, as `index` was before the move. The cursor stays where it was among
+    /// the other instructions.
+    pub fn move_to(&mut self, block: BlockId, index: usize) {
+        let ins = self.remove();
+        let index = match block == self.block && index > self.pos {
+            true => index - 1,
+            false => index,
+        };
+        if block == self.block && index < self.pos {
+            self.pos += 1;
+        }
+        self.func.blocks[block.0].ins_list.insert(index, ins);
+    }
+
+    /// Moves the instructions from the cursor on, and the terminator, to a new block
+    /// that the current block then jumps to. Phis of the successors take their
+    /// values from the new block. The cursor stays at the end of the current block.
+    ///
+    /// The cursor must be after the phis of the block, since the new block has only
+    /// the one predecessor and phis can't be moved there.
+    pub fn split_block(&mut self) -> BlockId {
+        let pos = self.pos;
+        assert!(
+            pos >= self.func.block(self.block).phis().count(),
+            "block is split after its phis"
+        );
+        let new = self.func.create_block();
+        let tail = self.bb().ins_list.split_off(pos);
+        let term = self.bb().terminator.replace(Terminator::Jmp(new));
+        let old = self.block;
+        let succs = term.as_ref().map_or(Vec::new(), |term| term.successors());
+        let bb = &mut self.func.blocks[new.0];
+        bb.ins_list = tail;
+        bb.terminator = term;
+        for succ in succs {
+            self.func.blocks[succ.0].rename_pred(old, new);
+        }
+        new
+    }
+}
+
+impl BasicBlock {
+    /// Makes the phis of the block take the values they had from `old` from `new`
+    /// instead.
+    pub(super) fn rename_pred(&mut self, old: BlockId, new: BlockId) {
+        for ins in self.ins_list.iter_mut() {
+            if let Ins::Phi(_, incoming) = ins {
+                for (from, _) in incoming.iter_mut() {
+                    if *from == old {
+                        *from = new;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Def-use and use-def chains of the values of a function.
+//!
+//! A [`Function`] builds its [`DefUse`] the first time it is asked for the uses or
+//! the definition of a value, and keeps it until its instructions may change, that is
+//! until a block or an emitter is borrowed mutably. Rewriting uses with
+//! [`Function::replace_all_uses_with`] and instructions with [`Function::replace_ins`]
+//! updates the chains instead of dropping them.
+
+use super::{BlockId, Function, Ins, RValue, Variable};
+use crate::typing::Typed;
+
+/// Where a value is defined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Def {
+    /// The parameter at this position, defined on entry.
+    Param(usize),
+    /// The instruction at this index of the block.
+    Ins(BlockId, usize),
+}
+
+/// Where a value is used: an operand of the instruction at an index of a block, or of
+/// the block's terminator.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Use {
+    Ins(BlockId, usize),
+    Term(BlockId),
+}
+
+impl Use {
+    pub fn block(&self) -> BlockId {
+        match self {
+            Use::Ins(block, _) | Use::Term(block) => *block,
+        }
+    }
+
+    /// Where the use comes in a walk over the blocks in order.
+    fn order(&self) -> (BlockId, usize) {
+        match self {
+            Use::Ins(block, i) => (*block, *i),
+            Use::Term(block) => (*block, usize::MAX),
+        }
+    }
+}
+
+/// The definition and the uses of every value of a function, by value id. An
+/// instruction that has a value as more than one operand uses it more than once.
+#[derive(Debug, Clone, Default)]
+pub struct DefUse {
+    defs: Vec<Option<Def>>,
+    uses: Vec<Vec<Use>>,
+}
+
+impl DefUse {
+    pub fn compute(func: &Function) -> DefUse {
+        let n = func.supervisor().vars().len();
+        let mut defs = vec![None; n];
+        let mut uses = vec![Vec::new(); n];
+        for (i, param) in func.params().iter().enumerate() {
+            defs[param.id()] = Some(Def::Param(i));
+        }
+        let mut add_use = |val: &RValue, site: Use| {
+            if let RValue::Var(var) = val {
+                uses[var.id()].push(site);
+            }
+        };
+        for (id, bb) in func.blocks() {
+            for (i, ins) in bb.ins_list().iter().enumerate() {
+                if let Some(dest) = ins.dest() {
+                    defs[dest.id()] = Some(Def::Ins(id, i));
+                }
+                for val in ins.operands() {
+                    add_use(val, Use::Ins(id, i));
+                }
+            }
+            for val in bb.terminator().into_iter().flat_map(|term| term.operands()) {
+                add_use(val, Use::Term(id));
+            }
+        }
+        DefUse { defs, uses }
+    }
+
+    /// The definition of `var`, or `None` if nothing defines it.
+    pub fn def_of(&self, var: Variable) -> Option<Def> {
+        self.defs.get(var.id()).copied().flatten()
+    }
+
+    /// Every use of `var`, in block order and in order within a block.
+    pub fn uses_of(&self, var: Variable) -> &[Use] {
+        self.uses.get(var.id()).map_or(&[], |uses| uses.as_slice())
+    }
+
+    pub fn is_used(&self, var: Variable) -> bool {
+        !self.uses_of(var).is_empty()
+    }
+
+    /// Moves the uses of `old` over to `new`, after the operands were rewritten.
+    pub(super) fn replace(&mut self, old: Variable, new: &RValue) {
+        let Some(uses) = self.uses.get_mut(old.id()) else {
+            return;
+        };
+        let moved = std::mem::take(uses);
+        if let RValue::Var(new) = new {
+            if self.uses.len() <= new.id() {
+                self.uses.resize(new.id() + 1, Vec::new());
+            }
+            let uses = &mut self.uses[new.id()];
+            uses.extend(moved);
+            uses.sort_by_key(Use::order);
+        }
+    }
+
+    /// Updates the chains after the instruction at `index` of `block` was replaced,
+    /// from `old` to `new`.
+    pub(super) fn replace_ins(&mut self, block: BlockId, index: usize, old: &Ins, new: &Ins) {
+        let site = Use::Ins(block, index);
+        if let Some(dest) = old.dest() {
+            self.defs[dest.id()] = None;
+        }
+        for val in old.operands() {
+            if let RValue::Var(var) = val {
+                let uses = &mut self.uses[var.id()];
+                if let Some(pos) = uses.iter().position(|use_| *use_ == site) {
+                    uses.remove(pos);
+                }
+            }
+        }
+        if let Some(dest) = new.dest() {
+            if self.defs.len() <= dest.id() {
+                self.defs.resize(dest.id() + 1, None);
+            }
+            self.defs[dest.id()] = Some(Def::Ins(block, index));
+        }
+        for val in new.operands() {
+            if let RValue::Var(var) = val {
+                if self.uses.len() <= var.id() {
+                    self.uses.resize(var.id() + 1, Vec::new());
+                }
+                let uses = &mut self.uses[var.id()];
+                let pos = uses.partition_point(|use_| use_.order() < site.order());
+                uses.insert(pos, site);
+            }
+        }
+    }
+}
+
+impl Function {
+    /// The def-use chains of the function, built when they are first needed after a
+    /// change.
+    pub fn def_use(&self) -> &DefUse {
+        self.def_use.get_or_init(|| DefUse::compute(self))
+    }
+
+    pub fn def_of(&self, var: Variable) -> Option<Def> {
+        self.def_use().def_of(var)
+    }
+
+    pub fn uses_of(&self, var: Variable) -> &[Use] {
+        self.def_use().uses_of(var)
+    }
+
+    /// The instruction that defines `var`, if it is not a parameter.
+    pub fn def_ins(&self, var: Variable) -> Option<&Ins> {
+        match self.def_of(var)? {
+            Def::Ins(block, i) => Some(&self.block(block).ins_list()[i]),
+            Def::Param(..) => None,
+        }
+    }
+
+    /// Rewrites every operand that is `old` to `new`, which has the same type. The
+    /// definition of `old` stays.
+    pub fn replace_all_uses_with<R: Into<RValue>>(&mut self, old: Variable, new: R) {
+        let new = new.into();
+        assert_eq!(
+            old.data_ty(),
+            new.data_ty(),
+            "replacement has the type of the value"
+        );
+        if new == RValue::Var(old) {
+            return;
+        }
+        let mut uses = self.uses_of(old).to_vec();
+        uses.dedup();
+        for site in uses {
+            let operands = match site {
+                Use::Ins(block, i) => self.blocks[block.0].ins_list[i].operands_mut(),
+                Use::Term(block) => match &mut self.blocks[block.0].terminator {
+                    Some(term) => term.operands_mut(),
+                    None => Vec::new(),
+                },
+            };
+            for operand in operands {
+                if *operand == RValue::Var(old) {
+                    *operand = new;
+                }
+            }
+        }
+        if let Some(def_use) = self.def_use.get_mut() {
+            def_use.replace(old, &new);
+        }
+    }
+
+    /// Puts `new` in place of the instruction at `index` of `block` and returns the old
+    /// one. The def-use chains are updated rather than dropped.
+    pub fn replace_ins(&mut self, block: BlockId, index: usize, new: Ins) -> Ins {
+        let old = std::mem::replace(&mut self.blocks[block.0].ins_list[index], new);
+        if let Some(def_use) = self.def_use.get_mut() {
+            def_use.replace_ins(block, index, &old, &self.blocks[block.0].ins_list[index]);
+        }
+        old
+    }
+}
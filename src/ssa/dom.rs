@@ -0,0 +1,329 @@
+//! Dominance analysis over the CFG of a function.
+//!
+//! Immediate dominators are found with the iterative algorithm of Cooper, Harvey and
+//! Kennedy, "A Simple, Fast Dominance Algorithm". The dominator tree is then numbered
+//! in pre and post order, which answers "does `a` dominate `b`" in constant time.
+//! Post-dominators are the dominators of the reversed CFG, where every returning
+//! block flows into a virtual exit.
+
+use super::cfg::Cfg;
+use super::{BlockId, Function};
+
+/// Dominator tree over nodes `0..n` of some graph, computed from `root`.
+#[derive(Debug, Clone)]
+struct Tree {
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    pre: Vec<usize>,
+    post: Vec<usize>,
+    /// Nodes in preorder of the tree.
+    preorder: Vec<usize>,
+}
+
+const UNREACHABLE: usize = usize::MAX;
+
+impl Tree {
+    fn compute(cfg: &Cfg, root: usize) -> Tree {
+        let n = cfg.len();
+        let postorder: Vec<usize> = cfg
+            .postorder(BlockId(root))
+            .iter()
+            .map(|block| block.0)
+            .collect();
+        let mut po_num = vec![UNREACHABLE; n];
+        for (i, node) in postorder.iter().enumerate() {
+            po_num[*node] = i;
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; n];
+        idom[root] = Some(root);
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while po_num[a] < po_num[b] {
+                    a = idom[a].expect("processed node has an idom");
+                }
+                while po_num[b] < po_num[a] {
+                    b = idom[b].expect("processed node has an idom");
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in postorder.iter().rev().filter(|node| **node != root) {
+                let mut new_idom: Option<usize> = None;
+                for pred in cfg.predecessors(BlockId(*node)) {
+                    if idom[pred.0].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred.0,
+                        Some(cur) => intersect(&idom, pred.0, cur),
+                    });
+                }
+                if new_idom != idom[*node] {
+                    idom[*node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[root] = None;
+
+        let mut children = vec![Vec::new(); n];
+        for node in postorder.iter().rev() {
+            if let Some(parent) = idom[*node] {
+                children[parent].push(*node);
+            }
+        }
+
+        let mut pre = vec![UNREACHABLE; n];
+        let mut post = vec![UNREACHABLE; n];
+        let mut preorder = Vec::with_capacity(postorder.len());
+        let mut post_count = 0;
+        let mut stack = vec![(root, 0)];
+        pre[root] = 0;
+        preorder.push(root);
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match children[node].get(*next) {
+                Some(&child) => {
+                    *next += 1;
+                    pre[child] = preorder.len();
+                    preorder.push(child);
+                    stack.push((child, 0));
+                }
+                None => {
+                    post[node] = post_count;
+                    post_count += 1;
+                    stack.pop();
+                }
+            }
+        }
+
+        Tree {
+            idom,
+            children,
+            pre,
+            post,
+            preorder,
+        }
+    }
+
+    fn is_reachable(&self, node: usize) -> bool {
+        self.pre[node] != UNREACHABLE
+    }
+
+    fn dominates(&self, a: usize, b: usize) -> bool {
+        self.is_reachable(a)
+            && self.is_reachable(b)
+            && self.pre[a] <= self.pre[b]
+            && self.post[b] <= self.post[a]
+    }
+
+    /// Dominance frontiers, using the formulation from the same paper: a node is in
+    /// the frontier of every node on the path from its predecessors up to, but
+    /// excluding, its immediate dominator. The root has no immediate dominator, so it
+    /// is in the frontier of everything on a path back to it.
+    fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut df: Vec<Vec<usize>> = vec![Vec::new(); cfg.len()];
+        for node in 0..cfg.len() {
+            if !self.is_reachable(node) {
+                continue;
+            }
+            for pred in cfg.predecessors(BlockId(node)) {
+                let mut runner = pred.0;
+                while self.is_reachable(runner) && Some(runner) != self.idom[node] {
+                    if !df[runner].contains(&node) {
+                        df[runner].push(node);
+                    }
+                    match self.idom[runner] {
+                        Some(up) => runner = up,
+                        None => break,
+                    }
+                }
+            }
+        }
+        df
+    }
+}
+
+fn to_blocks(nodes: &[usize]) -> Vec<BlockId> {
+    nodes.iter().map(|node| BlockId(*node)).collect()
+}
+
+/// The dominator tree of a function. Blocks that can't be reached from the entry are
+/// not part of the tree: they neither dominate nor are dominated by anything.
+#[derive(Debug, Clone)]
+pub struct DomTree {
+    tree: Tree,
+    frontiers: Vec<Vec<usize>>,
+}
+
+impl DomTree {
+    pub fn compute(func: &Function) -> DomTree {
+        DomTree::from_cfg(&func.cfg(), func.entry())
+    }
+
+    pub fn from_cfg(cfg: &Cfg, entry: BlockId) -> DomTree {
+        let tree = Tree::compute(cfg, entry.0);
+        let frontiers = tree.frontiers(cfg);
+        DomTree { tree, frontiers }
+    }
+
+    /// The immediate dominator, or `None` for the entry and unreachable blocks.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.tree.idom[block.0].map(BlockId)
+    }
+
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        to_blocks(&self.tree.children[block.0])
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.tree.is_reachable(block.0)
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`. Every block
+    /// dominates itself.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        self.tree.dominates(a.0, b.0)
+    }
+
+    pub fn strictly_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    /// Position of the block in a preorder walk of the tree, `None` if unreachable.
+    pub fn pre_number(&self, block: BlockId) -> Option<usize> {
+        Some(self.tree.pre[block.0]).filter(|n| *n != UNREACHABLE)
+    }
+
+    /// Position of the block in a postorder walk of the tree, `None` if unreachable.
+    pub fn post_number(&self, block: BlockId) -> Option<usize> {
+        Some(self.tree.post[block.0]).filter(|n| *n != UNREACHABLE)
+    }
+
+    /// Reachable blocks with every block before the blocks it dominates.
+    pub fn preorder(&self) -> Vec<BlockId> {
+        to_blocks(&self.tree.preorder)
+    }
+
+    /// The closest block that dominates both `a` and `b`.
+    pub fn nearest_common_dominator(&self, mut a: BlockId, mut b: BlockId) -> Option<BlockId> {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return None;
+        }
+        while !self.dominates(a, b) {
+            a = self.idom(a)?;
+        }
+        while !self.dominates(b, a) {
+            b = self.idom(b)?;
+        }
+        Some(a)
+    }
+
+    /// Blocks where the dominance of `block` ends: they have a predecessor dominated by
+    /// `block` without being strictly dominated by it.
+    pub fn frontier(&self, block: BlockId) -> Vec<BlockId> {
+        to_blocks(&self.frontiers[block.0])
+    }
+
+    /// The iterated dominance frontier of `blocks`, which is where phis are needed for
+    /// a variable assigned in those blocks.
+    pub fn iterated_frontier(&self, blocks: &[BlockId]) -> Vec<BlockId> {
+        let mut result: Vec<BlockId> = Vec::new();
+        let mut work: Vec<BlockId> = blocks.to_vec();
+        while let Some(block) = work.pop() {
+            for df in self.frontier(block) {
+                if !result.contains(&df) {
+                    result.push(df);
+                    work.push(df);
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+}
+
+/// The post-dominator tree of a function, built on the reversed CFG. Every block
+/// that returns is a child of a virtual exit, which is not a block of the function.
+/// Blocks from which no return can be reached, like the body of an endless loop, are
+/// not part of the tree.
+#[derive(Debug, Clone)]
+pub struct PostDomTree {
+    tree: Tree,
+    frontiers: Vec<Vec<usize>>,
+    exit: usize,
+}
+
+impl PostDomTree {
+    pub fn compute(func: &Function) -> PostDomTree {
+        PostDomTree::from_cfg(&func.cfg(), func)
+    }
+
+    pub fn from_cfg(cfg: &Cfg, func: &Function) -> PostDomTree {
+        // The reversed graph goes from the exit to every returning block, and from every
+        // block to its predecessors.
+        let exit = cfg.len();
+        let mut reversed: Vec<Vec<BlockId>> = (0..exit)
+            .map(|b| cfg.predecessors(BlockId(b)).to_vec())
+            .collect();
+        reversed.push(
+            func.blocks()
+                .filter(|(_, bb)| matches!(bb.terminator(), Some(super::Terminator::Ret(..))))
+                .map(|(id, _)| id)
+                .collect(),
+        );
+        let reversed = Cfg::from_successors(reversed);
+        let tree = Tree::compute(&reversed, exit);
+        let frontiers = tree.frontiers(&reversed);
+        PostDomTree {
+            tree,
+            frontiers,
+            exit,
+        }
+    }
+
+    /// The immediate post-dominator. `None` means the block is only post-dominated by
+    /// the virtual exit, or can't reach it.
+    pub fn ipdom(&self, block: BlockId) -> Option<BlockId> {
+        self.tree.idom[block.0]
+            .filter(|node| *node != self.exit)
+            .map(BlockId)
+    }
+
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        to_blocks(&self.tree.children[block.0])
+    }
+
+    /// Blocks that only the virtual exit post-dominates.
+    pub fn roots(&self) -> Vec<BlockId> {
+        to_blocks(&self.tree.children[self.exit])
+    }
+
+    /// Whether a return can be reached from the block.
+    pub fn reaches_exit(&self, block: BlockId) -> bool {
+        self.tree.is_reachable(block.0)
+    }
+
+    /// Whether every path from `b` to a return goes through `a`.
+    pub fn post_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        self.tree.dominates(a.0, b.0)
+    }
+
+    pub fn strictly_post_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        a != b && self.post_dominates(a, b)
+    }
+
+    /// The post-dominance frontier: blocks whose branch decides whether `block` runs,
+    /// in other words the blocks `block` is control dependent on.
+    pub fn frontier(&self, block: BlockId) -> Vec<BlockId> {
+        self.frontiers[block.0]
+            .iter()
+            .filter(|node| **node != self.exit)
+            .map(|node| BlockId(*node))
+            .collect()
+    }
+}
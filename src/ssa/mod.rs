@@ -0,0 +1,1316 @@
+use crate::rtl;
+
+use super::typing::{self, Typed};
+use std::{cell::OnceCell, fmt, hash::Hash, hash::Hasher};
+
+pub mod builder;
+pub mod cfg;
+pub mod cursor;
+pub mod defuse;
+pub mod dom;
+pub mod parse;
+mod print;
+pub mod verify;
+
+#[derive(Default, Debug)]
+pub struct GLIRSupervisor {
+    variables: Vec<Variable>,
+}
+
+impl GLIRSupervisor {
+    pub fn new() -> GLIRSupervisor {
+        Self::default()
+    }
+
+    pub fn create_var(&mut self, ty: typing::Type) -> Variable {
+        let v = Variable {
+            id: self.variables.len(),
+            ty,
+        };
+        self.variables.push(v);
+        v
+    }
+
+    pub fn vars(&self) -> &Vec<Variable> {
+        &self.variables
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub(self) id: usize,
+    pub(self) ty: typing::Type,
+}
+
+impl Hash for Variable {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        hasher.write_usize(self.id);
+    }
+}
+
+impl Variable {
+    /*
+    pub fn ssa_bump(&self) -> Variable {
+        Variable {
+            name: self.name,
+            typ: self.typ,
+            id: self.id,
+        }
+    }*/
+
+    pub fn as_vir_reg(&self) -> rtl::VirRegister {
+        rtl::VirRegister {
+            bytes: self.data_ty().mem_size(),
+            n: self.id,
+            class: rtl::RegClass::of(self.data_ty()),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl From<Variable> for rtl::VirRegister {
+    fn from(v: Variable) -> Self {
+        v.as_vir_reg()
+    }
+}
+
+impl typing::Typed for Variable {
+    fn data_ty(&self) -> typing::Type {
+        self.ty
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.id)
+    }
+}
+
+pub enum BinOpTy {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr,
+    AShr,
+}
+
+pub enum UnOpTy {
+    Not,
+    Neg,
+}
+
+/// Predicate of a comparison. Signed and unsigned orderings are separate predicates,
+/// the operand type does not pick one.
+///
+/// Floats are compared with the `F` predicates. `Feq` and the orderings `Flt` to `Fge`
+/// are false when either operand is NaN, while `Fne` and the orderings `Fult` to `Fuge`
+/// are true then, so every predicate has an inverse.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CmpTy {
+    Eq,
+    Ne,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    Feq,
+    Fne,
+    Flt,
+    Fle,
+    Fgt,
+    Fge,
+    Fult,
+    Fule,
+    Fugt,
+    Fuge,
+}
+
+impl CmpTy {
+    pub const ALL: [CmpTy; 20] = [
+        CmpTy::Eq,
+        CmpTy::Ne,
+        CmpTy::Slt,
+        CmpTy::Sle,
+        CmpTy::Sgt,
+        CmpTy::Sge,
+        CmpTy::Ult,
+        CmpTy::Ule,
+        CmpTy::Ugt,
+        CmpTy::Uge,
+        CmpTy::Feq,
+        CmpTy::Fne,
+        CmpTy::Flt,
+        CmpTy::Fle,
+        CmpTy::Fgt,
+        CmpTy::Fge,
+        CmpTy::Fult,
+        CmpTy::Fule,
+        CmpTy::Fugt,
+        CmpTy::Fuge,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CmpTy::Eq => "eq",
+            CmpTy::Ne => "ne",
+            CmpTy::Slt => "slt",
+            CmpTy::Sle => "sle",
+            CmpTy::Sgt => "sgt",
+            CmpTy::Sge => "sge",
+            CmpTy::Ult => "ult",
+            CmpTy::Ule => "ule",
+            CmpTy::Ugt => "ugt",
+            CmpTy::Uge => "uge",
+            CmpTy::Feq => "feq",
+            CmpTy::Fne => "fne",
+            CmpTy::Flt => "flt",
+            CmpTy::Fle => "fle",
+            CmpTy::Fgt => "fgt",
+            CmpTy::Fge => "fge",
+            CmpTy::Fult => "fult",
+            CmpTy::Fule => "fule",
+            CmpTy::Fugt => "fugt",
+            CmpTy::Fuge => "fuge",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CmpTy> {
+        CmpTy::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    /// The predicate that gives the same result with the operands swapped.
+    pub fn swapped(&self) -> CmpTy {
+        match self {
+            CmpTy::Eq | CmpTy::Ne | CmpTy::Feq | CmpTy::Fne => *self,
+            CmpTy::Slt => CmpTy::Sgt,
+            CmpTy::Sle => CmpTy::Sge,
+            CmpTy::Sgt => CmpTy::Slt,
+            CmpTy::Sge => CmpTy::Sle,
+            CmpTy::Ult => CmpTy::Ugt,
+            CmpTy::Ule => CmpTy::Uge,
+            CmpTy::Ugt => CmpTy::Ult,
+            CmpTy::Uge => CmpTy::Ule,
+            CmpTy::Flt => CmpTy::Fgt,
+            CmpTy::Fle => CmpTy::Fge,
+            CmpTy::Fgt => CmpTy::Flt,
+            CmpTy::Fge => CmpTy::Fle,
+            CmpTy::Fult => CmpTy::Fugt,
+            CmpTy::Fule => CmpTy::Fuge,
+            CmpTy::Fugt => CmpTy::Fult,
+            CmpTy::Fuge => CmpTy::Fule,
+        }
+    }
+
+    /// The predicate that gives the opposite result.
+    pub fn inverse(&self) -> CmpTy {
+        match self {
+            CmpTy::Eq => CmpTy::Ne,
+            CmpTy::Ne => CmpTy::Eq,
+            CmpTy::Slt => CmpTy::Sge,
+            CmpTy::Sle => CmpTy::Sgt,
+            CmpTy::Sgt => CmpTy::Sle,
+            CmpTy::Sge => CmpTy::Slt,
+            CmpTy::Ult => CmpTy::Uge,
+            CmpTy::Ule => CmpTy::Ugt,
+            CmpTy::Ugt => CmpTy::Ule,
+            CmpTy::Uge => CmpTy::Ult,
+            CmpTy::Feq => CmpTy::Fne,
+            CmpTy::Fne => CmpTy::Feq,
+            CmpTy::Flt => CmpTy::Fuge,
+            CmpTy::Fle => CmpTy::Fugt,
+            CmpTy::Fgt => CmpTy::Fule,
+            CmpTy::Fge => CmpTy::Fult,
+            CmpTy::Fult => CmpTy::Fge,
+            CmpTy::Fule => CmpTy::Fgt,
+            CmpTy::Fugt => CmpTy::Fle,
+            CmpTy::Fuge => CmpTy::Flt,
+        }
+    }
+
+    pub fn is_equality(&self) -> bool {
+        matches!(self, CmpTy::Eq | CmpTy::Ne)
+    }
+
+    /// Whether the predicate compares floats.
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            CmpTy::Feq
+                | CmpTy::Fne
+                | CmpTy::Flt
+                | CmpTy::Fle
+                | CmpTy::Fgt
+                | CmpTy::Fge
+                | CmpTy::Fult
+                | CmpTy::Fule
+                | CmpTy::Fugt
+                | CmpTy::Fuge
+        )
+    }
+}
+
+/// Conversion between value types.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CastTy {
+    /// Signed integer to float.
+    SiToFp,
+    /// Unsigned integer to float.
+    UiToFp,
+    /// Float to signed integer, rounding towards zero.
+    FpToSi,
+    /// Float to unsigned integer, rounding towards zero.
+    FpToUi,
+    /// `f32` to `f64`.
+    FpExt,
+    /// `f64` to `f32`, rounding to nearest.
+    FpTrunc,
+    /// Integer or `Bool` to a wider integer, filling in zeros.
+    ZExt,
+    /// Integer to a wider integer, filling in copies of the sign bit.
+    SExt,
+    /// Integer to a narrower integer, or to a `Bool`, keeping the low bits.
+    Trunc,
+    /// Reinterprets the bits as another type of the same size, like `i32` as `u32`,
+    /// `f64` as `u64` or `ptr` as `i64`.
+    BitCast,
+}
+
+impl CastTy {
+    pub const ALL: [CastTy; 10] = [
+        CastTy::SiToFp,
+        CastTy::UiToFp,
+        CastTy::FpToSi,
+        CastTy::FpToUi,
+        CastTy::FpExt,
+        CastTy::FpTrunc,
+        CastTy::ZExt,
+        CastTy::SExt,
+        CastTy::Trunc,
+        CastTy::BitCast,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CastTy::SiToFp => "sitofp",
+            CastTy::UiToFp => "uitofp",
+            CastTy::FpToSi => "fptosi",
+            CastTy::FpToUi => "fptoui",
+            CastTy::FpExt => "fpext",
+            CastTy::FpTrunc => "fptrunc",
+            CastTy::ZExt => "zext",
+            CastTy::SExt => "sext",
+            CastTy::Trunc => "trunc",
+            CastTy::BitCast => "bitcast",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CastTy> {
+        CastTy::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    /// Whether the cast turns a value of type `from` into one of type `to`.
+    pub fn accepts(&self, from: typing::Type, to: typing::Type) -> bool {
+        match self {
+            CastTy::SiToFp => from.is_signed() && to.is_float(),
+            CastTy::UiToFp => from.is_unsigned() && to.is_float(),
+            CastTy::FpToSi => from.is_float() && to.is_signed(),
+            CastTy::FpToUi => from.is_float() && to.is_unsigned(),
+            CastTy::FpExt => from == typing::Type::F32 && to == typing::Type::F64,
+            CastTy::FpTrunc => from == typing::Type::F64 && to == typing::Type::F32,
+            CastTy::ZExt => match from {
+                typing::Type::Bool => to.is_integer(),
+                _ => from.is_integer() && to.is_integer() && to.mem_size() > from.mem_size(),
+            },
+            CastTy::SExt => from.is_integer() && to.is_integer() && to.mem_size() > from.mem_size(),
+            CastTy::Trunc => match to {
+                typing::Type::Bool => from.is_integer(),
+                _ => from.is_integer() && to.is_integer() && to.mem_size() < from.mem_size(),
+            },
+            CastTy::BitCast => {
+                from != to
+                    && from != typing::Type::Bool
+                    && to != typing::Type::Bool
+                    && from.mem_size() == to.mem_size()
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Literal {
+    Bool(bool),
+    F32(f32),
+    F64(f64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Ptr(u64),
+}
+
+impl Literal {
+    pub fn zero(ty: typing::Type) -> Literal {
+        Literal::from_bits(ty, 0)
+    }
+
+    /// Whether all bits are zero. `-0.0` is not.
+    pub fn is_zero(&self) -> bool {
+        self.to_bits() == 0
+    }
+
+    /// The literal of type `ty` with the low bits of `bits` as its two's complement
+    /// representation. A `Bool` is true for any odd `bits`.
+    pub fn from_bits(ty: typing::Type, bits: u64) -> Literal {
+        match ty {
+            typing::Type::Bool => Literal::Bool(bits & 1 == 1),
+            typing::Type::F32 => Literal::F32(f32::from_bits(bits as u32)),
+            typing::Type::F64 => Literal::F64(f64::from_bits(bits)),
+            typing::Type::I8 => Literal::I8(bits as i8),
+            typing::Type::I16 => Literal::I16(bits as i16),
+            typing::Type::I32 => Literal::I32(bits as i32),
+            typing::Type::I64 => Literal::I64(bits as i64),
+            typing::Type::U8 => Literal::U8(bits as u8),
+            typing::Type::U16 => Literal::U16(bits as u16),
+            typing::Type::U32 => Literal::U32(bits as u32),
+            typing::Type::U64 => Literal::U64(bits),
+            typing::Type::Ptr => Literal::Ptr(bits),
+        }
+    }
+
+    /// The two's complement representation of the literal, zero extended to 64 bits.
+    pub fn to_bits(&self) -> u64 {
+        match self {
+            Literal::Bool(val) => *val as u64,
+            Literal::F32(val) => val.to_bits() as u64,
+            Literal::F64(val) => val.to_bits(),
+            Literal::I8(val) => *val as u8 as u64,
+            Literal::I16(val) => *val as u16 as u64,
+            Literal::I32(val) => *val as u32 as u64,
+            Literal::I64(val) => *val as u64,
+            Literal::U8(val) => *val as u64,
+            Literal::U16(val) => *val as u64,
+            Literal::U32(val) => *val as u64,
+            Literal::U64(val) => *val,
+            Literal::Ptr(val) => *val,
+        }
+    }
+}
+
+/// Literals are equal when they have the same type and bits, so a NaN equals itself
+/// and `0.0` differs from `-0.0`.
+impl PartialEq for Literal {
+    fn eq(&self, other: &Literal) -> bool {
+        self.data_ty() == other.data_ty() && self.to_bits() == other.to_bits()
+    }
+}
+
+impl Eq for Literal {}
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.data_ty().hash(hasher);
+        hasher.write_u64(self.to_bits());
+    }
+}
+
+impl typing::Typed for Literal {
+    fn data_ty(&self) -> typing::Type {
+        match self {
+            Literal::Bool(..) => typing::Type::Bool,
+            Literal::F32(..) => typing::Type::F32,
+            Literal::F64(..) => typing::Type::F64,
+            Literal::I8(..) => typing::Type::I8,
+            Literal::I16(..) => typing::Type::I16,
+            Literal::I32(..) => typing::Type::I32,
+            Literal::I64(..) => typing::Type::I64,
+            Literal::U8(..) => typing::Type::U8,
+            Literal::U16(..) => typing::Type::U16,
+            Literal::U32(..) => typing::Type::U32,
+            Literal::U64(..) => typing::Type::U64,
+            Literal::Ptr(..) => typing::Type::Ptr,
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Bool(val) => write!(f, "{}", *val),
+            // Unlike `Display`, `Debug` keeps the decimal point of whole numbers and
+            // writes very large and small ones with an exponent.
+            Literal::F32(val) => write!(f, "{:?}", *val),
+            Literal::F64(val) => write!(f, "{:?}", *val),
+            Literal::I8(val) => write!(f, "{}", *val),
+            Literal::I16(val) => write!(f, "{}", *val),
+            Literal::I32(val) => write!(f, "{}", *val),
+            Literal::I64(val) => write!(f, "{}", *val),
+            Literal::U8(val) => write!(f, "{}", *val),
+            Literal::U16(val) => write!(f, "{}", *val),
+            Literal::U32(val) => write!(f, "{}", *val),
+            Literal::U64(val) => write!(f, "{}", *val),
+            Literal::Ptr(val) => write!(f, "{}", *val),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RValue {
+    Var(Variable),
+    Lit(Literal),
+}
+
+impl From<Variable> for RValue {
+    fn from(v: Variable) -> Self {
+        RValue::Var(v)
+    }
+}
+
+impl From<Literal> for RValue {
+    fn from(lit: Literal) -> Self {
+        RValue::Lit(lit)
+    }
+}
+
+impl typing::Typed for RValue {
+    fn data_ty(&self) -> typing::Type {
+        match self {
+            RValue::Lit(lit) => lit.data_ty(),
+            RValue::Var(var) => var.data_ty(),
+        }
+    }
+}
+
+impl fmt::Display for RValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RValue::Lit(lit) => fmt::Display::fmt(lit, f),
+            RValue::Var(var) => fmt::Display::fmt(var, f),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Ins {
+    Add(Variable, /* = */ RValue, /* + */ RValue),
+    Sub(Variable, /* = */ RValue, /* - */ RValue),
+    Mul(Variable, /* = */ RValue, /* * */ RValue),
+    /// Divides floats. Integers are divided by `SDiv` or `UDiv`.
+    Div(Variable, /* = */ RValue, /* / */ RValue),
+    /// Divides integers as signed, rounding towards zero. Dividing by zero, or the
+    /// smallest value by -1, is undefined.
+    SDiv(Variable, /* = */ RValue, /* / */ RValue),
+    /// Divides integers as unsigned. Dividing by zero is undefined.
+    UDiv(Variable, /* = */ RValue, /* / */ RValue),
+    /// The remainder of `SDiv`, which has the sign of the dividend. Undefined where
+    /// `SDiv` is.
+    SRem(Variable, /* = */ RValue, /* % */ RValue),
+    /// The remainder of `UDiv`. Undefined where `UDiv` is.
+    URem(Variable, /* = */ RValue, /* % */ RValue),
+    And(Variable, /* = */ RValue, /* & */ RValue),
+    Or(Variable, /* = */ RValue, /* | */ RValue),
+    Xor(Variable, /* = */ RValue, /* ^ */ RValue),
+    /// Shifts the first value left by the second, which is less than its bit width.
+    Shl(Variable, /* = */ RValue, /* << */ RValue),
+    /// Shifts right, filling in zeros. The count is less than the bit width.
+    LShr(Variable, /* = */ RValue, /* >> */ RValue),
+    /// Shifts right, filling in copies of the sign bit. The count is less than the bit
+    /// width.
+    AShr(Variable, /* = */ RValue, /* >> */ RValue),
+    /// Flips every bit of an integer, or a `Bool`.
+    Not(Variable, /* = ~ */ RValue),
+    /// Two's complement negation.
+    Neg(Variable, /* = - */ RValue),
+    Cpy(Variable, /* = */ RValue),
+    /// Compares two values of the same type, the result is a `Bool`.
+    Cmp(Variable, /* = */ CmpTy, RValue, RValue),
+    /// Converts a value to the type of the destination.
+    Cast(Variable, /* = */ CastTy, RValue),
+    /// Picks the first value if the `Bool` condition is true, otherwise the second.
+    /// The values are integers, `Bool`s or pointers.
+    Select(
+        Variable,
+        /* = */ RValue,
+        /* ? */ RValue,
+        /* : */ RValue,
+    ),
+    /// Picks the value paired with the predecessor control came from. Phis are
+    /// always placed before any other instruction of a block.
+    Phi(Variable, /* = */ Vec<(BlockId, RValue)>),
+    /// Reserves `size` bytes aligned to `align` in the frame of the function, the
+    /// result is their address. Every run of the instruction gives the same address.
+    Alloca(Variable, /* size */ u32, /* align */ u32),
+    /// Reads a value of the destination type from an address aligned to `align`.
+    Load(Variable, /* = * */ RValue, /* align */ u32),
+    /// Writes the second value to the address in the first, which is aligned to
+    /// `align`.
+    Store(
+        /* * */ RValue,
+        /* = */ RValue,
+        /* align */ u32,
+    ),
+    /// The address of a part of the data of the given type at the pointer. The first
+    /// index steps over whole values of the type, like indexing an array of them. Each
+    /// following index picks a field of a struct, which must be a literal, or an
+    /// element of an array. Indices of unsigned types are zero extended, others sign
+    /// extended.
+    Gep(
+        Variable,
+        /* = & */ typing::MemType,
+        /* * */ RValue,
+        /* [ */ Vec<RValue>, /* ] */
+    ),
+    /// The address of a global of the module, by name.
+    Addr(Variable, /* = & */ String),
+    /// Calls a function of the module or an extern by name. There is no result when
+    /// the callee returns nothing.
+    Call(
+        Option<Variable>,
+        /* = */ String,
+        /* ( */ Vec<RValue>, /* ) */
+    ),
+}
+
+impl Ins {
+    pub fn dest(&self) -> Option<Variable> {
+        match self {
+            Ins::Add(dest, ..)
+            | Ins::Sub(dest, ..)
+            | Ins::Mul(dest, ..)
+            | Ins::Div(dest, ..)
+            | Ins::SDiv(dest, ..)
+            | Ins::UDiv(dest, ..)
+            | Ins::SRem(dest, ..)
+            | Ins::URem(dest, ..)
+            | Ins::And(dest, ..)
+            | Ins::Or(dest, ..)
+            | Ins::Xor(dest, ..)
+            | Ins::Shl(dest, ..)
+            | Ins::LShr(dest, ..)
+            | Ins::AShr(dest, ..)
+            | Ins::Not(dest, ..)
+            | Ins::Neg(dest, ..)
+            | Ins::Cpy(dest, ..)
+            | Ins::Cmp(dest, ..)
+            | Ins::Cast(dest, ..)
+            | Ins::Select(dest, ..)
+            | Ins::Phi(dest, ..)
+            | Ins::Alloca(dest, ..)
+            | Ins::Load(dest, ..)
+            | Ins::Gep(dest, ..)
+            | Ins::Addr(dest, ..) => Some(*dest),
+            Ins::Store(..) => None,
+            Ins::Call(dest, ..) => *dest,
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&RValue> {
+        match self {
+            Ins::Add(_, a, b)
+            | Ins::Sub(_, a, b)
+            | Ins::Mul(_, a, b)
+            | Ins::Div(_, a, b)
+            | Ins::SDiv(_, a, b)
+            | Ins::UDiv(_, a, b)
+            | Ins::SRem(_, a, b)
+            | Ins::URem(_, a, b)
+            | Ins::And(_, a, b)
+            | Ins::Or(_, a, b)
+            | Ins::Xor(_, a, b)
+            | Ins::Shl(_, a, b)
+            | Ins::LShr(_, a, b)
+            | Ins::AShr(_, a, b) => vec![a, b],
+            Ins::Not(_, rhs) | Ins::Neg(_, rhs) | Ins::Cpy(_, rhs) => vec![rhs],
+            Ins::Cmp(_, _, a, b) => vec![a, b],
+            Ins::Cast(_, _, val) => vec![val],
+            Ins::Select(_, cond, a, b) => vec![cond, a, b],
+            Ins::Phi(_, incoming) => incoming.iter().map(|(_, val)| val).collect(),
+            Ins::Alloca(..) | Ins::Addr(..) => Vec::new(),
+            Ins::Load(_, ptr, _) => vec![ptr],
+            Ins::Store(ptr, val, _) => vec![ptr, val],
+            Ins::Gep(_, _, ptr, indices) => std::iter::once(ptr).chain(indices).collect(),
+            Ins::Call(_, _, args) => args.iter().collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut RValue> {
+        match self {
+            Ins::Add(_, a, b)
+            | Ins::Sub(_, a, b)
+            | Ins::Mul(_, a, b)
+            | Ins::Div(_, a, b)
+            | Ins::SDiv(_, a, b)
+            | Ins::UDiv(_, a, b)
+            | Ins::SRem(_, a, b)
+            | Ins::URem(_, a, b)
+            | Ins::And(_, a, b)
+            | Ins::Or(_, a, b)
+            | Ins::Xor(_, a, b)
+            | Ins::Shl(_, a, b)
+            | Ins::LShr(_, a, b)
+            | Ins::AShr(_, a, b) => vec![a, b],
+            Ins::Not(_, rhs) | Ins::Neg(_, rhs) | Ins::Cpy(_, rhs) => vec![rhs],
+            Ins::Cmp(_, _, a, b) => vec![a, b],
+            Ins::Cast(_, _, val) => vec![val],
+            Ins::Select(_, cond, a, b) => vec![cond, a, b],
+            Ins::Phi(_, incoming) => incoming.iter_mut().map(|(_, val)| val).collect(),
+            Ins::Alloca(..) | Ins::Addr(..) => Vec::new(),
+            Ins::Load(_, ptr, _) => vec![ptr],
+            Ins::Store(ptr, val, _) => vec![ptr, val],
+            Ins::Gep(_, _, ptr, indices) => std::iter::once(ptr).chain(indices).collect(),
+            Ins::Call(_, _, args) => args.iter_mut().collect(),
+        }
+    }
+
+    pub fn is_phi(&self) -> bool {
+        matches!(self, Ins::Phi(..))
+    }
+
+    /// Whether the instruction does more than compute its result: it writes memory,
+    /// calls, or may trap, like a division by a value that could be zero. Such
+    /// instructions have to stay even when their result is unused.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Ins::Store(..) | Ins::Call(..) => true,
+            Ins::UDiv(_, _, divisor) | Ins::URem(_, _, divisor) => {
+                !matches!(divisor, RValue::Lit(lit) if !lit.is_zero())
+            }
+            Ins::SDiv(_, _, divisor) | Ins::SRem(_, _, divisor) => !matches!(
+                divisor,
+                RValue::Lit(lit)
+                    if !lit.is_zero() && *lit != Literal::from_bits(lit.data_ty(), u64::MAX)
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub(crate) usize);
+
+impl BlockId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// The instruction that ends a basic block and decides where control goes next.
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jmp(BlockId),
+    /// Branches to the first block if the `Bool` condition is true, otherwise to the second.
+    Br(RValue, /* ? */ BlockId, /* : */ BlockId),
+    Ret(Option<RValue>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jmp(target) => vec![*target],
+            Terminator::Br(_, then, els) if then == els => vec![*then],
+            Terminator::Br(_, then, els) => vec![*then, *els],
+            Terminator::Ret(..) => Vec::new(),
+        }
+    }
+
+    /// The only block this terminator can go to, if it is known without running it.
+    pub fn known_target(&self) -> Option<BlockId> {
+        match self {
+            Terminator::Jmp(target) => Some(*target),
+            Terminator::Br(RValue::Lit(lit), then, els) => {
+                Some(if lit.is_zero() { *els } else { *then })
+            }
+            Terminator::Br(_, then, els) if then == els => Some(*then),
+            Terminator::Br(..) | Terminator::Ret(..) => None,
+        }
+    }
+
+    /// Makes every edge to `old` go to `new` instead.
+    pub fn replace_target(&mut self, old: BlockId, new: BlockId) {
+        let targets = match self {
+            Terminator::Jmp(target) => vec![target],
+            Terminator::Br(_, then, els) => vec![then, els],
+            Terminator::Ret(..) => Vec::new(),
+        };
+        for target in targets {
+            if *target == old {
+                *target = new;
+            }
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&RValue> {
+        match self {
+            Terminator::Br(cond, ..) => vec![cond],
+            Terminator::Ret(Some(val)) => vec![val],
+            Terminator::Jmp(..) | Terminator::Ret(None) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut RValue> {
+        match self {
+            Terminator::Br(cond, ..) => vec![cond],
+            Terminator::Ret(Some(val)) => vec![val],
+            Terminator::Jmp(..) | Terminator::Ret(None) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct BasicBlock {
+    pub(crate) ins_list: Vec<Ins>,
+    pub(crate) terminator: Option<Terminator>,
+}
+
+impl BasicBlock {
+    pub fn new() -> BasicBlock {
+        Self::default()
+    }
+
+    pub fn emitter<'a>(&'a mut self, sv: &'a mut GLIRSupervisor) -> BasicBlockEmitter<'a> {
+        BasicBlockEmitter { bb: self, sv }
+    }
+
+    pub fn ins_list(&self) -> &[Ins] {
+        &self.ins_list
+    }
+
+    pub fn terminator(&self) -> Option<&Terminator> {
+        self.terminator.as_ref()
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminator.is_some()
+    }
+
+    pub fn phis(&self) -> impl Iterator<Item = &Ins> + '_ {
+        self.ins_list.iter().take_while(|ins| ins.is_phi())
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        match &self.terminator {
+            Some(term) => term.successors(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Parameter and return types of a function. `ret` is `None` for functions that
+/// return nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signature {
+    pub params: Vec<typing::Type>,
+    pub ret: Option<typing::Type>,
+}
+
+impl Signature {
+    pub fn new(params: Vec<typing::Type>, ret: Option<typing::Type>) -> Signature {
+        Signature { params, ret }
+    }
+}
+
+/// A function made up of basic blocks connected by their terminators.
+/// The first block that is created is the entry block. Parameters are the first
+/// values of the function and are defined on entry.
+#[derive(Debug)]
+pub struct Function {
+    name: String,
+    sig: Signature,
+    params: Vec<Variable>,
+    pub(crate) blocks: Vec<BasicBlock>,
+    pub(crate) sv: GLIRSupervisor,
+    /// Dropped whenever instructions may change, see [`defuse`].
+    def_use: OnceCell<defuse::DefUse>,
+}
+
+impl Function {
+    pub fn new<S: Into<String>>(name: S, sig: Signature) -> Function {
+        let mut sv = GLIRSupervisor::new();
+        let params = sig.params.iter().map(|ty| sv.create_var(*ty)).collect();
+        Function {
+            name: name.into(),
+            sig,
+            params,
+            blocks: Vec::new(),
+            sv,
+            def_use: OnceCell::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.sig
+    }
+
+    pub fn params(&self) -> &[Variable] {
+        &self.params
+    }
+
+    pub fn create_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock::new());
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Removes every block but the entry that `keep` rejects, and numbers the others
+    /// again in the same order. Phis drop the values they take from removed blocks.
+    /// None of the blocks that are kept may branch to a removed one.
+    pub fn retain_blocks(&mut self, mut keep: impl FnMut(BlockId) -> bool) {
+        let mut count = 0;
+        let renamed: Vec<Option<BlockId>> = self
+            .block_ids()
+            .map(|id| {
+                (id == self.entry() || keep(id)).then(|| {
+                    count += 1;
+                    BlockId(count - 1)
+                })
+            })
+            .collect();
+        if count == self.blocks.len() {
+            return;
+        }
+        self.def_use.take();
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks
+            .into_iter()
+            .zip(&renamed)
+            .filter_map(|(bb, id)| id.map(|_| bb))
+            .collect();
+        let rename = |id: &mut BlockId| *id = renamed[id.0].expect("branch to a kept block");
+        for bb in self.blocks.iter_mut() {
+            for ins in bb.ins_list.iter_mut() {
+                if let Ins::Phi(_, incoming) = ins {
+                    incoming.retain_mut(|(from, _)| match renamed[from.0] {
+                        Some(id) => {
+                            *from = id;
+                            true
+                        }
+                        None => false,
+                    });
+                }
+            }
+            match &mut bb.terminator {
+                Some(Terminator::Jmp(target)) => rename(target),
+                Some(Terminator::Br(_, then, els)) => {
+                    rename(then);
+                    rename(els);
+                }
+                Some(Terminator::Ret(..)) | None => (),
+            }
+        }
+    }
+
+    /// Drops the values the phis of `block` take from `pred`, which no longer branches
+    /// there.
+    pub fn remove_incoming(&mut self, block: BlockId, pred: BlockId) {
+        for ins in self.block_mut(block).ins_list.iter_mut() {
+            if let Ins::Phi(_, incoming) = ins {
+                incoming.retain(|(from, _)| *from != pred);
+            }
+        }
+    }
+
+    pub fn entry(&self) -> BlockId {
+        assert!(!self.blocks.is_empty(), "function has no blocks");
+        BlockId(0)
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut BasicBlock {
+        self.def_use.take();
+        &mut self.blocks[id.0]
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &BasicBlock)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(i, bb)| (BlockId(i), bb))
+    }
+
+    pub fn supervisor(&self) -> &GLIRSupervisor {
+        &self.sv
+    }
+
+    pub fn emitter(&mut self, block: BlockId) -> BasicBlockEmitter<'_> {
+        self.def_use.take();
+        BasicBlockEmitter {
+            bb: &mut self.blocks[block.0],
+            sv: &mut self.sv,
+        }
+    }
+
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.block(block).successors()
+    }
+
+    /// Scans every terminator in the function. Use [`cfg::Cfg`] when predecessors
+    /// of many blocks are needed.
+    pub fn predecessors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks()
+            .filter(|(_, bb)| bb.successors().contains(&block))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn cfg(&self) -> cfg::Cfg {
+        cfg::Cfg::compute(self)
+    }
+}
+
+/// A function that is defined outside of the module and only called.
+#[derive(Debug, Clone)]
+pub struct ExternDecl {
+    pub name: String,
+    pub sig: Signature,
+}
+
+/// The initial contents of a global.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalInit {
+    /// That many zero bytes.
+    Zeroed(usize),
+    Bytes(Vec<u8>),
+    /// Literals one after the other, each aligned to its type like the fields of a
+    /// struct. A `bool` takes a byte.
+    Literals(Vec<Literal>),
+}
+
+impl GlobalInit {
+    fn layout(lits: &[Literal]) -> typing::MemType {
+        let fields = lits.iter().map(|lit| lit.data_ty().into()).collect();
+        typing::MemType::Struct(fields)
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            GlobalInit::Zeroed(size) => *size,
+            GlobalInit::Bytes(bytes) => bytes.len(),
+            GlobalInit::Literals(lits) => GlobalInit::layout(lits).size(),
+        }
+    }
+
+    /// The contents in memory, little endian. Padding between literals is zero.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            GlobalInit::Zeroed(size) => vec![0; *size],
+            GlobalInit::Bytes(bytes) => bytes.clone(),
+            GlobalInit::Literals(lits) => {
+                let layout = GlobalInit::layout(lits);
+                let mut bytes = vec![0; layout.size()];
+                for (i, lit) in lits.iter().enumerate() {
+                    let (offset, _) = layout.field(i).expect("a field per literal");
+                    let size = lit.data_ty().mem_size();
+                    bytes[offset..offset + size]
+                        .copy_from_slice(&lit.to_bits().to_le_bytes()[..size]);
+                }
+                bytes
+            }
+        }
+    }
+}
+
+/// Memory that lives as long as the program, addressed with [`Ins::Addr`]. Writing to
+/// a constant global is undefined.
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub constant: bool,
+    pub init: GlobalInit,
+    pub align: u32,
+}
+
+/// The functions of a program, the external functions they call and the globals they
+/// share. Calls and [`Ins::Addr`] refer to them by name.
+#[derive(Debug, Default)]
+pub struct Module {
+    functions: Vec<Function>,
+    externs: Vec<ExternDecl>,
+    globals: Vec<Global>,
+}
+
+impl Module {
+    pub fn new() -> Module {
+        Self::default()
+    }
+
+    pub fn add_function(&mut self, func: Function) {
+        self.functions.push(func);
+    }
+
+    pub fn declare_extern<S: Into<String>>(&mut self, name: S, sig: Signature) {
+        self.externs.push(ExternDecl {
+            name: name.into(),
+            sig,
+        });
+    }
+
+    pub fn add_global(&mut self, global: Global) {
+        self.globals.push(global);
+    }
+
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub fn functions_mut(&mut self) -> &mut [Function] {
+        &mut self.functions
+    }
+
+    pub fn externs(&self) -> &[ExternDecl] {
+        &self.externs
+    }
+
+    pub fn globals(&self) -> &[Global] {
+        &self.globals
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|global| global.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|func| func.name() == name)
+    }
+
+    /// The signature of the function or extern declaration called `name`.
+    pub fn signature_of(&self, name: &str) -> Option<&Signature> {
+        match self.function(name) {
+            Some(func) => Some(func.signature()),
+            None => self
+                .externs
+                .iter()
+                .find(|decl| decl.name == name)
+                .map(|decl| &decl.sig),
+        }
+    }
+}
+
+pub struct BasicBlockEmitter<'bb> {
+    bb: &'bb mut BasicBlock,
+    sv: &'bb mut GLIRSupervisor,
+}
+
+impl<'bb> BasicBlockEmitter<'bb> {
+    fn push(&mut self, ins: Ins) {
+        assert!(
+            !self.bb.is_terminated(),
+            "cannot emit into a terminated block"
+        );
+        self.bb.ins_list.push(ins);
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        assert!(!self.bb.is_terminated(), "block is already terminated");
+        self.bb.terminator = Some(term);
+    }
+
+    pub fn emit_cpy<R: Into<RValue>>(&mut self, var: R) -> Variable {
+        let var = var.into();
+        let copy = self.sv.create_var(var.data_ty());
+        self.push(Ins::Cpy(copy, var));
+        copy
+    }
+
+    pub fn emit_binop<A: Into<RValue>, B: Into<RValue>>(
+        &mut self,
+        a: A,
+        b: B,
+        ty: BinOpTy,
+    ) -> Variable {
+        let (a, b) = (a.into(), b.into());
+        assert_eq!(a.data_ty(), b.data_ty());
+        let res = self.sv.create_var(a.data_ty());
+        self.push(match ty {
+            BinOpTy::Add => Ins::Add(res, a, b),
+            BinOpTy::Sub => Ins::Sub(res, a, b),
+            BinOpTy::Mul => Ins::Mul(res, a, b),
+            BinOpTy::Div => Ins::Div(res, a, b),
+            BinOpTy::SDiv => Ins::SDiv(res, a, b),
+            BinOpTy::UDiv => Ins::UDiv(res, a, b),
+            BinOpTy::SRem => Ins::SRem(res, a, b),
+            BinOpTy::URem => Ins::URem(res, a, b),
+            BinOpTy::And => Ins::And(res, a, b),
+            BinOpTy::Or => Ins::Or(res, a, b),
+            BinOpTy::Xor => Ins::Xor(res, a, b),
+            BinOpTy::Shl => Ins::Shl(res, a, b),
+            BinOpTy::LShr => Ins::LShr(res, a, b),
+            BinOpTy::AShr => Ins::AShr(res, a, b),
+        });
+        res
+    }
+
+    pub fn emit_unop<A: Into<RValue>>(&mut self, a: A, ty: UnOpTy) -> Variable {
+        let a = a.into();
+        let res = self.sv.create_var(a.data_ty());
+        self.push(match ty {
+            UnOpTy::Not => Ins::Not(res, a),
+            UnOpTy::Neg => Ins::Neg(res, a),
+        });
+        res
+    }
+
+    pub fn emit_cmp<A: Into<RValue>, B: Into<RValue>>(
+        &mut self,
+        a: A,
+        b: B,
+        ty: CmpTy,
+    ) -> Variable {
+        let (a, b) = (a.into(), b.into());
+        assert_eq!(a.data_ty(), b.data_ty());
+        let res = self.sv.create_var(typing::Type::Bool);
+        self.push(Ins::Cmp(res, ty, a, b));
+        res
+    }
+
+    pub fn emit_cast<R: Into<RValue>>(&mut self, val: R, ty: CastTy, to: typing::Type) -> Variable {
+        let val = val.into();
+        assert!(
+            ty.accepts(val.data_ty(), to),
+            "{} can't convert {} to {}",
+            ty.name(),
+            val.data_ty(),
+            to
+        );
+        let res = self.sv.create_var(to);
+        self.push(Ins::Cast(res, ty, val));
+        res
+    }
+
+    /// `a` if `cond` is true, otherwise `b`, see [`Ins::Select`].
+    pub fn emit_select<C: Into<RValue>, A: Into<RValue>, B: Into<RValue>>(
+        &mut self,
+        cond: C,
+        a: A,
+        b: B,
+    ) -> Variable {
+        let (cond, a, b) = (cond.into(), a.into(), b.into());
+        assert_eq!(cond.data_ty(), typing::Type::Bool, "selects on a bool");
+        assert_eq!(a.data_ty(), b.data_ty());
+        let res = self.sv.create_var(a.data_ty());
+        self.push(Ins::Select(res, cond, a, b));
+        res
+    }
+
+    pub fn emit_alloca(&mut self, size: u32, align: u32) -> Variable {
+        let res = self.sv.create_var(typing::Type::Ptr);
+        self.push(Ins::Alloca(res, size, align));
+        res
+    }
+
+    pub fn emit_load<P: Into<RValue>>(&mut self, ty: typing::Type, ptr: P, align: u32) -> Variable {
+        let ptr = ptr.into();
+        assert_eq!(ptr.data_ty(), typing::Type::Ptr, "loads from a pointer");
+        let res = self.sv.create_var(ty);
+        self.push(Ins::Load(res, ptr, align));
+        res
+    }
+
+    pub fn emit_store<P: Into<RValue>, R: Into<RValue>>(&mut self, ptr: P, val: R, align: u32) {
+        let ptr = ptr.into();
+        assert_eq!(ptr.data_ty(), typing::Type::Ptr, "stores to a pointer");
+        self.push(Ins::Store(ptr, val.into(), align));
+    }
+
+    /// The address of the part of a `ty` at `ptr` that `indices` lead to, see
+    /// [`Ins::Gep`].
+    pub fn emit_gep<P: Into<RValue>>(
+        &mut self,
+        ty: typing::MemType,
+        ptr: P,
+        indices: Vec<RValue>,
+    ) -> Variable {
+        let ptr = ptr.into();
+        assert_eq!(ptr.data_ty(), typing::Type::Ptr, "indexes from a pointer");
+        let res = self.sv.create_var(typing::Type::Ptr);
+        self.push(Ins::Gep(res, ty, ptr, indices));
+        res
+    }
+
+    /// The address of the global called `name`.
+    pub fn emit_addr<S: Into<String>>(&mut self, name: S) -> Variable {
+        let res = self.sv.create_var(typing::Type::Ptr);
+        self.push(Ins::Addr(res, name.into()));
+        res
+    }
+
+    /// Calls `callee` with `args`. The result has type `ret`, and there is none if the
+    /// callee returns nothing.
+    pub fn emit_call<S: Into<String>>(
+        &mut self,
+        callee: S,
+        args: Vec<RValue>,
+        ret: Option<typing::Type>,
+    ) -> Option<Variable> {
+        let res = ret.map(|ty| self.sv.create_var(ty));
+        self.push(Ins::Call(res, callee.into(), args));
+        res
+    }
+
+    /// Adds a phi after the phis already at the top of the block. This is allowed
+    /// even when the block has been terminated.
+    pub fn emit_phi(&mut self, ty: typing::Type, incoming: Vec<(BlockId, RValue)>) -> Variable {
+        assert!(
+            incoming.iter().all(|(_, val)| val.data_ty() == ty),
+            "phi operands have the phi's type"
+        );
+        let res = self.sv.create_var(ty);
+        let pos = self.bb.phis().count();
+        self.bb.ins_list.insert(pos, Ins::Phi(res, incoming));
+        res
+    }
+
+    pub fn emit_jmp(&mut self, target: BlockId) {
+        self.terminate(Terminator::Jmp(target));
+    }
+
+    pub fn emit_br<C: Into<RValue>>(&mut self, cond: C, then: BlockId, els: BlockId) {
+        self.terminate(Terminator::Br(cond.into(), then, els));
+    }
+
+    pub fn emit_ret<R: Into<RValue>>(&mut self, val: R) {
+        self.terminate(Terminator::Ret(Some(val.into())));
+    }
+
+    pub fn emit_ret_void(&mut self) {
+        self.terminate(Terminator::Ret(None));
+    }
+}
@@ -0,0 +1,966 @@
+//! The textual GLIR format (`.glir` files).
+//!
+//! A file holds a module: any number of functions, declarations of external
+//! functions and globals, in any order. Whitespace and line breaks only separate
+//! tokens, and `;` starts a comment that runs to the end of the line.
+//!
+//! ```text
+//! ; Sums the numbers below %n.
+//! fn @sum(u32 %n) -> u32 {
+//! entry:
+//!     jmp header
+//! header:
+//!     %i = phi u32 [0, entry], [%i.next, body]
+//!     %sum = phi u32 [0, entry], [%sum.next, body]
+//!     %more = cmp ult u32 %i, %n
+//!     br bool %more, body, exit
+//! body:
+//!     %sum.next = add u32 %sum, %i
+//!     %i.next = add u32 %i, 1
+//!     jmp header
+//! exit:
+//!     ret u32 %sum
+//! }
+//! ```
+//!
+//! A function is `fn @name(T %a, U %b) -> R { ... }` around its blocks, where `-> R`
+//! is left out when it returns nothing. `extern @name(T, U) -> R` declares a function
+//! defined elsewhere. `global @name = init, align A` defines a global, and `const`
+//! instead of `global` one that is never written. Its `init` is `zeroinit N` for `N`
+//! zero bytes, a string like `"hi\0A\00"` where `\` and two hex digits stand for a
+//! byte, or literals like `[i32 1, i8 2]` that are laid out like the fields of a
+//! struct. Each block starts with a `label:` and the first block is the entry. Values
+//! are written `%name` and literals as plain numbers, or `true` and `false`. Every
+//! instruction names the type of its operands, which is also the type of literal
+//! operands:
+//!
+//! | Syntax                              | Instruction                          |
+//! |-------------------------------------|--------------------------------------|
+//! | `%d = add T a, b`                   | also `sub`, `mul` and, for floats,   |
+//! |                                     | `div`                                |
+//! | `%d = sdiv T a, b`                  | integer division, also `udiv` and    |
+//! |                                     | the remainders `srem` and `urem`     |
+//! | `%d = and T a, b`                   | also `or` and `xor`                  |
+//! | `%d = shl T a, b`                   | also `lshr` and `ashr`, `b` is less  |
+//! |                                     | than the bit width                   |
+//! | `%d = not T a`                      | also `neg`                           |
+//! | `%d = cpy T a`                      | copy                                 |
+//! | `%d = cmp P T a, b`                 | compare, `%d` is a `bool`            |
+//! | `%d = sitofp T a to U`              | also `uitofp`, `fptosi`, `fptoui`,   |
+//! |                                     | `fpext`, `fptrunc`, `zext`, `sext`,  |
+//! |                                     | `trunc` and `bitcast`                |
+//! | `%d = select T c, a, b`             | `a` if the `bool` `c` is true, else  |
+//! |                                     | `b`                                  |
+//! | `%d = phi T [a, label], ...`        | phi, one entry per predecessor       |
+//! | `%d = alloca N, align A`            | `N` bytes in the frame, `%d` is a    |
+//! |                                     | `ptr`                                |
+//! | `%d = load T p, align A`            | read a `T` from the `ptr` `p`        |
+//! | `store T a, p, align A`             | write `a` to the `ptr` `p`           |
+//! | `%d = gep M p, T a, U b, ...`       | address of a part of the `M` at `p`, |
+//! |                                     | see [`Ins::Gep`]                     |
+//! | `%d = addr @g`                      | address of the global `@g`           |
+//! | `%d = call R @f(T a, U b)`          | call, `call @f(..)` if `@f` returns  |
+//! |                                     | nothing                              |
+//! | `jmp label`                         | jump                                 |
+//! | `br bool c, label, label`           | to the first label if `c` is true    |
+//! | `ret T a` or `ret`                  | return                               |
+//!
+//! Types are `bool`, the signed `i8`, `i16`, `i32`, `i64`, the unsigned `u8`, `u16`,
+//! `u32`, `u64`, the floats `f32`, `f64` and `ptr` for addresses. The predicate `P` of
+//! a comparison is `eq`, `ne`, the signed `slt`, `sle`, `sgt`, `sge` or the unsigned
+//! `ult`, `ule`, `ugt`, `uge` for integers and pointers, and one of `feq`, `fne`,
+//! `flt`, `fle`, `fgt`, `fge`, `fult`, `fule`, `fugt`, `fuge` for floats (see
+//! [`CmpTy`]). Float literals may have an exponent, and `NaN`, `inf` and `-inf` are
+//! float literals too. The alignment `A` of a memory access is a power of two. The
+//! layout type `M` of a `gep` is one of the types, a struct `{M, M, ...}` or an array
+//! `[N x M]`.
+//!
+//! Names of values and blocks are local to their function, and parameters are the
+//! first values of it. The parser numbers values in the order they are defined and
+//! blocks in the order they appear, which is also how [`Function`]'s `Display` names
+//! them, so printing a parsed function and parsing the result again gives back the
+//! same text.
+
+use super::{
+    BasicBlock, BlockId, CastTy, CmpTy, Function, GLIRSupervisor, Global, GlobalInit, Ins, Literal,
+    Module, RValue, Signature, Terminator, Variable,
+};
+use crate::typing::{MemType, Type};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: Pos,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pos, self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Parses a file holding exactly one function.
+pub fn parse_function(src: &str) -> ParseResult<Function> {
+    let mut parser = Parser::new(src)?;
+    let func = parser.function()?;
+    parser.expect_eof()?;
+    Ok(func)
+}
+
+/// Parses a file holding any number of functions and extern declarations.
+pub fn parse_module(src: &str) -> ParseResult<Module> {
+    let mut parser = Parser::new(src)?;
+    let mut module = Module::new();
+    while parser.peek().tok != Tok::Eof {
+        match &parser.peek().tok {
+            Tok::Ident(kw) if kw == "extern" => {
+                let (name, sig) = parser.extern_decl()?;
+                module.declare_extern(name, sig);
+            }
+            Tok::Ident(kw) if kw == "global" || kw == "const" => {
+                module.add_global(parser.global_def()?);
+            }
+            _ => module.add_function(parser.function()?),
+        }
+    }
+    Ok(module)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    /// `%name`
+    Value(String),
+    /// `@name`
+    Global(String),
+    /// `"..."`, with escapes already replaced by their bytes.
+    Str(Vec<u8>),
+    Punct(char),
+    /// `->`
+    Arrow,
+    Eof,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Ident(name) | Tok::Number(name) => write!(f, "'{}'", name),
+            Tok::Value(name) => write!(f, "'%{}'", name),
+            Tok::Global(name) => write!(f, "'@{}'", name),
+            Tok::Str(..) => write!(f, "a string"),
+            Tok::Punct(c) => write!(f, "'{}'", c),
+            Tok::Arrow => write!(f, "'->'"),
+            Tok::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    pos: Pos,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn lex(src: &str) -> ParseResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut pos = Pos { line: 1, col: 1 };
+
+    macro_rules! bump {
+        () => {{
+            let c = chars.next();
+            match c {
+                Some('\n') => {
+                    pos.line += 1;
+                    pos.col = 1;
+                }
+                Some(_) => pos.col += 1,
+                None => (),
+            }
+            c
+        }};
+    }
+
+    macro_rules! take_while {
+        ($pred:expr) => {{
+            let mut buf = String::new();
+            while let Some(&c) = chars.peek() {
+                if !$pred(c) {
+                    break;
+                }
+                buf.push(c);
+                bump!();
+            }
+            buf
+        }};
+    }
+
+    while let Some(&c) = chars.peek() {
+        let start = pos;
+        let tok = match c {
+            c if c.is_whitespace() => {
+                bump!();
+                continue;
+            }
+            ';' => {
+                take_while!(|c| c != '\n');
+                continue;
+            }
+            '%' | '@' => {
+                bump!();
+                let name = take_while!(is_name_char);
+                if name.is_empty() {
+                    return Err(ParseError {
+                        pos: start,
+                        msg: format!("expected a name after '{}'", c),
+                    });
+                }
+                match c {
+                    '%' => Tok::Value(name),
+                    _ => Tok::Global(name),
+                }
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut num = String::new();
+                if c == '-' {
+                    num.push(c);
+                    bump!();
+                    if chars.peek() == Some(&'>') {
+                        bump!();
+                        tokens.push(Token {
+                            tok: Tok::Arrow,
+                            pos: start,
+                        });
+                        continue;
+                    }
+                }
+                loop {
+                    num.push_str(&take_while!(|c: char| c.is_ascii_alphanumeric() || c == '.'));
+                    // The sign of an exponent, as in `1.5e-7`.
+                    match chars.peek() {
+                        Some(&sign @ ('-' | '+')) if num.ends_with(['e', 'E']) => {
+                            num.push(sign);
+                            bump!();
+                        }
+                        _ => break,
+                    }
+                }
+                Tok::Number(num)
+            }
+            c if is_name_char(c) => Tok::Ident(take_while!(is_name_char)),
+            '"' => {
+                bump!();
+                let mut bytes = Vec::new();
+                loop {
+                    match bump!() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            let hex: String = (0..2).filter_map(|_| bump!()).collect();
+                            match u8::from_str_radix(&hex, 16) {
+                                Ok(byte) if hex.len() == 2 => bytes.push(byte),
+                                _ => {
+                                    return Err(ParseError {
+                                        pos: start,
+                                        msg: "expected two hex digits after '\\'".to_string(),
+                                    })
+                                }
+                            }
+                        }
+                        Some(c) => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                        None => {
+                            return Err(ParseError {
+                                pos: start,
+                                msg: "unterminated string".to_string(),
+                            })
+                        }
+                    }
+                }
+                Tok::Str(bytes)
+            }
+            '{' | '}' | '(' | ')' | '[' | ']' | ',' | '=' | ':' => {
+                bump!();
+                Tok::Punct(c)
+            }
+            _ => {
+                return Err(ParseError {
+                    pos: start,
+                    msg: format!("unexpected character '{}'", c),
+                })
+            }
+        };
+        tokens.push(Token { tok, pos: start });
+    }
+    tokens.push(Token { tok: Tok::Eof, pos });
+    Ok(tokens)
+}
+
+/// Names that the current function defines, found before its body is parsed so that
+/// phis and branches can refer to values and blocks further down.
+#[derive(Default)]
+struct Scope {
+    values: HashMap<String, usize>,
+    value_types: Vec<Option<Type>>,
+    blocks: HashMap<String, BlockId>,
+    /// Every use of a value with the type it was used at, checked once all
+    /// definitions are known.
+    uses: Vec<(Variable, Pos)>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn new(src: &str) -> ParseResult<Parser> {
+        Ok(Parser {
+            tokens: lex(src)?,
+            next: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next]
+    }
+
+    fn peek_at(&self, offset: usize) -> &Tok {
+        let idx = (self.next + offset).min(self.tokens.len() - 1);
+        &self.tokens[idx].tok
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.next].clone();
+        if token.tok != Tok::Eof {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, pos: Pos, msg: String) -> ParseResult<T> {
+        Err(ParseError { pos, msg })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> ParseResult<T> {
+        let token = self.peek();
+        self.error(
+            token.pos,
+            format!("expected {}, found {}", expected, token.tok),
+        )
+    }
+
+    fn expect_eof(&self) -> ParseResult<()> {
+        match self.peek().tok {
+            Tok::Eof => Ok(()),
+            _ => self.unexpected("end of input"),
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> ParseResult<()> {
+        match self.peek().tok {
+            Tok::Punct(p) if p == c => {
+                self.bump();
+                Ok(())
+            }
+            _ => self.unexpected(&format!("'{}'", c)),
+        }
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        match self.peek().tok {
+            Tok::Punct(p) if p == c => {
+                self.bump();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> ParseResult<()> {
+        match &self.peek().tok {
+            Tok::Ident(name) if name == kw => {
+                self.bump();
+                Ok(())
+            }
+            _ => self.unexpected(&format!("'{}'", kw)),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> ParseResult<(String, Pos)> {
+        match &self.peek().tok {
+            Tok::Ident(name) => {
+                let name = name.clone();
+                Ok((name, self.bump().pos))
+            }
+            _ => self.unexpected(what),
+        }
+    }
+
+    fn number(&mut self, what: &str) -> ParseResult<u32> {
+        if let Tok::Number(num) = &self.peek().tok {
+            if let Ok(n) = num.parse() {
+                self.bump();
+                return Ok(n);
+            }
+        }
+        self.unexpected(what)
+    }
+
+    /// `, align N`
+    fn align(&mut self) -> ParseResult<u32> {
+        self.expect_punct(',')?;
+        self.expect_keyword("align")?;
+        self.number("an alignment")
+    }
+
+    fn ty(&mut self) -> ParseResult<Type> {
+        if let Tok::Ident(name) = &self.peek().tok {
+            if let Some(ty) = Type::from_name(name) {
+                self.bump();
+                return Ok(ty);
+            }
+        }
+        self.unexpected("a type")
+    }
+
+    /// A scalar type, `{T, U}` for a struct or `[N x T]` for an array.
+    fn mem_ty(&mut self) -> ParseResult<MemType> {
+        if self.eat_punct('{') {
+            let mut fields = Vec::new();
+            if !self.eat_punct('}') {
+                loop {
+                    fields.push(self.mem_ty()?);
+                    if self.eat_punct('}') {
+                        break;
+                    }
+                    self.expect_punct(',')?;
+                }
+            }
+            return Ok(MemType::Struct(fields));
+        }
+        if self.eat_punct('[') {
+            let len = self.number("an array length")?;
+            self.expect_keyword("x")?;
+            let elem = self.mem_ty()?;
+            self.expect_punct(']')?;
+            return Ok(MemType::Array(Box::new(elem), len as usize));
+        }
+        Ok(MemType::Scalar(self.ty()?))
+    }
+
+    fn at_type(&self) -> bool {
+        matches!(self.peek_at(0), Tok::Ident(name) if Type::from_name(name).is_some())
+            && *self.peek_at(1) != Tok::Punct(':')
+    }
+
+    fn global(&mut self, what: &str) -> ParseResult<String> {
+        match &self.peek().tok {
+            Tok::Global(name) => {
+                let name = name.clone();
+                self.bump();
+                Ok(name)
+            }
+            _ => self.unexpected(what),
+        }
+    }
+
+    /// `-> T` after a parameter list, if the function returns a value.
+    fn ret_ty(&mut self) -> ParseResult<Option<Type>> {
+        match self.peek().tok {
+            Tok::Arrow => {
+                self.bump();
+                Ok(Some(self.ty()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// `(item, ...)`, the items of a parameter or argument list.
+    fn paren_list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Parser) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        self.expect_punct('(')?;
+        let mut items = Vec::new();
+        if self.eat_punct(')') {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if !self.eat_punct(',') {
+                break;
+            }
+        }
+        self.expect_punct(')')?;
+        Ok(items)
+    }
+
+    fn extern_decl(&mut self) -> ParseResult<(String, Signature)> {
+        self.expect_keyword("extern")?;
+        let name = self.global("a function name")?;
+        let params = self.paren_list(|parser| parser.ty())?;
+        let ret = self.ret_ty()?;
+        Ok((name, Signature::new(params, ret)))
+    }
+
+    /// `global @name = init, align A`, or `const` instead of `global`.
+    fn global_def(&mut self) -> ParseResult<Global> {
+        let (kw, _) = self.ident("'global' or 'const'")?;
+        let name = self.global("a global name")?;
+        self.expect_punct('=')?;
+        let init = match self.peek().tok.clone() {
+            Tok::Str(bytes) => {
+                self.bump();
+                GlobalInit::Bytes(bytes)
+            }
+            Tok::Ident(kw) if kw == "zeroinit" => {
+                self.bump();
+                GlobalInit::Zeroed(self.number("a size")? as usize)
+            }
+            Tok::Punct('[') => {
+                self.bump();
+                let mut lits = Vec::new();
+                if !self.eat_punct(']') {
+                    loop {
+                        let ty = self.ty()?;
+                        let token = self.bump();
+                        let lit = match &token.tok {
+                            Tok::Number(num) | Tok::Ident(num) => parse_literal(num, ty),
+                            _ => None,
+                        };
+                        match lit {
+                            Some(lit) => lits.push(lit),
+                            None => {
+                                let msg = format!("expected a {} literal, found {}", ty, token.tok);
+                                return self.error(token.pos, msg);
+                            }
+                        }
+                        if self.eat_punct(']') {
+                            break;
+                        }
+                        self.expect_punct(',')?;
+                    }
+                }
+                GlobalInit::Literals(lits)
+            }
+            _ => return self.unexpected("a string, 'zeroinit' or a list of literals"),
+        };
+        let align = self.align()?;
+        Ok(Global {
+            name,
+            constant: kw == "const",
+            init,
+            align,
+        })
+    }
+
+    fn function(&mut self) -> ParseResult<Function> {
+        self.expect_keyword("fn")?;
+        let name = self.global("a function name")?;
+        let mut scope = Scope::default();
+        let params = self.paren_list(|parser| {
+            let ty = parser.ty()?;
+            let token = parser.bump();
+            let param = match token.tok {
+                Tok::Value(param) => param,
+                tok => {
+                    let msg = format!("expected a parameter name, found {}", tok);
+                    return parser.error(token.pos, msg);
+                }
+            };
+            if scope.values.contains_key(&param) {
+                return parser.error(token.pos, format!("value '%{}' is defined twice", param));
+            }
+            scope.values.insert(param, scope.values.len());
+            scope.value_types.push(Some(ty));
+            Ok(ty)
+        })?;
+        let ret = self.ret_ty()?;
+        self.expect_punct('{')?;
+
+        let mut func = Function::new(name, Signature::new(params, ret));
+        self.scan_body(&mut func, &mut scope)?;
+
+        let mut current: Option<(BlockId, String)> = None;
+        while !self.eat_punct('}') {
+            let token = self.peek().clone();
+            match (&token.tok, self.peek_at(1)) {
+                (Tok::Eof, _) => return self.unexpected("'}'"),
+                (Tok::Ident(label), Tok::Punct(':')) => {
+                    current = Some((scope.blocks[label], label.clone()));
+                    self.bump();
+                    self.bump();
+                }
+                _ => {
+                    let (block, label) = match &current {
+                        Some((block, label)) => (*block, label),
+                        None => {
+                            return self.error(
+                                token.pos,
+                                "expected a block label before the first instruction".into(),
+                            )
+                        }
+                    };
+                    if func.block(block).is_terminated() {
+                        return self.error(
+                            token.pos,
+                            format!("instruction after the terminator of block '{}'", label),
+                        );
+                    }
+                    self.statement(&mut scope, func.block_mut(block))?;
+                }
+            }
+        }
+
+        let mut variables = Vec::with_capacity(scope.value_types.len());
+        for (id, ty) in scope.value_types.iter().enumerate() {
+            variables.push(Variable {
+                id,
+                ty: ty.expect("every scanned value is defined"),
+            });
+        }
+        for (used, pos) in &scope.uses {
+            let defined = variables[used.id];
+            if defined.ty != used.ty {
+                return self.error(
+                    *pos,
+                    format!("value is defined as {} but used as {}", defined.ty, used.ty),
+                );
+            }
+        }
+        func.sv = GLIRSupervisor { variables };
+        Ok(func)
+    }
+
+    /// Creates the function's blocks and numbers its values after the parameters,
+    /// without consuming tokens.
+    fn scan_body(&self, func: &mut Function, scope: &mut Scope) -> ParseResult<()> {
+        let mut i = self.next;
+        // Struct types nest braces inside the body.
+        let mut depth = 0;
+        loop {
+            let token = &self.tokens[i];
+            let next = &self.tokens[(i + 1).min(self.tokens.len() - 1)].tok;
+            match (&token.tok, next) {
+                (Tok::Eof, _) => break,
+                (Tok::Punct('{'), _) => depth += 1,
+                (Tok::Punct('}'), _) if depth == 0 => break,
+                (Tok::Punct('}'), _) => depth -= 1,
+                (Tok::Ident(label), Tok::Punct(':')) => {
+                    if scope.blocks.contains_key(label) {
+                        return self
+                            .error(token.pos, format!("block '{}' is defined twice", label));
+                    }
+                    scope.blocks.insert(label.clone(), func.create_block());
+                }
+                (Tok::Value(name), Tok::Punct('=')) => {
+                    if scope.values.contains_key(name) {
+                        return self
+                            .error(token.pos, format!("value '%{}' is defined twice", name));
+                    }
+                    scope.values.insert(name.clone(), scope.values.len());
+                    scope.value_types.push(None);
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn block_ref(&mut self, scope: &Scope) -> ParseResult<BlockId> {
+        let (label, pos) = self.ident("a block label")?;
+        match scope.blocks.get(&label) {
+            Some(block) => Ok(*block),
+            None => self.error(pos, format!("unknown block '{}'", label)),
+        }
+    }
+
+    fn operand(&mut self, scope: &mut Scope, ty: Type) -> ParseResult<RValue> {
+        let token = self.bump();
+        match &token.tok {
+            Tok::Value(name) => match scope.values.get(name) {
+                Some(id) => {
+                    let var = Variable { id: *id, ty };
+                    scope.uses.push((var, token.pos));
+                    Ok(RValue::Var(var))
+                }
+                None => self.error(token.pos, format!("undefined value '%{}'", name)),
+            },
+            Tok::Number(num) | Tok::Ident(num) => match parse_literal(num, ty) {
+                Some(lit) => Ok(RValue::Lit(lit)),
+                None => self.error(
+                    token.pos,
+                    format!("'{}' is not a valid {} literal", num, ty),
+                ),
+            },
+            _ => self.error(
+                token.pos,
+                format!("expected a value or literal, found {}", token.tok),
+            ),
+        }
+    }
+
+    fn statement(&mut self, scope: &mut Scope, bb: &mut BasicBlock) -> ParseResult<()> {
+        let dest = match &self.peek().tok {
+            Tok::Value(name) => {
+                let name = name.clone();
+                let pos = self.bump().pos;
+                self.expect_punct('=')?;
+                Some((scope.values[&name], pos))
+            }
+            _ => None,
+        };
+        let (opcode, op_pos) = self.ident("an instruction")?;
+
+        let term = match opcode.as_str() {
+            "jmp" => Some(Terminator::Jmp(self.block_ref(scope)?)),
+            "br" => {
+                let ty = self.ty()?;
+                let cond = self.operand(scope, ty)?;
+                self.expect_punct(',')?;
+                let then = self.block_ref(scope)?;
+                self.expect_punct(',')?;
+                let els = self.block_ref(scope)?;
+                Some(Terminator::Br(cond, then, els))
+            }
+            "ret" => match self.at_type() {
+                true => {
+                    let ty = self.ty()?;
+                    Some(Terminator::Ret(Some(self.operand(scope, ty)?)))
+                }
+                false => Some(Terminator::Ret(None)),
+            },
+            _ => None,
+        };
+        if let Some(term) = term {
+            if let Some((_, pos)) = dest {
+                return self.error(pos, format!("'{}' does not produce a value", opcode));
+            }
+            bb.terminator = Some(term);
+            return Ok(());
+        }
+
+        if opcode == "call" {
+            let dest = match dest {
+                Some((id, _)) => {
+                    let ty = self.ty()?;
+                    scope.value_types[id] = Some(ty);
+                    Some(Variable { id, ty })
+                }
+                None => None,
+            };
+            let callee = self.global("a function name")?;
+            let args = self.paren_list(|parser| {
+                let ty = parser.ty()?;
+                parser.operand(scope, ty)
+            })?;
+            bb.ins_list.push(Ins::Call(dest, callee, args));
+            return Ok(());
+        }
+        if opcode == "store" {
+            if let Some((_, pos)) = dest {
+                return self.error(pos, "'store' does not produce a value".into());
+            }
+            let ty = self.ty()?;
+            let val = self.operand(scope, ty)?;
+            self.expect_punct(',')?;
+            let ptr = self.operand(scope, Type::Ptr)?;
+            let align = self.align()?;
+            bb.ins_list.push(Ins::Store(ptr, val, align));
+            return Ok(());
+        }
+
+        let dest_id = match dest {
+            Some((id, _)) => id,
+            None => return self.error(op_pos, format!("'{}' must be assigned to a value", opcode)),
+        };
+        if let Some(cast) = CastTy::from_name(&opcode) {
+            let from = self.ty()?;
+            let val = self.operand(scope, from)?;
+            self.expect_keyword("to")?;
+            let to = self.ty()?;
+            scope.value_types[dest_id] = Some(to);
+            bb.ins_list.push(Ins::Cast(
+                Variable {
+                    id: dest_id,
+                    ty: to,
+                },
+                cast,
+                val,
+            ));
+            return Ok(());
+        }
+        if opcode == "alloca" {
+            let size = self.number("an allocation size")?;
+            let align = self.align()?;
+            scope.value_types[dest_id] = Some(Type::Ptr);
+            bb.ins_list.push(Ins::Alloca(
+                Variable {
+                    id: dest_id,
+                    ty: Type::Ptr,
+                },
+                size,
+                align,
+            ));
+            return Ok(());
+        }
+        if opcode == "addr" {
+            let name = self.global("a global name")?;
+            scope.value_types[dest_id] = Some(Type::Ptr);
+            bb.ins_list.push(Ins::Addr(
+                Variable {
+                    id: dest_id,
+                    ty: Type::Ptr,
+                },
+                name,
+            ));
+            return Ok(());
+        }
+        if opcode == "gep" {
+            let ty = self.mem_ty()?;
+            let ptr = self.operand(scope, Type::Ptr)?;
+            let mut indices = Vec::new();
+            while self.eat_punct(',') {
+                let idx_ty = self.ty()?;
+                indices.push(self.operand(scope, idx_ty)?);
+            }
+            scope.value_types[dest_id] = Some(Type::Ptr);
+            bb.ins_list.push(Ins::Gep(
+                Variable {
+                    id: dest_id,
+                    ty: Type::Ptr,
+                },
+                ty,
+                ptr,
+                indices,
+            ));
+            return Ok(());
+        }
+        let pred = match opcode.as_str() {
+            "cmp" => {
+                let (name, pos) = self.ident("a comparison predicate")?;
+                match CmpTy::from_name(&name) {
+                    Some(pred) => Some(pred),
+                    None => return self.error(pos, format!("unknown predicate '{}'", name)),
+                }
+            }
+            _ => None,
+        };
+        let ty = self.ty()?;
+        let dest_ty = match pred {
+            Some(_) => Type::Bool,
+            None => ty,
+        };
+        let dest = Variable {
+            id: dest_id,
+            ty: dest_ty,
+        };
+        scope.value_types[dest_id] = Some(dest_ty);
+
+        let ins = match opcode.as_str() {
+            "add" | "sub" | "mul" | "div" | "sdiv" | "udiv" | "srem" | "urem" | "and" | "or"
+            | "xor" | "shl" | "lshr" | "ashr" => {
+                let a = self.operand(scope, ty)?;
+                self.expect_punct(',')?;
+                let b = self.operand(scope, ty)?;
+                match opcode.as_str() {
+                    "add" => Ins::Add(dest, a, b),
+                    "sub" => Ins::Sub(dest, a, b),
+                    "mul" => Ins::Mul(dest, a, b),
+                    "div" => Ins::Div(dest, a, b),
+                    "sdiv" => Ins::SDiv(dest, a, b),
+                    "udiv" => Ins::UDiv(dest, a, b),
+                    "srem" => Ins::SRem(dest, a, b),
+                    "urem" => Ins::URem(dest, a, b),
+                    "and" => Ins::And(dest, a, b),
+                    "or" => Ins::Or(dest, a, b),
+                    "xor" => Ins::Xor(dest, a, b),
+                    "shl" => Ins::Shl(dest, a, b),
+                    "lshr" => Ins::LShr(dest, a, b),
+                    _ => Ins::AShr(dest, a, b),
+                }
+            }
+            "not" => Ins::Not(dest, self.operand(scope, ty)?),
+            "neg" => Ins::Neg(dest, self.operand(scope, ty)?),
+            "cpy" => Ins::Cpy(dest, self.operand(scope, ty)?),
+            "cmp" => {
+                let a = self.operand(scope, ty)?;
+                self.expect_punct(',')?;
+                let b = self.operand(scope, ty)?;
+                Ins::Cmp(dest, pred.expect("cmp has a predicate"), a, b)
+            }
+            "select" => {
+                let cond = self.operand(scope, Type::Bool)?;
+                self.expect_punct(',')?;
+                let a = self.operand(scope, ty)?;
+                self.expect_punct(',')?;
+                let b = self.operand(scope, ty)?;
+                Ins::Select(dest, cond, a, b)
+            }
+            "load" => {
+                let ptr = self.operand(scope, Type::Ptr)?;
+                Ins::Load(dest, ptr, self.align()?)
+            }
+            "phi" => {
+                let mut incoming = Vec::new();
+                if *self.peek_at(0) == Tok::Punct('[') {
+                    loop {
+                        self.expect_punct('[')?;
+                        let val = self.operand(scope, ty)?;
+                        self.expect_punct(',')?;
+                        let block = self.block_ref(scope)?;
+                        self.expect_punct(']')?;
+                        incoming.push((block, val));
+                        if !self.eat_punct(',') {
+                            break;
+                        }
+                    }
+                }
+                Ins::Phi(dest, incoming)
+            }
+            _ => return self.error(op_pos, format!("unknown instruction '{}'", opcode)),
+        };
+        bb.ins_list.push(ins);
+        Ok(())
+    }
+}
+
+fn parse_literal(num: &str, ty: Type) -> Option<Literal> {
+    match ty {
+        Type::Bool => num.parse().ok().map(Literal::Bool),
+        Type::F32 => num.parse().ok().map(Literal::F32),
+        Type::F64 => num.parse().ok().map(Literal::F64),
+        Type::I8 => num.parse().ok().map(Literal::I8),
+        Type::I16 => num.parse().ok().map(Literal::I16),
+        Type::I32 => num.parse().ok().map(Literal::I32),
+        Type::I64 => num.parse().ok().map(Literal::I64),
+        Type::U8 => num.parse().ok().map(Literal::U8),
+        Type::U16 => num.parse().ok().map(Literal::U16),
+        Type::U32 => num.parse().ok().map(Literal::U32),
+        Type::U64 => num.parse().ok().map(Literal::U64),
+        Type::Ptr => num.parse().ok().map(Literal::Ptr),
+    }
+}
@@ -0,0 +1,242 @@
+//! Printing of functions in the textual GLIR format, see [`super::parse`].
+
+use super::*;
+use std::fmt::{Display, Formatter, Result};
+
+fn write_binop(
+    f: &mut Formatter<'_>,
+    name: &str,
+    dest: &Variable,
+    a: &RValue,
+    b: &RValue,
+) -> Result {
+    write!(f, "{} = {} {} {}, {}", dest, name, dest.data_ty(), a, b)
+}
+
+impl Display for Ins {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Ins::Add(dest, a, b) => write_binop(f, "add", dest, a, b),
+            Ins::Sub(dest, a, b) => write_binop(f, "sub", dest, a, b),
+            Ins::Mul(dest, a, b) => write_binop(f, "mul", dest, a, b),
+            Ins::Div(dest, a, b) => write_binop(f, "div", dest, a, b),
+            Ins::SDiv(dest, a, b) => write_binop(f, "sdiv", dest, a, b),
+            Ins::UDiv(dest, a, b) => write_binop(f, "udiv", dest, a, b),
+            Ins::SRem(dest, a, b) => write_binop(f, "srem", dest, a, b),
+            Ins::URem(dest, a, b) => write_binop(f, "urem", dest, a, b),
+            Ins::And(dest, a, b) => write_binop(f, "and", dest, a, b),
+            Ins::Or(dest, a, b) => write_binop(f, "or", dest, a, b),
+            Ins::Xor(dest, a, b) => write_binop(f, "xor", dest, a, b),
+            Ins::Shl(dest, a, b) => write_binop(f, "shl", dest, a, b),
+            Ins::LShr(dest, a, b) => write_binop(f, "lshr", dest, a, b),
+            Ins::AShr(dest, a, b) => write_binop(f, "ashr", dest, a, b),
+            Ins::Not(dest, a) => write!(f, "{} = not {} {}", dest, dest.data_ty(), a),
+            Ins::Neg(dest, a) => write!(f, "{} = neg {} {}", dest, dest.data_ty(), a),
+            Ins::Cpy(dest, rhs) => write!(f, "{} = cpy {} {}", dest, dest.data_ty(), rhs),
+            Ins::Cmp(dest, ty, a, b) => write!(
+                f,
+                "{} = cmp {} {} {}, {}",
+                dest,
+                ty.name(),
+                a.data_ty(),
+                a,
+                b
+            ),
+            Ins::Cast(dest, ty, val) => write!(
+                f,
+                "{} = {} {} {} to {}",
+                dest,
+                ty.name(),
+                val.data_ty(),
+                val,
+                dest.data_ty()
+            ),
+            Ins::Select(dest, cond, a, b) => write!(
+                f,
+                "{} = select {} {}, {}, {}",
+                dest,
+                dest.data_ty(),
+                cond,
+                a,
+                b
+            ),
+            Ins::Phi(dest, incoming) => {
+                write!(f, "{} = phi {}", dest, dest.data_ty())?;
+                for (i, (block, val)) in incoming.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}[{}, {}]", sep, val, block)?;
+                }
+                Ok(())
+            }
+            Ins::Alloca(dest, size, align) => {
+                write!(f, "{} = alloca {}, align {}", dest, size, align)
+            }
+            Ins::Load(dest, ptr, align) => write!(
+                f,
+                "{} = load {} {}, align {}",
+                dest,
+                dest.data_ty(),
+                ptr,
+                align
+            ),
+            Ins::Call(dest, callee, args) => {
+                if let Some(dest) = dest {
+                    write!(f, "{} = call {} ", dest, dest.data_ty())?;
+                } else {
+                    write!(f, "call ")?;
+                }
+                write!(f, "@{}(", callee)?;
+                for (i, arg) in args.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{} {}", sep, arg.data_ty(), arg)?;
+                }
+                write!(f, ")")
+            }
+            Ins::Store(ptr, val, align) => write!(
+                f,
+                "store {} {}, {}, align {}",
+                val.data_ty(),
+                val,
+                ptr,
+                align
+            ),
+            Ins::Gep(dest, ty, ptr, indices) => {
+                write!(f, "{} = gep {} {}", dest, ty, ptr)?;
+                for idx in indices {
+                    write!(f, ", {} {}", idx.data_ty(), idx)?;
+                }
+                Ok(())
+            }
+            Ins::Addr(dest, name) => write!(f, "{} = addr @{}", dest, name),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Terminator::Jmp(target) => write!(f, "jmp {}", target),
+            Terminator::Br(cond, then, els) => {
+                write!(f, "br {} {}, {}, {}", cond.data_ty(), cond, then, els)
+            }
+            Terminator::Ret(Some(val)) => write!(f, "ret {} {}", val.data_ty(), val),
+            Terminator::Ret(None) => write!(f, "ret"),
+        }
+    }
+}
+
+impl Display for BasicBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for ins in &self.ins_list {
+            writeln!(f, "    {}", ins)?;
+        }
+        if let Some(term) = &self.terminator {
+            writeln!(f, "    {}", term)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_ret(f: &mut Formatter<'_>, ret: Option<typing::Type>) -> Result {
+    match ret {
+        Some(ty) => write!(f, " -> {}", ty),
+        None => Ok(()),
+    }
+}
+
+/// `(T, U) -> R`, the way an extern declaration is written.
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "(")?;
+        for (i, ty) in self.params.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{}", sep, ty)?;
+        }
+        write!(f, ")")?;
+        write_ret(f, self.ret)
+    }
+}
+
+impl Display for ExternDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "extern @{}{}", self.name, self.sig)
+    }
+}
+
+/// `zeroinit N`, a string of bytes or a list of literals. Printable ASCII other than
+/// `"` and `\` is written as is, any other byte as `\` and two hex digits.
+impl Display for GlobalInit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            GlobalInit::Zeroed(size) => write!(f, "zeroinit {}", size),
+            GlobalInit::Bytes(bytes) => {
+                write!(f, "\"")?;
+                for byte in bytes {
+                    match byte {
+                        b' '..=b'~' if !matches!(byte, b'"' | b'\\') => {
+                            write!(f, "{}", *byte as char)?
+                        }
+                        _ => write!(f, "\\{:02X}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            GlobalInit::Literals(lits) => {
+                write!(f, "[")?;
+                for (i, lit) in lits.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{} {}", sep, lit.data_ty(), lit)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Display for Global {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let kw = if self.constant { "const" } else { "global" };
+        writeln!(
+            f,
+            "{} @{} = {}, align {}",
+            kw, self.name, self.init, self.align
+        )
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "fn @{}(", self.name)?;
+        for (i, param) in self.params.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} {}", sep, param.data_ty(), param)?;
+        }
+        write!(f, ")")?;
+        write_ret(f, self.sig.ret)?;
+        writeln!(f, " {{")?;
+        for (id, bb) in self.blocks() {
+            writeln!(f, "{}:", id)?;
+            Display::fmt(bb, f)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for decl in &self.externs {
+            Display::fmt(decl, f)?;
+        }
+        for global in &self.globals {
+            Display::fmt(global, f)?;
+        }
+        let header = !self.externs.is_empty() || !self.globals.is_empty();
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 || header {
+                writeln!(f)?;
+            }
+            Display::fmt(func, f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,682 @@
+//! Structural and type checks for functions.
+//!
+//! [`verify`] reports every problem it finds instead of stopping at the first one.
+//! [`debug_verify`] panics on a malformed function in debug builds and does nothing in
+//! release builds. The pass manager calls it on the input of a pipeline and after
+//! every pass, and so do SSA construction and lowering.
+
+use super::cfg::Cfg;
+use super::dom::DomTree;
+use super::{BlockId, Function, Ins, Module, RValue, Terminator, Variable};
+use crate::typing::{MemType, Type, Typed};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    NoBlocks,
+    Unterminated(BlockId),
+    /// A terminator or phi refers to a block the function does not have.
+    UnknownBlock {
+        block: BlockId,
+        target: BlockId,
+    },
+    Redefinition {
+        block: BlockId,
+        var: Variable,
+    },
+    UndefinedValue {
+        block: BlockId,
+        var: Variable,
+    },
+    /// The definition of a value does not dominate one of its uses.
+    NotDominated {
+        block: BlockId,
+        var: Variable,
+    },
+    TypeMismatch {
+        block: BlockId,
+        site: String,
+        expected: Type,
+        found: Type,
+    },
+    /// An instruction does not accept operands of this type at all.
+    InvalidType {
+        block: BlockId,
+        site: String,
+        found: Type,
+    },
+    /// An alignment that is not a power of two, or that a frame can't provide.
+    InvalidAlign {
+        block: BlockId,
+        site: String,
+        align: u32,
+    },
+    /// A `gep` without indices, with more indices than its type has levels, or with a
+    /// field index that is not a literal or is out of range.
+    InvalidIndex {
+        block: BlockId,
+        site: String,
+    },
+    /// A return whose value does not match the return type of the function.
+    ReturnMismatch {
+        block: BlockId,
+    },
+    /// A call to a function that the module neither defines nor declares.
+    UnknownCallee {
+        block: BlockId,
+        callee: String,
+    },
+    /// A call with the wrong number of arguments, or that expects a result from a
+    /// function returning nothing or the other way around.
+    CallMismatch {
+        block: BlockId,
+        site: String,
+    },
+    /// Takes the address of a global that the module does not define.
+    UnknownGlobal {
+        block: BlockId,
+        global: String,
+    },
+    /// Two functions, extern declarations or globals of a module have the same name.
+    DuplicateFunction(String),
+    /// A global whose alignment is not a power of two.
+    InvalidGlobalAlign {
+        global: String,
+        align: u32,
+    },
+    MisplacedPhi {
+        block: BlockId,
+        phi: Variable,
+    },
+    /// A predecessor of the phi's block has no incoming value.
+    PhiMissingIncoming {
+        block: BlockId,
+        phi: Variable,
+        pred: BlockId,
+    },
+    /// The phi has an incoming value from a block that is not a predecessor, or more
+    /// than one from the same predecessor.
+    PhiExtraIncoming {
+        block: BlockId,
+        phi: Variable,
+        from: BlockId,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::NoBlocks => write!(f, "function has no blocks"),
+            VerifyError::Unterminated(block) => write!(f, "{}: block has no terminator", block),
+            VerifyError::UnknownBlock { block, target } => {
+                write!(f, "{}: refers to unknown block {}", block, target)
+            }
+            VerifyError::Redefinition { block, var } => {
+                write!(f, "{}: {} is defined more than once", block, var)
+            }
+            VerifyError::UndefinedValue { block, var } => {
+                write!(f, "{}: {} is never defined", block, var)
+            }
+            VerifyError::NotDominated { block, var } => {
+                write!(
+                    f,
+                    "{}: definition of {} does not dominate its use",
+                    block, var
+                )
+            }
+            VerifyError::TypeMismatch {
+                block,
+                site,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected {} but found {} in '{}'",
+                block, expected, found, site
+            ),
+            VerifyError::InvalidType { block, site, found } => {
+                write!(f, "{}: {} is not allowed in '{}'", block, found, site)
+            }
+            VerifyError::InvalidAlign { block, site, align } => {
+                write!(
+                    f,
+                    "{}: alignment {} is not allowed in '{}'",
+                    block, align, site
+                )
+            }
+            VerifyError::InvalidIndex { block, site } => {
+                write!(f, "{}: invalid indices in '{}'", block, site)
+            }
+            VerifyError::ReturnMismatch { block } => {
+                write!(f, "{}: return does not match the signature", block)
+            }
+            VerifyError::UnknownCallee { block, callee } => {
+                write!(f, "{}: calls unknown function @{}", block, callee)
+            }
+            VerifyError::CallMismatch { block, site } => {
+                write!(
+                    f,
+                    "{}: '{}' does not match the callee's signature",
+                    block, site
+                )
+            }
+            VerifyError::UnknownGlobal { block, global } => {
+                write!(f, "{}: refers to unknown global @{}", block, global)
+            }
+            VerifyError::DuplicateFunction(name) => write!(f, "@{} is defined twice", name),
+            VerifyError::InvalidGlobalAlign { global, align } => {
+                write!(f, "alignment {} of @{} is not allowed", align, global)
+            }
+            VerifyError::MisplacedPhi { block, phi } => {
+                write!(
+                    f,
+                    "{}: phi {} comes after a non-phi instruction",
+                    block, phi
+                )
+            }
+            VerifyError::PhiMissingIncoming { block, phi, pred } => {
+                write!(
+                    f,
+                    "{}: phi {} has no value for predecessor {}",
+                    block, phi, pred
+                )
+            }
+            VerifyError::PhiExtraIncoming { block, phi, from } => write!(
+                f,
+                "{}: phi {} has an unexpected value from {}",
+                block, phi, from
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks that
+/// - every block is terminated and only refers to existing blocks,
+/// - every value is defined once, and its definition dominates all of its uses,
+/// - operand, result and return types agree, returns match the signature, and branch
+///   conditions are `bool`s,
+/// - memory is accessed through pointers with an alignment that is a power of two,
+/// - phis come first in their block and have exactly one value per predecessor.
+///
+/// Calls and the globals of [`Ins::Addr`] are only checked against the module by
+/// [`verify_module`].
+pub fn verify(func: &Function) -> Result<(), Vec<VerifyError>> {
+    verify_in(func, None)
+}
+
+/// Checks every function like [`verify`], and also that names are unique, calls match
+/// the signature of their callee and globals exist and are aligned to a power of two.
+/// Errors come with the name of the function or global.
+pub fn verify_module(module: &Module) -> Result<(), Vec<(String, VerifyError)>> {
+    let mut errors = Vec::new();
+    let names = module
+        .functions()
+        .iter()
+        .map(|func| func.name())
+        .chain(module.externs().iter().map(|decl| decl.name.as_str()))
+        .chain(module.globals().iter().map(|global| global.name.as_str()));
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            errors.push((
+                name.to_string(),
+                VerifyError::DuplicateFunction(name.to_string()),
+            ));
+        }
+    }
+    for global in module.globals() {
+        if !global.align.is_power_of_two() {
+            errors.push((
+                global.name.clone(),
+                VerifyError::InvalidGlobalAlign {
+                    global: global.name.clone(),
+                    align: global.align,
+                },
+            ));
+        }
+    }
+    for func in module.functions() {
+        if let Err(func_errors) = verify_in(func, Some(module)) {
+            errors.extend(
+                func_errors
+                    .into_iter()
+                    .map(|err| (func.name().to_string(), err)),
+            );
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+fn verify_in(func: &Function, module: Option<&Module>) -> Result<(), Vec<VerifyError>> {
+    if func.block_count() == 0 {
+        return Err(vec![VerifyError::NoBlocks]);
+    }
+    let mut verifier = Verifier {
+        func,
+        module,
+        errors: Vec::new(),
+        defs: HashMap::new(),
+    };
+    verifier.check_blocks();
+    // The CFG can't be built when a terminator points outside of the function.
+    let unknown_block = |err: &VerifyError| matches!(err, VerifyError::UnknownBlock { .. });
+    if verifier.errors.iter().any(unknown_block) {
+        return Err(verifier.errors);
+    }
+    let cfg = func.cfg();
+    verifier.collect_defs();
+    verifier.check_uses(&cfg);
+    verifier.check_types();
+    match verifier.errors.is_empty() {
+        true => Ok(()),
+        false => Err(verifier.errors),
+    }
+}
+
+/// Panics with every problem [`verify`] finds, but only in debug builds. `after`
+/// names what last changed the function.
+pub fn debug_verify(func: &Function, after: &str) {
+    if cfg!(debug_assertions) {
+        if let Err(errors) = verify(func) {
+            let list: Vec<String> = errors.iter().map(|err| format!("  {}", err)).collect();
+            panic!(
+                "@{} is malformed after {}:\n{}\n{}",
+                func.name(),
+                after,
+                list.join("\n"),
+                func
+            );
+        }
+    }
+}
+
+/// Where a value is used. Phi operands count as used at the end of the predecessor
+/// they come from.
+#[derive(Copy, Clone)]
+enum UseSite {
+    Ins(BlockId, usize),
+    EndOf(BlockId),
+}
+
+struct Verifier<'f> {
+    func: &'f Function,
+    module: Option<&'f Module>,
+    errors: Vec<VerifyError>,
+    /// Defining block and instruction index of every value. Parameters have no index,
+    /// they are defined on entry.
+    defs: HashMap<usize, (Variable, BlockId, Option<usize>)>,
+}
+
+impl<'f> Verifier<'f> {
+    fn check_blocks(&mut self) {
+        let n = self.func.block_count();
+        for (id, bb) in self.func.blocks() {
+            let mut targets = bb.successors();
+            for ins in bb.phis() {
+                if let Ins::Phi(_, incoming) = ins {
+                    targets.extend(incoming.iter().map(|(from, _)| *from));
+                }
+            }
+            for target in targets {
+                if target.0 >= n {
+                    self.errors
+                        .push(VerifyError::UnknownBlock { block: id, target });
+                }
+            }
+            if !bb.is_terminated() {
+                self.errors.push(VerifyError::Unterminated(id));
+            }
+        }
+    }
+
+    fn collect_defs(&mut self) {
+        for param in self.func.params() {
+            self.defs
+                .insert(param.id, (*param, self.func.entry(), None));
+        }
+        for (id, bb) in self.func.blocks() {
+            let mut seen_non_phi = false;
+            for (i, ins) in bb.ins_list().iter().enumerate() {
+                match ins {
+                    Ins::Phi(phi, _) if seen_non_phi => {
+                        self.errors.push(VerifyError::MisplacedPhi {
+                            block: id,
+                            phi: *phi,
+                        })
+                    }
+                    Ins::Phi(..) => (),
+                    _ => seen_non_phi = true,
+                }
+                if let Some(dest) = ins.dest() {
+                    match self.defs.entry(dest.id) {
+                        Entry::Occupied(..) => self.errors.push(VerifyError::Redefinition {
+                            block: id,
+                            var: dest,
+                        }),
+                        Entry::Vacant(entry) => {
+                            entry.insert((dest, id, Some(i)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_uses(&mut self, cfg: &Cfg) {
+        let doms = DomTree::from_cfg(cfg, self.func.entry());
+        for (id, bb) in self.func.blocks() {
+            for (i, ins) in bb.ins_list().iter().enumerate() {
+                if let Ins::Phi(phi, incoming) = ins {
+                    self.check_phi_incoming(id, *phi, incoming, cfg);
+                    for (from, val) in incoming {
+                        self.check_use(val, UseSite::EndOf(*from), &ins.to_string(), &doms);
+                    }
+                } else {
+                    for val in ins.operands() {
+                        self.check_use(val, UseSite::Ins(id, i), &ins.to_string(), &doms);
+                    }
+                }
+            }
+            if let Some(term) = bb.terminator() {
+                for val in term.operands() {
+                    self.check_use(val, UseSite::EndOf(id), &term.to_string(), &doms);
+                }
+            }
+        }
+    }
+
+    fn check_phi_incoming(
+        &mut self,
+        block: BlockId,
+        phi: Variable,
+        incoming: &[(BlockId, RValue)],
+        cfg: &Cfg,
+    ) {
+        let preds = cfg.predecessors(block);
+        for pred in preds {
+            if !incoming.iter().any(|(from, _)| from == pred) {
+                self.errors.push(VerifyError::PhiMissingIncoming {
+                    block,
+                    phi,
+                    pred: *pred,
+                });
+            }
+        }
+        for (i, (from, _)) in incoming.iter().enumerate() {
+            let duplicate = incoming[..i].iter().any(|(other, _)| other == from);
+            if duplicate || !preds.contains(from) {
+                self.errors.push(VerifyError::PhiExtraIncoming {
+                    block,
+                    phi,
+                    from: *from,
+                });
+            }
+        }
+    }
+
+    fn check_use(&mut self, val: &RValue, site: UseSite, text: &str, doms: &DomTree) {
+        let var = match val {
+            RValue::Var(var) => *var,
+            RValue::Lit(..) => return,
+        };
+        let block = match site {
+            UseSite::Ins(block, _) | UseSite::EndOf(block) => block,
+        };
+        let (def, def_block, def_idx) = match self.defs.get(&var.id) {
+            Some(def) => *def,
+            None => {
+                self.errors.push(VerifyError::UndefinedValue { block, var });
+                return;
+            }
+        };
+        if def.ty != var.ty {
+            self.errors.push(VerifyError::TypeMismatch {
+                block,
+                site: text.to_string(),
+                expected: def.ty,
+                found: var.ty,
+            });
+        }
+        let dominated = match site {
+            // Anything goes in unreachable code.
+            _ if !doms.is_reachable(block) => true,
+            UseSite::Ins(_, idx) if def_block == block => def_idx.is_none_or(|def| def < idx),
+            UseSite::EndOf(_) if def_block == block => true,
+            _ => doms.dominates(def_block, block),
+        };
+        if !dominated {
+            self.errors.push(VerifyError::NotDominated { block, var });
+        }
+    }
+
+    fn check_types(&mut self) {
+        for (id, bb) in self.func.blocks() {
+            for ins in bb.ins_list() {
+                self.check_ins_types(id, ins);
+            }
+            if let Some(Terminator::Br(cond, ..)) = bb.terminator() {
+                let term = bb.terminator().expect("block is terminated");
+                self.expect_ty(id, term, Type::Bool, cond.data_ty());
+            }
+            if let Some(Terminator::Ret(val)) = bb.terminator() {
+                if val.map(|val| val.data_ty()) != self.func.signature().ret {
+                    self.errors.push(VerifyError::ReturnMismatch { block: id });
+                }
+            }
+        }
+    }
+
+    fn check_ins_types(&mut self, block: BlockId, ins: &Ins) {
+        match ins {
+            Ins::Cmp(dest, pred, a, b) => {
+                self.expect_ty(block, ins, Type::Bool, dest.data_ty());
+                self.expect_ty(block, ins, a.data_ty(), b.data_ty());
+                let ty = a.data_ty();
+                let valid = match pred.is_float() {
+                    true => ty.is_float(),
+                    false => ty.is_integer() || ty == Type::Bool || ty == Type::Ptr,
+                };
+                if !valid {
+                    self.errors.push(VerifyError::InvalidType {
+                        block,
+                        site: ins.to_string(),
+                        found: ty,
+                    });
+                }
+            }
+            Ins::Cast(dest, cast, val) => {
+                if !cast.accepts(val.data_ty(), dest.data_ty()) {
+                    self.errors.push(VerifyError::InvalidType {
+                        block,
+                        site: ins.to_string(),
+                        found: val.data_ty(),
+                    });
+                }
+            }
+            Ins::Select(dest, cond, a, b) => {
+                self.expect_ty(block, ins, Type::Bool, cond.data_ty());
+                self.expect_ty(block, ins, dest.data_ty(), a.data_ty());
+                self.expect_ty(block, ins, dest.data_ty(), b.data_ty());
+                if dest.data_ty().is_float() {
+                    self.errors.push(VerifyError::InvalidType {
+                        block,
+                        site: ins.to_string(),
+                        found: dest.data_ty(),
+                    });
+                }
+            }
+            Ins::Add(dest, ..) | Ins::Sub(dest, ..) | Ins::Mul(dest, ..)
+                if !dest.data_ty().is_integer() && !dest.data_ty().is_float() =>
+            {
+                self.errors.push(VerifyError::InvalidType {
+                    block,
+                    site: ins.to_string(),
+                    found: dest.data_ty(),
+                });
+            }
+            Ins::Div(dest, ..) if !dest.data_ty().is_float() => {
+                self.errors.push(VerifyError::InvalidType {
+                    block,
+                    site: ins.to_string(),
+                    found: dest.data_ty(),
+                });
+            }
+            Ins::And(dest, ..) | Ins::Or(dest, ..) | Ins::Xor(dest, ..) | Ins::Not(dest, ..)
+                if !dest.data_ty().is_integer() && dest.data_ty() != Type::Bool =>
+            {
+                self.errors.push(VerifyError::InvalidType {
+                    block,
+                    site: ins.to_string(),
+                    found: dest.data_ty(),
+                });
+            }
+            Ins::SDiv(dest, ..)
+            | Ins::UDiv(dest, ..)
+            | Ins::SRem(dest, ..)
+            | Ins::URem(dest, ..)
+            | Ins::Shl(dest, ..)
+            | Ins::LShr(dest, ..)
+            | Ins::AShr(dest, ..)
+            | Ins::Neg(dest, ..)
+                if !dest.data_ty().is_integer() =>
+            {
+                self.errors.push(VerifyError::InvalidType {
+                    block,
+                    site: ins.to_string(),
+                    found: dest.data_ty(),
+                });
+            }
+            Ins::Alloca(_, _, align) => {
+                // The frame pointer is only aligned to 16 bytes.
+                if !align.is_power_of_two() || *align > 16 {
+                    self.invalid_align(block, ins, *align);
+                }
+            }
+            Ins::Call(dest, callee, args) => self.check_call(block, ins, *dest, callee, args),
+            Ins::Addr(_, global) => {
+                if self
+                    .module
+                    .is_some_and(|module| module.global(global).is_none())
+                {
+                    self.errors.push(VerifyError::UnknownGlobal {
+                        block,
+                        global: global.clone(),
+                    });
+                }
+            }
+            Ins::Gep(_, ty, ptr, indices) => {
+                self.expect_ty(block, ins, Type::Ptr, ptr.data_ty());
+                for idx in indices {
+                    if !idx.data_ty().is_integer() {
+                        self.errors.push(VerifyError::InvalidType {
+                            block,
+                            site: ins.to_string(),
+                            found: idx.data_ty(),
+                        });
+                    }
+                }
+                if !valid_indices(ty, indices) {
+                    self.errors.push(VerifyError::InvalidIndex {
+                        block,
+                        site: ins.to_string(),
+                    });
+                }
+            }
+            Ins::Load(_, ptr, align) | Ins::Store(ptr, _, align) => {
+                self.expect_ty(block, ins, Type::Ptr, ptr.data_ty());
+                if !align.is_power_of_two() {
+                    self.invalid_align(block, ins, *align);
+                }
+            }
+            _ => {
+                if let Some(dest) = ins.dest() {
+                    for val in ins.operands() {
+                        self.expect_ty(block, ins, dest.data_ty(), val.data_ty());
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_call(
+        &mut self,
+        block: BlockId,
+        ins: &Ins,
+        dest: Option<Variable>,
+        callee: &str,
+        args: &[RValue],
+    ) {
+        let Some(module) = self.module else {
+            return;
+        };
+        let Some(sig) = module.signature_of(callee) else {
+            self.errors.push(VerifyError::UnknownCallee {
+                block,
+                callee: callee.to_string(),
+            });
+            return;
+        };
+        let dest_ty = dest.map(|dest| dest.data_ty());
+        if args.len() != sig.params.len() || dest_ty.is_some() != sig.ret.is_some() {
+            self.errors.push(VerifyError::CallMismatch {
+                block,
+                site: ins.to_string(),
+            });
+            return;
+        }
+        for (param, arg) in sig.params.iter().zip(args) {
+            self.expect_ty(block, ins, *param, arg.data_ty());
+        }
+        if let (Some(expected), Some(found)) = (sig.ret, dest_ty) {
+            self.expect_ty(block, ins, expected, found);
+        }
+    }
+
+    fn invalid_align(&mut self, block: BlockId, ins: &Ins, align: u32) {
+        self.errors.push(VerifyError::InvalidAlign {
+            block,
+            site: ins.to_string(),
+            align,
+        });
+    }
+
+    fn expect_ty(&mut self, block: BlockId, site: &dyn fmt::Display, expected: Type, found: Type) {
+        if expected != found {
+            self.errors.push(VerifyError::TypeMismatch {
+                block,
+                site: site.to_string(),
+                expected,
+                found,
+            });
+        }
+    }
+}
+
+/// Whether `indices` lead from a value of type `ty` to a part of it, see [`Ins::Gep`].
+fn valid_indices(ty: &MemType, indices: &[RValue]) -> bool {
+    if indices.is_empty() {
+        return false;
+    }
+    let mut ty = ty;
+    for idx in &indices[1..] {
+        ty = match (ty, idx) {
+            (MemType::Struct(fields), RValue::Lit(lit)) => {
+                let ty = lit.data_ty();
+                let negative = ty.is_signed() && lit.to_bits() >> (ty.bits() - 1) == 1;
+                match fields.get(lit.to_bits() as usize) {
+                    Some(field) if !negative => field,
+                    _ => return false,
+                }
+            }
+            (MemType::Array(elem, _), _) => elem,
+            _ => return false,
+        };
+    }
+    true
+}
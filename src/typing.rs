@@ -1,14 +1,177 @@
+use std::fmt;
+
 #[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
+    Bool,
+    I8,
+    I16,
     I32,
+    I64,
+    U8,
+    U16,
     U32,
+    U64,
+    F32,
+    F64,
+    /// Address of a value in memory.
+    Ptr,
 }
 
 impl Type {
     pub const fn mem_size(&self) -> usize {
         match self {
-            Type::I32 => 4,
-            Type::U32 => 4,
+            Type::Bool => 1,
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 => 4,
+            Type::I64 | Type::U64 => 8,
+            Type::F32 => 4,
+            Type::F64 => 8,
+            Type::Ptr => 8,
+        }
+    }
+
+    pub const fn is_integer(&self) -> bool {
+        self.is_signed() || self.is_unsigned()
+    }
+
+    pub const fn is_signed(&self) -> bool {
+        matches!(self, Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    pub const fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    pub const fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    /// Width in bits of a value of this type.
+    pub const fn bits(&self) -> u32 {
+        self.mem_size() as u32 * 8
+    }
+
+    /// Alignment in memory, which is the size for every scalar.
+    pub const fn mem_align(&self) -> usize {
+        self.mem_size()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Bool => "bool",
+            Type::I8 => "i8",
+            Type::I16 => "i16",
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+            Type::U8 => "u8",
+            Type::U16 => "u16",
+            Type::U32 => "u32",
+            Type::U64 => "u64",
+            Type::F32 => "f32",
+            Type::F64 => "f64",
+            Type::Ptr => "ptr",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "bool" => Some(Type::Bool),
+            "i8" => Some(Type::I8),
+            "i16" => Some(Type::I16),
+            "i32" => Some(Type::I32),
+            "i64" => Some(Type::I64),
+            "u8" => Some(Type::U8),
+            "u16" => Some(Type::U16),
+            "u32" => Some(Type::U32),
+            "u64" => Some(Type::U64),
+            "f32" => Some(Type::F32),
+            "f64" => Some(Type::F64),
+            "ptr" => Some(Type::Ptr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The type of data in memory: a scalar, or an aggregate of them laid out by the rules
+/// of the System V ABI for C structs and arrays.
+#[derive(Hash, Debug, Clone, PartialEq, Eq)]
+pub enum MemType {
+    Scalar(Type),
+    /// Fields in order, each at the next offset that is aligned for it.
+    Struct(Vec<MemType>),
+    /// A number of elements, one after the other.
+    Array(Box<MemType>, usize),
+}
+
+impl MemType {
+    /// Bytes taken, including padding at the end that keeps the elements of an array
+    /// aligned.
+    pub fn size(&self) -> usize {
+        match self {
+            MemType::Scalar(ty) => ty.mem_size(),
+            MemType::Struct(fields) => fields_end(fields).next_multiple_of(self.align()),
+            MemType::Array(elem, len) => elem.size() * len,
+        }
+    }
+
+    /// The largest alignment of any scalar inside. An empty struct has alignment 1.
+    pub fn align(&self) -> usize {
+        match self {
+            MemType::Scalar(ty) => ty.mem_align(),
+            MemType::Struct(fields) => fields.iter().map(|field| field.align()).max().unwrap_or(1),
+            MemType::Array(elem, _) => elem.align(),
+        }
+    }
+
+    /// Offset of field `idx` from the start of a struct, and the type of the field.
+    pub fn field(&self, idx: usize) -> Option<(usize, &MemType)> {
+        let MemType::Struct(fields) = self else {
+            return None;
+        };
+        let field = fields.get(idx)?;
+        Some((
+            fields_end(&fields[..idx]).next_multiple_of(field.align()),
+            field,
+        ))
+    }
+}
+
+/// The offset right after the last of these fields, laid out from offset 0.
+fn fields_end(fields: &[MemType]) -> usize {
+    fields.iter().fold(0, |offset, field| {
+        offset.next_multiple_of(field.align()) + field.size()
+    })
+}
+
+impl From<Type> for MemType {
+    fn from(ty: Type) -> MemType {
+        MemType::Scalar(ty)
+    }
+}
+
+/// Written like `{i32, [4 x f64]}`.
+impl fmt::Display for MemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemType::Scalar(ty) => fmt::Display::fmt(ty, f),
+            MemType::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                write!(f, "}}")
+            }
+            MemType::Array(elem, len) => write!(f, "[{} x {}]", len, elem),
         }
     }
 }
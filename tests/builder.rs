@@ -0,0 +1,95 @@
+//! SSA construction through [`FunctionBuilder`].
+
+use glair::ssa::builder::FunctionBuilder;
+use glair::ssa::{self, BinOpTy, CmpTy, Literal};
+use glair::typing::Type;
+
+/// A variable that nested loops only read gets phis in both headers while they are
+/// unsealed, which turn out trivial one after the other. The instructions that were
+/// emitted with them must end up using the parameter.
+#[test]
+fn trivial_phis_in_nested_loops() {
+    // n = 0; while n < 10 { m = 0; while m < n { m = m + x } n = n + x + 1 } return n + x
+    let sig = ssa::Signature::new(vec![Type::U32], Some(Type::U32));
+    let mut func = ssa::Function::new("f", sig);
+    let param = func.params()[0];
+    let mut b = FunctionBuilder::new(&mut func);
+    let (x, n, m) = (
+        b.declare_var(Type::U32),
+        b.declare_var(Type::U32),
+        b.declare_var(Type::U32),
+    );
+    let [entry, outer, outer_body, inner, inner_body, inner_exit, exit] =
+        [(); 7].map(|_| b.create_block());
+
+    b.seal_block(entry);
+    b.def_var(entry, x, param);
+    b.def_var(entry, n, Literal::U32(0));
+    b.emitter(entry).emit_jmp(outer);
+
+    let n_val = b.use_var(outer, n);
+    let cond = b
+        .emitter(outer)
+        .emit_cmp(n_val, Literal::U32(10), CmpTy::Ult);
+    b.emitter(outer).emit_br(cond, outer_body, exit);
+
+    b.seal_block(outer_body);
+    b.def_var(outer_body, m, Literal::U32(0));
+    b.emitter(outer_body).emit_jmp(inner);
+
+    let (m_val, n_val) = (b.use_var(inner, m), b.use_var(inner, n));
+    let cond = b.emitter(inner).emit_cmp(m_val, n_val, CmpTy::Ult);
+    b.emitter(inner).emit_br(cond, inner_body, inner_exit);
+
+    b.seal_block(inner_body);
+    let (m_val, x_val) = (b.use_var(inner_body, m), b.use_var(inner_body, x));
+    let sum = b.emitter(inner_body).emit_binop(m_val, x_val, BinOpTy::Add);
+    b.def_var(inner_body, m, sum);
+    b.emitter(inner_body).emit_jmp(inner);
+    b.seal_block(inner);
+
+    b.seal_block(inner_exit);
+    let (n_val, x_val) = (b.use_var(inner_exit, n), b.use_var(inner_exit, x));
+    let sum = b.emitter(inner_exit).emit_binop(n_val, x_val, BinOpTy::Add);
+    let next = b
+        .emitter(inner_exit)
+        .emit_binop(sum, Literal::U32(1), BinOpTy::Add);
+    b.def_var(inner_exit, n, next);
+    b.emitter(inner_exit).emit_jmp(outer);
+    b.seal_block(outer);
+
+    b.seal_block(exit);
+    let (n_val, x_val) = (b.use_var(exit, n), b.use_var(exit, x));
+    let ret = b.emitter(exit).emit_binop(n_val, x_val, BinOpTy::Add);
+    b.emitter(exit).emit_ret(ret);
+    b.finish();
+
+    assert_eq!(ssa::verify::verify(&func), Ok(()));
+    let expected = "\
+fn @f(u32 %0) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %1 = phi u32 [0, bb0], [%10, bb5]
+    %2 = cmp ult u32 %1, 10
+    br bool %2, bb2, bb6
+bb2:
+    jmp bb3
+bb3:
+    %3 = phi u32 [0, bb2], [%7, bb4]
+    %5 = cmp ult u32 %3, %1
+    br bool %5, bb4, bb5
+bb4:
+    %7 = add u32 %3, %0
+    jmp bb3
+bb5:
+    %9 = add u32 %1, %0
+    %10 = add u32 %9, 1
+    jmp bb1
+bb6:
+    %11 = add u32 %1, %0
+    ret u32 %11
+}
+";
+    assert_eq!(func.to_string(), expected);
+}
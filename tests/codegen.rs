@@ -0,0 +1,353 @@
+//! Modules compiled from GLIR text all the way to assembly.
+
+use glair::codegen::{Codegen, CodegenContext};
+use glair::compile::CompileIntoModule;
+use glair::ssa::parse;
+
+/// Compiles the module in `src` and checks its assembly against `expected`.
+#[track_caller]
+fn assert_asm(src: &str, expected: &str) {
+    let module = parse::parse_module(src).unwrap_or_else(|err| panic!("{}", err));
+    let asm = module
+        .compile_into_module()
+        .codegen_string(&mut CodegenContext::default());
+    assert_eq!(asm, expected);
+}
+
+#[test]
+fn f64_argument_and_return() {
+    // Both arguments come in `xmm0` and `xmm1`, the result goes out in `xmm0`, and the
+    // literal 1.5 is moved in through a general purpose register.
+    assert_asm(
+        "
+fn @scale(f64 %x, f64 %y) -> f64 {
+entry:
+    %m = mul f64 %x, %y
+    %r = add f64 %m, 1.5
+    ret f64 %r
+}
+",
+        "\
+default rel
+global scale
+
+section .text
+
+scale:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+movaps xmm0, xmm0
+movaps xmm1, xmm1
+.LBB_0:
+movaps xmm2, xmm0
+mulsd xmm2, xmm1
+movaps xmm0, xmm2
+mov r11, 4609434218613702656
+movq xmm15, r11
+addsd xmm0, xmm15
+movaps xmm0, xmm0
+leave
+ret
+",
+    );
+}
+
+#[test]
+fn fptoui() {
+    // There is only a signed conversion. Values from 2^63 up are converted after
+    // taking 2^63 off, which is put back as the top bit. A `u32` fits in the signed
+    // 64-bit conversion.
+    assert_asm(
+        "
+fn @to_u64(f64 %x) -> u64 {
+entry:
+    %u = fptoui f64 %x to u64
+    ret u64 %u
+}
+fn @to_u32(f64 %x) -> u32 {
+entry:
+    %u = fptoui f64 %x to u32
+    ret u32 %u
+}
+",
+        "\
+default rel
+global to_u64
+global to_u32
+
+section .text
+
+to_u64:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+movaps xmm0, xmm0
+.LBB_0:
+movsd xmm15, xmm0
+mov r11, 4890909195324358656
+movq xmm14, r11
+ucomisd xmm15, xmm14
+jae .L0
+cvttsd2si r11, xmm15
+jmp .L1
+.L0:
+subsd xmm15, xmm14
+cvttsd2si r11, xmm15
+btc r11, 63
+.L1:
+mov rax, r11
+mov rax, rax
+leave
+ret
+
+to_u32:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+movaps xmm0, xmm0
+.LBB_0:
+cvttsd2si r11, xmm0
+mov eax, r11d
+mov eax, eax
+leave
+ret
+",
+    );
+}
+
+#[test]
+fn call_with_arguments_on_the_stack() {
+    // The seventh and eighth integer arguments go on the stack, which stays aligned to
+    // 16 bytes at the call, and the callee finds them above its return address.
+    assert_asm(
+        "
+extern @many(u64, u64, u64, u64, u64, u64, u64, u32) -> u64
+fn @caller(u64 %a) -> u64 {
+entry:
+    %r = call u64 @many(u64 %a, u64 1, u64 2, u64 3, u64 4, u64 5, u64 6, u32 7)
+    ret u64 %r
+}
+fn @callee(u64 %a, u64 %b, u64 %c, u64 %d, u64 %e, u64 %f, u64 %g, u32 %h) -> u64 {
+entry:
+    %x = sub u64 %g, %a
+    ret u64 %x
+}
+",
+        "\
+default rel
+extern many
+global caller
+global callee
+
+section .text
+
+caller:
+push rbp
+mov rbp, rsp
+sub rsp, 16
+;; <unnamed block>
+mov rax, rdi
+.LBB_0:
+mov qword [rsp], 6
+mov dword [rsp+8], 7
+mov rdi, rax
+mov rsi, 1
+mov rdx, 2
+mov rcx, 3
+mov r8, 4
+mov r9, 5
+call many wrt ..plt
+mov rax, rax
+mov rax, rax
+leave
+ret
+
+callee:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov rax, rdi
+mov rsi, rsi
+mov rdx, rdx
+mov rcx, rcx
+mov rcx, r8
+mov rcx, r9
+mov rcx, qword [rbp+16]
+mov edx, dword [rbp+24]
+.LBB_0:
+mov rdx, rcx
+sub rdx, rax
+mov rax, rdx
+leave
+ret
+",
+    );
+}
+
+#[test]
+fn extension_and_truncation() {
+    // `sext` is a `movsx`, or `movsxd` from 32 bits. `zext` is a `movzx`, or a 32-bit
+    // `mov` from 32 bits, which clears the upper half. `trunc` moves the low part.
+    assert_asm(
+        "
+fn @widen(i8 %a, u16 %b) -> i64 {
+entry:
+    %s = sext i8 %a to i64
+    %z = zext u16 %b to i64
+    %r = add i64 %s, %z
+    ret i64 %r
+}
+fn @widen32(i32 %a, u32 %b) -> i64 {
+entry:
+    %s = sext i32 %a to i64
+    %z = zext u32 %b to u64
+    %zz = bitcast u64 %z to i64
+    %r = add i64 %s, %zz
+    ret i64 %r
+}
+fn @narrow(i64 %a) -> i8 {
+entry:
+    %t = trunc i64 %a to i8
+    ret i8 %t
+}
+fn @bytes(u8 %a) -> u32 {
+entry:
+    %z = zext u8 %a to u32
+    %t = trunc u32 %z to u16
+    %w = zext u16 %t to u32
+    ret u32 %w
+}
+",
+        "\
+default rel
+global widen
+global widen32
+global narrow
+global bytes
+
+section .text
+
+widen:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov al, dil
+mov cx, si
+.LBB_0:
+movsx rdx, al
+movzx rax, cx
+mov rcx, rdx
+add rcx, rax
+mov rax, rcx
+leave
+ret
+
+widen32:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov eax, edi
+mov ecx, esi
+.LBB_0:
+movsxd rdx, eax
+mov eax, ecx
+mov rcx, rax
+mov rax, rdx
+add rax, rcx
+mov rax, rax
+leave
+ret
+
+narrow:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov rax, rdi
+.LBB_0:
+mov cl, al
+mov al, cl
+leave
+ret
+
+bytes:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov al, dil
+.LBB_0:
+movzx ecx, al
+mov ax, cx
+movzx ecx, ax
+mov eax, ecx
+leave
+ret
+",
+    );
+}
+
+#[test]
+fn globals_in_each_section() {
+    // Initialized globals go to `.data`, constants to `.rodata` and zeroed globals to
+    // `.bss`. The `u16, u16, i8` of the table are padded to its alignment of 2.
+    assert_asm(
+        "
+global @counter = [u32 7], align 4
+const @table = [u16 1, u16 2, i8 -3], align 2
+global @buffer = zeroinit 16, align 8
+fn @read() -> u32 {
+entry:
+    %c = addr @counter
+    %v = load u32 %c, align 4
+    %t = addr @table
+    %w = load u16 %t, align 2
+    %b = addr @buffer
+    store u32 %v, %b, align 8
+    %x = zext u16 %w to u32
+    %r = add u32 %v, %x
+    ret u32 %r
+}
+",
+        "\
+default rel
+global read
+global counter
+global table
+global buffer
+
+section .text
+
+read:
+push rbp
+mov rbp, rsp
+.LBB_0:
+lea rax, [rel counter]
+mov ecx, dword [rax]
+lea rax, [rel table]
+mov dx, word [rax]
+lea rax, [rel buffer]
+mov dword [rax], ecx
+movzx eax, dx
+mov edx, ecx
+add edx, eax
+mov eax, edx
+leave
+ret
+
+section .data
+align 4
+counter:
+db 7, 0, 0, 0
+
+section .rodata
+align 2
+table:
+db 1, 0, 2, 0, 253, 0
+
+section .bss
+alignb 8
+buffer:
+resb 16
+",
+    );
+}
@@ -0,0 +1,34 @@
+//! Running passes over functions written as GLIR text.
+
+use glair::opt;
+use glair::pass::PassManager;
+use glair::ssa::parse;
+
+/// Parses the module in `src`, runs `pipeline` over it and prints its functions.
+pub fn run(pipeline: &str, src: &str) -> String {
+    let mut module = parse::parse_module(src).unwrap_or_else(|err| panic!("{}", err));
+    PassManager::parse(&opt::registry(), pipeline)
+        .unwrap()
+        .run(&mut module);
+    module
+        .functions()
+        .iter()
+        .map(|func| func.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Checks that `pipeline` turns `src` into `expected`, both without their leading
+/// and trailing whitespace.
+#[track_caller]
+pub fn assert_pipeline(pipeline: &str, src: &str, expected: &str) {
+    let out = run(pipeline, src);
+    assert_eq!(
+        out.trim(),
+        expected.trim(),
+        "\n{} turned\n{}\ninto\n{}",
+        pipeline,
+        src.trim(),
+        out.trim()
+    );
+}
@@ -0,0 +1,101 @@
+//! Copy propagation, from GLIR text to printed text.
+
+mod common;
+
+use common::assert_pipeline;
+
+#[test]
+fn copies_of_values_and_literals() {
+    assert_pipeline(
+        "copyprop",
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    %a = cpy u32 %x
+    %b = cpy u32 %a
+    %k = cpy u32 5
+    %s = add u32 %b, %k
+    ret u32 %s
+}
+",
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    %4 = add u32 %0, 5
+    ret u32 %4
+}
+",
+    );
+}
+
+#[test]
+fn phis_of_one_value() {
+    assert_pipeline(
+        "copyprop",
+        "
+fn @f(bool %c, u32 %x) -> u32 {
+entry:
+    %y = cpy u32 %x
+    br bool %c, l, r
+l:
+    jmp join
+r:
+    jmp join
+join:
+    %p = phi u32 [%x, l], [%y, r]
+    %q = phi u32 [%x, l], [1, r]
+    %s = add u32 %p, %q
+    ret u32 %s
+}
+",
+        "
+fn @f(bool %0, u32 %1) -> u32 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    jmp bb3
+bb2:
+    jmp bb3
+bb3:
+    %4 = phi u32 [%1, bb1], [1, bb2]
+    %5 = add u32 %1, %4
+    ret u32 %5
+}
+",
+    );
+}
+
+#[test]
+fn loop_phi_that_takes_itself() {
+    // `%x` is `%a` on entry and itself around the loop, so it is `%a` throughout, and
+    // the inner phi becomes trivial once it is.
+    assert_pipeline(
+        "copyprop",
+        "
+fn @f(u32 %a, bool %c) -> u32 {
+entry:
+    jmp loop
+loop:
+    %x = phi u32 [%a, entry], [%x, loop], [%y, inner]
+    br bool %c, loop, inner
+inner:
+    %y = phi u32 [%x, loop]
+    br bool %c, loop, exit
+exit:
+    ret u32 %y
+}
+",
+        "
+fn @f(u32 %0, bool %1) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    br bool %1, bb1, bb2
+bb2:
+    br bool %1, bb1, bb3
+bb3:
+    ret u32 %0
+}
+",
+    );
+}
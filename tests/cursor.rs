@@ -0,0 +1,250 @@
+//! Editing functions through a cursor.
+
+use glair::ssa::defuse::{Def, DefUse, Use};
+use glair::ssa::{parse, verify, Function, Ins, Literal, RValue, Variable};
+use glair::typing::Type;
+
+fn looped() -> Function {
+    parse::parse_function(
+        "
+fn @f(u32 %n) -> u32 {
+entry:
+    jmp header
+header:
+    %i = phi u32 [0, entry], [%next, header]
+    %next = add u32 %i, 1
+    %more = cmp ult u32 %next, %n
+    br bool %more, header, exit
+exit:
+    ret u32 %next
+}
+",
+    )
+    .unwrap()
+}
+
+#[test]
+fn split_after_the_phis() {
+    let mut func = looped();
+    let header = func.block_ids().nth(1).unwrap();
+    let tail = func.cursor_after_phis(header).split_block();
+    assert_eq!(verify::verify(&func), Ok(()));
+    let expected = "\
+fn @f(u32 %0) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %1 = phi u32 [0, bb0], [%2, bb3]
+    jmp bb3
+bb2:
+    ret u32 %2
+bb3:
+    %2 = add u32 %1, 1
+    %3 = cmp ult u32 %2, %0
+    br bool %3, bb1, bb2
+}
+";
+    assert_eq!(func.to_string(), expected);
+    assert_eq!(func.predecessors(tail), [header]);
+}
+
+#[test]
+#[should_panic(expected = "block is split after its phis")]
+fn split_among_the_phis() {
+    let mut func = looped();
+    let header = func.block_ids().nth(1).unwrap();
+    func.cursor(header, 0).split_block();
+}
+
+/// The chains the function keeps match the ones built from scratch.
+fn assert_fresh(func: &Function) {
+    let fresh = DefUse::compute(func);
+    for var in func.supervisor().vars() {
+        assert_eq!(func.def_of(*var), fresh.def_of(*var), "def of {}", var);
+        assert_eq!(func.uses_of(*var), fresh.uses_of(*var), "uses of {}", var);
+    }
+}
+
+/// The values an instruction of each block defines, in order.
+fn dests(func: &Function) -> Vec<Vec<Variable>> {
+    func.blocks()
+        .map(|(_, bb)| bb.ins_list().iter().filter_map(Ins::dest).collect())
+        .collect()
+}
+
+#[test]
+fn insert_at_the_start_and_end() {
+    let mut func = parse::parse_function(
+        "
+fn @f(u32 %a) -> u32 {
+entry:
+    %x = add u32 %a, 1
+    %y = mul u32 %x, 2
+    ret u32 %y
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let a = func.params()[0];
+    let [x, y] = [dests(&func)[0][0], dests(&func)[0][1]];
+    assert_eq!(func.def_of(x), Some(Def::Ins(entry, 0)));
+
+    let mut cursor = func.cursor(entry, 0);
+    let first = cursor.create_var(Type::U32);
+    cursor.insert(Ins::Cpy(first, RValue::Var(a)));
+    assert_eq!(cursor.index(), 1);
+    assert_eq!(cursor.current().and_then(Ins::dest), Some(x));
+    assert_fresh(&func);
+    assert_eq!(func.def_of(first), Some(Def::Ins(entry, 0)));
+    assert_eq!(func.def_of(x), Some(Def::Ins(entry, 1)));
+    assert_eq!(func.uses_of(a), [Use::Ins(entry, 0), Use::Ins(entry, 1)]);
+
+    let mut cursor = func.cursor_at_end(entry);
+    let last = cursor.create_var(Type::U32);
+    cursor.insert(Ins::Add(last, RValue::Var(y), RValue::Var(first)));
+    assert!(cursor.is_at_end());
+    assert_fresh(&func);
+    assert_eq!(func.def_of(last), Some(Def::Ins(entry, 3)));
+    assert_eq!(func.uses_of(y), [Use::Ins(entry, 3), Use::Term(entry)]);
+    assert_eq!(func.uses_of(first), [Use::Ins(entry, 3)]);
+    assert_eq!(verify::verify(&func), Ok(()));
+}
+
+#[test]
+fn insert_after_keeps_the_cursor() {
+    let mut func = parse::parse_function(
+        "
+fn @f(u32 %a) -> u32 {
+entry:
+    %x = add u32 %a, 1
+    %y = mul u32 %x, 2
+    ret u32 %y
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let [x, y] = [dests(&func)[0][0], dests(&func)[0][1]];
+
+    let mut cursor = func.cursor(entry, 0);
+    let after_first = cursor.create_var(Type::U32);
+    cursor.insert_after(Ins::Sub(after_first, RValue::Var(x), RValue::Var(x)));
+    assert_eq!(cursor.index(), 0);
+    assert_eq!(cursor.current().and_then(Ins::dest), Some(x));
+    cursor.goto(entry, 2);
+    let after_last = cursor.create_var(Type::U32);
+    cursor.insert_after(Ins::Add(
+        after_last,
+        RValue::Var(y),
+        RValue::Var(after_first),
+    ));
+    assert_eq!(cursor.index(), 2);
+    assert_eq!(cursor.current().and_then(Ins::dest), Some(y));
+    assert_fresh(&func);
+    assert_eq!(dests(&func), [vec![x, after_first, y, after_last]]);
+    assert_eq!(
+        func.uses_of(x),
+        [Use::Ins(entry, 1), Use::Ins(entry, 1), Use::Ins(entry, 2)]
+    );
+    assert_eq!(func.uses_of(after_first), [Use::Ins(entry, 3)]);
+    assert_eq!(verify::verify(&func), Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "there is an instruction to insert after")]
+fn insert_after_the_end() {
+    let mut func = looped();
+    let exit = func.block_ids().nth(2).unwrap();
+    let mut cursor = func.cursor_at_end(exit);
+    let var = cursor.create_var(Type::U32);
+    cursor.insert_after(Ins::Cpy(var, RValue::Lit(Literal::U32(0))));
+}
+
+#[test]
+fn remove_the_first_and_last() {
+    let mut func = parse::parse_function(
+        "
+fn @f(u32 %a) -> u32 {
+entry:
+    %u = add u32 %a, 5
+    %x = add u32 %a, 1
+    %y = mul u32 %x, 2
+    %v = sub u32 %a, 3
+    ret u32 %y
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let a = func.params()[0];
+    let [u, x, y, v] = <[Variable; 4]>::try_from(dests(&func).remove(0)).unwrap();
+
+    let mut cursor = func.cursor(entry, 0);
+    assert_eq!(cursor.remove().dest(), Some(u));
+    assert_eq!(cursor.current().and_then(Ins::dest), Some(x));
+    assert_fresh(&func);
+    assert_eq!(func.def_of(u), None);
+    assert_eq!(func.def_of(x), Some(Def::Ins(entry, 0)));
+    assert_eq!(func.uses_of(a), [Use::Ins(entry, 0), Use::Ins(entry, 2)]);
+
+    let mut cursor = func.cursor_at_end(entry);
+    assert!(cursor.step_back());
+    assert_eq!(cursor.remove().dest(), Some(v));
+    assert!(cursor.is_at_end());
+    assert_fresh(&func);
+    assert_eq!(func.def_of(v), None);
+    assert_eq!(func.uses_of(a), [Use::Ins(entry, 0)]);
+    assert_eq!(func.uses_of(y), [Use::Term(entry)]);
+    assert_eq!(verify::verify(&func), Ok(()));
+}
+
+#[test]
+fn move_within_and_between_blocks() {
+    let mut func = parse::parse_function(
+        "
+fn @f(u32 %a) -> u32 {
+entry:
+    %x = add u32 %a, 1
+    %y = mul u32 %a, 2
+    %z = sub u32 %a, 3
+    jmp exit
+exit:
+    %s = add u32 %x, %y
+    %t = add u32 %s, %z
+    ret u32 %t
+}
+",
+    )
+    .unwrap();
+    let [entry, exit] = [func.entry(), func.block_ids().nth(1).unwrap()];
+    let [x, y, z] = <[Variable; 3]>::try_from(dests(&func).remove(0)).unwrap();
+    let [s, t] = [dests(&func)[1][0], dests(&func)[1][1]];
+
+    // From the start to the end: the cursor is left before what followed `%x`.
+    let mut cursor = func.cursor(entry, 0);
+    cursor.move_to(entry, 3);
+    assert_eq!(cursor.index(), 0);
+    assert_eq!(cursor.current().and_then(Ins::dest), Some(y));
+    assert_eq!(dests(cursor.func())[0], [y, z, x]);
+
+    // And back to the start: the cursor stays after `%y` and `%z`.
+    cursor.goto(entry, 2);
+    cursor.move_to(entry, 0);
+    assert!(cursor.is_at_end());
+    assert_eq!(dests(cursor.func())[0], [x, y, z]);
+    assert_fresh(&func);
+    assert_eq!(func.def_of(x), Some(Def::Ins(entry, 0)));
+    assert_eq!(func.def_of(z), Some(Def::Ins(entry, 2)));
+
+    // To the start of the next block.
+    let mut cursor = func.cursor(entry, 2);
+    cursor.move_to(exit, 0);
+    assert!(cursor.is_at_end());
+    assert_fresh(&func);
+    assert_eq!(dests(&func), [vec![x, y], vec![z, s, t]]);
+    assert_eq!(func.def_of(z), Some(Def::Ins(exit, 0)));
+    assert_eq!(func.def_of(s), Some(Def::Ins(exit, 1)));
+    assert_eq!(func.uses_of(z), [Use::Ins(exit, 2)]);
+    assert_eq!(verify::verify(&func), Ok(()));
+}
@@ -0,0 +1,163 @@
+//! Dead code elimination, from GLIR text to printed text.
+
+mod common;
+
+use common::assert_pipeline;
+
+#[test]
+fn dead_chains() {
+    assert_pipeline(
+        "dce",
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    %a = add u32 %x, 1
+    %b = mul u32 %a, %a
+    %c = xor u32 %b, %x
+    %keep = sub u32 %x, 2
+    ret u32 %keep
+}
+",
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    %4 = sub u32 %0, 2
+    ret u32 %4
+}
+",
+    );
+}
+
+#[test]
+fn side_effects_and_possible_traps_stay() {
+    // Only the division by a constant that is neither zero nor -1 can't trap.
+    assert_pipeline(
+        "dce",
+        "
+fn @g(u32 %x) -> u32 {
+entry:
+    ret u32 %x
+}
+
+fn @f(i32 %x, u32 %y, ptr %p) {
+entry:
+    store u32 %y, %p, align 4
+    %r = call u32 @g(u32 %y)
+    %q = sdiv i32 %x, %x
+    %s = srem i32 %x, -1
+    %u = udiv u32 %y, 0
+    %v = urem u32 %y, %y
+    %gone = sdiv i32 %x, 3
+    %gone2 = udiv u32 %y, 7
+    %l = load u32 %p, align 4
+    ret
+}
+",
+        "
+fn @g(u32 %0) -> u32 {
+bb0:
+    ret u32 %0
+}
+
+fn @f(i32 %0, u32 %1, ptr %2) {
+bb0:
+    store u32 %1, %2, align 4
+    %3 = call u32 @g(u32 %1)
+    %4 = sdiv i32 %0, %0
+    %5 = srem i32 %0, -1
+    %6 = udiv u32 %1, 0
+    %7 = urem u32 %1, %1
+    ret
+}
+",
+    );
+}
+
+const DEAD_LOOP: &str = "
+fn @f(u32 %n) -> u32 {
+entry:
+    jmp header
+header:
+    %i = phi u32 [0, entry], [%next, body]
+    %acc = phi u32 [1, entry], [%acc2, body]
+    %more = cmp ult u32 %i, %n
+    br bool %more, body, exit
+body:
+    %acc2 = mul u32 %acc, 3
+    %next = add u32 %i, 1
+    jmp header
+exit:
+    ret u32 %n
+}
+";
+
+#[test]
+fn dce_keeps_phis_that_use_each_other() {
+    assert_pipeline(
+        "dce",
+        DEAD_LOOP,
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %1 = phi u32 [0, bb0], [%5, bb2]
+    %2 = phi u32 [1, bb0], [%4, bb2]
+    %3 = cmp ult u32 %1, %0
+    br bool %3, bb2, bb3
+bb2:
+    %4 = mul u32 %2, 3
+    %5 = add u32 %1, 1
+    jmp bb1
+bb3:
+    ret u32 %0
+}
+",
+    );
+}
+
+#[test]
+fn adce_removes_a_dead_loops_phis() {
+    // The counter decides the branch, so only the accumulator goes.
+    assert_pipeline(
+        "adce",
+        DEAD_LOOP,
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %1 = phi u32 [0, bb0], [%5, bb2]
+    %3 = cmp ult u32 %1, %0
+    br bool %3, bb2, bb3
+bb2:
+    %5 = add u32 %1, 1
+    jmp bb1
+bb3:
+    ret u32 %0
+}
+",
+    );
+}
+
+#[test]
+fn adce_removes_unreachable_blocks() {
+    assert_pipeline(
+        "adce",
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    ret u32 %x
+dead:
+    %a = add u32 %x, 1
+    jmp dead
+}
+",
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    ret u32 %0
+}
+",
+    );
+}
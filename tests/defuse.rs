@@ -0,0 +1,42 @@
+//! Def-use chains kept up to date through edits.
+
+use glair::ssa::defuse::DefUse;
+use glair::ssa::{parse, Function, Ins, Literal, RValue};
+
+/// The chains the function kept match the ones built from scratch.
+fn assert_fresh(func: &Function) {
+    let fresh = DefUse::compute(func);
+    for var in func.supervisor().vars() {
+        assert_eq!(func.def_of(*var), fresh.def_of(*var), "def of {}", var);
+        assert_eq!(func.uses_of(*var), fresh.uses_of(*var), "uses of {}", var);
+    }
+}
+
+#[test]
+fn replacing_instructions_keeps_the_chains() {
+    let mut func = parse::parse_function(
+        "
+fn @f(i32 %a, i32 %b) -> i32 {
+entry:
+    %x = add i32 %a, %b
+    %y = mul i32 %x, %x
+    %z = sub i32 %y, %a
+    ret i32 %z
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let [a, b] = [func.params()[0], func.params()[1]];
+    let [x, y] = [1, 2].map(|i| func.block(entry).ins_list()[i - 1].dest().unwrap());
+    assert_fresh(&func);
+
+    let old = func.replace_ins(entry, 1, Ins::Mul(y, RValue::Var(b), RValue::Var(x)));
+    assert_eq!(old.to_string(), format!("{} = mul i32 {}, {}", y, x, x));
+    assert_fresh(&func);
+
+    let mut cursor = func.cursor(entry, 0);
+    cursor.replace(Ins::Sub(x, RValue::Var(a), RValue::Lit(Literal::I32(1))));
+    assert_fresh(&func);
+    assert_eq!(func.uses_of(b).len(), 1);
+}
@@ -0,0 +1,189 @@
+//! Dominators, post-dominators and their frontiers, against answers worked out by hand.
+
+use glair::ssa::dom::{DomTree, PostDomTree};
+use glair::ssa::{parse, BlockId, Function};
+
+/// A loop around a diamond, and a block nothing jumps to that joins the loop.
+///
+/// ```text
+/// entry -> head -> body -> left  -> latch -> head
+///          head -> exit    body -> right -> latch
+///                          dead -> latch
+/// ```
+const LOOP: &str = "
+fn @f(bool %c, bool %d) {
+entry:
+    jmp head
+head:
+    br bool %c, body, exit
+body:
+    br bool %d, left, right
+left:
+    jmp latch
+right:
+    jmp latch
+latch:
+    jmp head
+exit:
+    ret
+dead:
+    jmp latch
+}
+";
+
+const ENTRY: usize = 0;
+const HEAD: usize = 1;
+const BODY: usize = 2;
+const LEFT: usize = 3;
+const RIGHT: usize = 4;
+const LATCH: usize = 5;
+const EXIT: usize = 6;
+const DEAD: usize = 7;
+
+fn blocks(src: &str) -> (Function, Vec<BlockId>) {
+    let func = parse::parse_function(src).unwrap();
+    let ids = func.block_ids().collect();
+    (func, ids)
+}
+
+#[test]
+fn dominators() {
+    let (func, b) = blocks(LOOP);
+    let dom = DomTree::compute(&func);
+    let idoms: Vec<_> = b.iter().map(|block| dom.idom(*block)).collect();
+    assert_eq!(
+        idoms,
+        [
+            None,
+            Some(b[ENTRY]),
+            Some(b[HEAD]),
+            Some(b[BODY]),
+            Some(b[BODY]),
+            Some(b[BODY]),
+            Some(b[HEAD]),
+            None,
+        ]
+    );
+    let mut children = dom.children(b[BODY]);
+    children.sort();
+    assert_eq!(children, [b[LEFT], b[RIGHT], b[LATCH]]);
+    assert!(dom.dominates(b[HEAD], b[LATCH]));
+    assert!(dom.dominates(b[LATCH], b[LATCH]));
+    assert!(!dom.strictly_dominates(b[LATCH], b[LATCH]));
+    assert!(!dom.dominates(b[LATCH], b[HEAD]));
+    assert!(!dom.dominates(b[LEFT], b[LATCH]));
+    assert!(!dom.is_reachable(b[DEAD]));
+    assert!(!dom.dominates(b[ENTRY], b[DEAD]));
+    assert_eq!(dom.pre_number(b[ENTRY]), Some(0));
+    assert_eq!(dom.pre_number(b[DEAD]), None);
+}
+
+#[test]
+fn nearest_common_dominator() {
+    let (func, b) = blocks(LOOP);
+    let dom = DomTree::compute(&func);
+    let ncd = |x: usize, y: usize| dom.nearest_common_dominator(b[x], b[y]);
+    assert_eq!(ncd(LEFT, RIGHT), Some(b[BODY]));
+    assert_eq!(ncd(LATCH, EXIT), Some(b[HEAD]));
+    assert_eq!(ncd(LEFT, LATCH), Some(b[BODY]));
+    assert_eq!(ncd(LATCH, BODY), Some(b[BODY]));
+    assert_eq!(ncd(ENTRY, LEFT), Some(b[ENTRY]));
+    assert_eq!(ncd(DEAD, LEFT), None);
+}
+
+#[test]
+fn dominance_frontiers() {
+    // The header is in its own frontier through the back edge, and the unreachable
+    // block adds nothing to the frontier of the latch it jumps to.
+    let (func, b) = blocks(LOOP);
+    let dom = DomTree::compute(&func);
+    let frontiers: Vec<_> = b.iter().map(|block| dom.frontier(*block)).collect();
+    assert_eq!(
+        frontiers,
+        [
+            vec![],
+            vec![b[HEAD]],
+            vec![b[HEAD]],
+            vec![b[LATCH]],
+            vec![b[LATCH]],
+            vec![b[HEAD]],
+            vec![],
+            vec![],
+        ]
+    );
+    assert_eq!(dom.iterated_frontier(&[b[LEFT]]), [b[HEAD], b[LATCH]]);
+    assert_eq!(dom.iterated_frontier(&[b[ENTRY], b[EXIT]]), []);
+}
+
+#[test]
+fn post_dominators() {
+    let (func, b) = blocks(LOOP);
+    let pdom = PostDomTree::compute(&func);
+    let ipdoms: Vec<_> = b.iter().map(|block| pdom.ipdom(*block)).collect();
+    assert_eq!(
+        ipdoms,
+        [
+            Some(b[HEAD]),
+            Some(b[EXIT]),
+            Some(b[LATCH]),
+            Some(b[LATCH]),
+            Some(b[LATCH]),
+            Some(b[HEAD]),
+            None,
+            Some(b[LATCH]),
+        ]
+    );
+    assert_eq!(pdom.roots(), [b[EXIT]]);
+    assert!(pdom.post_dominates(b[LATCH], b[LEFT]));
+    assert!(pdom.post_dominates(b[EXIT], b[DEAD]));
+    assert!(!pdom.post_dominates(b[LEFT], b[BODY]));
+    assert!(!pdom.strictly_post_dominates(b[EXIT], b[EXIT]));
+    assert!(pdom.reaches_exit(b[DEAD]));
+}
+
+#[test]
+fn post_dominance_frontiers() {
+    // Each block is control dependent on the branches that decide whether it runs.
+    let (func, b) = blocks(LOOP);
+    let pdom = PostDomTree::compute(&func);
+    let frontiers: Vec<_> = b.iter().map(|block| pdom.frontier(*block)).collect();
+    assert_eq!(
+        frontiers,
+        [
+            vec![],
+            vec![b[HEAD]],
+            vec![b[HEAD]],
+            vec![b[BODY]],
+            vec![b[BODY]],
+            vec![b[HEAD]],
+            vec![],
+            vec![],
+        ]
+    );
+}
+
+#[test]
+fn endless_loop_is_not_post_dominated() {
+    let (func, b) = blocks(
+        "
+fn @f(bool %c) {
+entry:
+    br bool %c, spin, out
+spin:
+    jmp spin
+out:
+    ret
+}
+",
+    );
+    let [entry, spin, out] = [b[0], b[1], b[2]];
+    let pdom = PostDomTree::compute(&func);
+    assert!(!pdom.reaches_exit(spin));
+    assert_eq!(pdom.ipdom(spin), None);
+    assert_eq!(pdom.ipdom(entry), Some(out));
+    assert!(!pdom.post_dominates(out, spin));
+    assert_eq!(pdom.frontier(out), []);
+    let dom = DomTree::compute(&func);
+    assert_eq!(dom.idom(spin), Some(entry));
+    assert_eq!(dom.frontier(spin), [spin]);
+}
@@ -0,0 +1,171 @@
+//! Global value numbering, from GLIR text to printed text.
+
+mod common;
+
+use common::assert_pipeline;
+
+#[test]
+fn redundant_in_a_dominated_block() {
+    assert_pipeline(
+        "gvn",
+        "
+fn @f(bool %c, u32 %x, u32 %y) -> u32 {
+entry:
+    %a = add u32 %x, %y
+    %b = add u32 %a, 1
+    br bool %c, then, exit
+then:
+    %a2 = add u32 %x, %y
+    %b2 = add u32 %a2, 1
+    %d = sub u32 %b2, %b
+    ret u32 %d
+exit:
+    %a3 = add u32 %x, %y
+    ret u32 %a3
+}
+",
+        "
+fn @f(bool %0, u32 %1, u32 %2) -> u32 {
+bb0:
+    %3 = add u32 %1, %2
+    %4 = add u32 %3, 1
+    br bool %0, bb1, bb2
+bb1:
+    %7 = sub u32 %4, %4
+    ret u32 %7
+bb2:
+    ret u32 %3
+}
+",
+    );
+}
+
+#[test]
+fn commutative_operands_and_swapped_comparisons() {
+    assert_pipeline(
+        "gvn",
+        "
+fn @f(u32 %x, u32 %y) -> u32 {
+entry:
+    %a = mul u32 %x, %y
+    %b = mul u32 %y, %x
+    %c = add u32 3, %x
+    %d = add u32 %x, 3
+    %e = sub u32 %x, 4294967293
+    %lt = cmp ult u32 %x, %y
+    %gt = cmp ugt u32 %y, %x
+    %s1 = select u32 %lt, %a, %c
+    %s2 = select u32 %gt, %b, %d
+    %s3 = select u32 %gt, %b, %e
+    %r1 = xor u32 %s1, %s2
+    %r = xor u32 %r1, %s3
+    ret u32 %r
+}
+",
+        "
+fn @f(u32 %0, u32 %1) -> u32 {
+bb0:
+    %2 = mul u32 %0, %1
+    %4 = add u32 3, %0
+    %7 = cmp ult u32 %0, %1
+    %9 = select u32 %7, %2, %4
+    %12 = xor u32 %9, %9
+    %13 = xor u32 %12, %9
+    ret u32 %13
+}
+",
+    );
+}
+
+#[test]
+fn siblings_stay_apart() {
+    // Neither arm dominates the other or the join, so each keeps its own `add`.
+    assert_pipeline(
+        "gvn",
+        "
+fn @f(bool %c, u32 %x) -> u32 {
+entry:
+    br bool %c, l, r
+l:
+    %a = add u32 %x, 1
+    jmp join
+r:
+    %b = add u32 %x, 1
+    jmp join
+join:
+    %p = phi u32 [%a, l], [%b, r]
+    %d = add u32 %x, 1
+    %s = add u32 %p, %d
+    ret u32 %s
+}
+",
+        "
+fn @f(bool %0, u32 %1) -> u32 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    %2 = add u32 %1, 1
+    jmp bb3
+bb2:
+    %3 = add u32 %1, 1
+    jmp bb3
+bb3:
+    %4 = phi u32 [%2, bb1], [%3, bb2]
+    %5 = add u32 %1, 1
+    %6 = add u32 %4, %5
+    ret u32 %6
+}
+",
+    );
+}
+
+#[test]
+fn memory_and_calls_are_not_merged() {
+    assert_pipeline(
+        "gvn",
+        "
+fn @g() -> u32 {
+entry:
+    ret u32 1
+}
+
+fn @f(ptr %p) -> u32 {
+entry:
+    %a = load u32 %p, align 4
+    store u32 7, %p, align 4
+    %b = load u32 %p, align 4
+    store u32 7, %p, align 4
+    %c = call u32 @g()
+    %d = call u32 @g()
+    %m = alloca 4, align 4
+    %n = alloca 4, align 4
+    %s1 = add u32 %a, %b
+    %s2 = add u32 %c, %d
+    %s = add u32 %s1, %s2
+    ret u32 %s
+}
+",
+        "
+fn @g() -> u32 {
+bb0:
+    ret u32 1
+}
+
+fn @f(ptr %0) -> u32 {
+bb0:
+    %1 = load u32 %0, align 4
+    store u32 7, %0, align 4
+    %2 = load u32 %0, align 4
+    store u32 7, %0, align 4
+    %3 = call u32 @g()
+    %4 = call u32 @g()
+    %5 = alloca 4, align 4
+    %6 = alloca 4, align 4
+    %7 = add u32 %1, %2
+    %8 = add u32 %3, %4
+    %9 = add u32 %7, %8
+    ret u32 %9
+}
+",
+    );
+}
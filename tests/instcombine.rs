@@ -0,0 +1,197 @@
+//! Instruction combining, one rule at a time and as a pass.
+
+mod common;
+
+use common::assert_pipeline;
+use glair::opt::instcombine::{Fired, InstCombine, RULES};
+use glair::pass::{Analyses, FunctionPass};
+use glair::ssa::parse;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// For each rule, the result type and the instructions of a function of `u32 %x` and
+/// `u32 %y` whose last one, `%r`, is what the rule rewrites, and how.
+const CASES: &[(&str, &str, &str, &str)] = &[
+    ("fold", "u32", "%r = mul u32 6, 7", "42"),
+    (
+        "literal-right",
+        "u32",
+        "%r = add u32 3, %x",
+        "%2 = add u32 %0, 3",
+    ),
+    ("identity", "u32", "%r = mul u32 %x, 1", "%0"),
+    ("absorb", "u32", "%r = or u32 %x, 4294967295", "4294967295"),
+    ("self-cancel", "u32", "%r = xor u32 %x, %x", "0"),
+    ("self-cmp", "bool", "%r = cmp ule u32 %y, %y", "true"),
+    (
+        "double-negation",
+        "u32",
+        "%n = not u32 %x\n    %r = not u32 %n",
+        "%0",
+    ),
+    (
+        "sub-literal",
+        "u32",
+        "%r = sub u32 %x, 3",
+        "%2 = add u32 %0, 4294967293",
+    ),
+    (
+        "sub-neg",
+        "u32",
+        "%n = neg u32 %y\n    %r = sub u32 %x, %n",
+        "%3 = add u32 %0, %1",
+    ),
+    (
+        "reassociate",
+        "u32",
+        "%a = xor u32 %x, 5\n    %r = xor u32 %a, 3",
+        "%3 = xor u32 %0, 6",
+    ),
+    (
+        "mul-pow2",
+        "u32",
+        "%r = mul u32 %x, 8",
+        "%2 = shl u32 %0, 3",
+    ),
+    (
+        "udiv-pow2",
+        "u32",
+        "%r = udiv u32 %x, 16",
+        "%2 = lshr u32 %0, 4",
+    ),
+    (
+        "urem-pow2",
+        "u32",
+        "%r = urem u32 %x, 8",
+        "%2 = and u32 %0, 7",
+    ),
+];
+
+#[test]
+fn each_rule() {
+    for rule in RULES {
+        assert!(
+            CASES.iter().any(|(name, ..)| *name == rule.name),
+            "no case for {}",
+            rule.name
+        );
+    }
+    for (name, ty, ins, expected) in CASES {
+        let src = format!(
+            "fn @f(u32 %x, u32 %y) -> {ty} {{\nentry:\n    {ins}\n    ret {ty} %r\n}}\n",
+            ty = ty,
+            ins = ins
+        );
+        let func = parse::parse_function(&src).unwrap_or_else(|err| panic!("{}", err));
+        let ins = func.block(func.entry()).ins_list().last().unwrap();
+        // The rule of the case has to be the first one that matches, or the pass
+        // would never get to it.
+        let (fired, rewrite) = RULES
+            .iter()
+            .find_map(|rule| Some((rule.name, (rule.apply)(ins, &func)?)))
+            .unwrap_or_else(|| panic!("no rule rewrites {}", ins));
+        assert_eq!(fired, *name, "rule that rewrites {}", ins);
+        assert_eq!(rewrite.to_string(), *expected, "{} on {}", name, ins);
+    }
+}
+
+#[test]
+fn floats_are_left_alone() {
+    let src = "
+fn @f(f64 %x) -> f64 {
+entry:
+    %a = add f64 %x, 0.0
+    %b = sub f64 %a, %a
+    %c = mul f64 %b, 1.0
+    ret f64 %c
+}
+";
+    let func = parse::parse_function(src).unwrap();
+    for ins in func.block(func.entry()).ins_list() {
+        let fired: Vec<_> = RULES
+            .iter()
+            .filter(|rule| (rule.apply)(ins, &func).is_some())
+            .map(|rule| rule.name)
+            .collect();
+        assert!(fired.is_empty(), "{:?} rewrite {}", fired, ins);
+    }
+}
+
+#[test]
+fn rules_feed_each_other() {
+    // `(x * 1 + 5) - 5` is `x` once the subtraction is an addition that reassociates.
+    // The pass leaves the instructions nothing uses any more to DCE.
+    assert_pipeline(
+        "instcombine",
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    %a = mul u32 %x, 1
+    %b = add u32 5, %a
+    %c = sub u32 %b, 5
+    %d = urem u32 %c, 4
+    ret u32 %d
+}
+",
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    %2 = add u32 %0, 5
+    %4 = and u32 %0, 3
+    ret u32 %4
+}
+",
+    );
+}
+
+const DEBUGGED: &str = "
+fn @f(u32 %x) -> u32 {
+entry:
+    %a = add u32 %x, 1
+    %b = add u32 %a, 2
+    %c = mul u32 %b, 4
+    ret u32 %c
+}
+";
+
+#[test]
+fn debug_sink_records_each_rewrite() {
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&fired);
+    let mut pass = InstCombine::new().debug(move |record| sink.borrow_mut().push(record));
+    assert_eq!(pass.name(), "instcombine-debug");
+    let mut func = parse::parse_function(DEBUGGED).unwrap();
+    pass.run(&mut func, &mut Analyses::default());
+    let entry = func.entry();
+    assert_eq!(
+        *fired.borrow(),
+        [
+            Fired {
+                function: "f".to_string(),
+                block: entry,
+                rule: "reassociate",
+                before: "%2 = add u32 %1, 2".to_string(),
+                after: "%2 = add u32 %0, 3".to_string(),
+            },
+            Fired {
+                function: "f".to_string(),
+                block: entry,
+                rule: "mul-pow2",
+                before: "%3 = mul u32 %2, 4".to_string(),
+                after: "%3 = shl u32 %2, 2".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        fired.borrow()[0].to_string(),
+        "@f: bb0: reassociate: %2 = add u32 %1, 2 => %2 = add u32 %0, 3"
+    );
+}
+
+#[test]
+fn debug_pipeline_rewrites_like_the_plain_one() {
+    assert_eq!(
+        common::run("instcombine-debug", DEBUGGED),
+        common::run("instcombine", DEBUGGED)
+    );
+}
@@ -0,0 +1,98 @@
+//! The GLIR text format: printing what was parsed, and where malformed input is
+//! reported.
+
+use glair::ssa::parse;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn examples_round_trip() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/glir");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "glir"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no examples in {}", dir.display());
+    for path in paths {
+        let src = fs::read_to_string(&path).unwrap();
+        let printed = parse::parse_module(&src)
+            .unwrap_or_else(|err| panic!("{}:{}", path.display(), err))
+            .to_string();
+        let reprinted = parse::parse_module(&printed)
+            .unwrap_or_else(|err| panic!("{} printed as\n{}\n{}", path.display(), printed, err))
+            .to_string();
+        assert_eq!(printed, reprinted, "{}", path.display());
+    }
+}
+
+/// Checks that parsing `src` fails with `expected`, which starts with `line:col`.
+#[track_caller]
+fn assert_error(src: &str, expected: &str) {
+    match parse::parse_module(src) {
+        Ok(module) => panic!("parsed\n{}", module),
+        Err(err) => assert_eq!(err.to_string(), expected),
+    }
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    assert_error(
+        "fn @f() {
+entry:
+    ret
+}
+fn @g(u32 %x) -> u32 {
+entry:
+    %y = frob u32 %x, 1
+    ret u32 %y
+}
+",
+        "7:10: unknown instruction 'frob'",
+    );
+    assert_error(
+        "fn @f(u32 %x) -> u32 {
+entry:
+    %y = add u32 %x, %z
+    ret u32 %y
+}
+",
+        "3:22: undefined value '%z'",
+    );
+    assert_error(
+        "fn @f(bool %c) {
+entry:
+    br bool %c, entry, nowhere
+}
+",
+        "3:24: unknown block 'nowhere'",
+    );
+    assert_error(
+        "fn @f(i32 %x) -> bool {
+entry:
+    %c = cmp lt i32 %x, 0
+    ret bool %c
+}
+",
+        "3:14: unknown predicate 'lt'",
+    );
+}
+
+#[test]
+fn errors_in_tokens() {
+    assert_error(
+        "; A comment, then a stray character.
+fn @f() {
+entry:
+  # ret
+}
+",
+        "4:3: unexpected character '#'",
+    );
+    assert_error(
+        "global @s = \"abc\\4\", align 1\n",
+        "1:13: expected two hex digits after '\\'",
+    );
+    assert_error("fn % @f() {}", "1:4: expected a name after '%'");
+}
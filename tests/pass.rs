@@ -0,0 +1,233 @@
+//! Pipelines run by the pass manager, and the checks it makes between passes.
+
+use glair::codegen::{Codegen, CodegenContext};
+use glair::compile::ralloc::Liveness;
+use glair::compile::{self, CompileIntoModule, LowerIntoModule};
+use glair::opt;
+use glair::pass::{Analyses, FunctionPass, PassManager, Preserved};
+use glair::rtl;
+use glair::ssa::dom::DomTree;
+use glair::ssa::{parse, Function};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SUM: &str = "
+fn @sum(u32 %n) -> u32 {
+entry:
+    %a = add u32 %n, 1
+    %b = add u32 %a, 2
+    ret u32 %b
+}
+";
+
+/// Removes the first instruction, leaving its uses without a definition.
+struct Break;
+
+impl FunctionPass<Function> for Break {
+    fn name(&self) -> &str {
+        "break"
+    }
+
+    fn run(&mut self, func: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        let entry = func.entry();
+        func.cursor(entry, 0).remove();
+        Preserved::none()
+    }
+}
+
+#[test]
+#[cfg_attr(not(debug_assertions), ignore)]
+#[should_panic(expected = "malformed after break")]
+fn verifies_after_each_pass() {
+    let mut module = parse::parse_module(SUM).unwrap();
+    let mut passes = PassManager::new();
+    passes.add_function_pass(Break);
+    passes.run(&mut module);
+}
+
+#[test]
+#[cfg_attr(not(debug_assertions), ignore)]
+#[should_panic(expected = "malformed after the input of the pipeline")]
+fn verifies_the_input() {
+    let src = "
+fn @f(bool %c) -> u32 {
+entry:
+    br bool %c, then, exit
+then:
+    %x = add u32 1, 2
+    jmp exit
+exit:
+    ret u32 %x
+}
+";
+    let mut module = parse::parse_module(src).unwrap();
+    PassManager::parse(&opt::registry(), "dce")
+        .unwrap()
+        .run(&mut module);
+}
+
+#[test]
+fn pipeline_from_a_string() {
+    let passes = PassManager::parse(&opt::registry(), "constfold,dce,copyprop").unwrap();
+    assert_eq!(passes.pipeline(), ["constfold", "dce", "copyprop"]);
+    let err = PassManager::parse(&opt::registry(), "constfold,,dce").err();
+    assert_eq!(err.unwrap().pass, "");
+}
+
+#[test]
+fn constfold_leaves_branches_and_undefined_results() {
+    let src = "
+fn @f(u8 %x) -> u8 {
+entry:
+    %a = add u8 200, 100
+    %b = mul u8 %a, 3
+    %c = udiv u8 %b, 0
+    %d = add u8 %x, %b
+    %unused = sub u8 %d, %d
+    %k = cmp ult u8 %a, 50
+    br bool %k, l, r
+l:
+    ret u8 %c
+r:
+    ret u8 %d
+}
+";
+    let expected = "\
+fn @f(u8 %0) -> u8 {
+bb0:
+    %3 = udiv u8 132, 0
+    %4 = add u8 %0, 132
+    br bool true, bb1, bb2
+bb1:
+    ret u8 %3
+bb2:
+    ret u8 %4
+}
+";
+    let mut module = parse::parse_module(src).unwrap();
+    PassManager::parse(&opt::registry(), "constfold,dce,copyprop")
+        .unwrap()
+        .run(&mut module);
+    assert_eq!(module.functions()[0].to_string(), expected);
+}
+
+#[test]
+fn analyses_are_cached_until_invalidated() {
+    let func = parse::parse_function(SUM).unwrap();
+    let mut analyses = Analyses::<Function>::default();
+    assert!(!analyses.is_cached::<DomTree>());
+    let first = analyses.get::<DomTree>(&func);
+    // Computing the dominator tree needed the CFG.
+    assert!(analyses.is_cached::<DomTree>() && analyses.is_cached::<glair::ssa::cfg::Cfg>());
+    assert!(Rc::ptr_eq(&first, &analyses.get::<DomTree>(&func)));
+
+    analyses.invalidate(&Preserved::All);
+    assert!(analyses.is_cached::<DomTree>());
+    analyses.invalidate(&Preserved::control_flow());
+    assert!(analyses.is_cached::<DomTree>());
+    analyses.invalidate(&Preserved::none().and::<Function, DomTree>());
+    assert!(analyses.is_cached::<DomTree>());
+    assert!(!analyses.is_cached::<glair::ssa::cfg::Cfg>());
+    analyses.invalidate(&Preserved::none());
+    assert!(!analyses.is_cached::<DomTree>());
+    assert!(!Rc::ptr_eq(&first, &analyses.get::<DomTree>(&func)));
+}
+
+/// Notes whether the dominator tree was still known when it ran, then asks for it.
+struct Probe(Rc<RefCell<Vec<bool>>>);
+
+impl FunctionPass<Function> for Probe {
+    fn name(&self) -> &str {
+        "probe"
+    }
+
+    fn run(&mut self, func: &mut Function, analyses: &mut Analyses<Function>) -> Preserved {
+        self.0.borrow_mut().push(analyses.is_cached::<DomTree>());
+        analyses.get::<DomTree>(func);
+        Preserved::All
+    }
+}
+
+/// Claims to have made the change `preserved` describes.
+struct Changes(Preserved);
+
+impl FunctionPass<Function> for Changes {
+    fn name(&self) -> &str {
+        "changes"
+    }
+
+    fn run(&mut self, _: &mut Function, _: &mut Analyses<Function>) -> Preserved {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn pipeline_keeps_what_passes_preserve() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut module = parse::parse_module(SUM).unwrap();
+    let mut passes = PassManager::new();
+    passes.add_function_pass(Probe(seen.clone()));
+    passes.add_function_pass(Changes(Preserved::control_flow()));
+    passes.add_function_pass(Probe(seen.clone()));
+    passes.add_function_pass(Changes(Preserved::none()));
+    passes.add_function_pass(Probe(seen.clone()));
+    let preserved = passes.run(&mut module);
+    assert_eq!(*seen.borrow(), [false, true, false]);
+    assert_eq!(preserved, Preserved::none());
+}
+
+/// Counts the ops of each function it sees, and asks for their liveness so the
+/// allocator after it can use it.
+struct CountOps(Rc<RefCell<Vec<usize>>>);
+
+impl FunctionPass<rtl::Function> for CountOps {
+    fn name(&self) -> &str {
+        "count-ops"
+    }
+
+    fn run(
+        &mut self,
+        func: &mut rtl::Function,
+        analyses: &mut Analyses<rtl::Function>,
+    ) -> Preserved {
+        let ops = func.blocks.iter().map(|block| block.ops.len()).sum();
+        self.0.borrow_mut().push(ops);
+        analyses.get::<Liveness>(func);
+        Preserved::All
+    }
+}
+
+#[test]
+fn rtl_passes_run_before_allocation() {
+    let mut registry = compile::registry();
+    assert_eq!(registry.names().collect::<Vec<_>>(), ["ralloc", "promote"]);
+    let counts = Rc::new(RefCell::new(Vec::new()));
+    let shared = counts.clone();
+    registry.register_function_pass("count-ops", move || CountOps(shared.clone()));
+
+    let ssa = parse::parse_module(SUM).unwrap();
+    let mut module = ssa.lower_into_module();
+    assert!(module.functions[0].to_string().contains("(reg:4 "));
+    let pipeline = format!("count-ops,{}", compile::ALLOCATION);
+    let preserved = PassManager::parse(&registry, &pipeline)
+        .unwrap()
+        .run(&mut module);
+    assert_eq!(*counts.borrow(), [7]);
+    assert_eq!(preserved, Preserved::none());
+
+    let mut context = CodegenContext::default();
+    let compiled = ssa.compile_into_module();
+    assert_eq!(
+        module.codegen_string(&mut context),
+        compiled.codegen_string(&mut CodegenContext::default())
+    );
+}
+
+#[test]
+#[should_panic(expected = "registers are allocated before they are promoted")]
+fn promotion_needs_allocation() {
+    let mut module = parse::parse_module(SUM).unwrap().lower_into_module();
+    PassManager::parse(&compile::registry(), "promote")
+        .unwrap()
+        .run(&mut module);
+}
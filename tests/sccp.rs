@@ -0,0 +1,189 @@
+//! Sparse conditional constant propagation, from GLIR text to printed text.
+
+mod common;
+
+use common::{assert_pipeline, run};
+
+/// `%r = op T a, b` returned from a function of its own, and what SCCP returns
+/// instead.
+fn fold(ty: &str, ins: &str) -> String {
+    let src = format!(
+        "fn @f() -> {ty} {{\nentry:\n    %r = {ins}\n    ret {ty} %r\n}}\n",
+        ty = ty,
+        ins = ins
+    );
+    let out = run("sccp", &src);
+    let ret = out.lines().find(|line| line.trim().starts_with("ret"));
+    match (out.lines().count(), ret) {
+        (4, Some(ret)) => ret.trim().trim_start_matches("ret ").to_string(),
+        _ => out,
+    }
+}
+
+#[test]
+fn wraparound_per_width() {
+    let cases = [
+        ("u8", "add u8 200, 100", "u8 44"),
+        ("i8", "add i8 127, 1", "i8 -128"),
+        ("i8", "sub i8 -128, 1", "i8 127"),
+        ("i8", "mul i8 16, 16", "i8 0"),
+        ("u16", "mul u16 300, 300", "u16 24464"),
+        ("i16", "neg i16 -32768", "i16 -32768"),
+        ("u32", "sub u32 0, 1", "u32 4294967295"),
+        ("i32", "add i32 2147483647, 1", "i32 -2147483648"),
+        ("i32", "sdiv i32 -7, 2", "i32 -3"),
+        ("i32", "srem i32 -7, 2", "i32 -1"),
+        ("u32", "udiv u32 4294967295, 2", "u32 2147483647"),
+        (
+            "i64",
+            "mul i64 4611686018427387904, 2",
+            "i64 -9223372036854775808",
+        ),
+        ("u64", "add u64 18446744073709551615, 2", "u64 1"),
+        ("u8", "shl u8 255, 4", "u8 240"),
+        ("i8", "ashr i8 -128, 7", "i8 -1"),
+        ("i8", "lshr i8 -128, 7", "i8 1"),
+        ("u8", "not u8 15", "u8 240"),
+        ("u8", "trunc u32 511 to u8", "u8 255"),
+        ("i32", "sext i8 -1 to i32", "i32 -1"),
+        ("u32", "zext i8 -1 to u32", "u32 255"),
+        ("bool", "cmp slt i8 -1, 0", "bool true"),
+        ("bool", "cmp ult i8 -1, 0", "bool false"),
+    ];
+    for (ty, ins, expected) in cases {
+        assert_eq!(fold(ty, ins), expected, "{}", ins);
+    }
+}
+
+#[test]
+fn undefined_results_are_not_folded() {
+    let cases = [
+        ("i32", "sdiv i32 1, 0"),
+        ("u32", "udiv u32 1, 0"),
+        ("i32", "srem i32 1, 0"),
+        ("u8", "urem u8 1, 0"),
+        ("i8", "sdiv i8 -128, -1"),
+        ("i32", "srem i32 -2147483648, -1"),
+        ("i64", "sdiv i64 -9223372036854775808, -1"),
+        ("u8", "shl u8 1, 8"),
+        ("u32", "lshr u32 1, 32"),
+        ("i64", "ashr i64 -1, 64"),
+        ("i32", "fptosi f64 1e30 to i32"),
+        ("u8", "fptoui f32 -1.0 to u8"),
+    ];
+    for (ty, ins) in cases {
+        let out = fold(ty, ins);
+        let op = ins.split(' ').next().unwrap();
+        assert!(
+            out.contains(&format!("= {} ", op)),
+            "{} became {}",
+            ins,
+            out
+        );
+    }
+}
+
+#[test]
+fn constants_through_phis() {
+    assert_pipeline(
+        "sccp",
+        "
+fn @f(u32 %n) -> u32 {
+entry:
+    jmp header
+header:
+    %i = phi u32 [7, entry], [%j, body]
+    %more = cmp ult u32 %i, %n
+    br bool %more, body, exit
+body:
+    %j = mul u32 %i, 1
+    jmp header
+exit:
+    %r = add u32 %i, 1
+    ret u32 %r
+}
+",
+        "
+fn @f(u32 %0) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %2 = cmp ult u32 7, %0
+    br bool %2, bb2, bb3
+bb2:
+    jmp bb1
+bb3:
+    ret u32 8
+}
+",
+    );
+}
+
+#[test]
+fn untaken_branch_prunes_phi_incoming() {
+    assert_pipeline(
+        "sccp",
+        "
+fn @f(u32 %a, u32 %b) -> u32 {
+entry:
+    %k = cmp slt i32 -5, 3
+    br bool %k, other, join
+other:
+    %c = add u32 %b, 1
+    jmp join
+join:
+    %p = phi u32 [%a, entry], [%c, other]
+    ret u32 %p
+}
+",
+        "
+fn @f(u32 %0, u32 %1) -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    %3 = add u32 %1, 1
+    jmp bb2
+bb2:
+    %4 = phi u32 [%3, bb1]
+    ret u32 %4
+}
+",
+    );
+}
+
+#[test]
+fn unreachable_values_stay_out_of_phis() {
+    // The loop never runs, so `%i` is only ever 0. The `add` is never reached and its
+    // result doesn't flow back into the phi.
+    assert_pipeline(
+        "sccp",
+        "
+fn @f() -> u32 {
+entry:
+    jmp header
+header:
+    %i = phi u32 [0, entry], [%next, body]
+    %more = cmp ugt u32 %i, 5
+    br bool %more, body, exit
+body:
+    %next = add u32 %i, 1
+    jmp header
+exit:
+    ret u32 %i
+}
+",
+        "
+fn @f() -> u32 {
+bb0:
+    jmp bb1
+bb1:
+    jmp bb3
+bb2:
+    %2 = add u32 0, 1
+    jmp bb1
+bb3:
+    ret u32 0
+}
+",
+    );
+}
@@ -0,0 +1,402 @@
+//! Control flow simplification, from GLIR text to printed text, and the selects it
+//! makes as they reach the assembly.
+
+mod common;
+
+use common::assert_pipeline;
+use glair::codegen::{Codegen, CodegenContext};
+use glair::compile::CompileIntoFunction;
+use glair::opt;
+use glair::pass::PassManager;
+use glair::ssa::parse;
+
+#[test]
+fn merge_into_predecessor() {
+    // The phi of the merged block becomes its one value, and the phi of the join now
+    // takes from the block it was merged into.
+    assert_pipeline(
+        "simplifycfg",
+        "
+fn @f(bool %c, u32 %x, ptr %p) -> u32 {
+entry:
+    br bool %c, a, join
+a:
+    %a1 = add u32 %x, 1
+    store u32 %a1, %p, align 4
+    jmp b
+b:
+    %bp = phi u32 [%a1, a]
+    %b1 = mul u32 %bp, 2
+    jmp join
+join:
+    %m = phi u32 [%b1, b], [%x, entry]
+    ret u32 %m
+}
+",
+        "
+fn @f(bool %0, u32 %1, ptr %2) -> u32 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    %3 = add u32 %1, 1
+    store u32 %3, %2, align 4
+    %5 = mul u32 %3, 2
+    jmp bb2
+bb2:
+    %6 = phi u32 [%5, bb1], [%1, bb0]
+    ret u32 %6
+}
+",
+    );
+}
+
+#[test]
+fn skip_forwarding_block() {
+    assert_pipeline(
+        "simplifycfg",
+        "
+fn @f(bool %c, u32 %x, ptr %p) -> u32 {
+entry:
+    br bool %c, fwd, other
+fwd:
+    jmp join
+other:
+    store u32 %x, %p, align 4
+    jmp join
+join:
+    %m = phi u32 [1, fwd], [%x, other]
+    ret u32 %m
+}
+",
+        "
+fn @f(bool %0, u32 %1, ptr %2) -> u32 {
+bb0:
+    br bool %0, bb2, bb1
+bb1:
+    store u32 %1, %2, align 4
+    jmp bb2
+bb2:
+    %3 = phi u32 [%1, bb1], [1, bb0]
+    ret u32 %3
+}
+",
+    );
+}
+
+#[test]
+fn forwarding_block_with_other_phi_values_stays() {
+    // `entry` already reaches `join` with `%x`, so it can't take `1.0` there too, and
+    // float phis don't become selects.
+    let src = "
+fn @f(bool %c, f64 %x) -> f64 {
+entry:
+    br bool %c, fwd, join
+fwd:
+    jmp join
+join:
+    %m = phi f64 [1.0, fwd], [%x, entry]
+    ret f64 %m
+}
+";
+    assert_pipeline(
+        "simplifycfg",
+        src,
+        "
+fn @f(bool %0, f64 %1) -> f64 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    jmp bb2
+bb2:
+    %2 = phi f64 [1.0, bb1], [%1, bb0]
+    ret f64 %2
+}
+",
+    );
+}
+
+#[test]
+fn unreachable_blocks_and_their_phi_values_go() {
+    assert_pipeline(
+        "simplifycfg",
+        "
+fn @f(u32 %a, u32 %b) -> u32 {
+entry:
+    jmp join
+dead:
+    %d = add u32 %b, 1
+    jmp join
+join:
+    %m = phi u32 [%a, entry], [%d, dead]
+    ret u32 %m
+}
+",
+        "
+fn @f(u32 %0, u32 %1) -> u32 {
+bb0:
+    ret u32 %0
+}
+",
+    );
+}
+
+#[test]
+fn constant_branch() {
+    assert_pipeline(
+        "simplifycfg",
+        "
+fn @f(u32 %a, ptr %p) -> u32 {
+entry:
+    br bool false, l, r
+l:
+    store u32 %a, %p, align 4
+    jmp join
+r:
+    %b = add u32 %a, 1
+    jmp join
+join:
+    %m = phi u32 [%a, l], [%b, r]
+    ret u32 %m
+}
+",
+        "
+fn @f(u32 %0, ptr %1) -> u32 {
+bb0:
+    %2 = add u32 %0, 1
+    ret u32 %2
+}
+",
+    );
+}
+
+#[test]
+fn diamond_and_triangle_to_select() {
+    // Phis that take the same value from both arms need no select.
+    assert_pipeline(
+        "simplifycfg",
+        "
+fn @diamond(bool %c, u32 %x) -> u32 {
+entry:
+    br bool %c, l, r
+l:
+    %a = add u32 %x, 1
+    jmp join
+r:
+    %b = mul u32 %x, 3
+    %b2 = xor u32 %b, 5
+    jmp join
+join:
+    %m = phi u32 [%a, l], [%b2, r]
+    %n = phi u32 [%x, l], [%x, r]
+    %s = add u32 %m, %n
+    ret u32 %s
+}
+
+fn @triangle(u32 %x) -> u32 {
+entry:
+    %c = cmp ugt u32 %x, 100
+    br bool %c, big, join
+big:
+    jmp join
+join:
+    %m = phi u32 [100, big], [%x, entry]
+    ret u32 %m
+}
+",
+        "
+fn @diamond(bool %0, u32 %1) -> u32 {
+bb0:
+    %2 = add u32 %1, 1
+    %3 = mul u32 %1, 3
+    %4 = xor u32 %3, 5
+    %8 = select u32 %0, %2, %4
+    %7 = add u32 %8, %1
+    ret u32 %7
+}
+
+fn @triangle(u32 %0) -> u32 {
+bb0:
+    %1 = cmp ugt u32 %0, 100
+    %3 = select u32 %1, 100, %0
+    ret u32 %3
+}
+",
+    );
+}
+
+#[test]
+fn floats_and_loads_are_not_selected() {
+    let src = "
+fn @float(bool %c, f64 %x) -> f64 {
+entry:
+    br bool %c, l, join
+l:
+    %a = add f64 %x, 1.0
+    jmp join
+join:
+    %m = phi f64 [%a, l], [%x, entry]
+    ret f64 %m
+}
+
+fn @load(bool %c, ptr %p) -> u32 {
+entry:
+    br bool %c, l, join
+l:
+    %a = load u32 %p, align 4
+    jmp join
+join:
+    %m = phi u32 [%a, l], [0, entry]
+    ret u32 %m
+}
+";
+    assert_pipeline("simplifycfg", src, &common::run("", src));
+}
+
+const THREADED: &str = "
+fn @f(bool %c, bool %d, ptr %p) -> u32 {
+entry:
+    br bool %c, a, b
+a:
+    store u32 1, %p, align 4
+    jmp h
+b:
+    store u32 2, %p, align 4
+    jmp h
+h:
+    %k = phi bool [true, a], [%d, b]
+    br bool %k, t, f
+t:
+    store u32 3, %p, align 4
+    ret u32 1
+f:
+    store u32 4, %p, align 4
+    ret u32 2
+}
+";
+
+#[test]
+fn thread_jump() {
+    // `a` always goes on to `t`, so it jumps there. `h` is left with `b`, which it is
+    // merged into.
+    assert_pipeline(
+        "simplifycfg",
+        THREADED,
+        "
+fn @f(bool %0, bool %1, ptr %2) -> u32 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    store u32 1, %2, align 4
+    jmp bb3
+bb2:
+    store u32 2, %2, align 4
+    br bool %1, bb3, bb4
+bb3:
+    store u32 3, %2, align 4
+    ret u32 1
+bb4:
+    store u32 4, %2, align 4
+    ret u32 2
+}
+",
+    );
+}
+
+/// `a` branches to `t` itself as well as through `h`, with `%v` for the phi of `t`.
+fn threading_onto_an_existing_edge(v: u32) -> String {
+    format!(
+        "
+fn @f(bool %c, bool %d, bool %e, ptr %p) -> u32 {{
+entry:
+    br bool %c, a, b
+a:
+    store u32 1, %p, align 4
+    br bool %e, t, h
+b:
+    store u32 2, %p, align 4
+    jmp h
+h:
+    %k = phi bool [true, a], [%d, b]
+    br bool %k, t, f
+t:
+    %v = phi u32 [{}, a], [20, h]
+    ret u32 %v
+f:
+    ret u32 0
+}}
+",
+        v
+    )
+}
+
+#[test]
+fn thread_jump_onto_an_existing_edge() {
+    // The phi of `t` would need both 10 and 20 from `a`.
+    let src = threading_onto_an_existing_edge(10);
+    assert_pipeline("simplifycfg", &src, &common::run("", &src));
+
+    // With the same value for both, `a` jumps to `t` either way.
+    assert_pipeline(
+        "simplifycfg",
+        &threading_onto_an_existing_edge(20),
+        "
+fn @f(bool %0, bool %1, bool %2, ptr %3) -> u32 {
+bb0:
+    br bool %0, bb1, bb2
+bb1:
+    store u32 1, %3, align 4
+    jmp bb3
+bb2:
+    store u32 2, %3, align 4
+    br bool %1, bb3, bb4
+bb3:
+    %5 = phi u32 [20, bb1], [20, bb2]
+    ret u32 %5
+bb4:
+    ret u32 0
+}
+",
+    );
+}
+
+#[test]
+fn select_becomes_cmov() {
+    // There is no byte `cmov`, so the bytes are moved as dwords.
+    let src = "
+fn @clamp(u8 %x) -> u8 {
+entry:
+    %c = cmp ugt u8 %x, 100
+    br bool %c, big, join
+big:
+    jmp join
+join:
+    %m = phi u8 [100, big], [%x, entry]
+    ret u8 %m
+}
+";
+    let mut module = parse::parse_module(src).unwrap();
+    PassManager::parse(&opt::registry(), "simplifycfg")
+        .unwrap()
+        .run(&mut module);
+    let asm = module.functions()[0]
+        .compile_into_function()
+        .codegen_string(&mut CodegenContext::default());
+    let expected = "\
+clamp:
+push rbp
+mov rbp, rsp
+;; <unnamed block>
+mov al, dil
+.LBB_0:
+cmp al, 100
+seta cl
+mov sil, 100
+mov dl, al
+cmp cl, 0
+cmovne edx, esi
+mov al, dl
+leave
+ret
+";
+    assert_eq!(asm, expected);
+}
@@ -0,0 +1,550 @@
+//! Division and multiplication by constants, as lowered without `div` and `imul`,
+//! checked against Rust's own arithmetic: over every 8-bit divisor, factor and operand,
+//! and over samples of 32 and 64-bit ones. Both the plans and the RTL they are lowered
+//! to are run, the RTL by a small interpreter for the ops the lowering emits.
+
+use glair::compile::strength::{
+    multiply, multiply_plan, sext, truncate, Division, SignedMagic, UnsignedMagic,
+};
+use glair::compile::CompileIntoBlocks;
+use glair::rtl::{self, amd64::Amd64Register, Op, RealRegister, Register};
+use glair::ssa::parse;
+use std::collections::HashMap;
+
+/// The type of `bits` wide integers.
+fn int_ty(bits: u32, signed: bool) -> String {
+    format!("{}{}", if signed { 'i' } else { 'u' }, bits)
+}
+
+/// The literal `val` of a `bits` wide integer, as GLIR writes it.
+fn literal(val: u64, bits: u32, signed: bool) -> String {
+    match signed {
+        true => sext(val, bits).to_string(),
+        false => truncate(val, bits).to_string(),
+    }
+}
+
+/// The blocks of `fn @f(<ty> %x) { %r = <op> <ty> %x, <by>; ret <ty> %r }`, lowered
+/// but with virtual registers.
+fn lower(op: &str, ty: &str, by: &str) -> Vec<rtl::Block> {
+    let src = format!(
+        "fn @f({ty} %x) -> {ty} {{\nentry:\n    %r = {op} {ty} %x, {by}\n    ret {ty} %r\n}}\n",
+        ty = ty,
+        op = op,
+        by = by
+    );
+    let func = parse::parse_function(&src).unwrap_or_else(|err| panic!("{}", err));
+    func.compile_into_blocks()
+}
+
+/// What an op of [`Program`] does with the values of its operands.
+#[derive(Clone, Copy)]
+enum Kind {
+    Copy,
+    Add,
+    Sub,
+    Mul,
+    MulHigh {
+        signed: bool,
+    },
+    And,
+    Shl,
+    LShr,
+    AShr,
+    Neg,
+    /// `a + b * scale + disp`.
+    Lea {
+        scale: u64,
+        disp: u64,
+    },
+}
+
+/// A register, by its slot, or an immediate.
+#[derive(Clone, Copy)]
+enum Val {
+    Slot(usize),
+    Imm(u64),
+}
+
+/// An op that writes `to`, and `hi` too for a multiply-high, from `a` and `b`.
+struct Step {
+    kind: Kind,
+    to: usize,
+    hi: usize,
+    a: Val,
+    b: Val,
+}
+
+/// The straight-line ops of a function of one integer, with its registers numbered,
+/// so it can be run on many values quickly. Registers hold their bits zero extended,
+/// and a value is read at the width of the register it is in.
+struct Program {
+    steps: Vec<Step>,
+    slots: HashMap<Register, usize>,
+    widths: Vec<u32>,
+    arg: usize,
+    ret: usize,
+}
+
+impl Program {
+    fn new(blocks: &[rtl::Block], bits: u32) -> Program {
+        let bytes = bits as usize / 8;
+        let real = |reg: Amd64Register| Register::Real(RealRegister::Amd64(reg.with_size(bytes)));
+        let mut program = Program {
+            steps: Vec::new(),
+            slots: HashMap::new(),
+            widths: Vec::new(),
+            arg: 0,
+            ret: 0,
+        };
+        program.arg = program.slot(&real(Amd64Register::Rdi));
+        program.ret = program.slot(&real(Amd64Register::Rax));
+        for op in blocks.iter().flat_map(|block| &block.ops) {
+            let reg = |reg: &Register| rtl::RValue::Register(*reg);
+            let (kind, to, hi, a, b) = match op {
+                Op::Copy(op) => (Kind::Copy, op.to, op.to, op.from, op.from),
+                Op::Add(op) => (Kind::Add, op.to, op.to, reg(&op.to), op.val),
+                Op::Sub(op) => (Kind::Sub, op.from, op.from, reg(&op.from), op.val),
+                Op::Mul(op) => (Kind::Mul, op.val, op.val, reg(&op.val), op.with),
+                Op::MulHigh(op) => {
+                    let kind = Kind::MulHigh { signed: op.signed };
+                    (kind, op.lo, op.hi, reg(&op.lo), op.with)
+                }
+                Op::And(op) => (Kind::And, op.to, op.to, reg(&op.to), op.val),
+                Op::Shl(op) => (Kind::Shl, op.val, op.val, reg(&op.val), op.by),
+                Op::LShr(op) => (Kind::LShr, op.val, op.val, reg(&op.val), op.by),
+                Op::AShr(op) => (Kind::AShr, op.val, op.val, reg(&op.val), op.by),
+                Op::Neg(op) => (Kind::Neg, op.val, op.val, reg(&op.val), reg(&op.val)),
+                Op::Lea(op) => {
+                    let (index, scale) = op.addr.index.expect("lea scales an index");
+                    let kind = Kind::Lea {
+                        scale: scale as u64,
+                        disp: op.addr.disp as i64 as u64,
+                    };
+                    (kind, op.to, op.to, reg(&op.addr.base), reg(&index))
+                }
+                Op::Ret(..) => break,
+                op => panic!("{} is not expected", op),
+            };
+            let step = Step {
+                kind,
+                to: program.slot(&to),
+                hi: program.slot(&hi),
+                a: program.val(&a),
+                b: program.val(&b),
+            };
+            program.steps.push(step);
+        }
+        program
+    }
+
+    fn slot(&mut self, reg: &Register) -> usize {
+        let widths = &mut self.widths;
+        *self.slots.entry(*reg).or_insert_with(|| {
+            widths.push(reg.sz() as u32 * 8);
+            widths.len() - 1
+        })
+    }
+
+    fn val(&mut self, val: &rtl::RValue) -> Val {
+        match val {
+            rtl::RValue::Register(reg) => Val::Slot(self.slot(reg)),
+            rtl::RValue::Lit(lit) => Val::Imm(lit.bits()),
+        }
+    }
+
+    /// What the function returns for `x`.
+    fn run(&self, x: u64) -> u64 {
+        let mut regs = vec![0; self.widths.len()];
+        regs[self.arg] = x;
+        for step in &self.steps {
+            let get = |val: Val| match val {
+                Val::Slot(slot) => regs[slot],
+                Val::Imm(imm) => imm,
+            };
+            let n = self.widths[step.to];
+            let (a, b) = (get(step.a), get(step.b));
+            let val = match step.kind {
+                Kind::Copy => b,
+                Kind::Add => a.wrapping_add(b),
+                Kind::Sub => a.wrapping_sub(b),
+                Kind::Mul => a.wrapping_mul(b),
+                Kind::MulHigh { signed } => {
+                    let product = match signed {
+                        true => (sext(a, n) as i128 * sext(b, n) as i128) as u128,
+                        false => a as u128 * b as u128,
+                    };
+                    regs[step.hi] = truncate((product >> n) as u64, n);
+                    product as u64
+                }
+                Kind::And => a & b,
+                Kind::Shl => a << b,
+                Kind::LShr => a >> b,
+                Kind::AShr => (sext(a, n) >> b) as u64,
+                Kind::Neg => a.wrapping_neg(),
+                Kind::Lea { scale, disp } => {
+                    a.wrapping_add(b.wrapping_mul(scale)).wrapping_add(disp)
+                }
+            };
+            regs[step.to] = truncate(val, n);
+        }
+        regs[self.ret]
+    }
+}
+
+/// Checks that dividing each of `dividends` by `divisor`, both as planned and by the
+/// lowered RTL, gives what `/` and `%` give.
+fn check_division(divisor: u64, dividends: &[u64], bits: u32) {
+    for signed in [false, true] {
+        let Some(division) = Division::new(divisor, bits, signed) else {
+            assert_eq!(truncate(divisor, bits), 0, "only 0 is left to div");
+            continue;
+        };
+        let (ty, by) = (int_ty(bits, signed), literal(divisor, bits, signed));
+        let ops = match signed {
+            true => ["sdiv", "srem"],
+            false => ["udiv", "urem"],
+        };
+        let [div, rem] = ops.map(|op| Program::new(&lower(op, &ty, &by), bits));
+        for &x in dividends {
+            let (quotient, remainder) = match signed {
+                true => {
+                    let (x, divisor) = (sext(x, bits), sext(divisor, bits));
+                    // `MIN / -1` overflows and wraps to `MIN`, as `neg` does.
+                    let quotient = x.wrapping_div(divisor);
+                    (
+                        truncate(quotient as u64, bits),
+                        truncate(x.wrapping_sub(quotient.wrapping_mul(divisor)) as u64, bits),
+                    )
+                }
+                false => (x / divisor, x % divisor),
+            };
+            assert_eq!(
+                division.quotient(x, bits),
+                quotient,
+                "{} / {} with {:?}",
+                x,
+                divisor,
+                division
+            );
+            assert_eq!(
+                division.remainder(x, divisor, bits),
+                remainder,
+                "{} % {} with {:?}",
+                x,
+                divisor,
+                division
+            );
+            assert_eq!(div.run(x), quotient, "{} / {} in {}", x, by, ty);
+            assert_eq!(rem.run(x), remainder, "{} % {} in {}", x, by, ty);
+        }
+    }
+}
+
+fn check_multiply(factor: u64, operands: &[u64], bits: u32) {
+    let ty = int_ty(bits, false);
+    let lowered = Program::new(&lower("mul", &ty, &literal(factor, bits, false)), bits);
+    for &x in operands {
+        let product = truncate(x.wrapping_mul(factor), bits);
+        assert_eq!(lowered.run(x), product, "{} * {} in {}", x, factor, ty);
+    }
+    let Some(steps) = multiply_plan(factor, bits) else {
+        return;
+    };
+    assert!(steps.len() <= 2, "{:?} is longer than an imul", steps);
+    for &x in operands {
+        assert_eq!(
+            multiply(&steps, x, bits),
+            truncate(x.wrapping_mul(factor), bits),
+            "{} * {} with {:?}",
+            x,
+            factor,
+            steps
+        );
+    }
+}
+
+#[test]
+fn exhaustive_8_bit_division() {
+    let dividends: Vec<u64> = (0..256).collect();
+    for divisor in 1..256 {
+        check_division(divisor, &dividends, 8);
+    }
+}
+
+#[test]
+fn exhaustive_8_bit_multiplication() {
+    let operands: Vec<u64> = (0..256).collect();
+    for factor in 0..256 {
+        check_multiply(factor, &operands, 8);
+    }
+}
+
+/// A xorshift generator, so the samples are the same on every run.
+struct Samples(u64);
+
+impl Iterator for Samples {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        Some(self.0)
+    }
+}
+
+/// The values around 0, the powers of two and the ends of the signed and unsigned
+/// ranges, where the shifts and corrections go wrong, and random ones in between.
+fn samples(bits: u32, seed: u64, random: usize) -> Vec<u64> {
+    let mut values: Vec<u64> = (0..=64).collect();
+    for k in 1..bits {
+        let power = 1u64 << k;
+        values.extend([power - 1, power, power + 1, power.wrapping_neg()]);
+        values.extend([power.wrapping_neg() - 1, power.wrapping_neg() + 1]);
+    }
+    values.extend((1..=64).map(|val: u64| val.wrapping_neg()));
+    values.extend(Samples(seed).take(random));
+    values.extend(
+        Samples(seed)
+            .take(random)
+            .map(|val| val >> (val % bits as u64)),
+    );
+    let mut values: Vec<u64> = values.into_iter().map(|val| truncate(val, bits)).collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+#[test]
+fn sampled_32_bit_division() {
+    let dividends = samples(32, 0x9e3779b97f4a7c15, 2000);
+    for divisor in samples(32, 0x2545f4914f6cdd1d, 1000) {
+        check_division(divisor, &dividends, 32);
+    }
+}
+
+#[test]
+fn sampled_64_bit_division() {
+    let dividends = samples(64, 0x9e3779b97f4a7c15, 1000);
+    for divisor in samples(64, 0x2545f4914f6cdd1d, 500) {
+        check_division(divisor, &dividends, 64);
+    }
+}
+
+#[test]
+fn sampled_32_bit_multiplication() {
+    let operands = samples(32, 0x9e3779b97f4a7c15, 500);
+    for factor in (0..1100).chain(samples(32, 0x2545f4914f6cdd1d, 100)) {
+        check_multiply(factor, &operands, 32);
+        check_multiply(factor, &operands, 64);
+    }
+}
+
+#[test]
+fn magic_numbers() {
+    let unsigned = |divisor| match Division::new(divisor, 32, false) {
+        Some(Division::Unsigned(magic)) => magic,
+        division => panic!("{:?} divides by {}", division, divisor),
+    };
+    let signed = |divisor| match Division::new(divisor as u64, 32, true) {
+        Some(Division::Signed(magic)) => magic,
+        division => panic!("{:?} divides by {}", division, divisor),
+    };
+    assert_eq!(
+        unsigned(3),
+        UnsignedMagic {
+            multiplier: 0xaaaaaaab,
+            shift: 1,
+            add: false
+        }
+    );
+    assert_eq!(
+        unsigned(7),
+        UnsignedMagic {
+            multiplier: 0x24924925,
+            shift: 2,
+            add: true
+        }
+    );
+    assert_eq!(
+        signed(3),
+        SignedMagic {
+            multiplier: 0x55555556,
+            shift: 0,
+            add: false,
+            negate: false
+        }
+    );
+    assert_eq!(
+        signed(7),
+        SignedMagic {
+            multiplier: 0x92492493,
+            shift: 2,
+            add: true,
+            negate: false
+        }
+    );
+    assert_eq!(
+        signed(-5),
+        SignedMagic {
+            multiplier: 0x66666667,
+            shift: 1,
+            add: false,
+            negate: true
+        }
+    );
+}
+
+#[test]
+fn small_multiplications() {
+    use glair::compile::strength::MulStep::*;
+    assert_eq!(multiply_plan(1, 32), Some(vec![]));
+    assert_eq!(multiply_plan(3, 32), Some(vec![Lea(2)]));
+    assert_eq!(multiply_plan(8, 32), Some(vec![Shl(3)]));
+    assert_eq!(multiply_plan(15, 32), Some(vec![Lea(2), Lea(4)]));
+    assert_eq!(multiply_plan(-1i64 as u64, 64), Some(vec![Neg]));
+    assert_eq!(multiply_plan(3, 8), Some(vec![Shl(1), Add]));
+    assert_eq!(multiply_plan(0, 32), None);
+    assert_eq!(multiply_plan(1000, 32), None);
+}
+
+/// The ops of the entry block of [`lower`], as text.
+fn lowered(op: &str, ty: &str, by: &str) -> Vec<String> {
+    let blocks = lower(op, ty, by);
+    let body = blocks
+        .iter()
+        .find(|block| block.name.as_deref() == Some("LBB_0"))
+        .expect("entry block is lowered");
+    body.ops.iter().map(|op| op.to_string()).collect()
+}
+
+/// Whether a `div` or `idiv` is among `ops`.
+fn divides(ops: &[String]) -> bool {
+    ops.iter()
+        .any(|op| op.starts_with("(udiv ") || op.starts_with("(sdiv ") || op.starts_with("(div "))
+}
+
+/// Whether an `imul` is among `ops`.
+fn multiplies(ops: &[String]) -> bool {
+    ops.iter().any(|op| op.starts_with("(mul "))
+}
+
+#[test]
+fn unsigned_division_by_7_in_rtl() {
+    // The magic number needs 33 bits, so the high half is added back in halves.
+    assert_eq!(
+        lowered("udiv", "u32", "7"),
+        [
+            "(copy (reg_amd64 eax) (reg:4 0))",
+            "(umul_high (reg_amd64 eax) (reg_amd64 edx) (lit_u32 613566757))",
+            "(copy (reg:4 2) (reg_amd64 edx))",
+            "(copy (reg:4 1) (reg:4 0))",
+            "(sub (reg:4 1) (reg:4 2))",
+            "(lshr (reg:4 1) (lit_u32 1))",
+            "(add (reg:4 1) (reg:4 2))",
+            "(lshr (reg:4 1) (lit_u32 2))",
+            "(copy (reg_amd64 eax) (reg:4 1))",
+            "(ret)",
+        ]
+    );
+}
+
+#[test]
+fn signed_division_by_minus_3_in_rtl() {
+    // The quotient by 3, rounded towards zero by adding its sign bit, then negated.
+    assert_eq!(
+        lowered("sdiv", "i32", "-3"),
+        [
+            "(copy (reg_amd64 eax) (reg:4 0))",
+            "(smul_high (reg_amd64 eax) (reg_amd64 edx) (lit_u32 1431655766))",
+            "(copy (reg:4 1) (reg_amd64 edx))",
+            "(copy (reg:4 2) (reg:4 1))",
+            "(lshr (reg:4 2) (lit_u32 31))",
+            "(add (reg:4 1) (reg:4 2))",
+            "(neg (reg:4 1))",
+            "(copy (reg_amd64 eax) (reg:4 1))",
+            "(ret)",
+        ]
+    );
+}
+
+#[test]
+fn remainders_and_products_in_rtl() {
+    // `x % 10` is `x - (x / 10) * 10`, with the product as a `lea` and a shift.
+    assert_eq!(
+        lowered("urem", "u32", "10"),
+        [
+            "(copy (reg_amd64 eax) (reg:4 0))",
+            "(umul_high (reg_amd64 eax) (reg_amd64 edx) (lit_u32 3435973837))",
+            "(copy (reg:4 2) (reg_amd64 edx))",
+            "(lshr (reg:4 2) (lit_u32 3))",
+            "(copy (reg:4 3) (reg:4 2))",
+            "(lea (reg:4 3) (mem (reg:4 3) (reg:4 3) 4 0))",
+            "(shl (reg:4 3) (lit_u32 1))",
+            "(copy (reg:4 1) (reg:4 0))",
+            "(sub (reg:4 1) (reg:4 3))",
+            "(copy (reg_amd64 eax) (reg:4 1))",
+            "(ret)",
+        ]
+    );
+    assert_eq!(
+        lowered("mul", "u32", "9"),
+        [
+            "(copy (reg:4 1) (reg:4 0))",
+            "(lea (reg:4 1) (mem (reg:4 1) (reg:4 1) 8 0))",
+            "(copy (reg_amd64 eax) (reg:4 1))",
+            "(ret)",
+        ]
+    );
+}
+
+#[test]
+fn no_div_or_imul_for_constants() {
+    let divisors: [(&str, u32, &[i128]); 8] = [
+        ("u8", 8, &[1, 2, 3, 7, 10, 128, 255]),
+        ("i8", 8, &[1, -1, 2, -2, 3, -3, 7, -128]),
+        ("u16", 16, &[3, 7, 10, 641, 65535]),
+        ("i16", 16, &[-1, 3, -7, 100, -32768]),
+        ("u32", 32, &[3, 7, 10, 641, 4294967295]),
+        ("i32", 32, &[-1, 3, -3, 7, -8, 1000, -2147483648]),
+        ("u64", 64, &[3, 7, 10, 274177, 18446744073709551615]),
+        ("i64", 64, &[-1, 3, -3, 7, -5, 9223372036854775807]),
+    ];
+    for (ty, bits, divisors) in divisors {
+        let (div, rem) = match ty.starts_with('i') {
+            true => ("sdiv", "srem"),
+            false => ("udiv", "urem"),
+        };
+        for &by in divisors {
+            let quotient = lowered(div, ty, &by.to_string());
+            assert!(
+                !divides(&quotient) && !multiplies(&quotient),
+                "{} {} by {}: {:#?}",
+                div,
+                ty,
+                by,
+                quotient
+            );
+            // The remainder multiplies the quotient back, with an `imul` only when
+            // there is no cheaper way to multiply by the divisor.
+            let remainder = lowered(rem, ty, &by.to_string());
+            let planned = multiply_plan(truncate(by as u64, bits), bits).is_some();
+            assert!(
+                !divides(&remainder) && (!planned || !multiplies(&remainder)),
+                "{} {} by {}: {:#?}",
+                rem,
+                ty,
+                by,
+                remainder
+            );
+        }
+    }
+    for (ty, factor) in [("u32", "3"), ("u32", "8"), ("i64", "-1"), ("u64", "45")] {
+        let product = lowered("mul", ty, factor);
+        assert!(!multiplies(&product), "{:#?}", product);
+    }
+    // Without a plan they stay.
+    assert!(divides(&lowered("udiv", "u32", "%x")));
+    assert!(multiplies(&lowered("mul", "u32", "1000")));
+}
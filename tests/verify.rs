@@ -0,0 +1,287 @@
+//! One malformed function for each kind of [`VerifyError`], and the errors
+//! [`verify`] finds in it.
+
+use glair::ssa::verify::{verify, verify_module, VerifyError};
+use glair::ssa::{parse, Function, Ins, Literal, RValue, Signature, Terminator};
+use glair::typing::Type;
+
+/// The errors of the function, as they are displayed.
+fn errors(func: &Function) -> Vec<String> {
+    match verify(func) {
+        Ok(()) => panic!("{} is well formed", func),
+        Err(errors) => errors.iter().map(|err| err.to_string()).collect(),
+    }
+}
+
+#[track_caller]
+fn assert_errors(src: &str, expected: &[&str]) {
+    let func = parse::parse_function(src).unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(errors(&func), expected);
+}
+
+/// The errors of the module, as they are displayed after the name they come with.
+#[track_caller]
+fn assert_module_errors(src: &str, expected: &[&str]) {
+    let module = parse::parse_module(src).unwrap_or_else(|err| panic!("{}", err));
+    let errors: Vec<String> = match verify_module(&module) {
+        Ok(()) => panic!("{} is well formed", module),
+        Err(errors) => errors
+            .iter()
+            .map(|(name, err)| format!("{}: {}", name, err))
+            .collect(),
+    };
+    assert_eq!(errors, expected);
+}
+
+#[test]
+fn well_formed() {
+    let func = parse::parse_function(
+        "
+fn @f(bool %c, u32 %x) -> u32 {
+entry:
+    br bool %c, a, b
+a:
+    %y = add u32 %x, 1
+    jmp b
+b:
+    %z = phi u32 [%y, a], [%x, entry]
+    ret u32 %z
+}
+",
+    )
+    .unwrap();
+    assert_eq!(verify(&func), Ok(()));
+}
+
+#[test]
+fn no_blocks() {
+    let func = Function::new("f", Signature::new(vec![], None));
+    assert_eq!(verify(&func), Err(vec![VerifyError::NoBlocks]));
+}
+
+#[test]
+fn missing_terminator() {
+    assert_errors(
+        "
+fn @f() {
+entry:
+    jmp next
+next:
+}
+",
+        &["bb1: block has no terminator"],
+    );
+}
+
+#[test]
+fn unknown_block() {
+    let other = parse::parse_function(
+        "
+fn @g() {
+a:
+    jmp b
+b:
+    ret
+}
+",
+    )
+    .unwrap();
+    let missing = other.block_ids().last().unwrap();
+    let mut func = Function::new("f", Signature::new(vec![], None));
+    let entry = func.create_block();
+    func.emitter(entry).emit_jmp(missing);
+    assert_eq!(errors(&func), ["bb0: refers to unknown block bb1"]);
+}
+
+#[test]
+fn redefinition() {
+    let mut func = parse::parse_function(
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    %y = add u32 %x, 1
+    ret u32 %y
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let y = func.block(entry).ins_list()[0].dest().unwrap();
+    func.cursor(entry, 1)
+        .insert(Ins::Cpy(y, RValue::Lit(Literal::U32(2))));
+    assert_eq!(errors(&func), ["bb0: %1 is defined more than once"]);
+}
+
+#[test]
+fn undefined_value() {
+    let mut func = parse::parse_function(
+        "
+fn @f() -> u32 {
+entry:
+    ret u32 0
+}
+",
+    )
+    .unwrap();
+    let entry = func.entry();
+    let mut cursor = func.cursor(entry, 0);
+    let never = cursor.create_var(Type::U32);
+    cursor.set_terminator(Terminator::Ret(Some(RValue::Var(never))));
+    assert_eq!(errors(&func), ["bb0: %0 is never defined"]);
+}
+
+#[test]
+fn use_not_dominated_by_its_def() {
+    // `%x` is only defined when the branch goes through `a`.
+    assert_errors(
+        "
+fn @f(bool %c) -> u32 {
+entry:
+    br bool %c, a, b
+a:
+    %x = add u32 1, 2
+    jmp b
+b:
+    %y = add u32 %x, 1
+    ret u32 %y
+}
+",
+        &["bb2: definition of %1 does not dominate its use"],
+    );
+}
+
+#[test]
+fn type_mismatch() {
+    let mut func = Function::new("f", Signature::new(vec![Type::U32], None));
+    let x = func.params()[0];
+    let [entry, a] = [func.create_block(), func.create_block()];
+    func.emitter(entry).emit_br(x, a, a);
+    func.emitter(a).emit_ret_void();
+    assert_eq!(
+        errors(&func),
+        ["bb0: expected bool but found u32 in 'br u32 %0, bb1, bb1'"]
+    );
+}
+
+#[test]
+fn invalid_type() {
+    assert_errors(
+        "
+fn @f(f64 %x) -> f64 {
+entry:
+    %y = sdiv f64 %x, %x
+    ret f64 %y
+}
+",
+        &["bb0: f64 is not allowed in '%1 = sdiv f64 %0, %0'"],
+    );
+}
+
+#[test]
+fn invalid_align() {
+    assert_errors(
+        "
+fn @f(ptr %p) -> u32 {
+entry:
+    %y = load u32 %p, align 3
+    ret u32 %y
+}
+",
+        &["bb0: alignment 3 is not allowed in '%1 = load u32 %0, align 3'"],
+    );
+}
+
+#[test]
+fn invalid_index() {
+    assert_errors(
+        "
+fn @f(ptr %p) -> ptr {
+entry:
+    %y = gep {u32, u32} %p, i32 0, i32 2
+    ret ptr %y
+}
+",
+        &["bb0: invalid indices in '%1 = gep {u32, u32} %0, i32 0, i32 2'"],
+    );
+}
+
+#[test]
+fn return_mismatch() {
+    assert_errors(
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    ret
+}
+",
+        &["bb0: return does not match the signature"],
+    );
+}
+
+#[test]
+fn misplaced_phi() {
+    assert_errors(
+        "
+fn @f(bool %c, u32 %x) -> u32 {
+entry:
+    br bool %c, a, b
+a:
+    jmp b
+b:
+    %y = add u32 %x, 1
+    %z = phi u32 [%x, entry], [1, a]
+    ret u32 %z
+}
+",
+        &["bb2: phi %3 comes after a non-phi instruction"],
+    );
+}
+
+#[test]
+fn phi_incoming_blocks_are_not_the_predecessors() {
+    // `b` is reached from `entry` and `a`, but its phi names `entry` and itself.
+    assert_errors(
+        "
+fn @f(bool %c) -> u32 {
+entry:
+    br bool %c, a, b
+a:
+    jmp b
+b:
+    %y = phi u32 [1, entry], [2, b]
+    ret u32 %y
+}
+",
+        &[
+            "bb2: phi %1 has no value for predecessor bb1",
+            "bb2: phi %1 has an unexpected value from bb2",
+        ],
+    );
+}
+
+#[test]
+fn module_errors() {
+    assert_module_errors(
+        "
+fn @f(u32 %x) -> u32 {
+entry:
+    %y = call u32 @g(u32 %x)
+    %a = addr @nothing
+    ret u32 %y
+}
+fn @h(u32 %x) -> u32 {
+entry:
+    %y = call u32 @h(u32 %x, u32 %x)
+    ret u32 %y
+}
+global @h = zeroinit 4, align 3
+",
+        &[
+            "h: @h is defined twice",
+            "h: alignment 3 of @h is not allowed",
+            "f: bb0: calls unknown function @g",
+            "f: bb0: refers to unknown global @nothing",
+            "h: bb0: '%1 = call u32 @h(u32 %0, u32 %0)' does not match the callee's signature",
+        ],
+    );
+}